@@ -46,8 +46,53 @@ pub enum Operation {
     Filter(SimpleExpr),
     Select(NonEmptyVec<SpreadExpr>),
     Aggregate(SimpleExpr),
+    /// `AGGREGATE <expr> GROUP BY <key>`, i.e. one aggregation per distinct value of `key`
+    AggregateBy(SimpleExpr, SimpleExpr),
+    /// `AGGREGATE <expr> WINDOW <size> [EVERY <slide>]`, i.e. one aggregation per time window
+    AggregateWindow(SimpleExpr, Window),
+    /// `ORDER BY <expr> [ASC|DESC]`, i.e. sorting all inputs by the value of `expr`
+    OrderBy(SimpleExpr, SortOrder),
     Limit(NonZeroU64),
+    /// `DISTINCT [ON <key>] [KEEP <n>]`, i.e. only the first input per key (or value), where
+    /// at most `n` keys are remembered
+    Distinct(Option<SimpleExpr>, Option<NonZeroU64>),
     Binding(String, SimpleExpr),
+    /// `[LEFT] JOIN <tag_expr> AS <name> ON <key> = <other_key>`
+    Join(Join),
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        render::render_operation(f, self)
+    }
+}
+
+/// Pairing of inputs with the events selected by `from` that have the same key.
+///
+/// `key` is computed from the input (bound to `_`), `other_key` from the event (bound to `name`).
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct Join {
+    /// also emit inputs without matching events, paired with NULL
+    pub outer: bool,
+    pub from: TagExpr,
+    pub name: String,
+    pub key: SimpleExpr,
+    pub other_key: SimpleExpr,
+}
+
+/// Time windows of `size` microseconds, starting every `slide` microseconds.
+///
+/// Windows are tumbling if `slide == size` and sliding (i.e. overlapping) if `slide < size`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct Window {
+    pub size: NonZeroU64,
+    pub slide: NonZeroU64,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum SortOrder {
+    Asc,
+    Desc,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -259,6 +304,13 @@ decl_op! {
         Max -> "MAX",
         First -> "FIRST",
         Last -> "LAST",
+        Count -> "COUNT",
+        Avg -> "AVG",
+        StdDev -> "STDDEV",
+        P50 -> "P50",
+        P90 -> "P90",
+        P95 -> "P95",
+        P99 -> "P99",
     }
 }
 
@@ -375,8 +427,23 @@ impl SimpleExpr {
                                 }
                             }
                             Operation::Aggregate(e) => e.traverse(f),
+                            Operation::AggregateBy(e, k) => {
+                                e.traverse(f);
+                                k.traverse(f);
+                            }
+                            Operation::AggregateWindow(e, _) => e.traverse(f),
+                            Operation::OrderBy(e, _) => e.traverse(f),
                             Operation::Limit(_) => {}
+                            Operation::Distinct(k, _) => {
+                                if let Some(k) = k {
+                                    k.traverse(f);
+                                }
+                            }
                             Operation::Binding(_, e) => e.traverse(f),
+                            Operation::Join(j) => {
+                                j.key.traverse(f);
+                                j.other_key.traverse(f);
+                            }
                         }
                     }
                 }
@@ -777,18 +844,102 @@ mod for_tests {
             fn Binding(g: &mut Gen) -> Operation {
                 Operation::Binding(Var::arbitrary(g).0, SimpleExpr::arbitrary(g))
             }
-            arb!(Operation: g => Filter Select Aggregate{ Context::Aggregate { now: Timestamp::now() } } Limit,,,, Binding)
+            #[allow(non_snake_case)]
+            fn AggregateBy(g: &mut Gen) -> Operation {
+                let key = SimpleExpr::arbitrary(g);
+                let prev = CTX.with(|c| c.replace(Context::Aggregate { now: Timestamp::now() }));
+                let expr = SimpleExpr::arbitrary(g);
+                CTX.with(|c| c.replace(prev));
+                Operation::AggregateBy(expr, key)
+            }
+            #[allow(non_snake_case)]
+            fn AggregateWindow(g: &mut Gen) -> Operation {
+                let prev = CTX.with(|c| c.replace(Context::Aggregate { now: Timestamp::now() }));
+                let expr = SimpleExpr::arbitrary(g);
+                CTX.with(|c| c.replace(prev));
+                Operation::AggregateWindow(expr, Window::arbitrary(g))
+            }
+            #[allow(non_snake_case)]
+            fn OrderBy(g: &mut Gen) -> Operation {
+                let order = *g.choose(&[SortOrder::Asc, SortOrder::Desc]).unwrap();
+                Operation::OrderBy(SimpleExpr::arbitrary(g), order)
+            }
+            #[allow(non_snake_case)]
+            fn Join(g: &mut Gen) -> Operation {
+                let name = Var::arbitrary(g).0;
+                let index = |g: &mut Gen, head: &str| {
+                    SimpleExpr::Indexing(Ind {
+                        head: Arc::new(SimpleExpr::Variable(Var(head.to_owned()))),
+                        tail: vec![Index::String(Var::arbitrary(g).0)].try_into().unwrap(),
+                    })
+                };
+                Operation::Join(super::Join {
+                    outer: bool::arbitrary(g),
+                    // interpolations could refer to `_`, which is not permitted here
+                    from: TagExpr::Atom(TagAtom::Tag(Arbitrary::arbitrary(g))),
+                    key: index(g, "_"),
+                    other_key: index(g, &name),
+                    name,
+                })
+            }
+            #[allow(non_snake_case)]
+            fn Distinct(g: &mut Gen) -> Operation {
+                Operation::Distinct(Arbitrary::arbitrary(g), Arbitrary::arbitrary(g))
+            }
+            arb!(Operation: g => Filter Select Aggregate{ Context::Aggregate { now: Timestamp::now() } } Limit,,,, Binding AggregateBy AggregateWindow OrderBy Join Distinct)
         }
         fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
             shrink!(Operation: self => Filter Select Aggregate Limit,,,
                 Operation::Binding(n, e) => {
                     let n = n.clone();
                     Box::new(e.shrink().map(move |e| Operation::Binding(n.clone(), e)))
+                },
+                Operation::AggregateBy(e, k) => {
+                    let k = k.clone();
+                    Box::new(e.shrink().map(move |e| Operation::AggregateBy(e, k.clone())))
+                },
+                Operation::AggregateWindow(e, w) => {
+                    let w = *w;
+                    Box::new(e.shrink().map(move |e| Operation::AggregateWindow(e, w)))
+                },
+                Operation::OrderBy(e, o) => {
+                    let o = *o;
+                    Box::new(e.shrink().map(move |e| Operation::OrderBy(e, o)))
+                },
+                Operation::Distinct(k, n) => {
+                    let n = *n;
+                    Box::new(k.shrink().map(move |k| Operation::Distinct(k, n)))
+                },
+                Operation::Join(j) => {
+                    let j = j.clone();
+                    Box::new(j.from.shrink().map(move |from| Operation::Join(super::Join { from, ..j.clone() })))
                 }
             )
         }
     }
 
+    impl Arbitrary for Window {
+        fn arbitrary(g: &mut Gen) -> Self {
+            // only durations that can be written in a query
+            let unit = *g
+                .choose(&[
+                    1_000,
+                    1_000_000,
+                    60_000_000,
+                    3_600_000_000,
+                    86_400_000_000,
+                    604_800_000_000,
+                ])
+                .unwrap();
+            let size = u64::arbitrary(g) % 100 + 1;
+            let slide = u64::arbitrary(g) % size + 1;
+            Self {
+                size: NonZeroU64::new(size * unit).unwrap(),
+                slide: NonZeroU64::new(slide * unit).unwrap(),
+            }
+        }
+    }
+
     impl Arbitrary for Source {
         fn arbitrary(g: &mut Gen) -> Self {
             if bool::arbitrary(g) {
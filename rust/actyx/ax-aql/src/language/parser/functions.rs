@@ -0,0 +1,223 @@
+//! User-defined functions, declared with `PRAGMA define Name(params) := body`.
+//!
+//! Calls to these functions are expanded while parsing by substituting the arguments for the
+//! parameters in the body, so the resulting [`Query`] only contains builtin function calls.
+//! Since nested calls may grow the expression exponentially, the size of each expanded call is
+//! limited to [`MAX_EXPANSION_SIZE`] expression nodes.
+use super::super::{FuncCall, Galactus, Ind, Query, SimpleExpr, Tactic, Traverse};
+use anyhow::{anyhow, bail, ensure, Result};
+use std::{collections::BTreeMap, sync::Arc};
+
+/// maximum number of expression nodes a single function call may expand to
+pub const MAX_EXPANSION_SIZE: usize = 10_000;
+
+struct Definition {
+    params: Vec<String>,
+    body: SimpleExpr,
+}
+
+#[derive(Default)]
+pub(super) struct Definitions(BTreeMap<String, Definition>);
+
+impl Definitions {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Add a definition, expanding calls to previously defined functions in its body.
+    pub fn define(&mut self, name: String, params: Vec<String>, body: SimpleExpr) -> Result<()> {
+        ensure!(
+            !self.0.contains_key(&name),
+            "function `{}` is defined more than once",
+            name
+        );
+        for (idx, param) in params.iter().enumerate() {
+            ensure!(
+                param != "_",
+                "`_` cannot be used as parameter name of function `{}`",
+                name
+            );
+            ensure!(
+                !params[..idx].contains(param),
+                "parameter `{}` of function `{}` is declared more than once",
+                param,
+                name
+            );
+        }
+        // check the body as written, a call to a later function may expand into a call to this one,
+        // which is reported by `check_order`
+        let mut error = None;
+        body.traverse(&mut |e| {
+            if error.is_some() {
+                return Traverse::Stop;
+            }
+            match e {
+                SimpleExpr::Variable(v) if !params.iter().any(|p| p == &**v) => {
+                    error = Some(anyhow!("variable `{}` is not a parameter of function `{}`", v, name));
+                }
+                SimpleExpr::KeyVar(v) | SimpleExpr::TimeVar(v) | SimpleExpr::Tags(v) | SimpleExpr::App(v) => {
+                    error = Some(anyhow!(
+                        "metadata of `{}` cannot be accessed in the definition of function `{}`",
+                        v,
+                        name
+                    ));
+                }
+                SimpleExpr::SubQuery(_) => {
+                    error = Some(anyhow!("function `{}` cannot contain sub-queries", name));
+                }
+                SimpleExpr::FuncCall(f) if f.name == name => {
+                    error = Some(anyhow!("function `{}` calls itself, recursion is not allowed", name));
+                }
+                _ => {}
+            }
+            Traverse::Descend
+        });
+        if let Some(e) = error {
+            return Err(e);
+        }
+        let body = self.expand(&body)?;
+        self.0.insert(name, Definition { params, body });
+        Ok(())
+    }
+
+    /// Check that functions only call functions that are defined before them, which makes
+    /// recursion impossible.
+    pub fn check_order(&self) -> Result<()> {
+        for (name, def) in self.0.iter() {
+            let mut later = None;
+            def.body.traverse(&mut |e| {
+                match e {
+                    SimpleExpr::FuncCall(f) if later.is_none() && self.0.contains_key(&f.name) => {
+                        later = Some(f.name.clone())
+                    }
+                    _ => {}
+                }
+                Traverse::Descend
+            });
+            if let Some(later) = later {
+                bail!(
+                    "function `{}` calls `{}`, which is defined after it (recursion is not allowed)",
+                    name,
+                    later
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn expand(&self, expr: &SimpleExpr) -> Result<SimpleExpr> {
+        let mut expander = Expander {
+            defs: self,
+            error: None,
+        };
+        let expr = expr.rewrite(&mut expander).0;
+        expander.error.map_or(Ok(expr), Err)
+    }
+
+    pub fn expand_query<'a>(&self, query: Query<'a>) -> Result<Query<'a>> {
+        let mut expander = Expander {
+            defs: self,
+            error: None,
+        };
+        let query = query.rewrite(&mut expander).0;
+        expander.error.map_or(Ok(query), Err)
+    }
+}
+
+struct Expander<'a> {
+    defs: &'a Definitions,
+    error: Option<anyhow::Error>,
+}
+
+impl<'a> Expander<'a> {
+    /// called after the arguments have been expanded
+    fn call(&mut self, expr: SimpleExpr) -> (SimpleExpr, bool) {
+        let SimpleExpr::FuncCall(FuncCall { name, args }) = &expr else {
+            return (expr, false);
+        };
+        let def = &self.defs.0[name];
+        if args.len() != def.params.len() {
+            self.error.get_or_insert_with(|| {
+                let plural = if def.params.len() == 1 { "argument" } else { "arguments" };
+                anyhow!(
+                    "wrong number of arguments: '{}' takes {} {} but {} were provided",
+                    name,
+                    def.params.len(),
+                    plural,
+                    args.len()
+                )
+            });
+            return (expr, false);
+        }
+        let mut subst = Substitution {
+            params: &def.params,
+            args,
+        };
+        let expanded = def.body.rewrite(&mut subst).0;
+        let mut size = 0;
+        expanded.traverse(&mut |_| {
+            size += 1;
+            if size > MAX_EXPANSION_SIZE {
+                Traverse::Stop
+            } else {
+                Traverse::Descend
+            }
+        });
+        if size > MAX_EXPANSION_SIZE {
+            self.error.get_or_insert_with(|| {
+                anyhow!(
+                    "expanding '{}' yields more than {} expression nodes",
+                    name,
+                    MAX_EXPANSION_SIZE
+                )
+            });
+            return (expr, false);
+        }
+        (expanded, true)
+    }
+}
+
+impl<'a> Galactus for Expander<'a> {
+    fn visit_expr(&mut self, expr: &SimpleExpr) -> Tactic<SimpleExpr, Self> {
+        match expr {
+            SimpleExpr::FuncCall(f) if self.defs.0.contains_key(&f.name) => Tactic::DevourLater(Self::call),
+            _ => Tactic::Scrutinise,
+        }
+    }
+}
+
+struct Substitution<'a> {
+    params: &'a [String],
+    args: &'a [SimpleExpr],
+}
+
+impl<'a> Galactus for Substitution<'a> {
+    fn visit_expr(&mut self, expr: &SimpleExpr) -> Tactic<SimpleExpr, Self> {
+        match expr {
+            SimpleExpr::Variable(v) => match self.params.iter().position(|p| p == &**v) {
+                Some(idx) => Tactic::Devour(self.args[idx].clone()),
+                None => Tactic::KeepAsIs,
+            },
+            SimpleExpr::Indexing(_) => Tactic::DevourLater(Self::flatten),
+            _ => Tactic::Scrutinise,
+        }
+    }
+}
+
+impl<'a> Substitution<'a> {
+    /// merge indexing into an argument that is itself an indexing expression, i.e. `_[0].x` instead of `(_[0]).x`
+    fn flatten(&mut self, expr: SimpleExpr) -> (SimpleExpr, bool) {
+        let SimpleExpr::Indexing(Ind { head, tail }) = &expr else {
+            return (expr, false);
+        };
+        let SimpleExpr::Indexing(inner) = &**head else {
+            return (expr, false);
+        };
+        let merged = inner.tail.iter().chain(tail.iter()).cloned().collect::<Vec<_>>();
+        let ind = Ind {
+            head: Arc::clone(&inner.head),
+            tail: merged.try_into().expect("tail of an indexing expression is not empty"),
+        };
+        (SimpleExpr::Indexing(ind), true)
+    }
+}
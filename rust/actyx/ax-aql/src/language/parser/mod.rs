@@ -3,18 +3,20 @@
 
 use std::{
     convert::{TryFrom, TryInto},
+    num::NonZeroU64,
     str::FromStr,
     sync::Arc,
 };
 
 use super::{
-    non_empty::NonEmptyVec, AggrOp, Arr, FuncCall, Ind, Index, Num, Obj, Operation, Query, SimpleExpr, Source,
-    SpreadExpr, TagAtom, TagExpr,
+    non_empty::NonEmptyVec, AggrOp, Arr, BinOp, FuncCall, Ind, Index, Join, Num, Obj, Operation, Query, SimpleExpr,
+    SortOrder, Source, SpreadExpr, TagAtom, TagExpr, Traverse, Window,
 };
 use crate::SortKey;
-use anyhow::{bail, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use ax_types::{service::Order, Tag, Timestamp};
 use chrono::{FixedOffset, TimeZone, Timelike, Utc};
+use functions::Definitions;
 use once_cell::sync::Lazy;
 use pest::{
     pratt_parser::{Assoc, Op, PrattParser},
@@ -36,6 +38,7 @@ impl std::error::Error for NoVal {}
 #[grammar = "language/aql.pest"]
 struct Aql;
 
+mod functions;
 mod utils;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -189,6 +192,43 @@ fn r_tag_from_to(p: P, f: FromTo, ctx: Context) -> Result<TagExpr> {
     })
 }
 
+fn r_window_duration(p: P) -> Result<NonZeroU64> {
+    let mut p = p.inner()?;
+    let count = p.natural()?;
+    let unit = match p.next().ok_or(NoVal("window_unit"))?.as_str() {
+        "ms" => 1_000,
+        "s" => 1_000_000,
+        "m" => 60_000_000,
+        "h" => 3_600_000_000,
+        "D" => 86_400_000_000,
+        "W" => 604_800_000_000,
+        x => bail!("unknown window_unit {}", x),
+    };
+    let micros = count
+        .checked_mul(unit)
+        .ok_or_else(|| anyhow!("window duration too large"))?;
+    Ok(NonZeroU64::new(micros).ok_or(NoVal("positive window duration"))?)
+}
+
+/// maximum number of windows a single event may fall into, i.e. the ratio of size to slide
+pub const MAX_WINDOW_OVERLAP: u64 = 10_000;
+
+fn r_window(p: P) -> Result<Window> {
+    let mut p = p.inner()?;
+    let size = r_window_duration(p.next().ok_or(NoVal("window size"))?)?;
+    let slide = match p.next() {
+        Some(slide) => r_window_duration(slide)?,
+        None => size,
+    };
+    ensure!(slide <= size, "WINDOW slide must not be larger than its size");
+    ensure!(
+        size.get() / slide.get() <= MAX_WINDOW_OVERLAP,
+        "WINDOW size must not be more than {} times its slide",
+        MAX_WINDOW_OVERLAP
+    );
+    Ok(Window { size, slide })
+}
+
 fn r_tag_comp(p: P) -> Result<FromTo> {
     Ok(match p.as_rule() {
         Rule::lt => FromTo::To(false),
@@ -447,6 +487,13 @@ fn r_aggr(p: P, ctx: Context) -> Result<SimpleExpr> {
         Rule::aggr_max => SimpleExpr::AggrOp(Arc::new((AggrOp::Max, r_simple_expr(p.single()?, ctx.simple())?))),
         Rule::aggr_first => SimpleExpr::AggrOp(Arc::new((AggrOp::First, r_simple_expr(p.single()?, ctx.simple())?))),
         Rule::aggr_last => SimpleExpr::AggrOp(Arc::new((AggrOp::Last, r_simple_expr(p.single()?, ctx.simple())?))),
+        Rule::aggr_count => SimpleExpr::AggrOp(Arc::new((AggrOp::Count, r_simple_expr(p.single()?, ctx.simple())?))),
+        Rule::aggr_avg => SimpleExpr::AggrOp(Arc::new((AggrOp::Avg, r_simple_expr(p.single()?, ctx.simple())?))),
+        Rule::aggr_stddev => SimpleExpr::AggrOp(Arc::new((AggrOp::StdDev, r_simple_expr(p.single()?, ctx.simple())?))),
+        Rule::aggr_p50 => SimpleExpr::AggrOp(Arc::new((AggrOp::P50, r_simple_expr(p.single()?, ctx.simple())?))),
+        Rule::aggr_p90 => SimpleExpr::AggrOp(Arc::new((AggrOp::P90, r_simple_expr(p.single()?, ctx.simple())?))),
+        Rule::aggr_p95 => SimpleExpr::AggrOp(Arc::new((AggrOp::P95, r_simple_expr(p.single()?, ctx.simple())?))),
+        Rule::aggr_p99 => SimpleExpr::AggrOp(Arc::new((AggrOp::P99, r_simple_expr(p.single()?, ctx.simple())?))),
         x => bail!("unexpected token: {:?}", x),
     })
 }
@@ -571,6 +618,65 @@ fn r_simple_expr(p: P, ctx: Context) -> Result<SimpleExpr> {
         .parse(p.inner()?)
 }
 
+/// whether the expression refers to the variable `name`
+fn mentions(e: &SimpleExpr, name: &str) -> bool {
+    let mut found = false;
+    e.traverse(&mut |e| match e {
+        SimpleExpr::Variable(v)
+        | SimpleExpr::KeyVar(v)
+        | SimpleExpr::TimeVar(v)
+        | SimpleExpr::Tags(v)
+        | SimpleExpr::App(v) => {
+            found |= &**v == name;
+            Traverse::Stop
+        }
+        _ => Traverse::Descend,
+    });
+    found
+}
+
+fn tag_mentions(t: &TagExpr, name: &str) -> bool {
+    match t {
+        TagExpr::Or(x) | TagExpr::And(x) => tag_mentions(&x.0, name) || tag_mentions(&x.1, name),
+        TagExpr::Atom(TagAtom::Interpolation(s)) => s.items.iter().any(|e| mentions(e, name)),
+        TagExpr::Atom(_) => false,
+    }
+}
+
+fn r_join(p: P, ctx: Context) -> Result<Join> {
+    // not a separate rule, so that parse errors before a query operation still mention `join`
+    let outer = p.as_str().starts_with("LEFT");
+    let mut p = p.inner()?;
+    let from = r_tag_expr(p.next().ok_or(NoVal("tag expression"))?, ctx.simple())?;
+    ensure!(
+        !tag_mentions(&from, "_"),
+        "the tag expression to JOIN cannot refer to the current value `_`"
+    );
+    let name = p.string()?;
+    ensure!(name != "_", "the events to JOIN cannot be named `_`");
+    let cond = r_simple_expr(p.single()?, ctx.simple())?;
+    let (left, right) = match &cond {
+        SimpleExpr::BinOp(op) if op.0 == BinOp::Eq => (op.1.clone(), op.2.clone()),
+        _ => bail!("JOIN condition must be of the form `<key> = <key>`"),
+    };
+    let (key, other_key) = match (mentions(&left, &name), mentions(&right, &name)) {
+        (false, true) => (left, right),
+        (true, false) => (right, left),
+        _ => bail!("exactly one side of the JOIN condition must refer to `{}`", name),
+    };
+    ensure!(
+        !mentions(&other_key, "_"),
+        "the key of the joined events cannot refer to the current value `_`"
+    );
+    Ok(Join {
+        outer,
+        from,
+        name,
+        key,
+        other_key,
+    })
+}
+
 fn r_query<'a>(pragmas: Vec<(&'a str, &'a str)>, features: Vec<String>, p: P, ctx: Context) -> Result<Query<'a>> {
     let mut p = p.inner()?;
     let source = match p.peek().unwrap().as_rule() {
@@ -601,29 +707,88 @@ fn r_query<'a>(pragmas: Vec<(&'a str, &'a str)>, features: Vec<String>, p: P, ct
                 let v = r_array(o, ctx.simple())?.items.to_vec();
                 q.ops.push(Operation::Select(v.try_into()?))
             }
-            Rule::aggregate => q
-                .ops
-                .push(Operation::Aggregate(r_simple_expr(o.single()?, ctx.aggregate())?)),
+            Rule::aggregate => {
+                let mut p = o.inner()?;
+                let expr = r_simple_expr(p.next().ok_or(NoVal("aggregate expression"))?, ctx.aggregate())?;
+                match p.next() {
+                    Some(clause) => match clause.as_rule() {
+                        Rule::group_by => {
+                            let key = r_simple_expr(clause.single()?, ctx.simple())?;
+                            q.ops.push(Operation::AggregateBy(expr, key));
+                        }
+                        Rule::window => q.ops.push(Operation::AggregateWindow(expr, r_window(clause)?)),
+                        x => bail!("unexpected token: {:?}", x),
+                    },
+                    None => q.ops.push(Operation::Aggregate(expr)),
+                }
+            }
+            Rule::order_by => {
+                let mut p = o.inner()?;
+                let expr = r_simple_expr(p.next().ok_or(NoVal("sort expression"))?, ctx.simple())?;
+                let order = match p.next().map(|o| o.as_str()) {
+                    None | Some("ASC") => SortOrder::Asc,
+                    Some("DESC") => SortOrder::Desc,
+                    Some(x) => bail!("unexpected sort order: {:?}", x),
+                };
+                q.ops.push(Operation::OrderBy(expr, order));
+            }
             Rule::limit => q.ops.push(Operation::Limit(o.single()?.natural()?.try_into()?)),
+            Rule::distinct => {
+                let mut key = None;
+                let mut keep = None;
+                for p in o.into_inner() {
+                    match p.as_rule() {
+                        Rule::simple_expr => key = Some(r_simple_expr(p, ctx.simple())?),
+                        Rule::distinct_keep => keep = Some(p.single()?.natural()?.try_into()?),
+                        x => bail!("unexpected token: {:?}", x),
+                    }
+                }
+                q.ops.push(Operation::Distinct(key, keep));
+            }
             Rule::binding => {
                 let mut p = o.inner()?;
                 let ident = p.string()?;
                 let expr = r_simple_expr(p.single()?, ctx.simple())?;
                 q.ops.push(Operation::Binding(ident, expr));
             }
+            Rule::join => q.ops.push(Operation::Join(r_join(o, ctx)?)),
             x => bail!("unexpected token: {:?}", x),
         }
     }
     Ok(q)
 }
 
+fn r_define(p: P, ctx: Context, defs: &mut Definitions) -> Result<()> {
+    let mut p = p.inner()?;
+    let name = p.string()?;
+    let mut params = vec![];
+    let mut body = None;
+    for p in p {
+        match p.as_rule() {
+            Rule::ident => params.push(p.as_str().nfc().collect()),
+            Rule::simple_expr => body = Some(r_simple_expr(p, ctx)?),
+            x => bail!("unexpected token: {:?}", x),
+        }
+    }
+    defs.define(name, params, body.ok_or(NoVal("function body"))?)
+}
+
 pub(crate) fn query_from_str(s: &str) -> Result<Query<'_>> {
     let p = Aql::parse(Rule::main_query, s)?.single()?;
     let mut p = p.inner()?;
+    let now = Timestamp::now();
     let mut pragmas = Vec::new();
+    let mut defs = Definitions::default();
     while p.peek().map(|p| p.as_rule()) == Some(Rule::pragma) {
-        pragmas.push(r_pragma(p.next().unwrap())?);
+        let pragma = p.next().unwrap();
+        match pragma.clone().single() {
+            Ok(define) if define.as_rule() == Rule::pragma_define => {
+                r_define(define, Context::Simple { now }, &mut defs)?
+            }
+            _ => pragmas.push(r_pragma(pragma)?),
+        }
     }
+    defs.check_order()?;
     let mut f = p.next().ok_or(NoVal("main query"))?;
     let features = if f.as_rule() == Rule::features {
         let features = f.inner()?.map(|mut ff| ff.string()).collect::<Result<_>>()?;
@@ -632,8 +797,12 @@ pub(crate) fn query_from_str(s: &str) -> Result<Query<'_>> {
     } else {
         vec![]
     };
-    let now = Timestamp::now();
-    r_query(pragmas, features, f, Context::Simple { now })
+    let query = r_query(pragmas, features, f, Context::Simple { now })?;
+    if defs.is_empty() {
+        Ok(query)
+    } else {
+        defs.expand_query(query)
+    }
 }
 
 impl TryFrom<(Timestamp, &str)> for TagExpr {
@@ -933,7 +1102,7 @@ mod tests {
             parser: Aql,
             input: "FROM 'x' ELECT 'x'",
             rule: Rule::main_query,
-            positives: vec![Rule::EOI, Rule::query_order, Rule::filter, Rule::select, Rule::aggregate, Rule::limit, Rule::binding, Rule::and, Rule::or],
+            positives: vec![Rule::EOI, Rule::query_order, Rule::filter, Rule::select, Rule::aggregate, Rule::order_by, Rule::limit, Rule::distinct, Rule::binding, Rule::join, Rule::and, Rule::or],
             negatives: vec![],
             pos: 9
         };
@@ -941,7 +1110,7 @@ mod tests {
             parser: Aql,
             input: "FROM 'x' FITTER 'x'",
             rule: Rule::main_query,
-            positives: vec![Rule::EOI, Rule::query_order, Rule::filter, Rule::select, Rule::aggregate, Rule::limit, Rule::binding, Rule::and, Rule::or],
+            positives: vec![Rule::EOI, Rule::query_order, Rule::filter, Rule::select, Rule::aggregate, Rule::order_by, Rule::limit, Rule::distinct, Rule::binding, Rule::join, Rule::and, Rule::or],
             negatives: vec![],
             pos: 9
         };
@@ -1082,6 +1251,193 @@ mod tests {
         );
     }
 
+    #[test]
+    fn group_by() {
+        let q = Query::parse("FROM 'x' AGGREGATE SUM(_.n) GROUP BY _.machine").unwrap();
+        assert_eq!(
+            &q.ops[0],
+            &Operation::AggregateBy(
+                SimpleExpr::AggrOp(Arc::new((AggrOp::Sum, Ind::with("_", &[&"n"])))),
+                Ind::with("_", &[&"machine"])
+            )
+        );
+        assert_eq!(q.to_string(), "FROM 'x' AGGREGATE SUM(_.n) GROUP BY _.machine END");
+
+        let e = Query::parse("FROM 'x' AGGREGATE SUM(1) GROUP BY SUM(_)")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            e.contains("aggregators are only valid in AGGREGATE clauses"),
+            "received: {}",
+            e
+        );
+        let e = Query::parse("FROM 'x' AGGREGATE _ GROUP BY _.machine")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            e.contains("current value _ not available in AGGREGATE clauses"),
+            "received: {}",
+            e
+        );
+    }
+
+    #[test]
+    fn window() {
+        let q = Query::parse("FROM 'x' AGGREGATE COUNT(_) WINDOW 5m").unwrap();
+        assert_eq!(
+            &q.ops[0],
+            &Operation::AggregateWindow(
+                SimpleExpr::AggrOp(Arc::new((
+                    AggrOp::Count,
+                    SimpleExpr::Variable(Var::try_from("_").unwrap())
+                ))),
+                Window {
+                    size: NonZeroU64::new(300_000_000).unwrap(),
+                    slide: NonZeroU64::new(300_000_000).unwrap(),
+                }
+            )
+        );
+        assert_eq!(q.to_string(), "FROM 'x' AGGREGATE COUNT(_) WINDOW 5m END");
+
+        let q = Query::parse("FROM 'x' AGGREGATE MAX(_.t) WINDOW 60m EVERY 1500ms").unwrap();
+        assert_eq!(
+            &q.ops[0],
+            &Operation::AggregateWindow(
+                SimpleExpr::AggrOp(Arc::new((AggrOp::Max, Ind::with("_", &[&"t"])))),
+                Window {
+                    size: NonZeroU64::new(3_600_000_000).unwrap(),
+                    slide: NonZeroU64::new(1_500_000).unwrap(),
+                }
+            )
+        );
+        assert_eq!(q.to_string(), "FROM 'x' AGGREGATE MAX(_.t) WINDOW 1h EVERY 1500ms END");
+
+        let e = Query::parse("FROM 'x' AGGREGATE COUNT(_) WINDOW 1m EVERY 2m")
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("must not be larger than its size"), "received: {}", e);
+        let e = Query::parse("FROM 'x' AGGREGATE COUNT(_) WINDOW 1W EVERY 1ms")
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("not be more than 10000 times its slide"), "received: {}", e);
+        Query::parse("FROM 'x' AGGREGATE COUNT(_) WINDOW 10s EVERY 1ms").unwrap();
+        Query::parse("FROM 'x' AGGREGATE COUNT(_) WINDOW 0s").unwrap_err();
+        Query::parse("FROM 'x' AGGREGATE COUNT(_) WINDOW 1Y").unwrap_err();
+        Query::parse("FROM 'x' AGGREGATE COUNT(_) GROUP BY _.a WINDOW 1h").unwrap_err();
+    }
+
+    #[test]
+    fn statistics() {
+        let q = Query::parse(
+            "FROM 'x' AGGREGATE [COUNT(_), AVG(_.n), STDDEV(_.n), P50(_.n), P90(_.n), P95(_.n), P99(_.n)]",
+        )
+        .unwrap();
+        assert_eq!(
+            q.to_string(),
+            "FROM 'x' AGGREGATE [COUNT(_), AVG(_.n), STDDEV(_.n), P50(_.n), P90(_.n), P95(_.n), P99(_.n)] END"
+        );
+        let e = Query::parse("FROM 'x' SELECT P95(_)").unwrap_err().to_string();
+        assert!(
+            e.contains("aggregators are only valid in AGGREGATE clauses"),
+            "received: {}",
+            e
+        );
+    }
+
+    #[test]
+    fn order_by() {
+        let q = Query::parse("FROM 'x' ORDER BY _.priority DESC LIMIT 3").unwrap();
+        assert_eq!(
+            &q.ops[0],
+            &Operation::OrderBy(Ind::with("_", &[&"priority"]), SortOrder::Desc)
+        );
+        assert_eq!(q.to_string(), "FROM 'x' ORDER BY _.priority DESC LIMIT 3 END");
+
+        let q = Query::parse("FROM 'x' ORDER DESC ORDER BY _.a + 1").unwrap();
+        assert!(matches!(
+            q.source,
+            Source::Events {
+                order: Some(Order::Desc),
+                ..
+            }
+        ));
+        assert_eq!(q.to_string(), "FROM 'x' ORDER DESC ORDER BY (_.a + 1) ASC END");
+
+        let e = Query::parse("FROM 'x' ORDER BY SUM(_)").unwrap_err().to_string();
+        assert!(
+            e.contains("aggregators are only valid in AGGREGATE clauses"),
+            "received: {}",
+            e
+        );
+    }
+
+    #[test]
+    fn join() {
+        let q = Query::parse("FROM 'started' JOIN 'finished' & 'ok' AS f ON _.id = f.workpiece.id").unwrap();
+        assert_eq!(
+            &q.ops[0],
+            &Operation::Join(Join {
+                outer: false,
+                from: TagAtom::Tag(tag!("finished")).and(TagAtom::Tag(tag!("ok"))),
+                name: "f".to_owned(),
+                key: Ind::with("_", &[&"id"]),
+                other_key: Ind::with("f", &[&"workpiece", &"id"]),
+            })
+        );
+        assert_eq!(
+            q.to_string(),
+            "FROM 'started' JOIN ('finished' & 'ok') AS f ON _.id = f.workpiece.id END"
+        );
+
+        // the sides of the condition may be swapped
+        let q = Query::parse("FROM 'a' LEFT JOIN 'b' AS other ON KEY(other) = _[0]").unwrap();
+        match &q.ops[0] {
+            Operation::Join(j) => {
+                assert!(j.outer);
+                assert_eq!(j.key, Ind::with("_", &[&0u64]));
+            }
+            op => panic!("unexpected {:?}", op),
+        }
+        assert_eq!(
+            q.to_string(),
+            "FROM 'a' LEFT JOIN 'b' AS other ON _[0] = KEY(other) END"
+        );
+
+        let e = |s: &str| Query::parse(s).unwrap_err().to_string();
+        let err = e("FROM 'a' JOIN 'b' AS b ON _.x < b.x");
+        assert!(err.contains("must be of the form `<key> = <key>`"), "received: {}", err);
+        let err = e("FROM 'a' JOIN 'b' AS b ON _.x = _.y");
+        assert!(err.contains("must refer to `b`"), "received: {}", err);
+        let err = e("FROM 'a' JOIN 'b' AS b ON b.x = b.y");
+        assert!(err.contains("must refer to `b`"), "received: {}", err);
+        let err = e("FROM 'a' JOIN 'b' AS b ON 1 = b.x + _.x");
+        assert!(err.contains("cannot refer to the current value"), "received: {}", err);
+        let err = e("FROM 'a' JOIN `b{_.x}` AS b ON _.x = b.x");
+        assert!(err.contains("cannot refer to the current value"), "received: {}", err);
+        let err = e("FROM 'a' JOIN 'b' AS _ ON _.x = _.y");
+        assert!(err.contains("cannot be named `_`"), "received: {}", err);
+    }
+
+    #[test]
+    fn distinct() {
+        let q = Query::parse("FROM 'x' DISTINCT").unwrap();
+        assert_eq!(&q.ops[0], &Operation::Distinct(None, None));
+        assert_eq!(q.to_string(), "FROM 'x' DISTINCT END");
+
+        let q = Query::parse("FROM 'x' DISTINCT ON _.id KEEP 1000 SELECT _.id").unwrap();
+        assert_eq!(
+            &q.ops[0],
+            &Operation::Distinct(Some(Ind::with("_", &[&"id"])), NonZeroU64::new(1000))
+        );
+        assert_eq!(q.to_string(), "FROM 'x' DISTINCT ON _.id KEEP 1000 SELECT _.id END");
+
+        let q = Query::parse("FROM 'x' DISTINCT KEEP 5").unwrap();
+        assert_eq!(&q.ops[0], &Operation::Distinct(None, NonZeroU64::new(5)));
+
+        Query::parse("FROM 'x' DISTINCT KEEP 0").unwrap_err();
+        Query::parse("FROM 'x' DISTINCT ON").unwrap_err();
+    }
+
     #[test]
     fn limit() {
         let q = Query::parse("FROM 'x' LIMIT 10").unwrap();
@@ -1231,4 +1587,75 @@ mod tests {
         let q = Query::parse(&s).unwrap();
         assert_eq!(q.pragmas, vec![("x", "y "), ("a", "hello")]);
     }
+
+    #[test]
+    fn define() {
+        let s = "PRAGMA define Net(x) := x.gross - x.tax
+            PRAGMA define Total(a, b) := Net(a) + Net(b)
+            PRAGMA define := opaque text
+            FROM 'x' SELECT Total(_[0], _[1]) FILTER Net(_) > 0";
+        let q = Query::parse(s).unwrap();
+        assert_eq!(q.pragmas, vec![("define", "opaque text")]);
+        assert_eq!(
+            q.to_string(),
+            "FROM 'x' SELECT ((_[0].gross - _[0].tax) + (_[1].gross - _[1].tax)) FILTER ((_.gross - _.tax) > 0) END"
+        );
+
+        // definitions may span lines and be called from sub-queries
+        let q = Query::parse(
+            "PRAGMA define Clamp(x, lo, hi) := CASE x < lo => lo CASE x > hi => hi CASE TRUE => x ENDCASE
+            FROM 'x' SELECT FROM [1, 2, 3] SELECT Clamp(_, 2, Lower(_))",
+        )
+        .unwrap();
+        assert_eq!(
+            q.to_string(),
+            "FROM 'x' SELECT FROM [1, 2, 3] SELECT CASE (_ < 2) => 2 CASE (_ > Lower(_)) => Lower(_) CASE TRUE => _ ENDCASE END END"
+        );
+
+        let e = |s: &str| Query::parse(s).unwrap_err().to_string();
+        let err = e("PRAGMA define Fn(x) := Fn(x)\nFROM 'x'");
+        assert!(err.contains("recursion is not allowed"), "received: {}", err);
+        let err = e("PRAGMA define Fn(x) := Gn(x)\nPRAGMA define Gn(x) := Fn(x)\nFROM 'x'");
+        assert!(
+            err.contains("`Fn` calls `Gn`, which is defined after it"),
+            "received: {}",
+            err
+        );
+        let err = e("PRAGMA define Fn(x) := x + y\nFROM 'x'");
+        assert!(
+            err.contains("`y` is not a parameter of function `Fn`"),
+            "received: {}",
+            err
+        );
+        let err = e("PRAGMA define Fn(x) := _\nFROM 'x'");
+        assert!(
+            err.contains("`_` is not a parameter of function `Fn`"),
+            "received: {}",
+            err
+        );
+        let err = e("PRAGMA define Fn(x, x) := x\nFROM 'x'");
+        assert!(err.contains("declared more than once"), "received: {}", err);
+        let err = e("PRAGMA define Fn(x) := x\nPRAGMA define Fn(y) := y\nFROM 'x'");
+        assert!(err.contains("defined more than once"), "received: {}", err);
+        let err = e("PRAGMA define Fn(x) := KEY(x)\nFROM 'x'");
+        assert!(err.contains("metadata of `x` cannot be accessed"), "received: {}", err);
+        let err = e("PRAGMA define Fn(x) := FROM 'y'\nFROM 'x'");
+        assert!(err.contains("cannot contain sub-queries"), "received: {}", err);
+        let err = e("PRAGMA define Fn(x) := SUM(x)\nFROM 'x'");
+        assert!(err.contains("aggregators are only valid"), "received: {}", err);
+        let err = e("PRAGMA define Fa(x) := [x, x]
+            PRAGMA define Fb(x) := Fa(Fa(x))
+            PRAGMA define Fc(x) := Fb(Fb(x))
+            PRAGMA define Fd(x) := Fc(Fc(x))
+            PRAGMA define Fe(x) := Fd(Fd(x))
+            PRAGMA define Ff(x) := Fe(Fe(x))
+            FROM 'x' SELECT Ff(_)");
+        assert!(err.contains("more than 10000 expression nodes"), "received: {}", err);
+        let err = e("PRAGMA define Fn(x) := x\nFROM 'x' SELECT Fn(1, 2)");
+        assert!(
+            err.contains("'Fn' takes 1 argument but 2 were provided"),
+            "received: {}",
+            err
+        );
+    }
 }
@@ -170,7 +170,7 @@ fn render_bool(b: &bool, w: &mut impl Write) -> Result {
     }
 }
 
-fn render_operation(w: &mut impl Write, e: &Operation) -> Result {
+pub(crate) fn render_operation(w: &mut impl Write, e: &Operation) -> Result {
     match e {
         Operation::Filter(f) => {
             w.write_str("FILTER ")?;
@@ -196,13 +196,60 @@ fn render_operation(w: &mut impl Write, e: &Operation) -> Result {
             w.write_str("AGGREGATE ")?;
             render_simple_expr(w, a)
         }
+        Operation::AggregateBy(a, k) => {
+            w.write_str("AGGREGATE ")?;
+            render_simple_expr(w, a)?;
+            w.write_str(" GROUP BY ")?;
+            render_simple_expr(w, k)
+        }
+        Operation::AggregateWindow(a, win) => {
+            w.write_str("AGGREGATE ")?;
+            render_simple_expr(w, a)?;
+            w.write_str(" WINDOW ")?;
+            render_duration(w, win.size.get())?;
+            if win.slide != win.size {
+                w.write_str(" EVERY ")?;
+                render_duration(w, win.slide.get())?;
+            }
+            Ok(())
+        }
+        Operation::OrderBy(e, o) => {
+            w.write_str("ORDER BY ")?;
+            render_simple_expr(w, e)?;
+            match o {
+                SortOrder::Asc => w.write_str(" ASC"),
+                SortOrder::Desc => w.write_str(" DESC"),
+            }
+        }
         Operation::Limit(l) => {
             write!(w, "LIMIT {}", l)
         }
+        Operation::Distinct(k, n) => {
+            w.write_str("DISTINCT")?;
+            if let Some(k) = k {
+                w.write_str(" ON ")?;
+                render_simple_expr(w, k)?;
+            }
+            if let Some(n) = n {
+                write!(w, " KEEP {}", n)?;
+            }
+            Ok(())
+        }
         Operation::Binding(n, e) => {
             write!(w, "LET {} := ", n)?;
             render_simple_expr(w, e)
         }
+        Operation::Join(j) => {
+            if j.outer {
+                w.write_str("LEFT ")?;
+            }
+            w.write_str("JOIN ")?;
+            render_tag_expr(w, &j.from, None)?;
+            write!(w, " AS {} ON ", j.name)?;
+            render_simple_expr(w, &j.key)?;
+            w.write_str(" = ")?;
+            render_simple_expr(w, &j.other_key)
+        }
     }
 }
 
@@ -226,6 +273,24 @@ pub fn render_tag_expr(w: &mut impl Write, e: &TagExpr, _parent: Option<&TagExpr
     }
 }
 
+fn render_duration(w: &mut impl Write, micros: u64) -> Result {
+    const UNITS: [(u64, &str); 6] = [
+        (604_800_000_000, "W"),
+        (86_400_000_000, "D"),
+        (3_600_000_000, "h"),
+        (60_000_000, "m"),
+        (1_000_000, "s"),
+        (1_000, "ms"),
+    ];
+    for (unit, name) in UNITS {
+        if micros % unit == 0 {
+            return write!(w, "{}{}", micros / unit, name);
+        }
+    }
+    // not reachable for parsed queries, which only allow whole milliseconds
+    write!(w, "{}µs", micros)
+}
+
 fn render_timestamp(w: &mut impl Write, e: Timestamp) -> Result {
     use chrono::prelude::*;
     let dt: DateTime<Utc> = e.try_into().map_err(|e| {
@@ -84,8 +84,37 @@ impl Operation {
                 emit(|| Operation::Select(exprs), changed, self)
             }
             Operation::Aggregate(x) => map(x.rewrite(surfer), Operation::Aggregate),
+            Operation::AggregateBy(x, y) => {
+                let mut changed = false;
+                let x = shed(x.rewrite(surfer), &mut changed);
+                let y = shed(y.rewrite(surfer), &mut changed);
+                emit(|| Operation::AggregateBy(x, y), changed, self)
+            }
+            Operation::AggregateWindow(x, w) => map(x.rewrite(surfer), |x| Operation::AggregateWindow(x, *w)),
+            Operation::OrderBy(x, o) => map(x.rewrite(surfer), |x| Operation::OrderBy(x, *o)),
             Operation::Limit(x) => (Operation::Limit(*x), false),
+            Operation::Distinct(Some(k), n) => map(k.rewrite(surfer), |k| Operation::Distinct(Some(k), *n)),
+            Operation::Distinct(None, n) => (Operation::Distinct(None, *n), false),
             Operation::Binding(x, y) => map(y.rewrite(surfer), |y| Operation::Binding(x.clone(), y)),
+            Operation::Join(j) => {
+                let mut changed = false;
+                let from = shed(j.from.rewrite(surfer), &mut changed);
+                let key = shed(j.key.rewrite(surfer), &mut changed);
+                let other_key = shed(j.other_key.rewrite(surfer), &mut changed);
+                emit(
+                    || {
+                        Operation::Join(Join {
+                            outer: j.outer,
+                            from,
+                            name: j.name.clone(),
+                            key,
+                            other_key,
+                        })
+                    },
+                    changed,
+                    self,
+                )
+            }
         }
     }
 }
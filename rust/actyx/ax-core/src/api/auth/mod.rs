@@ -1,18 +1,26 @@
+mod revocations;
 mod validate_signed_manifest;
 
-use ax_types::{types::Binary, AppId, AppManifest, Timestamp};
+use ax_aql::TagExpr;
+use ax_types::{types::Binary, AppId, AppManifest, AppScopes, Timestamp};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use warp::{body, post, reply, Filter, Rejection, Reply};
+use std::str::FromStr;
+use warp::{body, path, post, reply, Filter, Rejection, Reply};
 
 use crate::{
     api::{
-        bearer_token::BearerToken, filters::accept_json, licensing::Licensing, reject, rejections::ApiError, AppMode,
-        NodeInfo, Token,
+        bearer_token::{BearerToken, MAX_TOKEN_LIFETIME},
+        filters::{accept_json, authenticate_token, header_token},
+        licensing::Licensing,
+        reject,
+        rejections::ApiError,
+        AppMode, NodeInfo, Token,
     },
     crypto::{PublicKey, SignedMessage},
 };
 
+pub use revocations::Revocations;
 use validate_signed_manifest::validate_signed_manifest;
 
 fn mk_success_log_msg(token: &BearerToken) -> String {
@@ -32,6 +40,7 @@ pub(crate) fn create_token(
     app_id: AppId,
     app_version: String,
     app_mode: AppMode,
+    scopes: AppScopes,
 ) -> anyhow::Result<Token> {
     let token = BearerToken {
         created: Timestamp::now(),
@@ -40,7 +49,13 @@ pub(crate) fn create_token(
         app_version,
         validity: node_info.token_validity,
         app_mode,
+        scopes,
+        origin: None,
     };
+    sign_token(node_info, token)
+}
+
+fn sign_token(node_info: NodeInfo, token: BearerToken) -> anyhow::Result<Token> {
     let bytes = serde_cbor::to_vec(&token)?;
     let signed = node_info.key_store.read().sign(bytes, vec![node_info.node_id.into()])?;
     tracing::info!(target: "AUTH", "{}", mk_success_log_msg(&token));
@@ -67,9 +82,12 @@ pub(crate) fn verify_token(node_info: NodeInfo, token: Token) -> Result<BearerTo
             token: token.clone(),
             msg: "Cannot parse CBOR.".to_owned(),
         })?;
-    match bearer_token.cycles != node_info.cycles || bearer_token.is_expired() {
-        true => Err(ApiError::TokenExpired),
-        false => Ok(bearer_token),
+    if bearer_token.cycles != node_info.cycles || bearer_token.is_expired() {
+        Err(ApiError::TokenExpired)
+    } else if node_info.revocations.is_revoked(&bearer_token) {
+        Err(ApiError::TokenRevoked)
+    } else {
+        Ok(bearer_token)
     }
 }
 
@@ -99,34 +117,87 @@ fn validate_manifest(
     }
 }
 
+fn validate_scopes(scopes: &AppScopes) -> Result<(), ApiError> {
+    if let Some(read) = &scopes.read {
+        TagExpr::from_str(read).map_err(|e| ApiError::InvalidManifest {
+            msg: format!("invalid read scope: {:#}", e),
+        })?;
+    }
+    Ok(())
+}
+
 async fn handle_auth(node_info: NodeInfo, manifest: AppManifest) -> Result<impl Reply, Rejection> {
-    match validate_manifest(&manifest, &node_info.ax_public_key, &node_info.licensing) {
-        Ok((is_trial, app_id, version)) => create_token(node_info, app_id, version, is_trial)
+    let scopes = manifest.scopes();
+    match validate_manifest(&manifest, &node_info.ax_public_key, &node_info.licensing)
+        .and_then(|res| validate_scopes(&scopes).map(|_| res))
+    {
+        Ok((is_trial, app_id, version)) => create_token(node_info, app_id, version, is_trial, scopes)
             .map(|token| reply::json(&TokenResponse::new(token)))
             .map_err(reject),
         Err(x) => Err(warp::reject::custom(x)),
     }
 }
 
+/// Hand out a fresh token for the app of a still valid token, without sending the manifest again.
+///
+/// The new token remembers the token the app originally received for its manifest, so that
+/// revoking any of them revokes all, and it does not outlive that token by more than
+/// [`MAX_TOKEN_LIFETIME`].
+async fn handle_refresh(node_info: NodeInfo, token: BearerToken) -> Result<impl Reply, Rejection> {
+    let origin = token.origin();
+    let created = Timestamp::now();
+    // in seconds
+    let remaining = ((origin.created + MAX_TOKEN_LIFETIME) - created) / 1_000_000;
+    let validity = node_info
+        .token_validity
+        .min(u32::try_from(remaining).unwrap_or(u32::MAX));
+    if validity == 0 {
+        return Err(warp::reject::custom(ApiError::TokenExpired));
+    }
+    let refreshed = BearerToken {
+        created,
+        cycles: node_info.cycles,
+        validity,
+        origin: Some(origin),
+        ..token
+    };
+    sign_token(node_info, refreshed)
+        .map(|token| reply::json(&TokenResponse::new(token)))
+        .map_err(reject)
+}
+
 pub(crate) fn route(node_info: NodeInfo) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    post()
+    let refresh_info = node_info.clone();
+    let refresh = path("refresh")
+        .and(path::end())
+        .and(post())
+        .and(accept_json())
+        .and(authenticate_token(node_info.clone(), header_token()))
+        .and_then(move |token: BearerToken| handle_refresh(refresh_info.clone(), token));
+    let auth = path::end()
+        .and(post())
         .and(accept_json())
         .and(body::json())
-        .and_then(move |manifest: AppManifest| handle_auth(node_info.clone(), manifest))
+        .and_then(move |manifest: AppManifest| handle_auth(node_info.clone(), manifest));
+    refresh.or(auth)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::crypto::{KeyStore, PrivateKey, PublicKey};
-    use ax_types::{app_id, AppManifest};
+    use ax_types::{app_id, AppManifest, AppScopes, Timestamp};
     use chrono::Utc;
     use hyper::http;
     use parking_lot::lock_api::RwLock;
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Duration};
     use warp::{reject::MethodNotAllowed, test, Filter, Rejection, Reply};
 
-    use super::{route, validate_manifest, verify_token, AppMode, NodeInfo, TokenResponse};
-    use crate::api::{licensing::Licensing, rejections::ApiError};
+    use super::{create_token, route, sign_token, validate_manifest, verify_token, AppMode, NodeInfo, TokenResponse};
+    use crate::api::{
+        bearer_token::{BearerToken, TokenOrigin, MAX_TOKEN_LIFETIME},
+        licensing::Licensing,
+        rejections::ApiError,
+    };
 
     fn test_route() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
         let mut key_store = KeyStore::default();
@@ -140,6 +211,7 @@ mod tests {
             ax_public_key: PrivateKey::generate().into(),
             licensing: Licensing::default(),
             started_at: Utc::now(),
+            revocations: Default::default(),
         };
         route(auth_args)
     }
@@ -182,6 +254,7 @@ mod tests {
             ax_public_key: PrivateKey::generate().into(),
             licensing: Licensing::default(),
             started_at: Utc::now(),
+            revocations: Default::default(),
         };
 
         let resp = test::request()
@@ -197,6 +270,131 @@ mod tests {
         assert!(verify_token(auth_args, token.token.into()).is_ok())
     }
 
+    #[tokio::test]
+    async fn refresh_and_revoke() {
+        let mut key_store = KeyStore::default();
+        let node_key = key_store.generate_key_pair().unwrap();
+        let auth_args = NodeInfo {
+            cycles: 0.into(),
+            key_store: Arc::new(RwLock::new(key_store)),
+            node_id: node_key.into(),
+            token_validity: 300,
+            ax_public_key: PrivateKey::generate().into(),
+            licensing: Licensing::default(),
+            started_at: Utc::now(),
+            revocations: Default::default(),
+        };
+        let app_id = app_id!("com.example.my-app");
+        let token = create_token(
+            auth_args.clone(),
+            app_id.clone(),
+            "1.0.0".into(),
+            AppMode::Trial,
+            AppScopes::default(),
+        )
+        .unwrap()
+        .to_string();
+
+        let refresh = |token: String| {
+            let route = route(auth_args.clone());
+            async move {
+                let resp = test::request()
+                    .method("POST")
+                    .path("/refresh")
+                    .header("Authorization", format!("Bearer {}", token))
+                    .reply(&route)
+                    .await;
+                assert_eq!(resp.status(), http::StatusCode::OK);
+                serde_json::from_slice::<TokenResponse>(resp.body()).unwrap().token
+            }
+        };
+        let refreshed = refresh(token.clone()).await;
+        let bearer = verify_token(auth_args.clone(), refreshed.clone().into()).unwrap();
+        assert_eq!(bearer.app_id, app_id);
+        let refreshed2 = refresh(refreshed.clone()).await;
+        let bearer2 = verify_token(auth_args.clone(), refreshed2.clone().into()).unwrap();
+        assert_eq!(bearer2.origin, bearer.origin);
+
+        // revoking any token of the chain revokes all of them
+        auth_args.revocations.revoke_token(&refreshed).unwrap();
+        for token in [token, refreshed, refreshed2] {
+            assert!(matches!(
+                verify_token(auth_args.clone(), token.into()),
+                Err(ApiError::TokenRevoked)
+            ));
+        }
+
+        let token = create_token(
+            auth_args.clone(),
+            app_id.clone(),
+            "1.0.0".into(),
+            AppMode::Trial,
+            AppScopes::default(),
+        )
+        .unwrap()
+        .to_string();
+        let refreshed = refresh(token).await;
+        auth_args.revocations.revoke_app(app_id);
+        assert!(matches!(
+            verify_token(auth_args, refreshed.into()),
+            Err(ApiError::TokenRevoked)
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_lifetime() {
+        let mut key_store = KeyStore::default();
+        let node_key = key_store.generate_key_pair().unwrap();
+        let auth_args = NodeInfo {
+            cycles: 0.into(),
+            key_store: Arc::new(RwLock::new(key_store)),
+            node_id: node_key.into(),
+            token_validity: 300,
+            ax_public_key: PrivateKey::generate().into(),
+            licensing: Licensing::default(),
+            started_at: Utc::now(),
+            revocations: Default::default(),
+        };
+        // a token refreshed from one that was created `age` ago
+        let token = |age: Duration| {
+            let token = BearerToken {
+                created: Timestamp::now(),
+                app_id: app_id!("com.example.my-app"),
+                cycles: 0.into(),
+                app_version: "1.0.0".into(),
+                validity: 300,
+                app_mode: AppMode::Trial,
+                scopes: AppScopes::default(),
+                origin: Some(TokenOrigin {
+                    id: [1; 32],
+                    created: Timestamp::now() - age,
+                }),
+            };
+            sign_token(auth_args.clone(), token).unwrap().to_string()
+        };
+        let refresh = |token: String| {
+            let route = route(auth_args.clone());
+            async move {
+                test::request()
+                    .method("POST")
+                    .path("/refresh")
+                    .header("Authorization", format!("Bearer {}", token))
+                    .reply(&route)
+                    .await
+            }
+        };
+
+        // the refreshed token expires with the end of the maximum lifetime
+        let resp = refresh(token(MAX_TOKEN_LIFETIME - Duration::from_secs(100))).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        let refreshed: TokenResponse = serde_json::from_slice(resp.body()).unwrap();
+        let bearer = verify_token(auth_args.clone(), refreshed.token.into()).unwrap();
+        assert!(bearer.validity <= 100, "validity {}", bearer.validity);
+
+        let resp = refresh(token(MAX_TOKEN_LIFETIME)).await;
+        assert_eq!(resp.status(), http::StatusCode::UNAUTHORIZED);
+    }
+
     #[tokio::test]
     async fn method_not_allowed() {
         let rejection = test::request().filter(&test_route()).await.map(|_| ()).unwrap_err();
@@ -0,0 +1,61 @@
+use crate::{
+    api::bearer_token::{BearerToken, MAX_TOKEN_LIFETIME},
+    crypto::SignedMessage,
+};
+use ax_types::{types::Binary, AppId, Timestamp};
+use parking_lot::Mutex;
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Tokens that must no longer be accepted although they have not yet expired.
+///
+/// Restarting the node invalidates all tokens anyway, so this list is only kept in memory.
+#[derive(Debug, Clone, Default)]
+pub struct Revocations(Arc<Mutex<Revoked>>);
+
+#[derive(Debug, Default)]
+struct Revoked {
+    /// origin IDs of revoked chains of refreshed tokens, with the time after which none of their
+    /// tokens are valid anymore and they can be forgotten
+    tokens: BTreeMap<[u8; 32], Timestamp>,
+    /// all tokens of an app created up to the given time
+    apps: BTreeMap<AppId, Timestamp>,
+}
+
+impl Revocations {
+    /// Revoke a token as handed out by the auth API, together with all tokens it was refreshed
+    /// from or that were refreshed from it.
+    pub fn revoke_token(&self, token: &str) -> anyhow::Result<()> {
+        let token = parse(token)?;
+        let origin = token.origin();
+        let expiration = token.expiration().max(origin.created + MAX_TOKEN_LIFETIME);
+        let mut revoked = self.0.lock();
+        let now = Timestamp::now();
+        revoked.tokens.retain(|_, expiration| *expiration >= now);
+        revoked.tokens.insert(origin.id, expiration);
+        Ok(())
+    }
+
+    /// Revoke all tokens that have been handed out to the given app so far.
+    pub fn revoke_app(&self, app_id: AppId) {
+        self.0.lock().apps.insert(app_id, Timestamp::now());
+    }
+
+    /// Check a token whose signature has been verified.
+    pub(crate) fn is_revoked(&self, token: &BearerToken) -> bool {
+        let revoked = self.0.lock();
+        revoked
+            .apps
+            .get(&token.app_id)
+            .map_or(false, |until| token.created <= *until)
+            || revoked.tokens.contains_key(&token.origin().id)
+    }
+}
+
+fn parse(token: &str) -> anyhow::Result<BearerToken> {
+    let bin: Binary = token.parse().map_err(|_| anyhow::anyhow!("cannot parse token bytes"))?;
+    let signed: SignedMessage = bin
+        .as_ref()
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("not a signed token"))?;
+    Ok(serde_cbor::from_slice::<BearerToken>(signed.message())?)
+}
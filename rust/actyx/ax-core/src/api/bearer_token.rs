@@ -1,6 +1,7 @@
 use crate::util::formats::NodeCycleCount;
-use ax_types::{AppId, Timestamp};
+use ax_types::{AppId, AppScopes, Timestamp};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::time::Duration;
 
 use super::AppMode;
@@ -20,9 +21,40 @@ pub struct BearerToken {
     pub validity: u32,
     /// App mode,
     pub app_mode: AppMode,
+    /// what the app may access, taken from its manifest
+    #[serde(default)]
+    pub scopes: AppScopes,
+    /// the first token of the chain of refreshes that led to this one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<TokenOrigin>,
+}
+
+/// Refreshed tokens expire at the latest this long after the first token of their chain was created.
+pub const MAX_TOKEN_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Identifies a token that was handed out in exchange for an app manifest.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Ord, PartialOrd, Eq, PartialEq)]
+pub struct TokenOrigin {
+    /// hash of the token’s CBOR encoding
+    pub id: [u8; 32],
+    pub created: Timestamp,
 }
 
 impl BearerToken {
+    /// The first token of the chain of refreshes this token belongs to, which may be itself.
+    pub fn origin(&self) -> TokenOrigin {
+        self.origin.unwrap_or_else(|| {
+            let mut id = [0u8; 32];
+            id.copy_from_slice(&Sha256::digest(
+                serde_cbor::to_vec(self).expect("bearer token is serializable"),
+            ));
+            TokenOrigin {
+                id,
+                created: self.created,
+            }
+        })
+    }
+
     pub fn is_expired(&self) -> bool {
         Timestamp::now() > self.expiration()
     }
@@ -34,7 +66,7 @@ impl BearerToken {
 
 #[cfg(test)]
 mod bearer_token_tests {
-    use ax_types::{app_id, Timestamp};
+    use ax_types::{app_id, AppScopes, Timestamp};
     use std::time::Duration;
 
     use super::{AppMode, BearerToken};
@@ -48,6 +80,8 @@ mod bearer_token_tests {
             app_version: "1.0.0".into(),
             validity: 1,
             app_mode: AppMode::Signed,
+            scopes: AppScopes::default(),
+            origin: None,
         };
         assert!(token.is_expired());
 
@@ -58,6 +92,8 @@ mod bearer_token_tests {
             app_version: "1.0.0".into(),
             validity: 300,
             app_mode: AppMode::Signed,
+            scopes: AppScopes::default(),
+            origin: None,
         };
         assert!(!token.is_expired());
     }
@@ -72,6 +108,8 @@ mod bearer_token_tests {
             app_version: "1.0.0".into(),
             validity: 1,
             app_mode: AppMode::Signed,
+            scopes: AppScopes::default(),
+            origin: None,
         };
         assert_eq!(token.expiration(), now + Duration::from_secs(token.validity as u64));
     }
@@ -85,6 +123,8 @@ mod bearer_token_tests {
             app_version: "1.0.0".into(),
             validity: 1,
             app_mode: AppMode::Signed,
+            scopes: AppScopes::default(),
+            origin: None,
         };
         let json = serde_json::to_string(&token).unwrap();
         let round_tripped = serde_json::from_str(&json).unwrap();
@@ -109,6 +149,8 @@ mod bearer_token_tests {
             app_version: "1.4.2".into(),
             validity: 10,
             app_mode: AppMode::Signed,
+            scopes: AppScopes::default(),
+            origin: None,
         };
         assert_eq!(des, token);
     }
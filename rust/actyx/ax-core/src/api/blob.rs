@@ -1,6 +1,6 @@
 use crate::{
     api::{
-        filters::{authenticate, header_or_query_token},
+        filters::{authenticate_scoped, header_or_query_token},
         rejections::ApiError,
         NodeInfo,
     },
@@ -44,7 +44,7 @@ pub(crate) fn routes(
     store: BlobStore,
     node_info: NodeInfo,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
-    let f = authenticate(node_info, header_or_query_token())
+    let f = authenticate_scoped(node_info, header_or_query_token(), "blob", |scopes| scopes.blob)
         .and(path::param().and_then(|app: String| async move {
             AppId::try_from(&*app).map_err(|e| reject::custom(ApiError::BadRequest { cause: e.to_string() }))
         }))
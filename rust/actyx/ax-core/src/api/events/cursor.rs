@@ -0,0 +1,200 @@
+use crate::{api::rejections::ApiError, runtime::operation::StageState};
+use ax_types::{service::Order, EventKey, OffsetMap};
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+type CursorMac = Hmac<sha2_10::Sha256>;
+
+/// length of the authentication tag appended to an encoded cursor
+const TAG_LENGTH: usize = 32;
+
+/// The key authenticating the cursors handed out by a node, so that clients cannot forge the
+/// state a query continues from.
+#[derive(Clone)]
+pub(crate) struct CursorKey([u8; 32]);
+
+impl CursorKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    /// A key only valid for the lifetime of this process.
+    pub fn random() -> Self {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        Self(key)
+    }
+
+    fn mac(&self, bytes: &[u8]) -> CursorMac {
+        let mut mac = CursorMac::new_from_slice(&self.0).expect("HMAC accepts keys of any length");
+        mac.update(bytes);
+        mac
+    }
+}
+
+/// Everything needed to continue a query where the previous page of results ended.
+///
+/// Handed out to clients as an opaque string, see [`Cursor::encode`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Cursor {
+    /// prefix of the SHA-256 hash of the query text, to reject cursors for other queries
+    query: [u8; 8],
+    /// the bounds of the first request, which are kept for the whole query
+    pub lower: OffsetMap,
+    pub upper: OffsetMap,
+    pub order: Order,
+    /// events delivered so far: the new lower bound, or upper bound for [`Order::Desc`]
+    pub position: OffsetMap,
+    /// number of values consumed when the query reads from an array
+    pub inputs: u64,
+    /// number of outputs already delivered for the next input
+    pub skip: u64,
+    pub stages: Vec<StageState>,
+}
+
+impl Cursor {
+    pub fn new(query: &str, lower: OffsetMap, upper: OffsetMap, order: Order) -> Self {
+        let position = match order {
+            Order::Desc => upper.clone(),
+            Order::Asc | Order::StreamAsc => lower.clone(),
+        };
+        Self {
+            query: hash(query),
+            lower,
+            upper,
+            order,
+            position,
+            inputs: 0,
+            skip: 0,
+            stages: vec![],
+        }
+    }
+
+    /// Record that the next input has been consumed completely.
+    pub fn advance(&mut self, event: Option<&EventKey>) {
+        let Some(key) = event else {
+            self.inputs += 1;
+            return;
+        };
+        match self.order {
+            Order::Asc | Order::StreamAsc => {
+                self.position.update(key.stream, key.offset);
+            }
+            Order::Desc => {
+                let mut upper = std::mem::take(&mut self.position).into_inner();
+                match key.offset.pred() {
+                    Some(offset) => upper.insert(key.stream, offset),
+                    None => upper.remove(&key.stream),
+                };
+                self.position = upper.into();
+            }
+        }
+    }
+
+    /// Encode this cursor as CBOR followed by its HMAC-SHA256 tag under the given key.
+    pub fn encode(&self, key: &CursorKey) -> String {
+        let mut bytes = serde_cbor::to_vec(self).expect("cursor is serialisable");
+        let tag = key.mac(&bytes).finalize().into_bytes();
+        bytes.extend_from_slice(&tag);
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// Parse a cursor handed out for the given query, checking that it was created with `key`.
+    pub fn decode(cursor: &str, query: &str, key: &CursorKey) -> Result<Self, ApiError> {
+        let invalid = |e: &dyn std::fmt::Display| ApiError::BadRequest {
+            cause: format!("invalid cursor: {}", e),
+        };
+        let bytes = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).map_err(|e| invalid(&e))?;
+        if bytes.len() < TAG_LENGTH {
+            return Err(invalid(&"cursor is too short"));
+        }
+        let (bytes, tag) = bytes.split_at(bytes.len() - TAG_LENGTH);
+        key.mac(bytes)
+            .verify_slice(tag)
+            .map_err(|_| invalid(&"cursor was not created by this node"))?;
+        let cursor: Self = serde_cbor::from_slice(bytes).map_err(|e| invalid(&e))?;
+        if cursor.query != hash(query) {
+            return Err(invalid(&"cursor was created for a different query"));
+        }
+        Ok(cursor)
+    }
+}
+
+fn hash(query: &str) -> [u8; 8] {
+    let mut hash = [0u8; 8];
+    hash.copy_from_slice(&Sha256::digest(query.as_bytes())[..8]);
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ax_types::{NodeId, Offset, OffsetOrMin};
+
+    #[test]
+    fn roundtrip() {
+        let stream = NodeId::from_bytes(&[1; 32]).unwrap().stream(0.into());
+        let mut cursor = Cursor::new("FROM 'a'", OffsetMap::empty(), OffsetMap::empty(), Order::Asc);
+        cursor.position.update(stream, Offset::from(3));
+        cursor.skip = 1;
+        cursor.stages = vec![StageState::Stateless, StageState::Limit(5)];
+
+        let key = EventKey {
+            lamport: 7.into(),
+            stream,
+            offset: Offset::from(5),
+        };
+        cursor.advance(Some(&key));
+        assert_eq!(cursor.position.offset(stream), Offset::from(5).into());
+        cursor.advance(None);
+        assert_eq!(cursor.inputs, 1);
+
+        let key = CursorKey::random();
+        let encoded = cursor.encode(&key);
+        assert_eq!(Cursor::decode(&encoded, "FROM 'a'", &key).unwrap(), cursor);
+        assert!(Cursor::decode(&encoded, "FROM 'b'", &key).is_err());
+        assert!(Cursor::decode("not a cursor", "FROM 'a'", &key).is_err());
+    }
+
+    #[test]
+    fn authenticated() {
+        let key = CursorKey::new([1; 32]);
+        let mut cursor = Cursor::new("FROM 'a' LIMIT 3", OffsetMap::empty(), OffsetMap::empty(), Order::Asc);
+        cursor.stages = vec![StageState::Limit(1)];
+        let encoded = cursor.encode(&key);
+        let err = |cursor: &str, key: &CursorKey| Cursor::decode(cursor, "FROM 'a' LIMIT 3", key).unwrap_err();
+
+        // cursors of other nodes are rejected
+        assert!(err(&encoded, &CursorKey::new([2; 32]))
+            .to_string()
+            .contains("not created by this node"));
+
+        // as are modified cursors, even if they are valid CBOR
+        let bytes = base64::decode_config(&encoded, base64::URL_SAFE_NO_PAD).unwrap();
+        let (_, tag) = bytes.split_at(bytes.len() - TAG_LENGTH);
+        cursor.stages = vec![StageState::Limit(1000)];
+        let mut forged = serde_cbor::to_vec(&cursor).unwrap();
+        forged.extend_from_slice(tag);
+        let forged = base64::encode_config(forged, base64::URL_SAFE_NO_PAD);
+        assert!(err(&forged, &key).to_string().contains("not created by this node"));
+        assert!(err("AAAA", &key).to_string().contains("too short"));
+    }
+
+    #[test]
+    fn descending() {
+        let stream = NodeId::from_bytes(&[1; 32]).unwrap().stream(0.into());
+        let upper = [(stream, Offset::from(3))].into_iter().collect::<OffsetMap>();
+        let mut cursor = Cursor::new("FROM 'a'", OffsetMap::empty(), upper, Order::Desc);
+        let key = |offset: u32| EventKey {
+            lamport: 7.into(),
+            stream,
+            offset: Offset::from(offset),
+        };
+        cursor.advance(Some(&key(3)));
+        assert_eq!(cursor.position.offset(stream), Offset::from(2).into());
+        cursor.advance(Some(&key(0)));
+        assert_eq!(cursor.position.offset(stream), OffsetOrMin::MIN);
+    }
+}
@@ -0,0 +1,57 @@
+use futures::{stream, StreamExt};
+use std::io::{self, Write};
+use warp::{
+    http::header::{HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, VARY},
+    reply::Response,
+};
+
+/// Fast compression, the streams are mostly sent to constrained consumers in real time.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compress the response body with zstd.
+///
+/// Each chunk of the body is flushed as a separate block, so that streamed responses
+/// (including keep-alive messages) reach the client without delay.
+pub fn zstd(response: Response) -> Response {
+    let encoder = match zstd::stream::write::Encoder::new(Vec::new(), ZSTD_LEVEL) {
+        Ok(encoder) => encoder,
+        Err(error) => {
+            tracing::warn!(%error, "cannot create zstd encoder, sending uncompressed response");
+            return response;
+        }
+    };
+    let (mut parts, body) = response.into_parts();
+    let body = stream::unfold((body, Some(encoder)), |(mut body, encoder)| async move {
+        let mut encoder = encoder?;
+        let compressed = match body.next().await {
+            Some(Ok(chunk)) => encoder
+                .write_all(&chunk)
+                .and_then(|_| encoder.flush())
+                .map(|_| (std::mem::take(encoder.get_mut()), Some(encoder))),
+            Some(Err(error)) => Err(io::Error::new(io::ErrorKind::Other, error)),
+            None => encoder.finish().map(|compressed| (compressed, None)),
+        };
+        Some(match compressed {
+            Ok((bytes, encoder)) => (Ok(bytes), (body, encoder)),
+            Err(error) => (Err(error), (body, None)),
+        })
+    });
+    parts.headers.insert(CONTENT_ENCODING, HeaderValue::from_static("zstd"));
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+    Response::from_parts(parts, hyper::Body::wrap_stream(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn roundtrip() {
+        let chunks: Vec<Result<_, io::Error>> = vec![Ok("hello\n"), Ok("\n"), Ok("world\n")];
+        let response = zstd(Response::new(hyper::Body::wrap_stream(stream::iter(chunks))));
+        assert_eq!(response.headers()[CONTENT_ENCODING], "zstd");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(zstd::decode_all(&*body).unwrap(), b"hello\n\nworld\n");
+    }
+}
@@ -1,19 +1,29 @@
 use crate::api::{
+    bearer_token::BearerToken,
     events::{http::handlers, service::EventService},
-    filters::{accept_json, accept_ndjson, authenticate, header_or_query_token},
+    filters::{
+        accept_event_stream, accept_json, accept_ndjson_or_cbor, accept_zstd, authenticate_token, header_or_query_token,
+    },
     NodeInfo,
 };
 use ax_types::AppId;
-use warp::{any, body, get, path, post, Filter, Rejection, Reply};
+use warp::{body, get, header, path, post, Filter, Rejection, Reply};
 
-pub fn with_service(
+/// Authenticate the app and restrict the event service to the scopes of its token.
+fn authorize(
+    node_info: NodeInfo,
     event_service: EventService,
-) -> impl Filter<Extract = (EventService,), Error = std::convert::Infallible> + Clone {
-    any().map(move || event_service.clone())
-}
-
-fn authorize(node_info: NodeInfo) -> impl Filter<Extract = (AppId,), Error = Rejection> + Clone {
-    authenticate(node_info, header_or_query_token())
+) -> impl Filter<Extract = (AppId, EventService), Error = Rejection> + Clone {
+    authenticate_token(node_info, header_or_query_token())
+        .and_then(move |token: BearerToken| {
+            let scoped = event_service.with_scopes(&token.scopes);
+            async move {
+                scoped
+                    .map(|service| (token.app_id, service))
+                    .map_err(warp::reject::custom)
+            }
+        })
+        .untuple_one()
 }
 
 pub fn offsets(
@@ -23,9 +33,8 @@ pub fn offsets(
     path("offsets")
         .and(path::end())
         .and(get())
-        .and(authorize(node_info))
+        .and(authorize(node_info, event_service))
         .and(accept_json())
-        .and(with_service(event_service))
         .and_then(handlers::offsets)
 }
 
@@ -36,10 +45,9 @@ pub fn publish(
     path("publish")
         .and(path::end())
         .and(post())
-        .and(authorize(node_info))
+        .and(authorize(node_info, event_service))
         .and(accept_json())
         .and(body::json())
-        .and(with_service(event_service))
         .and_then(handlers::publish)
 }
 
@@ -50,10 +58,10 @@ pub fn query(
     path("query")
         .and(path::end())
         .and(post())
-        .and(authorize(node_info))
-        .and(accept_ndjson())
+        .and(authorize(node_info, event_service))
+        .and(accept_ndjson_or_cbor())
+        .and(accept_zstd())
         .and(body::json())
-        .and(with_service(event_service))
         .and_then(handlers::query)
 }
 
@@ -64,10 +72,11 @@ pub fn subscribe(
     path("subscribe")
         .and(path::end())
         .and(post())
-        .and(authorize(node_info))
-        .and(accept_ndjson())
+        .and(authorize(node_info, event_service))
+        .and(accept_event_stream())
+        .and(accept_zstd())
+        .and(header::optional("last-event-id"))
         .and(body::json())
-        .and(with_service(event_service))
         .and_then(handlers::subscribe)
 }
 
@@ -78,9 +87,10 @@ pub fn subscribe_monotonic(
     path("subscribe_monotonic")
         .and(path::end())
         .and(post())
-        .and(authorize(node_info))
-        .and(accept_ndjson())
+        .and(authorize(node_info, event_service))
+        .and(accept_event_stream())
+        .and(accept_zstd())
+        .and(header::optional("last-event-id"))
         .and(body::json())
-        .and(with_service(event_service))
         .and_then(handlers::subscribe_monotonic)
 }
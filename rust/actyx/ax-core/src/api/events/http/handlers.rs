@@ -1,7 +1,7 @@
-use super::ndjson;
+use super::{compression, ndjson, sse};
 
 use crate::{
-    api::{events::service::EventService, rejections::ApiError, Result},
+    api::{events::service::EventService, filters::StreamFormat, rejections::ApiError, Result},
     runtime::features::FeatureError,
     swarm::event_store_ref,
 };
@@ -9,7 +9,12 @@ use ax_types::{
     service::{PublishRequest, QueryRequest, SubscribeMonotonicRequest, SubscribeRequest},
     AppId,
 };
-use warp::{reply, Rejection, Reply};
+use futures::Stream;
+use serde::Serialize;
+use warp::{
+    reply::{self, Response},
+    Rejection, Reply,
+};
 
 pub async fn offsets(_app_id: AppId, event_service: EventService) -> Result<impl Reply> {
     event_service
@@ -20,7 +25,7 @@ pub async fn offsets(_app_id: AppId, event_service: EventService) -> Result<impl
         .map_err(reject)
 }
 
-pub async fn publish(app_id: AppId, request: PublishRequest, event_service: EventService) -> Result<impl Reply> {
+pub async fn publish(app_id: AppId, event_service: EventService, request: PublishRequest) -> Result<impl Reply> {
     event_service
         .publish(app_id, request)
         .await
@@ -28,32 +33,84 @@ pub async fn publish(app_id: AppId, request: PublishRequest, event_service: Even
         .map_err(reject)
 }
 
-pub async fn query(app_id: AppId, request: QueryRequest, event_service: EventService) -> Result<impl Reply> {
+/// Send the responses as NDJSON or CBOR sequence, compressed if requested.
+fn stream_reply<T: Serialize + Send + 'static>(
+    format: StreamFormat,
+    zstd: bool,
+    responses: impl Stream<Item = T> + Send + 'static,
+) -> Response {
+    let response = match format {
+        StreamFormat::Cbor => ndjson::reply_cbor(ndjson::keep_alive().cbor().stream(responses)).into_response(),
+        StreamFormat::Ndjson | StreamFormat::Sse => {
+            ndjson::reply(ndjson::keep_alive().stream(responses)).into_response()
+        }
+    };
+    compress(zstd, response)
+}
+
+fn compress(zstd: bool, response: Response) -> Response {
+    if zstd {
+        compression::zstd(response)
+    } else {
+        response
+    }
+}
+
+pub async fn query(
+    app_id: AppId,
+    event_service: EventService,
+    format: StreamFormat,
+    zstd: bool,
+    request: QueryRequest,
+) -> Result<Response> {
     event_service
         .query(app_id, request)
         .await
-        .map(|events| ndjson::reply(ndjson::keep_alive().stream(events)))
+        .map(|events| stream_reply(format, zstd, events))
         .map_err(reject)
 }
 
-pub async fn subscribe(app_id: AppId, request: SubscribeRequest, event_service: EventService) -> Result<impl Reply> {
-    event_service
-        .subscribe(app_id, request)
-        .await
-        .map(|events| ndjson::reply(ndjson::keep_alive().stream(events)))
-        .map_err(reject)
+pub async fn subscribe(
+    app_id: AppId,
+    event_service: EventService,
+    format: StreamFormat,
+    zstd: bool,
+    last_event_id: Option<String>,
+    mut request: SubscribeRequest,
+) -> Result<Response> {
+    if let Some(id) = last_event_id {
+        let resume = sse::decode_id(&id).map_err(warp::reject::custom)?;
+        request.lower_bound = Some(request.lower_bound.unwrap_or_default().union(&resume));
+    }
+    let lower_bound = request.lower_bound.clone().unwrap_or_default();
+    let events = event_service.subscribe(app_id, request).await.map_err(reject)?;
+    Ok(match format {
+        StreamFormat::Sse => compress(zstd, sse::reply(lower_bound, events).into_response()),
+        _ => stream_reply(format, zstd, events),
+    })
 }
 
 pub async fn subscribe_monotonic(
     app_id: AppId,
-    request: SubscribeMonotonicRequest,
     event_service: EventService,
-) -> Result<impl Reply> {
-    event_service
+    format: StreamFormat,
+    zstd: bool,
+    last_event_id: Option<String>,
+    mut request: SubscribeMonotonicRequest,
+) -> Result<Response> {
+    if let Some(id) = last_event_id {
+        let resume = sse::decode_id(&id).map_err(warp::reject::custom)?;
+        request.lower_bound.union_with(&resume);
+    }
+    let lower_bound = request.lower_bound.clone();
+    let events = event_service
         .subscribe_monotonic(app_id, request)
         .await
-        .map(|events| ndjson::reply(ndjson::keep_alive().stream(events)))
-        .map_err(reject)
+        .map_err(reject)?;
+    Ok(match format {
+        StreamFormat::Sse => compress(zstd, sse::reply(lower_bound, events).into_response()),
+        _ => stream_reply(format, zstd, events),
+    })
 }
 
 fn reject(err: anyhow::Error) -> Rejection {
@@ -64,6 +121,8 @@ fn reject(err: anyhow::Error) -> Rejection {
             event_store_ref::Error::Overload => warp::reject::custom(ApiError::Overloaded { cause }),
             event_store_ref::Error::InvalidUpperBounds => warp::reject::custom(ApiError::BadRequest { cause }),
             event_store_ref::Error::TagExprError(_) => warp::reject::custom(ApiError::BadRequest { cause }),
+            event_store_ref::Error::PreconditionFailed => warp::reject::custom(ApiError::PreconditionFailed { cause }),
+            event_store_ref::Error::Replica => warp::reject::custom(ApiError::Forbidden { cause }),
         };
     }
     let err = match err.downcast::<ApiError>() {
@@ -1,6 +1,8 @@
+mod compression;
 mod filters;
 mod handlers;
 mod ndjson;
+mod sse;
 
 use warp::Filter;
 
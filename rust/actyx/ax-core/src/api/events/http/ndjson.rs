@@ -2,6 +2,7 @@ use std::{
     error::Error,
     fmt::Display,
     future::Future,
+    io,
     pin::Pin,
     task::{Context, Poll},
     time::Duration,
@@ -18,17 +19,35 @@ use tokio::time::{self, Sleep};
 
 type Bytes = Vec<u8>;
 static DELIM: &[u8] = b"\n";
+/// CBOR `null`, sent as keep-alive message in CBOR sequences
+static CBOR_NULL: &[u8] = &[0xf6];
 
 pub fn reply<S>(event_stream: S) -> impl warp::Reply
 where
     S: TryStream<Ok = Bytes> + Send + 'static,
     S::Error: Error + Send + Sync + 'static,
 {
-    NdjsonReply { event_stream }
+    NdjsonReply {
+        event_stream,
+        content_type: "application/x-ndjson",
+    }
+}
+
+/// Reply with a stream created by [`KeepAlive::cbor`].
+pub fn reply_cbor<S>(event_stream: S) -> impl warp::Reply
+where
+    S: TryStream<Ok = Bytes> + Send + 'static,
+    S::Error: Error + Send + Sync + 'static,
+{
+    NdjsonReply {
+        event_stream,
+        content_type: "application/cbor",
+    }
 }
 
 struct NdjsonReply<S> {
     event_stream: S,
+    content_type: &'static str,
 }
 
 impl<S> warp::Reply for NdjsonReply<S>
@@ -50,7 +69,7 @@ where
         let mut res = warp::reply::Response::new(hyper::Body::wrap_stream(body_stream));
         res.headers_mut().insert(
             warp::http::header::CONTENT_TYPE,
-            warp::http::header::HeaderValue::from_static("application/x-ndjson"),
+            warp::http::header::HeaderValue::from_static(self.content_type),
         );
         res
     }
@@ -63,6 +82,7 @@ pub struct KeepAlive {
     max_interval: Duration,
     delimiter: Bytes,
     writer_capacity: usize,
+    cbor: bool,
 }
 
 impl KeepAlive {
@@ -93,6 +113,14 @@ impl KeepAlive {
         self
     }
 
+    /// Encode the responses as a sequence of CBOR data items instead of NDJSON,
+    /// using CBOR `null` as keep-alive value.
+    pub fn cbor(mut self) -> Self {
+        self.delimiter = CBOR_NULL.to_vec();
+        self.cbor = true;
+        self
+    }
+
     /// Wrap a response stream with keep-alive functionality.
     ///
     /// See [`keep_alive`](keep_alive) for more.
@@ -107,11 +135,16 @@ impl KeepAlive {
 
         let delimiter = self.delimiter.clone();
         let capacity = self.writer_capacity;
+        let cbor = self.cbor;
         let event_stream = event_stream.map(move |e| {
             let mut writer = Vec::with_capacity(capacity);
-            serde_json::to_writer(&mut writer, &e)?;
-            writer.extend(&delimiter);
-            Ok::<Bytes, serde_json::error::Error>(writer)
+            if cbor {
+                serde_cbor::to_writer(&mut writer, &e).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            } else {
+                serde_json::to_writer(&mut writer, &e)?;
+                writer.extend(&delimiter);
+            }
+            Ok::<Bytes, io::Error>(writer)
         });
 
         NdjsonKeepAlive {
@@ -138,6 +171,7 @@ pub fn keep_alive() -> KeepAlive {
         max_interval: Duration::from_secs(15),
         delimiter: DELIM.to_vec(),
         writer_capacity: 128,
+        cbor: false,
     }
 }
 
@@ -0,0 +1,131 @@
+use crate::api::rejections::ApiError;
+use ax_types::{
+    service::{EventMeta, EventResponse, SubscribeMonotonicResponse, SubscribeResponse},
+    OffsetMap, Payload,
+};
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use warp::sse;
+
+/// Messages that move the position from which a subscription can be resumed.
+pub trait Progress: Serialize {
+    /// Record the events covered by this message, returning `true` if the position has changed.
+    fn progress(&self, position: &mut OffsetMap) -> bool;
+}
+
+fn event_progress(event: &EventResponse<Payload>, position: &mut OffsetMap) -> bool {
+    match &event.meta {
+        EventMeta::Event { key, .. } => position.update(key.stream, key.offset).is_some(),
+        EventMeta::Range { .. } | EventMeta::Synthetic => false,
+    }
+}
+
+fn offsets_progress(offsets: &OffsetMap, position: &mut OffsetMap) -> bool {
+    let before = position.clone();
+    position.union_with(offsets);
+    *position != before
+}
+
+impl Progress for SubscribeResponse {
+    fn progress(&self, position: &mut OffsetMap) -> bool {
+        match self {
+            SubscribeResponse::Event(event) | SubscribeResponse::AntiEvent(event) => event_progress(event, position),
+            SubscribeResponse::Offsets(offsets) => offsets_progress(&offsets.offsets, position),
+            SubscribeResponse::Diagnostic(_) | SubscribeResponse::FutureCompat => false,
+        }
+    }
+}
+
+impl Progress for SubscribeMonotonicResponse {
+    fn progress(&self, position: &mut OffsetMap) -> bool {
+        match self {
+            SubscribeMonotonicResponse::Event { event, .. } => event_progress(event, position),
+            SubscribeMonotonicResponse::Offsets(offsets) => offsets_progress(&offsets.offsets, position),
+            SubscribeMonotonicResponse::TimeTravel { .. }
+            | SubscribeMonotonicResponse::Diagnostic(_)
+            | SubscribeMonotonicResponse::FutureCompat => false,
+        }
+    }
+}
+
+/// Encode the position of a subscription as SSE event ID.
+pub fn encode_id(position: &OffsetMap) -> String {
+    let bytes = serde_cbor::to_vec(position).expect("OffsetMap is serialisable");
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Parse the `Last-Event-ID` sent by a reconnecting client into the lower bound to resume from.
+pub fn decode_id(id: &str) -> Result<OffsetMap, ApiError> {
+    let invalid = |e: &dyn std::fmt::Display| ApiError::BadRequest {
+        cause: format!("invalid Last-Event-ID: {}", e),
+    };
+    let bytes = base64::decode_config(id, base64::URL_SAFE_NO_PAD).map_err(|e| invalid(&e))?;
+    serde_cbor::from_slice(&bytes).map_err(|e| invalid(&e))
+}
+
+/// Stream the responses as server-sent events, tagging each one that advances
+/// the position with an ID the subscription can be resumed from.
+pub fn reply<T: Progress + Send + 'static>(
+    lower_bound: OffsetMap,
+    responses: impl Stream<Item = T> + Send + 'static,
+) -> impl warp::Reply {
+    let mut position = lower_bound;
+    let events = responses.map(move |response| {
+        let event = sse::Event::default();
+        let event = if response.progress(&mut position) {
+            event.id(encode_id(&position))
+        } else {
+            event
+        };
+        event.json_data(&response)
+    });
+    sse::reply(sse::keep_alive().stream(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ax_types::{
+        app_id,
+        service::{Diagnostic, OffsetMapResponse},
+        EventKey, Metadata, NodeId, Offset, TagSet, Timestamp,
+    };
+
+    #[test]
+    fn progress() {
+        let stream = NodeId::from_bytes(&[1; 32]).unwrap().stream(0.into());
+        let event = |offset: u32| {
+            SubscribeResponse::Event(EventResponse {
+                meta: EventMeta::Event {
+                    key: EventKey {
+                        lamport: 1.into(),
+                        stream,
+                        offset: offset.into(),
+                    },
+                    meta: Metadata {
+                        timestamp: Timestamp::new(1),
+                        tags: TagSet::empty(),
+                        app_id: app_id!("test"),
+                    },
+                },
+                payload: Payload::null(),
+            })
+        };
+
+        let mut position = OffsetMap::empty();
+        assert!(event(3).progress(&mut position));
+        assert!(!event(2).progress(&mut position));
+        assert!(!SubscribeResponse::Diagnostic(Diagnostic::warn("x".to_owned())).progress(&mut position));
+        assert_eq!(position.offset(stream), Offset::from(3).into());
+
+        let offsets = [(stream, Offset::from(5))].into_iter().collect::<OffsetMap>();
+        assert!(SubscribeResponse::Offsets(OffsetMapResponse {
+            offsets: offsets.clone()
+        })
+        .progress(&mut position));
+        assert_eq!(position, offsets);
+
+        assert_eq!(decode_id(&encode_id(&position)).unwrap(), position);
+        assert!(decode_id("not an id").is_err());
+    }
+}
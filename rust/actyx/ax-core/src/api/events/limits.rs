@@ -0,0 +1,219 @@
+use crate::api::rejections::ApiError;
+use ax_types::{service::PublishEvent, AppId};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use std::{collections::BTreeMap, sync::Arc, time::Instant};
+
+lazy_static! {
+    static ref REJECTIONS: IntCounterVec = register_int_counter_vec!(
+        "ax_publish_rejections",
+        "Number of publish requests rejected due to the configured limits",
+        &["reason"]
+    )
+    .unwrap();
+}
+
+/// Bounds on the events apps may publish, see the `api.events` settings.
+///
+/// A limit of zero disables the respective check.
+#[derive(Debug, Clone, Default)]
+pub struct PublishLimits {
+    max_payload_size: usize,
+    max_events_per_request: usize,
+    /// sustained number of events per second and app
+    rate: f64,
+    /// number of events an app may publish at once before being throttled to `rate`
+    burst: f64,
+    buckets: Arc<Mutex<BTreeMap<AppId, Bucket>>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl PublishLimits {
+    pub fn new(max_payload_size: usize, max_events_per_request: usize, rate: f64, burst: u64) -> Self {
+        Self {
+            max_payload_size,
+            max_events_per_request,
+            rate,
+            burst: burst as f64,
+            buckets: Default::default(),
+        }
+    }
+
+    /// Check the size of the request and whether the app may publish its events now, consuming
+    /// one token of the app’s bucket per event.
+    pub fn check(&self, app_id: &AppId, events: &[PublishEvent]) -> Result<(), ApiError> {
+        self.check_at(app_id, events, Instant::now())
+            .map_err(|e| rejected(app_id, e))
+    }
+
+    /// Return the tokens consumed by [`Self::check`] for events that have not been published,
+    /// e.g. since the request was a retry of an earlier one with the same idempotency key.
+    pub fn refund(&self, app_id: &AppId, events: usize) {
+        self.refund_at(app_id, events, Instant::now())
+    }
+
+    fn check_at(&self, app_id: &AppId, events: &[PublishEvent], now: Instant) -> Result<(), (&'static str, ApiError)> {
+        self.check_size_at(events)?;
+        self.acquire_at(app_id, events.len(), now)
+    }
+
+    fn check_size_at(&self, events: &[PublishEvent]) -> Result<(), (&'static str, ApiError)> {
+        if self.max_events_per_request > 0 && events.len() > self.max_events_per_request {
+            return Err((
+                "events_per_request",
+                ApiError::BadRequest {
+                    cause: format!(
+                        "request contains {} events, the limit is {}",
+                        events.len(),
+                        self.max_events_per_request
+                    ),
+                },
+            ));
+        }
+        if self.max_payload_size > 0 {
+            if let Some(size) = events
+                .iter()
+                .map(|event| event.payload.as_slice().len())
+                .find(|size| *size > self.max_payload_size)
+            {
+                return Err((
+                    "payload_size",
+                    ApiError::TooLarge {
+                        size,
+                        limit: self.max_payload_size,
+                    },
+                ));
+            }
+        }
+        if self.rate > 0.0 && events.len() as f64 > self.burst {
+            return Err((
+                "rate",
+                ApiError::RateLimited {
+                    cause: format!(
+                        "request contains {} events, at most {} can be published at once",
+                        events.len(),
+                        self.burst
+                    ),
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    fn refill(&self, buckets: &mut BTreeMap<AppId, Bucket>, app_id: &AppId, now: Instant) -> f64 {
+        let bucket = buckets.entry(app_id.clone()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+        bucket.tokens
+    }
+
+    fn acquire_at(&self, app_id: &AppId, events: usize, now: Instant) -> Result<(), (&'static str, ApiError)> {
+        if self.rate <= 0.0 {
+            return Ok(());
+        }
+        let requested = events as f64;
+        let mut buckets = self.buckets.lock();
+        let tokens = self.refill(&mut buckets, app_id, now);
+        if requested > tokens {
+            return Err((
+                "rate",
+                ApiError::RateLimited {
+                    cause: format!(
+                        "app `{}` may publish {} events per second, retry in {:.1}s",
+                        app_id,
+                        self.rate,
+                        (requested - tokens) / self.rate
+                    ),
+                },
+            ));
+        }
+        buckets.get_mut(app_id).unwrap().tokens -= requested;
+        Ok(())
+    }
+
+    fn refund_at(&self, app_id: &AppId, events: usize, now: Instant) {
+        if self.rate <= 0.0 {
+            return;
+        }
+        let mut buckets = self.buckets.lock();
+        let tokens = self.refill(&mut buckets, app_id, now);
+        buckets.get_mut(app_id).unwrap().tokens = (tokens + events as f64).min(self.burst);
+    }
+}
+
+/// Count the rejection, the app ID is only logged since the set of apps is unbounded.
+fn rejected(app_id: &AppId, (reason, err): (&'static str, ApiError)) -> ApiError {
+    tracing::debug!("rejecting publication of app {}: {}", app_id, err);
+    REJECTIONS.with_label_values(&[reason]).inc();
+    err
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ax_types::{app_id, tags, Payload};
+    use std::time::Duration;
+
+    fn events(n: usize, payload: &str) -> Vec<PublishEvent> {
+        (0..n)
+            .map(|_| PublishEvent {
+                tags: tags!("a"),
+                payload: Payload::compact(&payload).unwrap(),
+            })
+            .collect()
+    }
+
+    fn reason(result: Result<(), (&'static str, ApiError)>) -> &'static str {
+        result.map_or_else(|(reason, _)| reason, |_| "ok")
+    }
+
+    #[test]
+    fn check() {
+        let app = app_id!("com.example.app");
+        let other = app_id!("com.example.other");
+        let limits = PublishLimits::new(10, 5, 2.0, 4);
+        let start = Instant::now();
+
+        assert_eq!(
+            reason(limits.check_at(&app, &events(6, ""), start)),
+            "events_per_request"
+        );
+        assert_eq!(
+            reason(limits.check_at(&app, &events(1, "far too long"), start)),
+            "payload_size"
+        );
+        assert_eq!(reason(limits.check_at(&app, &events(5, ""), start)), "rate");
+
+        assert_eq!(reason(limits.check_at(&app, &events(3, ""), start)), "ok");
+        assert_eq!(reason(limits.check_at(&app, &events(2, ""), start)), "rate");
+        assert_eq!(reason(limits.check_at(&other, &events(2, ""), start)), "ok");
+        let later = start + Duration::from_millis(500);
+        assert_eq!(reason(limits.check_at(&app, &events(2, ""), later)), "ok");
+        assert_eq!(reason(limits.check_at(&app, &events(1, ""), later)), "rate");
+
+        // refunded tokens are available again, up to the burst size
+        let later = start + Duration::from_secs(10);
+        assert_eq!(reason(limits.check_at(&app, &events(4, ""), later)), "ok");
+        limits.refund_at(&app, 3, later);
+        assert_eq!(reason(limits.check_at(&app, &events(4, ""), later)), "rate");
+        assert_eq!(reason(limits.check_at(&app, &events(3, ""), later)), "ok");
+        limits.refund_at(&app, 10, later);
+        assert_eq!(reason(limits.check_at(&app, &events(4, ""), later)), "ok");
+
+        let unlimited = PublishLimits::default();
+        assert_eq!(
+            reason(unlimited.check_at(&app, &events(100, "far too long"), start)),
+            "ok"
+        );
+    }
+}
@@ -1,4 +1,7 @@
+pub(crate) mod cursor;
 mod http;
+pub mod limits;
+pub mod schemas;
 pub mod service;
 mod ws;
 
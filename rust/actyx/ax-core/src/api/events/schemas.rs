@@ -0,0 +1,93 @@
+use crate::settings::Validator;
+use anyhow::Context;
+use ax_types::{Payload, Tag, TagSet};
+use std::{collections::BTreeMap, str::FromStr, sync::Arc};
+
+/// JSON Schemas registered for event payloads per tag, see the `api.events.schemas` setting.
+///
+/// An event conforms if its payload matches the schemas of all its tags, tags without
+/// schema impose no restrictions.
+#[derive(Debug, Clone, Default)]
+pub struct PayloadSchemas {
+    schemas: Arc<BTreeMap<Tag, Validator>>,
+    reject_invalid: bool,
+}
+
+impl PayloadSchemas {
+    pub fn new(schemas: &BTreeMap<String, serde_json::Value>, reject_invalid: bool) -> anyhow::Result<Self> {
+        let schemas = schemas
+            .iter()
+            .map(|(tag, schema)| {
+                let tag = Tag::from_str(tag).with_context(|| format!("invalid tag `{}` for payload schema", tag))?;
+                let validator = Validator::new(schema.clone())
+                    .with_context(|| format!("invalid payload schema for tag '{}'", tag))?;
+                Ok((tag, validator))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            schemas: Arc::new(schemas),
+            reject_invalid,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty()
+    }
+
+    /// Whether the publication of non-conforming events shall be rejected.
+    pub fn reject_invalid(&self) -> bool {
+        self.reject_invalid
+    }
+
+    /// Check the payload against the schemas of the given tags, describing the first violation.
+    pub fn check(&self, tags: &TagSet, payload: &Payload) -> Result<(), String> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let mut json = None;
+        for tag in tags.iter() {
+            if let Some(validator) = self.schemas.get(&tag) {
+                let json = json.get_or_insert_with(|| payload.json_value());
+                validator
+                    .validate(json)
+                    .map_err(|e| format!("payload does not conform to the schema for tag '{}': {}", tag, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ax_types::tags;
+    use maplit::btreemap;
+    use serde_json::json;
+
+    #[test]
+    fn check() {
+        let schemas = PayloadSchemas::new(
+            &btreemap! {
+                "temperature".to_owned() => json!({
+                    "type": "object",
+                    "properties": { "value": { "type": "number" } },
+                    "required": ["value"]
+                }),
+            },
+            true,
+        )
+        .unwrap();
+        let payload = |v: serde_json::Value| Payload::from_json_value(v).unwrap();
+
+        assert!(schemas
+            .check(&tags!("temperature"), &payload(json!({"value": 3.5})))
+            .is_ok());
+        assert!(schemas.check(&tags!("other"), &payload(json!("anything"))).is_ok());
+        let err = schemas
+            .check(&tags!("other", "temperature"), &payload(json!({"value": "hot"})))
+            .unwrap_err();
+        assert!(err.starts_with("payload does not conform to the schema for tag 'temperature'"));
+
+        assert!(PayloadSchemas::new(&btreemap! { "".to_owned() => json!({}) }, false).is_err());
+    }
+}
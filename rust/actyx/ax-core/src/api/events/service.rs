@@ -1,3 +1,8 @@
+use super::{
+    cursor::{Cursor, CursorKey},
+    limits::PublishLimits,
+    schemas::PayloadSchemas,
+};
 use crate::{
     api::rejections::ApiError,
     ax_futures_util::{stream::AxStreamExt, ReceiverExt},
@@ -9,19 +14,22 @@ use crate::{
         value::Value,
     },
     swarm::{
+        event_store::Precondition,
         event_store_ref::{EventStoreHandler, EventStoreRef},
         BanyanStore,
     },
+    trees::dnf::Dnf,
 };
-use ax_aql::{Arr, SimpleExpr, SpreadExpr};
+use ax_aql::{Arr, SimpleExpr, SpreadExpr, TagExpr};
 use ax_types::{
     app_id,
     service::{
-        Diagnostic, OffsetMapResponse, OffsetsResponse, Order, PublishEvent, PublishRequest, PublishResponse,
-        PublishResponseKey, QueryRequest, QueryResponse, Severity, SubscribeMonotonicRequest,
+        BatchQuery, CursorResponse, Diagnostic, EventMeta, InvalidPayloads, OffsetMapResponse, OffsetsResponse, Order,
+        PublishEvent, PublishPrecondition, PublishRequest, PublishResponse, PublishResponseKey, QueryBatchRequest,
+        QueryBatchResponse, QueryPlan, QueryRequest, QueryResponse, Severity, StagePlan, SubscribeMonotonicRequest,
         SubscribeMonotonicResponse, SubscribeRequest, SubscribeResponse,
     },
-    AppId, Event, EventKey, NodeId, OffsetMap, OffsetOrMin, Payload, TagSet, Timestamp,
+    AppId, AppScopes, Event, EventKey, NodeId, OffsetMap, OffsetOrMin, Payload, TagSet, Timestamp,
 };
 use futures::{
     future::{poll_fn, ready},
@@ -35,19 +43,71 @@ use std::{
     convert::{From, TryFrom},
     num::NonZeroU64,
     ops::Deref,
+    str::FromStr,
     task::{self, Poll},
 };
 use tokio::sync::mpsc;
 
+/// upper limit for [`PublishRequest::idempotency_key`], since keys are stored for a while
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 256;
+
 #[derive(Clone)]
 pub struct EventService {
     store: EventStoreRef,
     node_id: NodeId,
+    /// tags that may be published, see [`EventService::with_scopes`]
+    write_scope: Option<TagSet>,
+    schemas: PayloadSchemas,
+    limits: PublishLimits,
+    cursor_key: CursorKey,
 }
 
 impl EventService {
     pub fn new(store: EventStoreRef, node_id: NodeId) -> EventService {
-        EventService { store, node_id }
+        EventService {
+            store,
+            node_id,
+            write_scope: None,
+            schemas: PayloadSchemas::default(),
+            limits: PublishLimits::default(),
+            cursor_key: CursorKey::random(),
+        }
+    }
+
+    /// Authenticate query cursors with the given key instead of one that changes with every start.
+    pub(crate) fn with_cursor_key(self, cursor_key: CursorKey) -> EventService {
+        EventService { cursor_key, ..self }
+    }
+
+    /// Check published and subscribed events against the given payload schemas.
+    pub fn with_payload_schemas(self, schemas: PayloadSchemas) -> EventService {
+        EventService { schemas, ..self }
+    }
+
+    /// Bound the size and rate of published events.
+    pub fn with_publish_limits(self, limits: PublishLimits) -> EventService {
+        EventService { limits, ..self }
+    }
+
+    /// Restrict this service to the events an app may read and publish according to its scopes.
+    pub fn with_scopes(&self, scopes: &AppScopes) -> Result<EventService, ApiError> {
+        let store = match &scopes.read {
+            Some(read) => {
+                let scope = TagExpr::from_str(read).map_err(|e| ApiError::BadRequest {
+                    cause: format!("invalid read scope: {:#}", e),
+                })?;
+                self.store.restricted(scope)
+            }
+            None => self.store.clone(),
+        };
+        Ok(EventService {
+            store,
+            node_id: self.node_id,
+            write_scope: scopes.write.clone(),
+            schemas: self.schemas.clone(),
+            limits: self.limits.clone(),
+            cursor_key: self.cursor_key.clone(),
+        })
     }
 }
 
@@ -68,12 +128,84 @@ impl EventService {
     }
 
     pub async fn publish(&self, app_id: AppId, request: PublishRequest) -> anyhow::Result<PublishResponse> {
+        if let Some(allowed) = &self.write_scope {
+            if let Some(event) = request.data.iter().find(|event| !event.tags.is_subset(allowed)) {
+                return Err(ApiError::Forbidden {
+                    cause: format!(
+                        "app `{}` may not publish events tagged {}",
+                        app_id,
+                        event
+                            .tags
+                            .difference(allowed)
+                            .iter()
+                            .map(|t| format!("'{}'", t))
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    ),
+                }
+                .into());
+            }
+        }
+        if self.schemas.reject_invalid() {
+            for (idx, event) in request.data.iter().enumerate() {
+                self.schemas
+                    .check(&event.tags, &event.payload)
+                    .map_err(|cause| ApiError::BadRequest {
+                        cause: format!("event {}: {}", idx, cause),
+                    })?;
+            }
+        }
+        if let Some(key) = &request.idempotency_key {
+            if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+                return Err(ApiError::BadRequest {
+                    cause: format!(
+                        "idempotency key must have between 1 and {} bytes",
+                        MAX_IDEMPOTENCY_KEY_LEN
+                    ),
+                }
+                .into());
+            }
+        }
+        let precondition = match request.precondition {
+            Some(PublishPrecondition { tag_expr, offsets }) => {
+                let tag_expr = TagExpr::from_str(&tag_expr).map_err(|e| ApiError::BadRequest {
+                    cause: format!("invalid precondition: {:#}", e),
+                })?;
+                Some(Precondition { tag_expr, offsets })
+            }
+            None => None,
+        };
+        // only charge requests that are otherwise valid, and refund whatever is not published below
+        self.limits.check(&app_id, &request.data)?;
+        let count = request.data.len();
         let events = request
             .data
             .into_iter()
             .map(|PublishEvent { tags, payload }| (tags, payload))
             .collect();
-        let meta = self.store.persist(app_id, events).await?;
+        let persisted = if request.idempotency_key.is_some() || precondition.is_some() {
+            self.store
+                .persist_checked(app_id.clone(), request.idempotency_key, precondition, events)
+                .await
+                .map(|persisted| (persisted.meta, persisted.replayed))
+        } else {
+            self.store
+                .persist(app_id.clone(), events)
+                .await
+                .map(|meta| (meta, false))
+        };
+        let meta = match persisted {
+            Ok((meta, false)) => meta,
+            // retries of idempotent requests are not charged, they publish nothing
+            Ok((meta, true)) => {
+                self.limits.refund(&app_id, count);
+                meta
+            }
+            Err(e) => {
+                self.limits.refund(&app_id, count);
+                return Err(e.into());
+            }
+        };
         let response = PublishResponse {
             data: meta
                 .into_iter()
@@ -102,16 +234,28 @@ impl EventService {
         let enabled = query.enabled_features(&pragmas);
         features.validate(&enabled, Endpoint::Query)?;
         let mut feeder = query.make_feeder();
+        if request.explain {
+            feeder.record_stats();
+        }
 
-        async fn y(co: &Co<QueryResponse>, vs: Vec<anyhow::Result<Value>>) {
-            for v in vs {
-                co.yield_(match v {
-                    Ok(v) => QueryResponse::Event(v.into()),
-                    Err(e) => QueryResponse::Diagnostic(to_diagnostic(e)),
-                })
-                .await;
+        let page_size = request.page_size.map(|n| n.get());
+        let cursor = match &request.cursor {
+            Some(cursor) => Some(Cursor::decode(cursor, &request.query, &self.cursor_key)?),
+            None => None,
+        };
+        if page_size.is_some() || cursor.is_some() {
+            if let Err(idx) = feeder.checkpoint() {
+                return Err(ApiError::BadRequest {
+                    cause: format!("query stage `{}` does not support paging", query.stages[idx]),
+                }
+                .into());
             }
         }
+        if let Some(cursor) = &cursor {
+            feeder.restore(&cursor.stages).map_err(|e| ApiError::BadRequest {
+                cause: format!("invalid cursor: {:#}", e),
+            })?;
+        }
 
         let store = {
             let mut store = None;
@@ -121,13 +265,21 @@ impl EventService {
             store.unwrap_or_else(|| EphemeralStore(self.store.clone(), None))
         };
 
-        let upper_bound = match request.upper_bound {
-            Some(offsets) => offsets,
-            None => store.offsets().await?.present(),
+        // a cursor keeps the bounds and order of the first page
+        let (lower_bound, upper_bound, request_order) = match &cursor {
+            Some(cursor) => (cursor.lower.clone(), cursor.upper.clone(), cursor.order),
+            None => {
+                let upper_bound = match request.upper_bound {
+                    Some(offsets) => offsets,
+                    None => store.offsets().await?.present(),
+                };
+                (request.lower_bound.unwrap_or_default(), upper_bound, request.order)
+            }
         };
-        let lower_bound = request.lower_bound.unwrap_or_default();
 
-        let request_order = request.order;
+        let query_text = request.query;
+        let explain = request.explain;
+        let cursor_key = self.cursor_key.clone();
         let gen = Gen::new(move |co: Co<QueryResponse>| async move {
             let cx = Context::root(
                 Order::StreamAsc,
@@ -136,10 +288,15 @@ impl EventService {
                 upper_bound.clone(),
             );
             let mut cx = cx.child();
+            let cursor = cursor
+                .unwrap_or_else(|| Cursor::new(&query_text, lower_bound.clone(), upper_bound.clone(), request_order));
+            let from_store = matches!(query.source, ax_aql::Source::Events { .. });
+            let mut eval = Evaluation::new(&query, feeder, cursor, cursor_key, page_size, from_store, explain);
             let mut stream = match &query.source {
                 ax_aql::Source::Events { from, order } => {
-                    let order = order.or_else(|| feeder.preferred_order()).unwrap_or(request_order);
+                    let order = order.or_else(|| eval.feeder.preferred_order()).unwrap_or(request_order);
                     cx.order = order;
+                    eval.cursor.order = order;
                     let tag_expr = match cx.eval_from(from).await {
                         Ok(t) => t.into_owned(),
                         Err(e) => {
@@ -148,16 +305,33 @@ impl EventService {
                                 .await
                         }
                     };
+                    if let Some(plan) = &mut eval.plan {
+                        plan.tag_expr = Some(tag_expr.to_string());
+                        plan.dnf = Dnf::from(&tag_expr).terms().map(|t| t.to_string()).collect();
+                        plan.streams = match store
+                            .explain(tag_expr.clone(), lower_bound.clone(), upper_bound.clone())
+                            .await
+                        {
+                            Ok(streams) => streams,
+                            Err(e) => {
+                                return co
+                                    .yield_(QueryResponse::Diagnostic(Diagnostic::error(e.to_string())))
+                                    .await
+                            }
+                        };
+                    }
+                    // continue after the events delivered on previous pages
+                    let position = eval.cursor.position.clone();
                     let stream = match order {
                         Order::Asc => {
                             store
-                                .bounded_forward(tag_expr, lower_bound, upper_bound.clone(), false)
+                                .bounded_forward(tag_expr, position, upper_bound.clone(), false)
                                 .await
                         }
-                        Order::Desc => store.bounded_backward(tag_expr, lower_bound, upper_bound.clone()).await,
+                        Order::Desc => store.bounded_backward(tag_expr, lower_bound, position).await,
                         Order::StreamAsc => {
                             store
-                                .bounded_forward(tag_expr, lower_bound, upper_bound.clone(), true)
+                                .bounded_forward(tag_expr, position, upper_bound.clone(), true)
                                 .await
                         }
                     };
@@ -209,40 +383,202 @@ impl EventService {
                         .boxed()
                         .flatten_stream()
                     })
+                    .skip(usize::try_from(eval.cursor.inputs).unwrap_or(usize::MAX))
                     .right_stream(),
             };
 
             while let Some(ev) = stream.next().await {
-                let ev = match ev {
-                    Ok(ev) => ev,
+                let responses = match ev {
+                    Ok(ev) => eval.input(ev, &cx).await,
                     Err(e) => {
                         tracing::error!("aborting query due to {:#}", e);
-                        y(&co, vec![Err(e)]).await;
-                        return;
+                        eval.abort(to_diagnostic(e))
                     }
                 };
-                let vs = feeder.feed(Some(ev), &cx).await;
-                y(&co, vs).await;
-                if feeder.is_done() {
+                for response in responses {
+                    co.yield_(response).await;
+                }
+                if !eval.accepts_input() {
                     break;
                 }
             }
             drop(stream);
 
-            let vs = feeder.feed(None, &cx).await;
-            y(&co, vs).await;
+            for response in eval.finish(&cx, upper_bound).await {
+                co.yield_(response).await;
+            }
+        });
 
-            co.yield_(QueryResponse::Offsets(OffsetMapResponse { offsets: upper_bound }))
-                .await;
-        })
-        .take_until_condition(|r| {
-            ready(matches!(
-                r,
-                QueryResponse::Diagnostic(Diagnostic {
-                    severity: Severity::Error,
-                    ..
+        Ok(gen.boxed())
+    }
+
+    /// Evaluate several queries at once, see [`QueryBatchRequest`].
+    ///
+    /// Queries reading from the store are grouped by tag expression and order, each group
+    /// being fed from a single stream of events. All other queries, e.g. those reading from
+    /// arrays, are evaluated one after the other at the end.
+    pub async fn query_batch(
+        &self,
+        app_id: AppId,
+        request: QueryBatchRequest,
+    ) -> anyhow::Result<BoxStream<'static, QueryBatchResponse>> {
+        let upper_bound = match request.upper_bound {
+            Some(offsets) => offsets,
+            None => self.store.offsets().await?.present(),
+        };
+        let lower_bound = request.lower_bound.unwrap_or_default();
+        let request_order = request.order;
+
+        let mut shared = vec![];
+        let mut single = vec![];
+        for BatchQuery { id, query: text } in request.queries {
+            let query = ax_aql::Query::parse(&text).map_err(|e| ApiError::BadRequest {
+                cause: format!("query `{}`: {:#}", id, e),
+            })?;
+            let (query, pragmas) = Query::from(query, app_id.clone());
+            let features = Features::from_query(&query);
+            let enabled = query.enabled_features(&pragmas);
+            features.validate(&enabled, Endpoint::Query)?;
+            let from_store =
+                matches!(query.source, ax_aql::Source::Events { .. }) && pragmas.pragma("events").is_none();
+            if from_store {
+                shared.push((id, text, query));
+            } else {
+                let request = QueryRequest {
+                    lower_bound: Some(lower_bound.clone()),
+                    upper_bound: Some(upper_bound.clone()),
+                    query: text,
+                    order: request_order,
+                    explain: false,
+                    page_size: None,
+                    cursor: None,
+                };
+                single.push((id, self.query(app_id.clone(), request).await?));
+            }
+        }
+
+        async fn y(co: &Co<QueryBatchResponse>, id: &str, responses: Vec<QueryResponse>) {
+            for response in responses {
+                co.yield_(QueryBatchResponse {
+                    id: id.to_owned(),
+                    response,
                 })
-            ))
+                .await;
+            }
+        }
+
+        struct Member {
+            id: String,
+            eval: Evaluation,
+        }
+
+        let store = self.store.clone();
+        let cursor_key = self.cursor_key.clone();
+        let gen = Gen::new(move |co: Co<QueryBatchResponse>| async move {
+            let error = |id: String, message: String| QueryBatchResponse {
+                id,
+                response: QueryResponse::Diagnostic(Diagnostic::error(message)),
+            };
+            let cx = Context::root(
+                Order::StreamAsc,
+                store.clone(),
+                lower_bound.clone(),
+                upper_bound.clone(),
+            );
+
+            let mut groups: Vec<(TagExpr, Order, Vec<Member>)> = vec![];
+            for (id, text, query) in shared {
+                let ax_aql::Source::Events { from, order } = &query.source else {
+                    continue;
+                };
+                let feeder = query.make_feeder();
+                let order = order.or_else(|| feeder.preferred_order()).unwrap_or(request_order);
+                let tag_expr = match cx.child().eval_from(from).await {
+                    Ok(t) => t.into_owned(),
+                    Err(e) => {
+                        co.yield_(error(id, e.to_string())).await;
+                        continue;
+                    }
+                };
+                let cursor = Cursor::new(&text, lower_bound.clone(), upper_bound.clone(), order);
+                let member = Member {
+                    id,
+                    eval: Evaluation::new(&query, feeder, cursor, cursor_key.clone(), None, true, false),
+                };
+                match groups.iter_mut().find(|(t, o, _)| *t == tag_expr && *o == order) {
+                    Some((_, _, members)) => members.push(member),
+                    None => groups.push((tag_expr, order, vec![member])),
+                }
+            }
+
+            for (tag_expr, order, mut members) in groups {
+                let mut cx = cx.child();
+                cx.order = order;
+                let stream = match order {
+                    Order::Asc => {
+                        store
+                            .bounded_forward(tag_expr, lower_bound.clone(), upper_bound.clone(), false)
+                            .await
+                    }
+                    Order::Desc => {
+                        store
+                            .bounded_backward(tag_expr, lower_bound.clone(), upper_bound.clone())
+                            .await
+                    }
+                    Order::StreamAsc => {
+                        store
+                            .bounded_forward(tag_expr, lower_bound.clone(), upper_bound.clone(), true)
+                            .await
+                    }
+                };
+                let mut stream = match stream {
+                    Ok(s) => s.stop_on_error(),
+                    Err(e) => {
+                        for member in members {
+                            co.yield_(error(member.id, e.to_string())).await;
+                        }
+                        continue;
+                    }
+                };
+                while let Some(ev) = stream.next().await {
+                    match ev {
+                        Ok(ev) => {
+                            let ev = Value::from(ev);
+                            for member in members.iter_mut().filter(|m| m.eval.accepts_input()) {
+                                let responses = member.eval.input(ev.clone(), &cx).await;
+                                y(&co, &member.id, responses).await;
+                            }
+                        }
+                        Err(e) => {
+                            tracing::error!("aborting batched queries due to {:#}", e);
+                            let diagnostic = to_diagnostic(e.into());
+                            for member in members.iter_mut() {
+                                let responses = member.eval.abort(diagnostic.clone());
+                                y(&co, &member.id, responses).await;
+                            }
+                        }
+                    }
+                    if members.iter().all(|m| !m.eval.accepts_input()) {
+                        break;
+                    }
+                }
+                drop(stream);
+
+                for member in members.iter_mut() {
+                    let responses = member.eval.finish(&cx, upper_bound.clone()).await;
+                    y(&co, &member.id, responses).await;
+                }
+            }
+
+            for (id, mut responses) in single {
+                while let Some(response) = responses.next().await {
+                    co.yield_(QueryBatchResponse {
+                        id: id.clone(),
+                        response,
+                    })
+                    .await;
+                }
+            }
         });
 
         Ok(gen.boxed())
@@ -270,6 +606,8 @@ impl EventService {
         };
         let present = self.store.offsets().await?.present();
         let mut lower_bound = request.lower_bound.unwrap_or_default();
+        let schemas = self.schemas.clone();
+        let invalid_payloads = request.invalid_payloads;
 
         let features = Features::from_query(&query);
         let enabled = query.enabled_features(&pragmas);
@@ -282,7 +620,8 @@ impl EventService {
             // no sub-queries supported yet, so no OffsetMap needed
             OffsetMap::empty(),
             OffsetMap::empty(),
-        );
+        )
+        .live();
 
         let tag_expr = cx.child().eval_from(&tag_expr).await?.into_owned();
         let tags = tag_expr.clone(); // for logging
@@ -321,8 +660,10 @@ impl EventService {
                         return;
                     }
                 };
-                let vs = query.feed(Some(ev.into()), &cx).await;
-                y(&co, vs).await;
+                if screen(&co, &schemas, invalid_payloads, &ev).await {
+                    let vs = query.feed(Some(ev.into()), &cx).await;
+                    y(&co, vs).await;
+                }
             }
 
             let vs = query.feed(None, &cx).await;
@@ -341,10 +682,12 @@ impl EventService {
                             return;
                         }
                     };
-                    let vs = query.feed(Some(ev.into()), &cx).await;
-                    y(&co, vs).await;
-                    if query.is_done() {
-                        break 'a;
+                    if screen(&co, &schemas, invalid_payloads, &ev).await {
+                        let vs = query.feed(Some(ev.into()), &cx).await;
+                        y(&co, vs).await;
+                        if query.is_done() {
+                            break 'a;
+                        }
                     }
                     input = match unbounded.poll_next_unpin(&mut task::Context::from_waker(&noop_waker())) {
                         Poll::Ready(Some(ev)) => ev,
@@ -411,7 +754,8 @@ impl EventService {
             // no sub-queries supported yet, so no OffsetMap needed
             OffsetMap::empty(),
             OffsetMap::empty(),
-        );
+        )
+        .live();
 
         let tag_expr = cx.child().eval_from(&tag_expr).await?.into_owned();
         let tags = tag_expr.clone(); // for logging
@@ -541,6 +885,156 @@ impl EventService {
     }
 }
 
+/// The evaluation of a query's stages on its inputs, shared by `query` and `query_batch`.
+///
+/// It ends with the first error diagnostic, with a cursor once a page is full, or with
+/// the offsets after the remaining results have been flushed at the end of the inputs.
+struct Evaluation {
+    feeder: Feeder,
+    cursor: Cursor,
+    cursor_key: CursorKey,
+    page_size: Option<u64>,
+    /// whether inputs are events from the store, whose keys move the cursor
+    from_store: bool,
+    delivered: u64,
+    plan: Option<QueryPlan>,
+    ended: bool,
+}
+
+impl Evaluation {
+    fn new(
+        query: &Query,
+        feeder: Feeder,
+        cursor: Cursor,
+        cursor_key: CursorKey,
+        page_size: Option<u64>,
+        from_store: bool,
+        explain: bool,
+    ) -> Self {
+        let plan = explain.then(|| QueryPlan {
+            tag_expr: None,
+            dnf: vec![],
+            streams: vec![],
+            stages: query
+                .stages
+                .iter()
+                .map(|stage| StagePlan {
+                    stage: stage.to_string(),
+                    inputs: 0,
+                    outputs: 0,
+                    micros: 0,
+                })
+                .collect(),
+        });
+        Self {
+            feeder,
+            cursor,
+            cursor_key,
+            page_size,
+            from_store,
+            delivered: 0,
+            plan,
+            ended: false,
+        }
+    }
+
+    fn accepts_input(&self) -> bool {
+        !self.ended && !self.feeder.is_done()
+    }
+
+    async fn input(&mut self, ev: Value, cx: &Context<'_>) -> Vec<QueryResponse> {
+        let key = match ev.meta() {
+            EventMeta::Event { key, .. } if self.from_store => Some(*key),
+            _ => None,
+        };
+        let before = self.page_size.and_then(|_| self.feeder.checkpoint().ok());
+        let mut vs = self.feeder.feed(Some(ev), cx).await;
+        // drop the outputs for this input that were delivered on the previous page
+        let skipped = std::mem::take(&mut self.cursor.skip);
+        vs.drain(..usize::try_from(skipped).unwrap_or(usize::MAX).min(vs.len()));
+        if let Some(page_size) = self.page_size {
+            let room = usize::try_from(page_size - self.delivered).unwrap_or(usize::MAX);
+            if vs.len() > room || (vs.len() == room && !self.feeder.is_done()) {
+                if vs.len() > room {
+                    // stop in the middle of this input, so feed it again next time
+                    self.cursor.skip = skipped + room as u64;
+                    self.cursor.stages = before.unwrap_or_default();
+                } else {
+                    self.cursor.advance(key.as_ref());
+                    self.cursor.stages = self.feeder.checkpoint().unwrap_or_default();
+                }
+                vs.truncate(room);
+                let mut responses = self.responses(vs);
+                if !self.ended {
+                    self.ended = true;
+                    responses.push(QueryResponse::Cursor(CursorResponse {
+                        cursor: self.cursor.encode(&self.cursor_key),
+                    }));
+                }
+                return responses;
+            }
+            self.delivered += vs.len() as u64;
+        }
+        self.cursor.advance(key.as_ref());
+        self.responses(vs)
+    }
+
+    /// End the evaluation because the inputs have failed.
+    fn abort(&mut self, diagnostic: Diagnostic) -> Vec<QueryResponse> {
+        if self.ended {
+            return vec![];
+        }
+        self.ended = true;
+        vec![QueryResponse::Diagnostic(diagnostic)]
+    }
+
+    /// Flush the stages at the end of the inputs, followed by the plan if requested and the offsets.
+    async fn finish(&mut self, cx: &Context<'_>, offsets: OffsetMap) -> Vec<QueryResponse> {
+        if self.ended {
+            return vec![];
+        }
+        let vs = self.feeder.feed(None, cx).await;
+        let mut responses = self.responses(vs);
+        if self.ended {
+            return responses;
+        }
+        self.ended = true;
+        if let Some(mut plan) = self.plan.take() {
+            let stats = self.feeder.stats().unwrap_or_default();
+            for (stage, stats) in plan.stages.iter_mut().zip(stats) {
+                stage.inputs = stats.inputs;
+                stage.outputs = stats.outputs;
+                stage.micros = u64::try_from(stats.elapsed.as_micros()).unwrap_or(u64::MAX);
+            }
+            responses.push(QueryResponse::Plan(plan));
+        }
+        responses.push(QueryResponse::Offsets(OffsetMapResponse { offsets }));
+        responses
+    }
+
+    fn responses(&mut self, vs: Vec<anyhow::Result<Value>>) -> Vec<QueryResponse> {
+        let mut responses = vec![];
+        for v in vs {
+            if self.ended {
+                break;
+            }
+            let response = match v {
+                Ok(v) => QueryResponse::Event(v.into()),
+                Err(e) => QueryResponse::Diagnostic(to_diagnostic(e)),
+            };
+            self.ended = matches!(
+                response,
+                QueryResponse::Diagnostic(Diagnostic {
+                    severity: Severity::Error,
+                    ..
+                })
+            );
+            responses.push(response);
+        }
+        responses
+    }
+}
+
 fn to_diagnostic(err: anyhow::Error) -> Diagnostic {
     if let Some(err) = err.downcast_ref::<RuntimeFailure>() {
         Diagnostic {
@@ -555,6 +1049,28 @@ fn to_diagnostic(err: anyhow::Error) -> Diagnostic {
     }
 }
 
+/// Check an event before feeding it into a subscription, returning `false` if it is to be
+/// left out due to its invalid payload, possibly after flagging it.
+async fn screen(
+    co: &Co<SubscribeResponse>,
+    schemas: &PayloadSchemas,
+    invalid_payloads: Option<InvalidPayloads>,
+    event: &Event<Payload>,
+) -> bool {
+    let Some(invalid_payloads) = invalid_payloads else {
+        return true;
+    };
+    let Err(cause) = schemas.check(&event.meta.tags, &event.payload) else {
+        return true;
+    };
+    if invalid_payloads == InvalidPayloads::Flag {
+        let message = format!("skipped event {}/{}: {}", event.key.stream, event.key.offset, cause);
+        co.yield_(SubscribeResponse::Diagnostic(Diagnostic::warn(message)))
+            .await;
+    }
+    false
+}
+
 struct EphemeralStore(EventStoreRef, Option<BanyanStore>);
 impl Drop for EphemeralStore {
     fn drop(&mut self) {
@@ -719,6 +1235,8 @@ mod tests {
                 app_id!("test"),
                 PublishRequest {
                     data: vec![evp(tags, data)],
+                    idempotency_key: None,
+                    precondition: None,
                 },
             )
             .await
@@ -762,6 +1280,9 @@ mod tests {
                     upper_bound: None,
                     query: q.to_owned(),
                     order: Order::StreamAsc,
+                    explain: false,
+                    page_size: None,
+                    cursor: None,
                 },
             )
             .await
@@ -770,17 +1291,78 @@ mod tests {
                 QueryResponse::Event(e) => e.payload.json_string(),
                 QueryResponse::Offsets(_) => "offsets".to_owned(),
                 QueryResponse::Diagnostic(d) => d.message,
+                QueryResponse::Plan(_) => "plan".to_owned(),
+                QueryResponse::Cursor(_) => "cursor".to_owned(),
                 QueryResponse::FutureCompat => unreachable!(),
             })
             .collect()
             .await
     }
+    async fn explain(service: &EventService, q: &str) -> QueryPlan {
+        service
+            .query(
+                app_id!("test"),
+                QueryRequest {
+                    lower_bound: None,
+                    upper_bound: None,
+                    query: q.to_owned(),
+                    order: Order::StreamAsc,
+                    explain: true,
+                    page_size: None,
+                    cursor: None,
+                },
+            )
+            .await
+            .unwrap()
+            .filter_map(|x| match x {
+                QueryResponse::Plan(p) => ready(Some(p)),
+                _ => ready(None),
+            })
+            .next()
+            .await
+            .unwrap()
+    }
+    async fn page(
+        service: &EventService,
+        q: &str,
+        order: Order,
+        page_size: u64,
+        cursor: Option<String>,
+    ) -> anyhow::Result<(Vec<String>, Option<String>)> {
+        let mut stream = service
+            .query(
+                app_id!("test"),
+                QueryRequest {
+                    lower_bound: None,
+                    upper_bound: None,
+                    query: q.to_owned(),
+                    order,
+                    explain: false,
+                    page_size: NonZeroU64::new(page_size),
+                    cursor,
+                },
+            )
+            .await?;
+        let mut results = vec![];
+        let mut next = None;
+        while let Some(x) = stream.next().await {
+            match x {
+                QueryResponse::Event(e) => results.push(e.payload.json_string()),
+                QueryResponse::Offsets(_) => results.push("offsets".to_owned()),
+                QueryResponse::Diagnostic(d) => results.push(d.message),
+                QueryResponse::Cursor(c) => next = Some(c.cursor),
+                x => panic!("unexpected: {:?}", x),
+            }
+        }
+        Ok((results, next))
+    }
     async fn subscribe(service: &EventService, q: &str) -> Vec<String> {
         service
             .subscribe(
                 app_id!("test"),
                 SubscribeRequest {
                     lower_bound: None,
+                    invalid_payloads: None,
                     query: q.to_owned(),
                 },
             )
@@ -829,6 +1411,9 @@ mod tests {
                     upper_bound: None,
                     query: q.to_owned(),
                     order: Order::StreamAsc,
+                    explain: false,
+                    page_size: None,
+                    cursor: None,
                 },
             )
             .await
@@ -869,6 +1454,7 @@ mod tests {
                             app_id!("test"),
                             SubscribeRequest {
                                 lower_bound: Some(lower_bound.clone()),
+                                invalid_payloads: None,
                                 query: "FROM allEvents".to_owned(),
                             },
                         )
@@ -1094,6 +1680,547 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn explain_query() {
+        Runtime::new()
+            .unwrap()
+            .block_on(async {
+                timeout(TIMEOUT, async {
+                    let store = BanyanStore::test("explain").await.unwrap();
+                    let (_node_id, service) = setup(&store);
+
+                    publish(&service, tags!("a"), 1).await;
+                    publish(&service, tags!("a"), 2).await;
+                    publish(&service, tags!("b"), 3).await;
+
+                    let plan = explain(&service, "FROM 'a' | 'b' FILTER _ > 1 SELECT _ * 2").await;
+                    assert_eq!(plan.tag_expr.as_deref(), Some("('a' | 'b')"));
+                    assert_eq!(plan.dnf, vec!["'a'", "'b'"]);
+                    assert!(!plan.streams.is_empty());
+                    assert!(plan.streams.iter().all(|s| s.indexed));
+                    let stages = plan
+                        .stages
+                        .iter()
+                        .map(|s| (s.stage.as_str(), s.inputs, s.outputs))
+                        .collect::<Vec<_>>();
+                    assert_eq!(stages, vec![("FILTER (_ > 1)", 3, 2), ("SELECT (_ * 2)", 2, 2)]);
+
+                    let plan = explain(&service, "FROM allEvents").await;
+                    assert_eq!(plan.dnf, vec!["allEvents"]);
+                    assert!(!plan.streams.is_empty());
+                    assert!(plan.streams.iter().all(|s| !s.indexed));
+                    assert!(plan.stages.is_empty());
+
+                    let plan = explain(&service, "FROM [1, 2] LIMIT 1").await;
+                    assert_eq!(plan.tag_expr, None);
+                    assert!(plan.streams.is_empty());
+                    assert_eq!(plan.stages[0].stage, "LIMIT 1");
+                    assert_eq!((plan.stages[0].inputs, plan.stages[0].outputs), (1, 1));
+
+                    assert_eq!(
+                        query(&service, "FROM 'b'").await,
+                        vec!["3", "offsets"],
+                        "no plan unless requested"
+                    );
+                })
+                .await
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn idempotent_publish() {
+        Runtime::new()
+            .unwrap()
+            .block_on(async {
+                timeout(TIMEOUT, async {
+                    let store = BanyanStore::test("idempotent").await.unwrap();
+                    let (_node_id, service) = setup(&store);
+
+                    let request = |n: u32, key: &str| PublishRequest {
+                        data: vec![evp(tags!("a"), n)],
+                        idempotency_key: Some(key.to_owned()),
+                        precondition: None,
+                    };
+                    let first = service.publish(app_id!("test"), request(1, "k1")).await.unwrap();
+                    let retry = service.publish(app_id!("test"), request(2, "k1")).await.unwrap();
+                    assert_eq!(retry, first);
+                    let other = service.publish(app_id!("test"), request(3, "k2")).await.unwrap();
+                    assert_ne!(other, first);
+                    // keys are per app
+                    service.publish(app_id!("other"), request(4, "k1")).await.unwrap();
+                    assert_eq!(query(&service, "FROM 'a'").await, vec!["1", "3", "4", "offsets"]);
+
+                    let err = service.publish(app_id!("test"), request(5, "")).await.unwrap_err();
+                    assert_eq!(
+                        err.to_string(),
+                        "Invalid request. idempotency key must have between 1 and 256 bytes"
+                    );
+                })
+                .await
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn publish_precondition() {
+        Runtime::new()
+            .unwrap()
+            .block_on(async {
+                timeout(TIMEOUT, async {
+                    let store = BanyanStore::test("precondition").await.unwrap();
+                    let (_node_id, service) = setup(&store);
+
+                    let request = |n: u32, tag_expr: &str, offsets: &OffsetMap| PublishRequest {
+                        data: vec![evp(tags!("a"), n)],
+                        idempotency_key: None,
+                        precondition: Some(PublishPrecondition {
+                            tag_expr: tag_expr.to_owned(),
+                            offsets: offsets.clone(),
+                        }),
+                    };
+                    publish(&service, tags!("a"), 1).await;
+                    let seen = service.offsets().await.unwrap().present;
+                    publish(&service, tags!("b"), 2).await;
+
+                    // only events matching the tag expression are relevant
+                    service
+                        .publish(app_id!("test"), request(3, "'a'", &seen))
+                        .await
+                        .unwrap();
+                    // now there is a new event tagged 'a' beyond `seen`
+                    let err = service
+                        .publish(app_id!("test"), request(4, "'a'", &seen))
+                        .await
+                        .unwrap_err();
+                    assert!(matches!(
+                        err.downcast_ref::<event_store_ref::Error>(),
+                        Some(event_store_ref::Error::PreconditionFailed)
+                    ));
+                    let seen = service.offsets().await.unwrap().present;
+                    service
+                        .publish(app_id!("test"), request(5, "'a'", &seen))
+                        .await
+                        .unwrap();
+                    assert_eq!(query(&service, "FROM 'a'").await, vec!["1", "3", "5", "offsets"]);
+
+                    let err = service
+                        .publish(app_id!("test"), request(6, "'a' &", &seen))
+                        .await
+                        .unwrap_err();
+                    assert!(err.to_string().starts_with("Invalid request. invalid precondition"));
+                })
+                .await
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn publish_charges_only_valid_requests() {
+        Runtime::new()
+            .unwrap()
+            .block_on(async {
+                timeout(TIMEOUT, async {
+                    let store = BanyanStore::test("charges").await.unwrap();
+                    let (_node_id, service) = setup(&store);
+                    // two events at once and then practically nothing
+                    let service = service.with_publish_limits(PublishLimits::new(0, 0, 0.001, 2));
+
+                    let request = |n: u32, key: Option<&str>, tag_expr: Option<&str>| PublishRequest {
+                        data: vec![evp(tags!("a"), n)],
+                        idempotency_key: key.map(|k| k.to_owned()),
+                        precondition: tag_expr.map(|tag_expr| PublishPrecondition {
+                            tag_expr: tag_expr.to_owned(),
+                            offsets: OffsetMap::empty(),
+                        }),
+                    };
+                    // rejected before consuming tokens
+                    let err = service
+                        .publish(app_id!("test"), request(1, Some(""), None))
+                        .await
+                        .unwrap_err();
+                    assert!(err.to_string().contains("idempotency key"));
+                    let err = service
+                        .publish(app_id!("test"), request(1, None, Some("'a' &")))
+                        .await
+                        .unwrap_err();
+                    assert!(err.to_string().contains("invalid precondition"));
+
+                    service.publish(app_id!("test"), request(1, None, None)).await.unwrap();
+                    // failing after consuming tokens refunds them
+                    let err = service
+                        .publish(app_id!("test"), request(2, None, Some("'a'")))
+                        .await
+                        .unwrap_err();
+                    assert!(matches!(
+                        err.downcast_ref::<event_store_ref::Error>(),
+                        Some(event_store_ref::Error::PreconditionFailed)
+                    ));
+                    service.publish(app_id!("test"), request(3, None, None)).await.unwrap();
+
+                    let err = service
+                        .publish(app_id!("test"), request(4, None, None))
+                        .await
+                        .unwrap_err();
+                    assert!(err.to_string().contains("may publish 0.001 events per second"));
+                    assert_eq!(query(&service, "FROM 'a'").await, vec!["1", "3", "offsets"]);
+                })
+                .await
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn query_batch() {
+        Runtime::new()
+            .unwrap()
+            .block_on(async {
+                timeout(TIMEOUT, async {
+                    let store = BanyanStore::test("query_batch").await.unwrap();
+                    let (_node_id, service) = setup(&store);
+                    for n in 1..=3 {
+                        publish(&service, tags!("a"), n).await;
+                    }
+                    publish(&service, tags!("b"), 4).await;
+
+                    let request = |queries: &[(&str, &str)]| QueryBatchRequest {
+                        lower_bound: None,
+                        upper_bound: None,
+                        order: Order::Asc,
+                        queries: queries
+                            .iter()
+                            .map(|(id, query)| BatchQuery {
+                                id: id.to_string(),
+                                query: query.to_string(),
+                            })
+                            .collect(),
+                    };
+                    let batch = |queries: &'static [(&'static str, &'static str)]| {
+                        let service = service.clone();
+                        async move {
+                            let mut results = BTreeMap::<String, Vec<String>>::new();
+                            let mut responses = service.query_batch(app_id!("test"), request(queries)).await.unwrap();
+                            while let Some(QueryBatchResponse { id, response }) = responses.next().await {
+                                results.entry(id).or_default().push(match response {
+                                    QueryResponse::Event(e) => e.payload.json_string(),
+                                    QueryResponse::Offsets(_) => "offsets".to_owned(),
+                                    QueryResponse::Diagnostic(d) => d.message,
+                                    x => panic!("unexpected: {:?}", x),
+                                });
+                            }
+                            results
+                        }
+                    };
+                    let results = batch(&[
+                        ("all", "FROM 'a'"),
+                        ("times", "FROM 'a' SELECT _ * 10"),
+                        ("first", "FROM 'a' LIMIT 1"),
+                        ("last", "FROM 'a' ORDER DESC LIMIT 1"),
+                        ("other", "FROM 'b'"),
+                    ])
+                    .await;
+                    let expected = [
+                        ("all", vec!["1", "2", "3", "offsets"]),
+                        ("first", vec!["1", "offsets"]),
+                        ("last", vec!["3", "offsets"]),
+                        ("other", vec!["4", "offsets"]),
+                        ("times", vec!["10", "20", "30", "offsets"]),
+                    ]
+                    .into_iter()
+                    .map(|(id, r)| (id.to_owned(), r.into_iter().map(String::from).collect()))
+                    .collect::<BTreeMap<_, Vec<_>>>();
+                    assert_eq!(results, expected);
+
+                    // diagnostics are delivered like for single queries
+                    let warn = "FROM 'a' SELECT _.x";
+                    let results = batch(&[("warn", "FROM 'a' SELECT _.x"), ("all", "FROM 'a'")]).await;
+                    assert_eq!(results["warn"], query(&service, warn).await);
+                    assert_eq!(results["warn"].len(), 4);
+
+                    let err = service
+                        .query_batch(app_id!("test"), request(&[("ok", "FROM 'a'"), ("broken", "FROM")]))
+                        .await
+                        .err()
+                        .unwrap();
+                    assert!(err.to_string().starts_with("Invalid request. query `broken`:"));
+                })
+                .await
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn payload_schemas() {
+        Runtime::new()
+            .unwrap()
+            .block_on(async {
+                timeout(TIMEOUT, async {
+                    let store = BanyanStore::test("payload_schemas").await.unwrap();
+                    let (_node_id, service) = setup(&store);
+                    let schemas = |reject_invalid| {
+                        PayloadSchemas::new(
+                            &maplit::btreemap! {
+                                "a".to_owned() => serde_json::json!({ "type": "integer", "maximum": 2 }),
+                            },
+                            reject_invalid,
+                        )
+                        .unwrap()
+                    };
+
+                    let service = service.with_payload_schemas(schemas(false));
+                    for n in 1..=3 {
+                        publish(&service, tags!("a"), n).await;
+                    }
+                    let subscribe = |invalid_payloads| {
+                        let service = service.clone();
+                        async move {
+                            service
+                                .subscribe(
+                                    app_id!("test"),
+                                    SubscribeRequest {
+                                        query: "FROM 'a'".to_owned(),
+                                        lower_bound: None,
+                                        invalid_payloads,
+                                    },
+                                )
+                                .await
+                                .unwrap()
+                                .take_while(|x| ready(!matches!(x, SubscribeResponse::Offsets(_))))
+                                .map(|x| match x {
+                                    SubscribeResponse::Event(e) => e.payload.json_string(),
+                                    SubscribeResponse::Diagnostic(d) => d.message,
+                                    x => panic!("unexpected: {:?}", x),
+                                })
+                                .collect::<Vec<_>>()
+                                .await
+                        }
+                    };
+                    assert_eq!(subscribe(None).await, vec!["1", "2", "3"]);
+                    assert_eq!(subscribe(Some(InvalidPayloads::Skip)).await, vec!["1", "2"]);
+                    let flagged = subscribe(Some(InvalidPayloads::Flag)).await;
+                    assert_eq!(flagged[..2], ["1", "2"]);
+                    assert!(flagged[2].starts_with("skipped event"), "{}", flagged[2]);
+                    assert!(
+                        flagged[2].contains("payload does not conform to the schema for tag 'a'"),
+                        "{}",
+                        flagged[2]
+                    );
+
+                    let service = service.with_payload_schemas(schemas(true));
+                    let err = service
+                        .publish(
+                            app_id!("test"),
+                            PublishRequest {
+                                data: vec![evp(tags!("b"), 5), evp(tags!("a", "b"), 5)],
+                                idempotency_key: None,
+                                precondition: None,
+                            },
+                        )
+                        .await
+                        .unwrap_err();
+                    assert!(err
+                        .to_string()
+                        .starts_with("Invalid request. event 1: payload does not conform to the schema for tag 'a'"));
+                    publish(&service, tags!("b"), 5).await;
+                    assert_eq!(query(&service, "FROM 'b'").await, vec!["5", "offsets"]);
+                })
+                .await
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn read_scope() {
+        Runtime::new()
+            .unwrap()
+            .block_on(async {
+                timeout(TIMEOUT, async {
+                    let store = BanyanStore::test("read_scope").await.unwrap();
+                    let (_node_id, service) = setup(&store);
+
+                    publish(&service, tags!("a"), 1).await;
+                    publish(&service, tags!("b"), 2).await;
+                    publish(&service, tags!("a", "b"), 3).await;
+
+                    let scoped = service
+                        .with_scopes(&AppScopes {
+                            read: Some("'a'".to_owned()),
+                            ..AppScopes::default()
+                        })
+                        .unwrap();
+                    assert_eq!(query(&scoped, "FROM allEvents").await, vec!["1", "3", "offsets"]);
+                    assert_eq!(query(&scoped, "FROM 'b'").await, vec!["3", "offsets"]);
+                    // sub-queries are restricted as well
+                    assert_eq!(
+                        query(&scoped, "FEATURES(zøg subQuery) FROM 'a' SELECT FROM 'b'").await,
+                        vec!["[3]", "[3]", "offsets"]
+                    );
+                    assert_eq!(query(&service, "FROM 'b'").await, vec!["2", "3", "offsets"]);
+
+                    assert!(service
+                        .with_scopes(&AppScopes {
+                            read: Some("'a' &".to_owned()),
+                            ..AppScopes::default()
+                        })
+                        .is_err());
+                })
+                .await
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn paging() {
+        Runtime::new()
+            .unwrap()
+            .block_on(async {
+                timeout(TIMEOUT, async {
+                    let store = BanyanStore::test("paging").await.unwrap();
+                    let (_node_id, service) = setup(&store);
+
+                    for n in 1..=5 {
+                        publish(&service, tags!("a"), n).await;
+                    }
+                    async fn all_pages(service: &EventService, q: &str, order: Order, size: u64) -> Vec<Vec<String>> {
+                        let mut pages = vec![];
+                        let mut cursor = None;
+                        loop {
+                            let (results, next) = page(service, q, order, size, cursor).await.unwrap();
+                            pages.push(results);
+                            match next {
+                                Some(next) => cursor = Some(next),
+                                None => return pages,
+                            }
+                        }
+                    }
+
+                    assert_eq!(
+                        all_pages(&service, "FROM 'a'", Order::StreamAsc, 2).await,
+                        vec![vec!["1", "2"], vec!["3", "4"], vec!["5", "offsets"]]
+                    );
+                    assert_eq!(
+                        all_pages(&service, "FROM 'a'", Order::Desc, 2).await,
+                        vec![vec!["5", "4"], vec!["3", "2"], vec!["1", "offsets"]]
+                    );
+                    // a page may end in the middle of the outputs for one event
+                    assert_eq!(
+                        all_pages(&service, "FROM 'a' SELECT _, _ * 10", Order::Asc, 3).await,
+                        vec![
+                            vec!["1", "10", "2"],
+                            vec!["20", "3", "30"],
+                            vec!["4", "40", "5"],
+                            vec!["50", "offsets"]
+                        ]
+                    );
+                    // the remaining LIMIT is carried over
+                    assert_eq!(
+                        all_pages(&service, "FROM 'a' LIMIT 3", Order::Asc, 2).await,
+                        vec![vec!["1", "2"], vec!["3", "offsets"]]
+                    );
+                    assert_eq!(
+                        all_pages(&service, "FROM [1, 2, 3]", Order::Asc, 2).await,
+                        vec![vec!["1", "2"], vec!["3", "offsets"]]
+                    );
+
+                    let err = page(&service, "FROM 'a' AGGREGATE SUM(_)", Order::Asc, 2, None)
+                        .await
+                        .unwrap_err();
+                    assert_eq!(
+                        err.to_string(),
+                        "Invalid request. query stage `AGGREGATE SUM(_)` does not support paging"
+                    );
+
+                    let (_, cursor) = page(&service, "FROM 'a'", Order::Asc, 2, None).await.unwrap();
+                    let err = page(&service, "FROM 'a' FILTER _ > 1", Order::Asc, 2, cursor)
+                        .await
+                        .unwrap_err();
+                    assert_eq!(
+                        err.to_string(),
+                        "Invalid request. invalid cursor: cursor was created for a different query"
+                    );
+                })
+                .await
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn join() {
+        Runtime::new()
+            .unwrap()
+            .block_on(async {
+                timeout(TIMEOUT, async {
+                    let store = BanyanStore::test("join").await.unwrap();
+                    let (_node_id, service) = setup(&store);
+
+                    publish(&service, tags!("a"), 1).await;
+                    publish(&service, tags!("a"), 2).await;
+                    publish(&service, tags!("b"), 2).await;
+                    publish(&service, tags!("a"), 3).await;
+                    publish(&service, tags!("b"), 7).await;
+                    publish(&service, tags!("b"), 5).await;
+
+                    assert_eq!(
+                        query(&service, "FEATURES(join) FROM 'a' JOIN 'b' AS b ON _ = b").await,
+                        vec!["[2,2]", "offsets"]
+                    );
+                    assert_eq!(
+                        query(&service, "FEATURES(join) FROM 'a' LEFT JOIN 'b' AS b ON b % 2 = _ % 2").await,
+                        vec!["[1,7]", "[1,5]", "[2,2]", "[3,7]", "[3,5]", "offsets"]
+                    );
+                    assert_eq!(
+                        query(
+                            &service,
+                            "FEATURES(join) FROM 'a' LEFT JOIN 'b' AS b ON _ + 4 = b SELECT _[1]"
+                        )
+                        .await,
+                        vec!["5", "null", "7", "offsets"]
+                    );
+                })
+                .await
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn user_defined_functions() {
+        Runtime::new()
+            .unwrap()
+            .block_on(async {
+                timeout(TIMEOUT, async {
+                    let store = BanyanStore::test("define").await.unwrap();
+                    let (_node_id, service) = setup(&store);
+
+                    publish(&service, tags!("a"), 1).await;
+                    publish(&service, tags!("a"), 2).await;
+
+                    assert_eq!(
+                        query(
+                            &service,
+                            "PRAGMA define Double(x) := x * 2\nPRAGMA define Inc(x) := Double(x) + 1\n\
+                            FROM 'a' SELECT Inc(_)"
+                        )
+                        .await,
+                        vec!["3", "5", "offsets"]
+                    );
+                    assert_eq!(
+                        query(
+                            &service,
+                            "PRAGMA define Net(x) := x.gross - x.tax\nFROM 'a' SELECT Net(_)"
+                        )
+                        .await,
+                        vec![
+                            "property `gross` not found in Number",
+                            "property `gross` not found in Number",
+                            "offsets"
+                        ]
+                    );
+                })
+                .await
+            })
+            .unwrap();
+    }
+
     #[test]
     fn interpolation() {
         Runtime::new()
@@ -1474,6 +2601,7 @@ ENDPRAGMA
                     app_id!("test"),
                     SubscribeRequest {
                         lower_bound: None,
+                        invalid_payloads: None,
                         query: "PRAGMA features := aggregate
                                 FROM appId(me) AGGREGATE LAST(_)"
                             .to_owned(),
@@ -1486,6 +2614,7 @@ ENDPRAGMA
                     app_id!("ne"),
                     SubscribeRequest {
                         lower_bound: None,
+                        invalid_payloads: None,
                         query: "PRAGMA features := aggregate
                                 FROM 'a' AGGREGATE LAST(_)"
                             .to_owned(),
@@ -1498,6 +2627,7 @@ ENDPRAGMA
                     app_id!("ne"),
                     SubscribeRequest {
                         lower_bound: None,
+                        invalid_payloads: None,
                         query: "PRAGMA features := aggregate
                                 FROM 'a' AGGREGATE LAST(_) AGGREGATE SUM(1)"
                             .to_owned(),
@@ -1573,6 +2703,7 @@ ENDPRAGMA
                     app_id!("test"),
                     SubscribeRequest {
                         lower_bound: None,
+                        invalid_payloads: None,
                         query: "PRAGMA features := aggregate spread
                                 FROM allEvents
                                 AGGREGATE LAST(_)
@@ -1614,6 +2745,63 @@ ENDPRAGMA
             .unwrap();
     }
 
+    #[test]
+    fn subscribe_aggregate_distinct() {
+        let f = async {
+            let routes = vec![EventRoute::new(
+                TagExpr::from_str("'b'").unwrap(),
+                "subscribe_aggregate_distinct".to_string(),
+            )];
+            let store = BanyanStore::test_with_routing("subscribe_aggregate_distinct", routes)
+                .await
+                .unwrap();
+            let (_node_id, service) = setup(&store);
+
+            publish(&service, tags!("b"), 1).await;
+
+            let mut q = service
+                .subscribe(
+                    app_id!("test"),
+                    SubscribeRequest {
+                        lower_bound: None,
+                        invalid_payloads: None,
+                        query: "PRAGMA features := aggregate spread distinct
+                                FROM allEvents
+                                AGGREGATE LAST(_)
+                                SELECT ...[_ % 2, 1]
+                                DISTINCT"
+                            .to_owned(),
+                    },
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(SResp::next(q.as_mut()).await, SResp::event("2-1 1"));
+            assert_eq!(
+                SResp::next(q.as_mut()).await,
+                SResp::Offsets(btreemap! {0 => 1, 1 => 0})
+            );
+
+            // retracting the emitted value promotes the suppressed duplicate
+            publish(&service, tags!("b"), 2).await;
+            assert_eq!(SResp::next(q.as_mut()).await, SResp::anti("2-1 1"));
+            assert_eq!(SResp::next(q.as_mut()).await, SResp::event("2-1 1"));
+            assert_eq!(SResp::next(q.as_mut()).await, SResp::anti("2-1 1"));
+            assert_eq!(SResp::next(q.as_mut()).await, SResp::event("3-1 0"));
+            assert_eq!(SResp::next(q.as_mut()).await, SResp::event("3-1 1"));
+
+            publish(&service, tags!("b"), 4).await;
+            assert_eq!(SResp::next(q.as_mut()).await, SResp::anti("3-1 0"));
+            assert_eq!(SResp::next(q.as_mut()).await, SResp::anti("3-1 1"));
+            assert_eq!(SResp::next(q.as_mut()).await, SResp::event("4-1 0"));
+            assert_eq!(SResp::next(q.as_mut()).await, SResp::event("4-1 1"));
+        };
+        Runtime::new()
+            .unwrap()
+            .block_on(async { timeout(Duration::from_secs(10), f).await })
+            .unwrap();
+    }
+
     #[test]
     fn subscribe_aggregate_filter() {
         let f = async {
@@ -1633,6 +2821,7 @@ ENDPRAGMA
                     app_id!("test"),
                     SubscribeRequest {
                         lower_bound: None,
+                        invalid_payloads: None,
                         query: "PRAGMA features := aggregate spread
                                 FROM appId(me)
                                 AGGREGATE LAST(_)
@@ -1684,6 +2873,7 @@ ENDPRAGMA
                     app_id!("test"),
                     SubscribeRequest {
                         lower_bound: None,
+                        invalid_payloads: None,
                         query: "PRAGMA features := aggregate spread
                                 FROM appId(me)
                                 AGGREGATE LAST(_)
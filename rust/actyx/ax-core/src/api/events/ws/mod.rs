@@ -2,17 +2,18 @@ use std::sync::Arc;
 
 use maplit::btreemap;
 use warp::Filter;
-use wsrpc::Service;
+use wsrpc::{Codec, Service};
 
 use crate::api::{
     events::service::EventService,
-    filters::{authenticate, query_token, query_token_ws},
+    filters::{accept_zstd, authenticate_token, prefer_cbor, query_token, query_token_ws},
     NodeInfo,
 };
 
 mod offsets;
 mod publish;
 mod query;
+mod query_batch;
 mod subscribe;
 mod subscribe_monotonic;
 
@@ -22,18 +23,28 @@ pub(crate) fn routes(
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     // legacy support
     let token = query_token().or(query_token_ws()).unify();
-    let auth = authenticate(node_info, token);
+    let auth = authenticate_token(node_info, token);
     let services = Arc::new(btreemap! {
       "offsets"             => offsets::service(event_service.clone()).boxed(),
       "query"               => query::service(event_service.clone()).boxed(),
+      "query_batch"         => query_batch::service(event_service.clone()).boxed(),
       "subscribe"           => subscribe::service(event_service.clone()).boxed(),
       "subscribe_monotonic" => subscribe_monotonic::service(event_service.clone()).boxed(),
       "publish"             => publish::service(event_service).boxed(),
     });
 
+    // clients preferring CBOR get binary responses, compressed if they accept zstd
+    let codec = prefer_cbor()
+        .and(accept_zstd())
+        .map(|cbor: bool, zstd: bool| match cbor {
+            true => Codec::Cbor { zstd },
+            false => Codec::Json,
+        });
+
     warp::path::end()
         .and(warp::ws())
         .and(warp::any().map(move || services.clone()))
         .and(auth)
-        .and_then(wsrpc::serve)
+        .and(codec)
+        .and_then(wsrpc::serve_with)
 }
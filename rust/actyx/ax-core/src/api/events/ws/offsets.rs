@@ -1,11 +1,11 @@
-use ax_types::{service::OffsetsResponse, AppId};
+use ax_types::service::OffsetsResponse;
 use futures::{
     stream::{BoxStream, StreamExt},
     FutureExt,
 };
 use wsrpc::Service;
 
-use crate::api::events::service::EventService;
+use crate::api::{bearer_token::BearerToken, events::service::EventService};
 
 pub struct Offsets {
     event_service: EventService,
@@ -15,9 +15,9 @@ impl Service for Offsets {
     type Req = ();
     type Resp = OffsetsResponse;
     type Error = String;
-    type Ctx = AppId;
+    type Ctx = BearerToken;
 
-    fn serve(&self, _app_id: AppId, _req: ()) -> BoxStream<'static, Result<Self::Resp, Self::Error>> {
+    fn serve(&self, _token: BearerToken, _req: ()) -> BoxStream<'static, Result<Self::Resp, Self::Error>> {
         let service = self.event_service.clone();
         (async move { service.offsets().await.map_err(|e| e.to_string()) })
             .into_stream()
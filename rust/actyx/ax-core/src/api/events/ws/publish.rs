@@ -1,11 +1,8 @@
-use ax_types::{
-    service::{PublishRequest, PublishResponse},
-    AppId,
-};
+use ax_types::service::{PublishRequest, PublishResponse};
 use futures::{stream::BoxStream, FutureExt, StreamExt};
 use wsrpc::Service;
 
-use crate::api::events::service::EventService;
+use crate::api::{bearer_token::BearerToken, events::service::EventService};
 
 pub struct Publish {
     event_service: EventService,
@@ -15,11 +12,12 @@ impl Service for Publish {
     type Req = PublishRequest;
     type Resp = PublishResponse;
     type Error = String;
-    type Ctx = AppId;
+    type Ctx = BearerToken;
 
-    fn serve(&self, app_id: AppId, req: Self::Req) -> BoxStream<'static, Result<Self::Resp, Self::Error>> {
-        let service = self.event_service.clone();
-        (async move { service.publish(app_id, req).await.map_err(|e| e.to_string()) })
+    fn serve(&self, token: BearerToken, req: Self::Req) -> BoxStream<'static, Result<Self::Resp, Self::Error>> {
+        let service = self.event_service.with_scopes(&token.scopes);
+        (async move { service?.publish(token.app_id, req).await })
+            .map(|x| x.map_err(|e| e.to_string()))
             .into_stream()
             .boxed()
     }
@@ -0,0 +1,34 @@
+use ax_types::service::{QueryBatchRequest, QueryBatchResponse};
+use futures::{
+    stream::{self, BoxStream},
+    FutureExt, StreamExt,
+};
+use wsrpc::Service;
+
+use crate::api::{bearer_token::BearerToken, events::service::EventService};
+
+pub struct QueryBatch {
+    event_service: EventService,
+}
+
+impl Service for QueryBatch {
+    type Req = QueryBatchRequest;
+    type Resp = QueryBatchResponse;
+    type Error = String;
+    type Ctx = BearerToken;
+
+    fn serve(&self, token: BearerToken, req: Self::Req) -> BoxStream<'static, Result<Self::Resp, Self::Error>> {
+        let service = self.event_service.with_scopes(&token.scopes);
+        (async move { service?.query_batch(token.app_id, req).await })
+            .map(|x| match x {
+                Ok(stream) => stream.map(Ok).left_stream(),
+                Err(e) => stream::once(futures::future::err(e.to_string())).right_stream(),
+            })
+            .flatten_stream()
+            .boxed()
+    }
+}
+
+pub fn service(event_service: EventService) -> QueryBatch {
+    QueryBatch { event_service }
+}
@@ -1,14 +1,11 @@
-use ax_types::{
-    service::{SubscribeRequest, SubscribeResponse},
-    AppId,
-};
+use ax_types::service::{SubscribeRequest, SubscribeResponse};
 use futures::{
     stream::{self, BoxStream},
     FutureExt, StreamExt,
 };
 use wsrpc::Service;
 
-use crate::api::events::service::EventService;
+use crate::api::{bearer_token::BearerToken, events::service::EventService};
 
 pub struct Subscribe {
     event_service: EventService,
@@ -18,21 +15,17 @@ impl Service for Subscribe {
     type Req = SubscribeRequest;
     type Resp = SubscribeResponse;
     type Error = String;
-    type Ctx = AppId;
+    type Ctx = BearerToken;
 
-    fn serve(&self, app_id: AppId, req: Self::Req) -> BoxStream<'static, Result<Self::Resp, Self::Error>> {
-        let service = self.event_service.clone();
-        async move {
-            service
-                .subscribe(app_id, req)
-                .map(move |x| match x {
-                    Ok(stream) => stream.map(Ok).left_stream(),
-                    Err(e) => stream::once(futures::future::err(e.to_string())).right_stream(),
-                })
-                .await
-        }
-        .flatten_stream()
-        .boxed()
+    fn serve(&self, token: BearerToken, req: Self::Req) -> BoxStream<'static, Result<Self::Resp, Self::Error>> {
+        let service = self.event_service.with_scopes(&token.scopes);
+        (async move { service?.subscribe(token.app_id, req).await })
+            .map(|x| match x {
+                Ok(stream) => stream.map(Ok).left_stream(),
+                Err(e) => stream::once(futures::future::err(e.to_string())).right_stream(),
+            })
+            .flatten_stream()
+            .boxed()
     }
 }
 
@@ -1,14 +1,11 @@
-use ax_types::{
-    service::{SubscribeMonotonicRequest, SubscribeMonotonicResponse},
-    AppId,
-};
+use ax_types::service::{SubscribeMonotonicRequest, SubscribeMonotonicResponse};
 use futures::{
     stream::{self, BoxStream},
     FutureExt, StreamExt,
 };
 use wsrpc::Service;
 
-use crate::api::events::service::EventService;
+use crate::api::{bearer_token::BearerToken, events::service::EventService};
 
 pub struct SubscribeMonotonic {
     event_service: EventService,
@@ -18,11 +15,11 @@ impl Service for SubscribeMonotonic {
     type Req = SubscribeMonotonicRequest;
     type Resp = SubscribeMonotonicResponse;
     type Error = String;
-    type Ctx = AppId;
+    type Ctx = BearerToken;
 
-    fn serve(&self, app_id: AppId, req: Self::Req) -> BoxStream<'static, Result<Self::Resp, Self::Error>> {
-        let service = self.event_service.clone();
-        (async move { service.subscribe_monotonic(app_id, req).await })
+    fn serve(&self, token: BearerToken, req: Self::Req) -> BoxStream<'static, Result<Self::Resp, Self::Error>> {
+        let service = self.event_service.with_scopes(&token.scopes);
+        (async move { service?.subscribe_monotonic(token.app_id, req).await })
             .map(|x| match x {
                 Ok(stream) => stream.map(Ok).left_stream(),
                 Err(e) => stream::once(futures::future::err(e.to_string())).right_stream(),
@@ -5,7 +5,7 @@ use self::ipfs::{extract_query_from_host, extract_query_from_path, IpfsQuery};
 use crate::{
     api::{
         ans::{ActyxName, ActyxNamingService, PersistenceLevel},
-        filters::{authenticate, header_or_query_token},
+        filters::{authenticate_scoped, header_or_query_token},
         rejections::ApiError,
         NodeInfo,
     },
@@ -417,7 +417,7 @@ fn add(store: BanyanStore, node_info: NodeInfo) -> impl Filter<Extract = (impl R
 }
 
 fn authorize(node_info: NodeInfo) -> impl Filter<Extract = (AppId,), Error = Rejection> + Clone {
-    authenticate(node_info, header_or_query_token())
+    authenticate_scoped(node_info, header_or_query_token(), "files", |scopes| scopes.files)
 }
 
 fn mime(name: impl AsRef<Path>) -> String {
@@ -77,6 +77,7 @@ impl FilePinner {
                     app_id!("com.actyx"),
                     SubscribeRequest {
                         lower_bound: None,
+                        invalid_payloads: None,
                         query: "FROM isLocal & appId(com.actyx) & 'files:pinned'"
                             .parse()
                             .expect("valid syntax"),
@@ -213,6 +214,9 @@ async fn evaluate(event_svc: &EventService, ipfs: &Ipfs, app_id: &AppId, query:
                 upper_bound: None,
                 query,
                 order: Order::Desc,
+                explain: false,
+                page_size: None,
+                cursor: None,
             },
         )
         .await?
@@ -264,6 +268,8 @@ async fn publish_update(
                         query,
                     })?,
                 }],
+                idempotency_key: None,
+                precondition: None,
             },
         )
         .await?;
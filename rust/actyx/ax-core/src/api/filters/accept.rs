@@ -3,16 +3,34 @@ use warp::{header, reject, Filter, Rejection};
 
 use crate::api::rejections::ApiError;
 
+/// The media ranges listed in an `Accept` header without their parameters, most preferred first:
+/// sorted by descending quality, then in the given order, leaving out those with quality zero.
+fn media_ranges(accept: &str) -> Vec<String> {
+    let mut ranges = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut params = range.split(';').map(|s| s.trim().to_lowercase());
+            let media_range = params.next().filter(|mt| !mt.is_empty())?;
+            let quality = params
+                .find_map(|p| p.strip_prefix("q=").map(|q| q.trim().parse::<f32>().unwrap_or(1.0)))
+                .unwrap_or(1.0);
+            (quality > 0.0).then_some((media_range, quality))
+        })
+        .collect::<Vec<_>>();
+    // stable sort, so equally preferred ranges stay in the given order
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranges.into_iter().map(|(media_range, _)| media_range).collect()
+}
+
 pub fn accept(mime_types: &'static [&'static str]) -> impl Filter<Extract = (), Error = Rejection> + Clone {
     let mime_types_normalized: std::collections::BTreeSet<_> = mime_types.iter().map(|m| m.to_lowercase()).collect();
     header::optional("accept")
         .and_then(move |accept: Option<String>| match accept {
             Some(requested)
-                // TODO full content negotiation + q-factor weighting (preferably in warp)
-                if !requested
-                    .split(',')
-                    .map(|s| s.trim().to_lowercase())
-                    .any(|mt| mime_types_normalized.contains(&mt)) =>
+                // TODO wildcards like `application/*` (preferably in warp)
+                if !media_ranges(&requested)
+                    .iter()
+                    .any(|mt| mime_types_normalized.contains(mt)) =>
             {
                 future::err(reject::custom(ApiError::NotAcceptable {
                     requested,
@@ -34,9 +52,86 @@ pub fn accept_json() -> impl Filter<Extract = (), Error = Rejection> + Clone {
     accept(ACCEPT_JSON)
 }
 
-const ACCEPT_NDJSON: &[&str] = &["*/*", "application/x-ndjson"];
-pub fn accept_ndjson() -> impl Filter<Extract = (), Error = Rejection> + Clone {
-    accept(ACCEPT_NDJSON)
+/// Encodings for streams of API responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// Newline-delimited JSON, the default.
+    Ndjson,
+    /// Sequence of concatenated CBOR data items.
+    Cbor,
+    /// Server-sent events with JSON data.
+    Sse,
+}
+
+impl StreamFormat {
+    fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type {
+            "application/x-ndjson" => Some(Self::Ndjson),
+            "application/cbor" => Some(Self::Cbor),
+            "text/event-stream" => Some(Self::Sse),
+            _ => None,
+        }
+    }
+}
+
+/// Accept any of the given stream encodings, extracting the one listed first by the client.
+fn accept_stream(
+    mime_types: &'static [&'static str],
+) -> impl Filter<Extract = (StreamFormat,), Error = Rejection> + Clone {
+    accept(mime_types)
+        .and(header::optional("accept"))
+        .map(move |accept: Option<String>| {
+            accept
+                .iter()
+                .flat_map(|requested| media_ranges(requested))
+                .filter(|mt| mime_types.contains(&mt.as_str()))
+                .find_map(|mt| StreamFormat::from_mime_type(&mt))
+                .unwrap_or(StreamFormat::Ndjson)
+        })
+}
+
+const ACCEPT_STREAM: &[&str] = &["*/*", "application/x-ndjson", "application/cbor"];
+/// Accept NDJSON or CBOR response streams.
+pub fn accept_ndjson_or_cbor() -> impl Filter<Extract = (StreamFormat,), Error = Rejection> + Clone {
+    accept_stream(ACCEPT_STREAM)
+}
+
+const ACCEPT_EVENT_STREAM: &[&str] = &["*/*", "application/x-ndjson", "application/cbor", "text/event-stream"];
+/// Accept NDJSON, CBOR or server-sent events.
+pub fn accept_event_stream() -> impl Filter<Extract = (StreamFormat,), Error = Rejection> + Clone {
+    accept_stream(ACCEPT_EVENT_STREAM)
+}
+
+/// Extract whether the client prefers CBOR over JSON, without rejecting any other media types.
+pub fn prefer_cbor() -> impl Filter<Extract = (bool,), Error = Rejection> + Clone {
+    header::optional("accept").map(|accept: Option<String>| {
+        accept
+            .iter()
+            .flat_map(|requested| media_ranges(requested))
+            .find(|mt| {
+                matches!(
+                    mt.as_str(),
+                    "application/cbor" | "application/json" | "application/x-ndjson"
+                )
+            })
+            .map_or(false, |mt| mt == "application/cbor")
+    })
+}
+
+/// Extract whether the client accepts zstd compressed responses.
+///
+/// A quality value that cannot be parsed counts as zero, i.e. as not accepted.
+pub fn accept_zstd() -> impl Filter<Extract = (bool,), Error = Rejection> + Clone {
+    header::optional("accept-encoding").map(|accept: Option<String>| {
+        accept.iter().flat_map(|requested| requested.split(',')).any(|coding| {
+            let mut params = coding.split(';').map(|s| s.trim().to_lowercase());
+            params.next().map_or(false, |c| c == "zstd")
+                && params.all(|p| {
+                    p.strip_prefix("q=")
+                        .map_or(true, |q| q.trim().parse::<f32>().map_or(false, |q| q > 0.0))
+                })
+        })
+    })
 }
 
 #[cfg(test)]
@@ -61,7 +156,87 @@ mod test {
         check(&["application/json"], "application/json, text/plain, */*", true).await;
         check(&["application/json"], "text/plain, application/json, */*", true).await;
 
+        check(&["application/json"], "application/json; charset=utf-8", true).await;
+        check(&["application/json"], "text/plain;q=0.9, application/json;q=0.1", true).await;
+
         check(&["application/json"], "text/xml", false).await;
         check(&["application/json"], "text/xml, text/plain", false).await;
+        check(&["application/json"], "application/json;q=0", false).await;
+    }
+
+    async fn format(requested: &str) -> StreamFormat {
+        warp::test::request()
+            .header("Accept", requested)
+            .filter(&accept_event_stream())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_accept_stream() {
+        assert_eq!(format("text/event-stream").await, StreamFormat::Sse);
+        assert_eq!(
+            format("text/event-stream, application/x-ndjson").await,
+            StreamFormat::Sse
+        );
+        assert_eq!(
+            format("application/x-ndjson, text/event-stream").await,
+            StreamFormat::Ndjson
+        );
+        assert_eq!(format("application/cbor, */*").await, StreamFormat::Cbor);
+        assert_eq!(format("text/event-stream;q=1").await, StreamFormat::Sse);
+        assert_eq!(
+            format("application/x-ndjson;q=0.5, text/event-stream").await,
+            StreamFormat::Sse
+        );
+        assert_eq!(format("text/event-stream;q=0, */*").await, StreamFormat::Ndjson);
+        assert_eq!(format("*/*").await, StreamFormat::Ndjson);
+        assert_eq!(
+            warp::test::request().filter(&accept_event_stream()).await.unwrap(),
+            StreamFormat::Ndjson
+        );
+        assert!(warp::test::request()
+            .header("Accept", "text/event-stream")
+            .filter(&accept_ndjson_or_cbor())
+            .await
+            .is_err());
+    }
+
+    async fn cbor(requested: &str) -> bool {
+        warp::test::request()
+            .header("Accept", requested)
+            .filter(&prefer_cbor())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_prefer_cbor() {
+        assert!(cbor("application/cbor").await);
+        assert!(cbor("text/plain, application/cbor, application/json").await);
+        assert!(cbor("application/json;q=0.5, application/cbor").await);
+        assert!(!cbor("application/json, application/cbor").await);
+        assert!(!cbor("*/*").await);
+        assert!(!warp::test::request().filter(&prefer_cbor()).await.unwrap());
+    }
+
+    async fn zstd(requested: &str) -> bool {
+        warp::test::request()
+            .header("Accept-Encoding", requested)
+            .filter(&accept_zstd())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_accept_zstd() {
+        assert!(zstd("zstd").await);
+        assert!(zstd("gzip, ZSTD;q=0.5").await);
+        assert!(!zstd("gzip, deflate").await);
+        assert!(!zstd("zstd;q=0").await);
+        assert!(!zstd("zstd;q=high").await);
+        assert!(!zstd("zstd;q=").await);
+        assert!(zstd("zstd;q= 0.1").await);
+        assert!(!warp::test::request().filter(&accept_zstd()).await.unwrap());
     }
 }
@@ -1,9 +1,9 @@
-use ax_types::AppId;
+use ax_types::{AppId, AppScopes};
 use futures::FutureExt;
 use tracing::{debug, info};
 use warp::{reject, Filter, Rejection};
 
-use crate::api::{auth::verify_token, rejections::ApiError, NodeInfo, Token};
+use crate::api::{auth::verify_token, bearer_token::BearerToken, rejections::ApiError, NodeInfo, Token};
 
 /// Tries to extract the value given to the `access_token` query parameter.
 pub fn query_token() -> impl Filter<Extract = (Token,), Error = Rejection> + Clone {
@@ -106,11 +106,18 @@ pub(crate) fn authenticate(
     node_info: NodeInfo,
     token: impl Filter<Extract = (Token,), Error = Rejection> + Clone,
 ) -> impl Filter<Extract = (AppId,), Error = Rejection> + Clone {
+    authenticate_token(node_info, token).map(|bearer_token: BearerToken| bearer_token.app_id)
+}
+
+/// Like [`authenticate`], but yields the whole token so that its scopes can be enforced.
+pub(crate) fn authenticate_token(
+    node_info: NodeInfo,
+    token: impl Filter<Extract = (Token,), Error = Rejection> + Clone,
+) -> impl Filter<Extract = (BearerToken,), Error = Rejection> + Clone {
     token.and_then(move |t: Token| {
         let auth_args = node_info.clone();
         async move {
             let res = verify_token(auth_args, t)
-                .map(|bearer_token| bearer_token.app_id)
                 // TODO: add necessary checks for the flow from the PRD
                 .map_err(warp::reject::custom);
             if res.is_err() {
@@ -123,14 +130,32 @@ pub(crate) fn authenticate(
     })
 }
 
+/// Authenticate the app and reject it unless its token grants the access checked by `allowed`.
+pub(crate) fn authenticate_scoped(
+    node_info: NodeInfo,
+    token: impl Filter<Extract = (Token,), Error = Rejection> + Clone,
+    api: &'static str,
+    allowed: fn(&AppScopes) -> bool,
+) -> impl Filter<Extract = (AppId,), Error = Rejection> + Clone {
+    authenticate_token(node_info, token).and_then(move |bearer_token: BearerToken| async move {
+        if allowed(&bearer_token.scopes) {
+            Ok(bearer_token.app_id)
+        } else {
+            Err(reject::custom(ApiError::Forbidden {
+                cause: format!("app `{}` has no access to the {} API", bearer_token.app_id, api),
+            }))
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
-        api::{bearer_token::BearerToken, licensing::Licensing, AppMode},
+        api::{licensing::Licensing, AppMode},
         crypto::{KeyStore, PrivateKey},
     };
-    use ax_types::{app_id, types::Binary, Timestamp};
+    use ax_types::{app_id, types::Binary, AppScopes, Timestamp};
     use chrono::Utc;
     use parking_lot::RwLock;
     use std::sync::Arc;
@@ -145,6 +170,8 @@ mod tests {
             app_version: "1.0.0".into(),
             validity: validity.unwrap_or(300),
             app_mode: AppMode::Signed,
+            scopes: AppScopes::default(),
+            origin: None,
         };
         let bytes = serde_cbor::to_vec(&token).unwrap();
         let msg = store.sign(bytes, vec![key_id]).unwrap();
@@ -157,6 +184,7 @@ mod tests {
             ax_public_key: PrivateKey::generate().into(),
             licensing: Licensing::default(),
             started_at: Utc::now(),
+            revocations: Default::default(),
         };
 
         (auth_args, bearer)
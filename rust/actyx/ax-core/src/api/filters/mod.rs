@@ -1,5 +1,7 @@
 mod accept;
 mod authenticate;
 
-pub(crate) use accept::{accept_json, accept_ndjson, accept_text};
+pub(crate) use accept::{
+    accept_event_stream, accept_json, accept_ndjson_or_cbor, accept_text, accept_zstd, prefer_cbor, StreamFormat,
+};
 pub(crate) use authenticate::*;
@@ -13,9 +13,12 @@ mod rejections;
 #[cfg(test)]
 mod tests;
 
-pub use crate::api::events::service::EventService;
+pub use crate::api::{
+    auth::Revocations,
+    events::{limits::PublishLimits, schemas::PayloadSchemas, service::EventService},
+};
 use crate::{
-    api::{files::FilePinner, hyper_serve::serve_it, licensing::Licensing},
+    api::{events::cursor::CursorKey, files::FilePinner, hyper_serve::serve_it, licensing::Licensing},
     ax_panic, balanced_or,
     crypto::{KeyStoreRef, PublicKey},
     swarm::{blob_store::BlobStore, event_store_ref::EventStoreRef, BanyanStore},
@@ -44,6 +47,7 @@ pub struct NodeInfo {
     pub ax_public_key: PublicKey,
     pub licensing: Licensing,
     pub started_at: DateTime<Utc>,
+    pub revocations: Revocations,
 }
 
 impl NodeInfo {
@@ -53,6 +57,7 @@ impl NodeInfo {
         cycles: NodeCycleCount,
         licensing: Licensing,
         started_at: DateTime<Utc>,
+        revocations: Revocations,
     ) -> Self {
         Self {
             node_id,
@@ -62,6 +67,7 @@ impl NodeInfo {
             ax_public_key: PublicKey::ax_public_key(),
             licensing,
             started_at,
+            revocations,
         }
     }
 }
@@ -93,6 +99,7 @@ pub enum AppMode {
     Signed,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     node_info: NodeInfo,
     store: BanyanStore,
@@ -101,8 +108,25 @@ pub async fn run(
     bind_to: Arc<Mutex<SocketAddrHelper>>,
     snd: Sender<anyhow::Result<()>>,
     swarm_state: Reader<SwarmState>,
+    payload_schemas: PayloadSchemas,
+    publish_limits: PublishLimits,
 ) {
-    let event_service = events::service::EventService::new(event_store, node_info.node_id);
+    // cursors stay valid across restarts as long as the node keeps its key
+    let cursor_key = match node_info
+        .key_store
+        .read()
+        .derive_secret(node_info.node_id.into(), b"ax-query-cursor")
+    {
+        Ok(key) => CursorKey::new(key),
+        Err(e) => {
+            tracing::warn!("cannot derive the query cursor key, cursors expire on restart: {:#}", e);
+            CursorKey::random()
+        }
+    };
+    let event_service = events::service::EventService::new(event_store, node_info.node_id)
+        .with_payload_schemas(payload_schemas)
+        .with_publish_limits(publish_limits)
+        .with_cursor_key(cursor_key);
     let pinner = FilePinner::new(event_service.clone(), store.ipfs().clone());
     let api = routes(node_info, store, event_service, pinner, blobs, swarm_state);
     #[allow(clippy::needless_collect)]
@@ -58,6 +58,9 @@ pub enum ApiError {
     #[display(fmt = "Expired token.")]
     TokenExpired,
 
+    #[display(fmt = "Revoked token.")]
+    TokenRevoked,
+
     #[display(fmt = "Invalid token: '{}'. {} Please provide a valid bearer token.", token, msg)]
     TokenInvalid { token: String, msg: String },
 
@@ -73,6 +76,12 @@ pub enum ApiError {
     #[display(fmt = "Invalid request. {}", cause)]
     BadRequest { cause: String },
 
+    #[display(fmt = "Forbidden. {}", cause)]
+    Forbidden { cause: String },
+
+    #[display(fmt = "Conflict. {}", cause)]
+    PreconditionFailed { cause: String },
+
     #[display(fmt = "Feature `{}` is not supported on endpoint `{}`.", features, endpoint)]
     UnsupportedFeature { features: String, endpoint: String },
 
@@ -82,6 +91,9 @@ pub enum ApiError {
     #[display(fmt = "Service overloaded. {}", cause)]
     Overloaded { cause: String },
 
+    #[display(fmt = "Rate limit exceeded. {}", cause)]
+    RateLimited { cause: String },
+
     #[display(fmt = "Service shutting down. {}", cause)]
     Shutdown { cause: String },
 
@@ -127,6 +139,8 @@ impl From<ApiError> for ApiErrorResponse {
             ApiError::AppUnauthorized { .. } => (StatusCode::UNAUTHORIZED, "ERR_APP_UNAUTHORIZED"),
             ApiError::NodeUnauthorized { .. } => (StatusCode::UNAUTHORIZED, "ERR_NODE_UNAUTHORIZED"),
             ApiError::BadRequest { .. } => (StatusCode::BAD_REQUEST, "ERR_BAD_REQUEST"),
+            ApiError::Forbidden { .. } => (StatusCode::FORBIDDEN, "ERR_FORBIDDEN"),
+            ApiError::PreconditionFailed { .. } => (StatusCode::CONFLICT, "ERR_PRECONDITION_FAILED"),
             ApiError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "ERR_INTERNAL"),
             ApiError::InvalidManifest { .. } => (StatusCode::BAD_REQUEST, "ERR_MANIFEST_INVALID"),
             ApiError::MethodNotAllowed => (StatusCode::METHOD_NOT_ALLOWED, "ERR_METHOD_NOT_ALLOWED"),
@@ -135,8 +149,10 @@ impl From<ApiError> for ApiErrorResponse {
             ApiError::NotAcceptable { .. } => (StatusCode::NOT_ACCEPTABLE, "ERR_NOT_ACCEPTABLE"),
             ApiError::NotFound => (StatusCode::NOT_FOUND, "ERR_NOT_FOUND"),
             ApiError::Overloaded { .. } => (StatusCode::SERVICE_UNAVAILABLE, "ERR_SERVICE_OVERLOADED"),
+            ApiError::RateLimited { .. } => (StatusCode::TOO_MANY_REQUESTS, "ERR_RATE_LIMITED"),
             ApiError::Shutdown { .. } => (StatusCode::SERVICE_UNAVAILABLE, "ERR_SHUTTING_DOWN"),
             ApiError::TokenExpired => (StatusCode::UNAUTHORIZED, "ERR_TOKEN_EXPIRED"),
+            ApiError::TokenRevoked => (StatusCode::UNAUTHORIZED, "ERR_TOKEN_REVOKED"),
             ApiError::TokenInvalid { .. } => (StatusCode::BAD_REQUEST, "ERR_TOKEN_INVALID"),
             ApiError::TokenUnauthorized => (StatusCode::UNAUTHORIZED, "ERR_TOKEN_UNAUTHORIZED"),
             ApiError::UnsupportedAuthType { .. } => (StatusCode::UNAUTHORIZED, "ERR_UNSUPPORTED_AUTH_TYPE"),
@@ -11,15 +11,16 @@ use crate::{
 use ax_types::{
     app_id,
     service::{AuthenticationResponse, SwarmState},
-    NodeId,
+    AppScopes, NodeId,
 };
 use bytes::Bytes;
 use chrono::Utc;
 use futures::FutureExt;
-use hyper::Response;
+use hyper::{body::HttpBody, Response};
 use parking_lot::lock_api::RwLock;
 use serde_json::json;
-use tokio::{runtime::Handle, sync::mpsc};
+use std::time::Duration;
+use tokio::{runtime::Handle, sync::mpsc, time::timeout};
 use warp::{any, reject, test, Filter, Rejection, Reply};
 
 const UNAUTHORIZED_TOKEN: &str = "AAAAWaZnY3JlYXRlZBsABb3ls11m8mZhcHBfaWRyY29tLmV4YW1wbGUubXktYXBwZmN5Y2xlcwBndmVyc2lvbmUxLjAuMGh2YWxpZGl0eRkBLGlldmFsX21vZGX1AQv+4BIlF/5qZFHJ7xJflyew/CnF38qdV1BZr/ge8i0mPCFqXjnrZwqACX5unUO2mJPsXruWYKIgXyUQHwKwQpzXceNzo6jcLZxvAKYA05EFDnFvPIRfoso+gBJinSWpDQ==";
@@ -55,6 +56,7 @@ async fn test_routes() -> (
         ax_public_key: PrivateKey::generate().into(),
         licensing: Licensing::default(),
         started_at: Utc::now(),
+        revocations: Default::default(),
     };
     let event_store = {
         let store2 = store.clone();
@@ -84,6 +86,7 @@ async fn test_routes() -> (
         app_id!("com.example.my-app"),
         "1.0.0".into(),
         AppMode::Signed,
+        AppScopes::default(),
     )
     .unwrap();
     (route, token.to_string(), node_key, key_store)
@@ -122,6 +125,59 @@ async fn authenticate() {
     assert_eq!(resp.status(), http::StatusCode::OK);
 }
 
+#[tokio::test]
+async fn scopes() {
+    let (route, ..) = test_routes().await;
+    let auth = |scopes: serde_json::Value| {
+        test::request().path("/api/v2/auth").method("POST").json(&json!({
+          "appId": "com.example.my-app","displayName": "My Example App","version": "1.0.0","scopes": scopes
+        }))
+    };
+
+    let resp = auth(json!({"read": "'a' |"})).reply(&route).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    let resp = auth(json!({"read": "'a'", "write": ["a"], "blob": false}))
+        .reply(&route)
+        .await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let AuthenticationResponse { token, .. } = serde_json::from_slice(resp.body()).unwrap();
+
+    let publish = |tags: serde_json::Value| {
+        test::request()
+            .path("/api/v2/events/publish")
+            .method("POST")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({"data": [{"tags": tags, "payload": 42}]}))
+    };
+    let resp = publish(json!(["a"])).reply(&route).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let resp = publish(json!(["a", "b"])).reply(&route).await;
+    assert_err_response(
+        resp,
+        http::StatusCode::FORBIDDEN,
+        json!({
+          "code": "ERR_FORBIDDEN",
+          "message": "Forbidden. app `com.example.my-app` may not publish events tagged 'b'"
+        }),
+    );
+
+    let resp = test::request()
+        .path("/api/v2/blob/-/x")
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Accept", "*/*")
+        .reply(&route)
+        .await;
+    assert_err_response(
+        resp,
+        http::StatusCode::FORBIDDEN,
+        json!({
+          "code": "ERR_FORBIDDEN",
+          "message": "Forbidden. app `com.example.my-app` has no access to the blob API"
+        }),
+    );
+}
+
 #[tokio::test]
 async fn node_id() {
     let (route, _, node_key, ..) = test_routes().await;
@@ -189,6 +245,106 @@ async fn ok_accept_ndjson() {
     assert_eq!(resp.headers()["content-type"], "application/x-ndjson");
 }
 
+#[tokio::test]
+async fn ok_accept_cbor_zstd() {
+    let (route, token, ..) = test_routes().await;
+    let resp = test::request()
+        .path("/api/v2/events/query")
+        .method("POST")
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Accept", "application/cbor")
+        .header("Accept-Encoding", "gzip, zstd")
+        .json(&json!({"offsets": {}, "upperBound": {}, "query": "FROM 'a'", "order": "asc"}))
+        .reply(&route)
+        .await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(resp.headers()["content-type"], "application/cbor");
+    assert_eq!(resp.headers()["content-encoding"], "zstd");
+
+    let body = zstd::decode_all(&**resp.body()).unwrap();
+    let responses = serde_cbor::Deserializer::from_slice(&body)
+        .into_iter::<serde_json::Value>()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(responses.last().unwrap()["type"], json!("offsets"));
+}
+
+/// Read server-sent events up to the first `offsets` message, returning the payloads of the
+/// events before it and the last event ID.
+async fn read_sse(body: &mut hyper::Body) -> (Vec<serde_json::Value>, Option<String>) {
+    let mut buf = String::new();
+    let mut payloads = vec![];
+    let mut last_id = None;
+    loop {
+        while let Some(end) = buf.find("\n\n") {
+            let frame = buf[..end].to_owned();
+            buf.drain(..end + 2);
+            let mut data = None;
+            for line in frame.lines() {
+                if let Some(id) = line.strip_prefix("id:") {
+                    last_id = Some(id.trim().to_owned());
+                }
+                if let Some(d) = line.strip_prefix("data:") {
+                    data = Some(serde_json::from_str::<serde_json::Value>(d.trim()).unwrap());
+                }
+            }
+            match data {
+                Some(d) if d["type"] == "offsets" => return (payloads, last_id),
+                Some(d) if d["type"] == "event" => payloads.push(d["payload"].clone()),
+                _ => {}
+            }
+        }
+        let chunk = body.data().await.expect("subscription ended").unwrap();
+        buf.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+}
+
+#[tokio::test]
+async fn ok_sse_resume() {
+    let (route, token, ..) = test_routes().await;
+    let route = &route;
+    let publish = |payload: u64| {
+        test::request()
+            .path("/api/v2/events/publish")
+            .method("POST")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({"data": [{"tags": ["a"], "payload": payload}]}))
+            .reply(route)
+    };
+    let subscribe = |last_event_id: Option<&str>| {
+        let mut request = test::request()
+            .path("/api/v2/events/subscribe")
+            .method("POST")
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Accept", "application/x-ndjson;q=0.5, text/event-stream;q=1")
+            .json(&json!({"query": "FROM 'a'"}));
+        if let Some(id) = last_event_id {
+            request = request.header("Last-Event-ID", id);
+        }
+        async move { request.filter(route).await.unwrap().into_response() }
+    };
+
+    assert_eq!(publish(1).await.status(), http::StatusCode::OK);
+    assert_eq!(publish(2).await.status(), http::StatusCode::OK);
+
+    let resp = subscribe(None).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(resp.headers()["content-type"], "text/event-stream");
+    let mut body = resp.into_body();
+    let (payloads, id) = timeout(Duration::from_secs(5), read_sse(&mut body)).await.unwrap();
+    assert_eq!(payloads, vec![json!(1), json!(2)]);
+    let id = id.expect("no event ID");
+    drop(body);
+
+    // a reconnecting client only receives what it has not seen yet
+    assert_eq!(publish(3).await.status(), http::StatusCode::OK);
+    let resp = subscribe(Some(&id)).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let mut body = resp.into_body();
+    let (payloads, _) = timeout(Duration::from_secs(5), read_sse(&mut body)).await.unwrap();
+    assert_eq!(payloads, vec![json!(3)]);
+}
+
 #[tokio::test]
 async fn ok_accept_wildcard() {
     let (route, token, ..) = test_routes().await;
@@ -543,6 +699,23 @@ async fn bad_request_invalid_expression() {
     );
 }
 
+#[tokio::test]
+async fn bad_request_invalid_last_event_id() {
+    let (route, token, ..) = test_routes().await;
+    let resp = test::request()
+        .path("/api/v2/events/subscribe")
+        .method("POST")
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Accept", "text/event-stream")
+        .header("Last-Event-ID", "42")
+        .json(&json!({"query": "FROM 'a'"}))
+        .reply(&route)
+        .await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let js = serde_json::from_slice::<serde_json::Value>(resp.body()).unwrap();
+    assert_eq!(js["code"], "ERR_BAD_REQUEST");
+}
+
 #[tokio::test]
 async fn bad_request_unknown_stream() {
     let (route, token, ..) = test_routes().await;
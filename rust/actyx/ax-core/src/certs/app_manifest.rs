@@ -2,7 +2,7 @@ use crate::{
     certs::{developer_certificate::ManifestDeveloperCertificate, signature::Signature},
     crypto::{PrivateKey, PublicKey},
 };
-use ax_types::{AppId, AppManifest};
+use ax_types::{AppId, AppManifest, AppScopes};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
@@ -11,6 +11,9 @@ pub struct AppManifestSignatureProps {
     pub app_id: AppId,
     pub display_name: String,
     pub version: String,
+    // left out when absent so that signatures of manifests without scopes stay valid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<AppScopes>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
@@ -101,17 +104,21 @@ pub mod app_manifest_signer {
             app_id: manifest.app_id(),
             display_name: manifest.display_name().into(),
             version: manifest.version().into(),
+            scopes: manifest.declared_scopes().cloned(),
         };
         let dev_signature = Signature::new(&hash_input, dev_privkey)?;
         let manifest_signature = AppManifestSignature::new(dev_signature, dev_cert);
         let manifest_signature_string: String = manifest_signature.try_into()?;
-        let manifest: AppManifest = AppManifest::signed(
+        let signed = AppManifest::signed(
             manifest.app_id(),
             manifest.display_name().into(),
             manifest.version().into(),
             manifest_signature_string,
         );
-        Ok(manifest)
+        Ok(match manifest.declared_scopes() {
+            Some(scopes) => signed.with_scopes(scopes.clone()),
+            None => signed,
+        })
     }
 
     pub fn validate(manifest: &AppManifest, ax_public_key: &PublicKey) -> anyhow::Result<()> {
@@ -130,6 +137,7 @@ pub mod app_manifest_signer {
                 app_id,
                 display_name: String::from(manifest.display_name()),
                 version: String::from(manifest.version()),
+                scopes: manifest.declared_scopes().cloned(),
             };
             signature
                 .dev_signature
@@ -149,9 +157,11 @@ mod tests {
     use crate::certs::{
         developer_certificate::{DeveloperCertificateInput, ManifestDeveloperCertificate},
         signature::Signature,
+        AppDomain,
     };
+    use ax_types::{app_id, tags};
 
-    use super::{app_manifest_signer, AppManifest, AppManifestSignature};
+    use super::{app_manifest_signer, AppManifest, AppManifestSignature, AppScopes};
 
     struct TestFixture {
         ax_public_key: PublicKey,
@@ -210,6 +220,48 @@ mod tests {
         });
     }
 
+    #[test]
+    fn should_fail_validation_for_tampered_scopes() {
+        let ax_private_key = PrivateKey::generate();
+        let dev_private_key = PrivateKey::generate();
+        let dev_cert = ManifestDeveloperCertificate::new(
+            DeveloperCertificateInput::new(
+                dev_private_key.into(),
+                vec![AppDomain::from_str("com.actyx.*").unwrap()],
+            ),
+            ax_private_key,
+        )
+        .unwrap();
+        let manifest = AppManifest::signed(
+            app_id!("com.actyx.test-app"),
+            "display name".into(),
+            "version 0".into(),
+            String::new(),
+        )
+        .with_scopes(AppScopes {
+            read: Some("'a'".into()),
+            write: Some(tags!("a")),
+            files: false,
+            blob: false,
+        });
+        let signed = app_manifest_signer::make_signed(&manifest, dev_private_key, dev_cert).unwrap();
+        assert_eq!(signed.declared_scopes(), manifest.declared_scopes());
+        app_manifest_signer::validate(&signed, &ax_private_key.into()).unwrap();
+
+        let mut json = serde_json::to_value(&signed).unwrap();
+        json["scopes"]["blob"] = true.into();
+        let tampered = serde_json::from_value::<AppManifest>(json.clone()).unwrap();
+        let result = app_manifest_signer::validate(&tampered, &ax_private_key.into()).unwrap_err();
+        assert_eq!(
+            result.to_string(),
+            "Failed to validate app manifest. Invalid signature for provided input."
+        );
+
+        json.as_object_mut().unwrap().remove("scopes");
+        let stripped = serde_json::from_value::<AppManifest>(json).unwrap();
+        assert!(app_manifest_signer::validate(&stripped, &ax_private_key.into()).is_err());
+    }
+
     #[test]
     fn test_app_manifest_signature_version_is_0() {
         let private = PrivateKey::generate();
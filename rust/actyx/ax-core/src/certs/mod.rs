@@ -47,6 +47,7 @@ mod tests {
                     app_id: app_id!("my.examples.test-app"),
                     display_name: "display name".to_owned(),
                     version: "v0.0.1".to_owned(),
+                    scopes: None,
                 },
                 dev_private_key(),
             )
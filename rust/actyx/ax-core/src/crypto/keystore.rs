@@ -1,4 +1,4 @@
-//! A key store that can sign/verify (and later also encrypt/decrypt)
+//! A key store that can sign/verify and wrap keys for other key holders
 //!
 //! The store owns the cryptographic material and ensures proper zeroing before
 //! the memory is released.
@@ -8,9 +8,9 @@
 //!  - serialize an app ID and the current time, sign it, and base64 it; to be
 //!    given to apps such that they can present it as a bearer token to our APIs
 //!  - sign the root of an event stream in IPFS
-//!  - encrypt Salsa20 stream cipher keys so that they can be stored in IPFS such
-//!    that multiple other nodes can decrypt an event stream (given possession of
-//!    the private key for which the Salsa20 key was encrypted)
+//!  - encrypt the stream cipher keys of event streams so that they can be distributed
+//!    to multiple other nodes that can then decrypt an event stream (given possession
+//!    of the private key for which the stream key was encrypted)
 
 use crate::crypto::{pair::KeyPair, private::PrivateKey, public::PublicKey, signature::SignedMessage};
 use anyhow::{anyhow, bail, Result};
@@ -19,9 +19,14 @@ use chacha20poly1305::{
     aead::{AeadInPlace, NewAead},
     XChaCha20Poly1305,
 };
+use curve25519_dalek::{
+    constants::X25519_BASEPOINT, edwards::CompressedEdwardsY, montgomery::MontgomeryPoint, scalar::Scalar,
+};
+use hkdf::Hkdf;
 use parking_lot::RwLock;
 use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
     collections::{BTreeMap, BTreeSet},
     convert::{Into, TryFrom},
@@ -199,6 +204,18 @@ impl KeyStore {
         Ok(())
     }
 
+    /// Derive a symmetric key for the given purpose from the private key of `key`
+    ///
+    /// The private key does not leave the store; different purposes yield unrelated keys.
+    pub fn derive_secret(&self, key: PublicKey, purpose: &[u8]) -> Result<[u8; 32]> {
+        let private = self.pairs.get(&key).ok_or_else(|| anyhow!("key {} not found", key))?;
+        let mut secret = [0u8; 32];
+        Hkdf::<sha2_10::Sha256>::new(None, &private.to_bytes())
+            .expand(purpose, &mut secret)
+            .map_err(|e| anyhow!("cannot derive secret: {}", e))?;
+        Ok(secret)
+    }
+
     pub fn get_pair(&self, public: PublicKey) -> Option<KeyPair> {
         self.pairs.get(&public).map(|private| KeyPair {
             public,
@@ -218,11 +235,71 @@ impl KeyStore {
         self.pairs.keys().chain(self.publics.iter()).copied().collect()
     }
 
+    /// Encrypt a symmetric key such that it can only be decrypted with the private key of `recipient`
+    ///
+    /// The Ed25519 key of the recipient is converted to its X25519 form and used for an ephemeral
+    /// Diffie-Hellman key agreement, the resulting secret encrypts `key` using XChaCha20Poly1305.
+    /// This does not require any key from this store.
+    pub fn wrap_key(key: &[u8], recipient: PublicKey) -> Result<Vec<u8>> {
+        let mut ephemeral = [0u8; 64];
+        OsRng.fill_bytes(&mut ephemeral);
+        let ephemeral = Scalar::from_bytes_mod_order_wide(&ephemeral);
+        let ephemeral_public = X25519_BASEPOINT * ephemeral;
+        let cipher = Self::wrapping_cipher(ephemeral_public, recipient, x25519_public(recipient)? * ephemeral)?;
+
+        let mut out = Vec::with_capacity(1 + 32 + 24 + key.len() + 16);
+        out.push(Self::WRAP_VERSION_1);
+        out.extend_from_slice(ephemeral_public.as_bytes());
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+        out.extend_from_slice(&nonce);
+        let mut bytes = key.to_vec();
+        cipher.encrypt_in_place((&nonce[..]).into(), recipient.as_ref(), &mut bytes)?;
+        out.extend_from_slice(&bytes);
+        Ok(out)
+    }
+
+    /// Decrypt a key previously encrypted with [`wrap_key`](#method.wrap_key) for the given key pair
+    pub fn unwrap_key(&self, wrapped: &[u8], key: PublicKey) -> Result<Vec<u8>> {
+        let private = self.pairs.get(&key).ok_or_else(|| anyhow!("key {} not found", key))?;
+        match wrapped.first() {
+            Some(&Self::WRAP_VERSION_1) if wrapped.len() >= 1 + 32 + 24 + 16 => {
+                let mut ephemeral_public = [0u8; 32];
+                ephemeral_public.copy_from_slice(&wrapped[1..33]);
+                let ephemeral_public = MontgomeryPoint(ephemeral_public);
+                let cipher = Self::wrapping_cipher(ephemeral_public, key, ephemeral_public * x25519_secret(private))?;
+                let mut bytes = wrapped[57..].to_vec();
+                cipher.decrypt_in_place((&wrapped[33..57]).into(), key.as_ref(), &mut bytes)?;
+                Ok(bytes)
+            }
+            Some(&Self::WRAP_VERSION_1) => bail!("wrapped key is too short"),
+            Some(v) => Err(UnknownVersion(*v).into()),
+            None => bail!("wrapped key is empty"),
+        }
+    }
+
+    fn wrapping_cipher(
+        ephemeral_public: MontgomeryPoint,
+        recipient: PublicKey,
+        shared: MontgomeryPoint,
+    ) -> Result<XChaCha20Poly1305> {
+        if shared.as_bytes() == &[0u8; 32] {
+            bail!("invalid key agreement with {}", recipient);
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(b"ax-key-wrap");
+        hasher.update(ephemeral_public.as_bytes());
+        hasher.update(recipient.as_ref());
+        hasher.update(shared.as_bytes());
+        Ok(XChaCha20Poly1305::new(&hasher.finalize()))
+    }
+
     // dumps are obfuscated with this key (this does not provide much security since the key
     // can be extracted from AX binaries without much hassle, but it does make it a bit
     // less obvious to prying eyes)
     pub(crate) const DUMP_KEY: &'static [u8; 32] = b"uqTmyHA4*G!KQQ@77QMu_xhTg@!o*DnP";
     const VERSION_1: u8 = 1;
+    const WRAP_VERSION_1: u8 = 1;
 
     /// Write the state of this store into the given writer
     pub fn dump(&self, mut dst: impl Write) -> Result<()> {
@@ -268,6 +345,22 @@ impl KeyStore {
     }
 }
 
+/// The X25519 form of an Ed25519 public key
+fn x25519_public(key: PublicKey) -> Result<MontgomeryPoint> {
+    CompressedEdwardsY(key.0)
+        .decompress()
+        .map(|point| point.to_montgomery())
+        .ok_or_else(|| anyhow!("invalid public key {}", key))
+}
+
+/// The X25519 form of an Ed25519 private key, i.e. the clamped scalar derived from it
+fn x25519_secret(key: &PrivateKey) -> Scalar {
+    let expanded = ed25519_dalek::ExpandedSecretKey::from(&key.to_ed25519()).to_bytes();
+    let mut bits = [0u8; 32];
+    bits.copy_from_slice(&expanded[..32]);
+    Scalar::from_bits(bits)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -320,6 +413,20 @@ mod tests {
             .starts_with("invalid signature length"));
     }
 
+    #[test]
+    fn must_derive_secrets() {
+        let mut store = KeyStore::default();
+        let me = store.generate_key_pair().unwrap();
+        let other = store.generate_key_pair().unwrap();
+        let secret = store.derive_secret(me, b"a").unwrap();
+        assert_eq!(store.derive_secret(me, b"a").unwrap(), secret);
+        assert_ne!(store.derive_secret(me, b"b").unwrap(), secret);
+        assert_ne!(store.derive_secret(other, b"a").unwrap(), secret);
+
+        let unknown = KeyStore::default().generate_key_pair().unwrap();
+        assert!(store.derive_secret(unknown, b"a").is_err());
+    }
+
     #[test]
     fn must_dump_and_restore() {
         let mut store = KeyStore::default();
@@ -335,6 +442,29 @@ mod tests {
         assert_eq!(store2, store);
     }
 
+    #[test]
+    fn must_wrap_and_unwrap_keys() {
+        let mut store = KeyStore::default();
+        let me = store.generate_key_pair().unwrap();
+        let other = store.generate_key_pair().unwrap();
+        let key = [42u8; 64];
+
+        let wrapped = KeyStore::wrap_key(&key, me).unwrap();
+        assert_eq!(wrapped.len(), 1 + 32 + 24 + 64 + 16);
+        assert_ne!(wrapped, KeyStore::wrap_key(&key, me).unwrap());
+        assert_eq!(store.unwrap_key(&wrapped, me).unwrap(), key);
+
+        store.unwrap_key(&wrapped, other).unwrap_err();
+        let mut broken = wrapped.clone();
+        broken[40] ^= 1;
+        store.unwrap_key(&broken, me).unwrap_err();
+        assert!(KeyStore::default()
+            .unwrap_key(&wrapped, me)
+            .unwrap_err()
+            .to_string()
+            .starts_with("key"));
+    }
+
     #[test]
     fn must_create_an_empty_keystore() {
         KeyStore::restore_or_empty("/tmp/doesntexist").unwrap();
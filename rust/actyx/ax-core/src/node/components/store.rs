@@ -1,15 +1,18 @@
 use super::{Component, ComponentRequest};
 use crate::{
-    api::{licensing::Licensing, NodeInfo},
+    api::{licensing::Licensing, NodeInfo, PayloadSchemas, PublishLimits, Revocations},
     crypto::KeyStoreRef,
     node::{node_settings::Settings, BindTo},
     swarm::{
         blob_store::BlobStore,
         event_store_ref::{EventStoreHandler, EventStoreRef, EventStoreRequest},
-        BanyanStore, DbPath, EphemeralEventsConfig, EventRoute, GossipMessage, Ipfs, SwarmConfig,
+        ArchiveConfig, BanyanStore, DbPath, EphemeralEventsConfig, EventRoute, GossipMessage, Ipfs, StreamKeys,
+        SwarmConfig,
     },
     util::{
-        formats::{Connection, Failure, NodeCycleCount, Peer, PeerInfo, PingStats},
+        formats::{
+            Connection, Failure, NodeCycleCount, NotReplicatedStream, Peer, PeerInfo, PingStats, TokensRevokeTarget,
+        },
         variable::Reader,
         SocketAddrHelper,
     },
@@ -24,6 +27,7 @@ use ipfs_embed::{Direction, PeerId};
 use libp2p::{multiaddr::Protocol, Multiaddr};
 use parking_lot::Mutex;
 use std::{
+    collections::BTreeMap,
     convert::TryInto,
     path::PathBuf,
     sync::{
@@ -39,6 +43,7 @@ pub(crate) enum StoreRequest {
     NodesInspect(oneshot::Sender<Result<InspectResponse>>),
     EventsV2(EventStoreRequest),
     ActiveTopic(oneshot::Sender<String>),
+    RevokeTokens(TokensRevokeTarget, oneshot::Sender<Result<()>>),
 }
 
 impl std::fmt::Debug for StoreRequest {
@@ -50,6 +55,7 @@ impl std::fmt::Debug for StoreRequest {
                 f.debug_tuple("EventsV2").field(&req.as_str()).finish()
             }
             Self::ActiveTopic(_) => f.debug_tuple("ActiveTopic").finish(),
+            Self::RevokeTokens(target, _) => f.debug_tuple("RevokeTokens").field(target).finish(),
         }
     }
 }
@@ -61,6 +67,8 @@ pub(crate) struct InspectResponse {
     pub announce_addrs: Vec<String>,
     pub connections: Vec<Connection>,
     pub known_peers: Vec<Peer>,
+    pub replica: bool,
+    pub not_replicated_streams: Vec<NotReplicatedStream>,
 }
 
 pub(crate) type StoreTx = Sender<ComponentRequest<StoreRequest>>;
@@ -70,6 +78,13 @@ pub(crate) type StoreTx = Sender<ComponentRequest<StoreRequest>>;
 pub(crate) struct StoreConfig {
     swarm_config: SwarmConfig,
     licensing: Licensing,
+    /// JSON Schemas for event payloads by tag, compiled when starting the API
+    payload_schemas: BTreeMap<String, serde_json::Value>,
+    reject_invalid_payloads: bool,
+    max_payload_size: u64,
+    max_events_per_request: u64,
+    publish_rate: u64,
+    publish_burst: u64,
 }
 
 fn without_peer(addr: &Multiaddr) -> String {
@@ -105,6 +120,17 @@ fn connections(ipfs: &Ipfs) -> Vec<Connection> {
         .collect()
 }
 
+fn not_replicated_streams(store: &BanyanStore) -> Vec<NotReplicatedStream> {
+    store
+        .not_replicated_streams()
+        .into_iter()
+        .map(|(stream_id, name)| NotReplicatedStream {
+            stream_id: stream_id.to_string(),
+            name,
+        })
+        .collect()
+}
+
 fn known_peers(ipfs: &Ipfs) -> Vec<Peer> {
     ipfs.peers()
         .into_iter()
@@ -173,6 +199,8 @@ impl Component<StoreRequest, StoreConfig> for Store {
                         announce_addrs: announce_addrs(ipfs),
                         connections: connections(ipfs),
                         known_peers: known_peers(ipfs),
+                        replica: store.is_replica(),
+                        not_replicated_streams: not_replicated_streams(store),
                     }));
                 } else {
                     let _ = tx.send(Err(anyhow::anyhow!("Store not running")));
@@ -187,6 +215,16 @@ impl Component<StoreRequest, StoreConfig> for Store {
                 let state = self.state.as_ref().expect("Internal store state should be valid.");
                 let _ = tx.send(state.store.get_topic());
             }
+            StoreRequest::RevokeTokens(target, tx) => {
+                let result = match target {
+                    TokensRevokeTarget::Token(token) => self.revocations.revoke_token(&token),
+                    TokensRevokeTarget::App(app_id) => {
+                        self.revocations.revoke_app(app_id);
+                        Ok(())
+                    }
+                };
+                let _ = tx.send(result);
+            }
         }
         Ok(())
     }
@@ -213,6 +251,19 @@ impl Component<StoreRequest, StoreConfig> for Store {
                 self.node_cycle_count,
                 cfg.licensing.clone(),
                 self.started_at,
+                self.revocations.clone(),
+            );
+            // settings updates are validated, but settings stored by older versions may still be invalid
+            let payload_schemas = PayloadSchemas::new(&cfg.payload_schemas, cfg.reject_invalid_payloads)
+                .unwrap_or_else(|err| {
+                    tracing::error!("ignoring payload schemas: {:#}", err);
+                    PayloadSchemas::default()
+                });
+            let publish_limits = PublishLimits::new(
+                cfg.max_payload_size as usize,
+                cfg.max_events_per_request as usize,
+                cfg.publish_rate as f64,
+                cfg.publish_burst,
             );
             // client creation is setting up some tokio timers and therefore
             // needs to be called with a tokio runtime
@@ -231,7 +282,18 @@ impl Component<StoreRequest, StoreConfig> for Store {
                 let store = BanyanStore::new(swarm_config, swarm_observer).await?;
                 store.spawn_task(
                     "api".to_owned(),
-                    crate::api::run(node_info, store.clone(), event_store, blobs, bind_api, snd, swarm_state).boxed(),
+                    crate::api::run(
+                        node_info,
+                        store.clone(),
+                        event_store,
+                        blobs,
+                        bind_api,
+                        snd,
+                        swarm_state,
+                        payload_schemas,
+                        publish_limits,
+                    )
+                    .boxed(),
                 );
                 Ok::<BanyanStore, anyhow::Error>(store)
             })?;
@@ -263,7 +325,7 @@ impl Component<StoreRequest, StoreConfig> for Store {
         let db_path = self.working_dir.join(format!("{}.sqlite", topic));
         let index_store = Some(self.working_dir.join(format!("{}-index", topic)));
         let blob_store = Some(self.working_dir.join(format!("{}-blobs", topic)));
-        let read_only = s.api.events.read_only;
+        let read_only = s.api.events.read_only || s.swarm.replica;
 
         let event_routes = s
             .event_routing
@@ -271,7 +333,41 @@ impl Component<StoreRequest, StoreConfig> for Store {
             .into_iter()
             .map(|e| EventRoute::new(e.from, e.into))
             .collect();
-        let ephemeral_event_config = EphemeralEventsConfig::from(s.event_routing.streams);
+        let mut stream_keys = StreamKeys::default();
+        for (name, stream) in &s.event_routing.streams {
+            if stream.encryption.is_empty() {
+                continue;
+            }
+            let wrapped = match stream.encryption.get(&self.node_id.to_string()) {
+                Some(wrapped) => wrapped,
+                None => {
+                    stream_keys.insert_unavailable(name.clone());
+                    continue;
+                }
+            };
+            let inserted = StreamKeys::unwrap(&self.keystore.read(), wrapped, self.node_id)
+                .and_then(|key| stream_keys.insert(name.clone(), &key));
+            if let Err(e) = inserted {
+                tracing::error!(
+                    "cannot use the key of stream {}, the stream is unavailable: {:#}",
+                    name,
+                    e
+                );
+                stream_keys.insert_unavailable(name.clone());
+            }
+        }
+        let archive = s
+            .event_routing
+            .archive
+            .map(|archive| ArchiveConfig::new(self.working_dir.join(archive.directory), archive.max_file_size.into()));
+        let ephemeral_event_config = EphemeralEventsConfig::from(
+            s.event_routing
+                .streams
+                .into_iter()
+                .map(|(name, stream)| (name, stream.retain))
+                .collect::<BTreeMap<_, _>>(),
+        )
+        .with_archive(archive);
 
         let swarm_config = SwarmConfig {
             topic,
@@ -301,7 +397,7 @@ impl Component<StoreRequest, StoreConfig> for Store {
             block_cache_count: s.swarm.block_cache_count,
             block_cache_size: s.swarm.block_cache_size,
             block_gc_interval: Duration::from_secs(s.swarm.block_gc_interval),
-            enable_metrics: s.swarm.metrics_interval > 0,
+            enable_metrics: s.swarm.metrics_interval > 0 && !s.swarm.replica,
             metrics_interval: Duration::from_secs(s.swarm.metrics_interval),
             ping_timeout: Duration::from_secs(s.swarm.ping_timeout),
             bitswap_timeout: Duration::from_secs(s.swarm.bitswap_timeout),
@@ -309,11 +405,22 @@ impl Component<StoreRequest, StoreConfig> for Store {
             cadence_root_map: Duration::from_secs(s.swarm.gossip_interval),
             event_routes,
             ephemeral_event_config,
+            publish_key_window: Duration::from_secs(s.api.events.publish_key_window),
+            replica: s.swarm.replica,
+            accept_unsigned_roots: s.swarm.accept_unsigned_roots,
+            replicated_streams: Some(s.swarm.replicated_streams).filter(|streams| !streams.is_empty()),
+            stream_keys,
             ..SwarmConfig::basic()
         };
         Ok(StoreConfig {
             swarm_config,
             licensing: s.licensing,
+            payload_schemas: s.api.events.schemas,
+            reject_invalid_payloads: s.api.events.reject_invalid_payloads,
+            max_payload_size: s.api.events.max_payload_size,
+            max_events_per_request: s.api.events.max_events_per_request,
+            publish_rate: s.api.events.publish_rate,
+            publish_burst: s.api.events.publish_burst,
         })
     }
 }
@@ -338,6 +445,8 @@ pub(crate) struct Store {
     started_at: DateTime<Utc>,
     swarm_observer: ActoRef<(PeerId, GossipMessage)>,
     swarm_state: Reader<SwarmState>,
+    /// kept across restarts of the component, since tokens stay valid as well
+    revocations: Revocations,
 }
 
 impl Store {
@@ -369,6 +478,7 @@ impl Store {
             started_at: Utc::now(),
             swarm_observer,
             swarm_state,
+            revocations: Revocations::default(),
         })
     }
 }
@@ -13,6 +13,18 @@ use std::collections::{BTreeMap, BTreeSet};
 #[serde(rename_all = "camelCase")]
 pub struct Events {
     pub read_only: bool,
+    pub publish_key_window: u64,
+    /// JSON Schemas for event payloads, keyed by tag
+    pub schemas: BTreeMap<String, serde_json::Value>,
+    pub reject_invalid_payloads: bool,
+    /// Maximum size of an event payload in bytes, 0 for unlimited
+    pub max_payload_size: u64,
+    /// Maximum number of events per publish request, 0 for unlimited
+    pub max_events_per_request: u64,
+    /// Events per second an app may publish, 0 for unlimited
+    pub publish_rate: u64,
+    /// Events an app may publish at once before being limited to `publish_rate`
+    pub publish_burst: u64,
     #[serde(rename = "_internal")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub internal: Option<serde_json::Value>,
@@ -36,6 +48,12 @@ pub struct Swarm {
     pub gossip_interval: u64,
     pub detection_cycles_low_latency: f64,
     pub detection_cycles_high_latency: f64,
+    /// Only replicate the streams of other nodes, never create own streams
+    pub replica: bool,
+    /// Accept stream roots that are not signed by their owning node
+    pub accept_unsigned_roots: bool,
+    /// Names of the remote streams to replicate, all if empty
+    pub replicated_streams: BTreeSet<String>,
 }
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -101,10 +119,35 @@ pub struct Route {
     pub into: String,
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, Default)]
+pub struct StreamSettings {
+    #[serde(flatten)]
+    pub retain: crate::swarm::RetainConfig,
+    /// Stream key wrapped for each node that may read the stream, keyed by node ID
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub encryption: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveSettings {
+    /// Directory for the archive files, relative to the Actyx data directory unless absolute
+    pub directory: String,
+    #[serde(default = "default_archive_file_size")]
+    pub max_file_size: crate::swarm::StreamSize,
+}
+
+fn default_archive_file_size() -> crate::swarm::StreamSize {
+    crate::swarm::StreamSize::MegaBytes(100)
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, Default)]
 pub struct EventRouting {
-    pub streams: BTreeMap<String, crate::swarm::RetainConfig>,
+    pub streams: BTreeMap<String, StreamSettings>,
     pub routes: Vec<Route>,
+    /// Where to keep events before pruning removes them, not archived if absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<ArchiveSettings>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
@@ -138,6 +181,9 @@ impl Settings {
                 gossip_interval: 10,
                 detection_cycles_low_latency: 2.0,
                 detection_cycles_high_latency: 5.0,
+                replica: false,
+                accept_unsigned_roots: true,
+                replicated_streams: btreeset![],
             },
             admin: Admin {
                 display_name: "some name".into(),
@@ -149,6 +195,13 @@ impl Settings {
                 events: Events {
                     internal: None,
                     read_only: true,
+                    publish_key_window: 86400,
+                    schemas: Default::default(),
+                    reject_invalid_payloads: false,
+                    max_payload_size: 0,
+                    max_events_per_request: 0,
+                    publish_rate: 0,
+                    publish_burst: 1000,
                 },
             },
             event_routing: Default::default(),
@@ -30,7 +30,7 @@ use crate::{
             },
             events_protocol::{EventsProtocol, EventsRequest, EventsResponse},
             ActyxOSCode, ActyxOSError, ActyxOSResult, ActyxOSResultExt, NodeErrorContext, NodesInspectResponse,
-            TopicDeleteResponse, TopicLsResponse,
+            TokensRevokeResponse, TokensRevokeTarget, TopicDeleteResponse, TopicLsResponse,
         },
         version::NodeVersion,
         SocketAddrHelper,
@@ -378,6 +378,8 @@ fn inject_admin_event(state: &mut State, event: RequestReceived<AdminProtocol>)
                             admin_addrs,
                             connections: res.connections,
                             known_peers: res.known_peers,
+                            replica: res.replica,
+                            not_replicated_streams: res.not_replicated_streams,
                         }))
                     }
                     .then(move |res| async move {
@@ -435,6 +437,7 @@ fn inject_admin_event(state: &mut State, event: RequestReceived<AdminProtocol>)
             ),
             AdminRequest::TopicLs => handle_topic_ls(state, channel),
             AdminRequest::TopicDelete { name } => handle_topic_delete(state, channel, name),
+            AdminRequest::TokensRevoke { target } => handle_tokens_revoke(state, channel, target),
         };
     }
 }
@@ -512,6 +515,37 @@ fn handle_topic_delete(
     });
 }
 
+/// Handle the token revocation admin request.
+fn handle_tokens_revoke(
+    state: &mut State,
+    mut channel: mpsc::Sender<Result<AdminResponse, ActyxOSError>>,
+    target: TokensRevokeTarget,
+) {
+    let (tx, rx) = oneshot::channel();
+    let send_result = state
+        .store
+        .send(ComponentRequest::Individual(StoreRequest::RevokeTokens(target, tx)));
+
+    if let Err(error) = send_result {
+        let _ = channel.try_send(Err(ActyxOSError::from(error)));
+        return;
+    }
+
+    let node_id = state.node_id;
+    tokio::spawn(async move {
+        let response = match rx.await {
+            Ok(Ok(())) => Ok(AdminResponse::TokensRevokeResponse(TokensRevokeResponse { node_id })),
+            Ok(Err(error)) => {
+                Err(ActyxOSCode::ERR_INVALID_INPUT.with_message(format!("Cannot revoke token: {}", error)))
+            }
+            Err(error) => {
+                Err(ActyxOSCode::ERR_INTERNAL_ERROR.with_message(format!("Error waiting on channel: {}", error)))
+            }
+        };
+        let _ = channel.try_send(response);
+    });
+}
+
 /// Handle the topic listing admin request.
 fn handle_topic_ls(state: &mut State, mut channel: mpsc::Sender<Result<AdminResponse, ActyxOSError>>) {
     let (tx, rx) = oneshot::channel();
@@ -703,6 +737,8 @@ fn inject_events_event(state: &mut State, event: RequestReceived<EventsProtocol>
                                 QueryResponse::Event(ev) => EventsResponse::Event(ev),
                                 QueryResponse::Offsets(o) => EventsResponse::OffsetMap { offsets: o.offsets },
                                 QueryResponse::Diagnostic(d) => EventsResponse::Diagnostic(d),
+                                QueryResponse::Plan(p) => EventsResponse::Plan(p),
+                                QueryResponse::Cursor(c) => EventsResponse::Cursor(c),
                                 QueryResponse::FutureCompat => continue,
                             };
                             channel.feed(item).await?;
@@ -713,6 +749,18 @@ fn inject_events_event(state: &mut State, event: RequestReceived<EventsProtocol>
                         channel.feed(EventsResponse::Error { message: e.to_string() }).await?;
                     }
                 },
+                EventsRequest::QueryBatch(request) => {
+                    match events.query_batch(app_id!("com.actyx.cli"), request).await {
+                        Ok(mut resp) => {
+                            while let Some(msg) = resp.next().await {
+                                channel.feed(EventsResponse::QueryBatch(msg)).await?;
+                            }
+                        }
+                        Err(e) => {
+                            channel.feed(EventsResponse::Error { message: e.to_string() }).await?;
+                        }
+                    }
+                }
                 EventsRequest::Subscribe(request) => match events.subscribe(app_id!("com.actyx.cli"), request).await {
                     Ok(mut resp) => {
                         tracing::trace!("got response");
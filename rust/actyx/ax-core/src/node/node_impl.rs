@@ -413,7 +413,7 @@ mod test {
     use crate::{
         node::{
             components::Component,
-            node_settings::{EventRouting, Route, Settings},
+            node_settings::{EventRouting, Route, Settings, StreamSettings},
         },
         util::formats::NodeName,
     };
@@ -452,7 +452,10 @@ mod test {
               "branchCacheSize": 67108864,
               "gossipInterval": 10,
               "detectionCyclesLowLatency": 2,
-              "detectionCyclesHighLatency": 5
+              "detectionCyclesHighLatency": 5,
+              "replica": false,
+              "acceptUnsignedRoots": true,
+              "replicatedStreams": []
             },
             "admin": {
               "displayName": "My Node",
@@ -470,6 +473,13 @@ mod test {
             "api": {
               "events": {
                 "readOnly": false,
+                "publishKeyWindow": 86400,
+                "schemas": {},
+                "rejectInvalidPayloads": false,
+                "maxPayloadSize": 0,
+                "maxEventsPerRequest": 0,
+                "publishRate": 0,
+                "publishBurst": 1000,
                 "_internal": {
                   "allow_publish": true,
                   "topic": "actyxos-demo"
@@ -643,15 +653,21 @@ mod test {
             streams: BTreeMap::from([
                 (
                     "logs".to_string(),
-                    crate::swarm::RetainConfig {
-                        max_events: 1024.into(),
+                    StreamSettings {
+                        retain: crate::swarm::RetainConfig {
+                            max_events: 1024.into(),
+                            ..Default::default()
+                        },
                         ..Default::default()
                     },
                 ),
                 (
                     "metrics".to_string(),
-                    crate::swarm::RetainConfig {
-                        max_age: Some(crate::swarm::StreamAge::Hours(1)),
+                    StreamSettings {
+                        retain: crate::swarm::RetainConfig {
+                            max_age: Some(crate::swarm::StreamAge::Hours(1)),
+                            ..Default::default()
+                        },
                         ..Default::default()
                     },
                 ),
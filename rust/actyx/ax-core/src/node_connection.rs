@@ -15,7 +15,7 @@ use crate::{
 };
 use anyhow::anyhow;
 use ax_types::{
-    service::{Diagnostic, EventResponse, PublishResponse},
+    service::{Diagnostic, EventResponse, PublishResponse, QueryPlan},
     NodeId, Payload,
 };
 use derive_more::From;
@@ -307,6 +307,7 @@ pub async fn mk_swarm(key: AxPrivateKey) -> ActyxOSResult<(impl Future<Output =
                                 AdminRequest::TopicLs | AdminRequest::TopicDelete { .. } => {
                                     ["/actyx/admin/1.2"].as_slice()
                                 }
+                                AdminRequest::TokensRevoke { .. } => ["/actyx/admin/1.3"].as_slice(),
                                 _ => ["/actyx/admin/1.0.0", "/actyx/admin/1.1", "/actyx/admin/1.2"].as_slice(),
                             };
                             if unsupported_proto(infos.get(&peer_id), required, &mut channel) {
@@ -503,6 +504,7 @@ pub enum EventDiagnostic {
     Event(EventResponse<Payload>),
     AntiEvent(EventResponse<Payload>),
     Diagnostic(Diagnostic),
+    Plan(QueryPlan),
 }
 
 pub async fn request_events(
@@ -520,12 +522,19 @@ pub async fn request_events(
                 ready(Some(Err(ActyxOSCode::ERR_INVALID_INPUT.with_message(message))))
             }
             Ok(EventsResponse::Diagnostic(d)) => ready(Some(Ok(EventDiagnostic::Diagnostic(d)))),
+            Ok(EventsResponse::Plan(p)) => ready(Some(Ok(EventDiagnostic::Plan(p)))),
             Ok(EventsResponse::OffsetMap { offsets }) => {
                 tracing::info!("received OffsetMap covering {} events", offsets.size());
                 ready(None)
             }
-            Ok(x @ EventsResponse::Offsets(..) | x @ EventsResponse::Publish(..)) => ready(Some(Err(
-                ActyxOSCode::ERR_INTERNAL_ERROR.with_message(format!("unexpected: {:?}", x)),
+            Ok(EventsResponse::Cursor(c)) => {
+                tracing::info!("received cursor {}", c.cursor);
+                ready(None)
+            }
+            Ok(
+                x @ EventsResponse::Offsets(..) | x @ EventsResponse::Publish(..) | x @ EventsResponse::QueryBatch(..),
+            ) => ready(Some(Err(
+                ActyxOSCode::ERR_INTERNAL_ERROR.with_message(format!("unexpected: {:?}", x))
             ))),
             Ok(x @ EventsResponse::FutureCompat) => ready(Some(Err(
                 ActyxOSCode::ERR_INTERNAL_ERROR.with_message(format!("{:?}", x))
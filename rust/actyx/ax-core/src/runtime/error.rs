@@ -48,4 +48,10 @@ pub enum RuntimeFailure {
     AntiInputInMin,
     #[display(fmt = "anti-input cannot be processed in MAX()")]
     AntiInputInMax,
+    #[display(fmt = "anti-input cannot be processed in ORDER BY")]
+    AntiInputInOrderBy,
+    #[display(fmt = "anti-input in DISTINCT does not match any remembered input")]
+    AntiInputInDistinct,
+    #[display(fmt = "anti-input in {}() does not match any previous input", "_0.as_str()")]
+    UnmatchedAntiInput(#[error(ignore)] AggrOp),
 }
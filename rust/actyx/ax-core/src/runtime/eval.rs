@@ -1,8 +1,13 @@
 use crate::{
-    runtime::{error::RuntimeError, query::Query, value::Value},
+    runtime::{
+        error::RuntimeError,
+        functions::{self, Impl},
+        query::Query,
+        value::Value,
+    },
     swarm::event_store_ref::EventStoreRef,
 };
-use anyhow::{anyhow, bail, ensure};
+use anyhow::{anyhow, bail};
 use ax_aql::{BinOp, Ind, Index, Num, SimpleExpr, TagAtom, TagExpr};
 use ax_types::{
     service::{EventMeta, Order},
@@ -38,9 +43,16 @@ pub struct RootContext {
     from_offsets_excluding: OffsetMap,
     to_offsets_including: OffsetMap,
     order: Order,
+    live: bool,
 }
 
 impl RootContext {
+    /// mark this context as evaluating a live subscription, where more inputs may follow a flush
+    pub fn live(mut self) -> Self {
+        self.live = true;
+        self
+    }
+
     pub fn child(&self) -> Context<'_> {
         Context {
             root: self,
@@ -76,6 +88,7 @@ impl<'a> Context<'a> {
             from_offsets_excluding,
             to_offsets_including,
             order,
+            live: false,
         }
     }
 
@@ -109,6 +122,10 @@ impl<'a> Context<'a> {
         &self.root.to_offsets_including
     }
 
+    pub fn is_live(&self) -> bool {
+        self.root.live
+    }
+
     pub fn bind(&mut self, name: impl Into<String>, value: Value) {
         self.bindings.insert(name.into(), Ok(value));
     }
@@ -341,18 +358,26 @@ impl<'a> Context<'a> {
                 }
                 SimpleExpr::BinOp(b) => self.bin_op(&b.1, b.0, &b.2).await,
                 SimpleExpr::AggrOp(a) => bail!("internal error, unreplaced AGGREGATE operator: {}", a.0.as_str()),
-                SimpleExpr::FuncCall(f) => match f.name.as_str() {
-                    "IsDefined" => {
-                        ensure!(
-                            f.args.len() == 1,
-                            "wrong number of arguments: 'IsDefined' takes 1 argument but {} were provided",
-                            f.args.len()
-                        );
-                        let defined = self.eval(&f.args[0]).await.is_ok();
-                        Ok(Value::synthetic(self.mk_cbor(|b| b.encode_bool(defined))))
+                SimpleExpr::FuncCall(f) => {
+                    let func = functions::lookup(&f.name).ok_or_else(|| anyhow!("undefined function '{}'", f.name))?;
+                    func.check_arity(f.args.len())?;
+                    match func.imp {
+                        Impl::IsDefined => {
+                            let defined = self.eval(&f.args[0]).await.is_ok();
+                            Ok(Value::synthetic(self.mk_cbor(|b| b.encode_bool(defined))))
+                        }
+                        Impl::Strict(imp) => {
+                            let mut args = Vec::with_capacity(f.args.len());
+                            let mut meta = EventMeta::Synthetic;
+                            for arg in f.args.iter() {
+                                let v = self.eval(arg).await?;
+                                meta += v.meta();
+                                args.push(v);
+                            }
+                            Ok(Value::new_meta(imp(self, &args)?, meta))
+                        }
                     }
-                    _ => Err(anyhow!("undefined function '{}'", f.name)),
-                },
+                }
                 SimpleExpr::SubQuery(q) => {
                     let arr = Query::eval(q, self).await?;
                     let meta = arr.iter().fold(EventMeta::Synthetic, |mut meta, v| {
@@ -996,5 +1021,131 @@ mod tests {
             .unwrap_err()
             .to_string()
             .contains("wrong number of arguments"));
+        assert_eq!(
+            eval(&mut cx, "Frobnicate(1)").await.unwrap_err().to_string(),
+            "undefined function 'Frobnicate'"
+        );
+    }
+
+    #[tokio::test]
+    async fn string_functions() {
+        let cx = ctx();
+        let mut cx = cx.child();
+
+        assert_eq!(eval(&mut cx, "Lower('HeLLo')").await.unwrap(), "\"hello\"");
+        assert_eq!(eval(&mut cx, "Upper('HeLLo')").await.unwrap(), "\"HELLO\"");
+        assert_eq!(eval(&mut cx, "Substring('Grüße', 2)").await.unwrap(), "\"üße\"");
+        assert_eq!(eval(&mut cx, "Substring('Grüße', 1, 2)").await.unwrap(), "\"rü\"");
+        assert_eq!(eval(&mut cx, "Substring('abc', 5)").await.unwrap(), "\"\"");
+        assert_eq!(eval(&mut cx, "StartsWith('abc', 'ab')").await.unwrap(), "true");
+        assert_eq!(eval(&mut cx, "StartsWith('abc', 'b')").await.unwrap(), "false");
+        assert_eq!(eval(&mut cx, "Contains('abc', 'bc')").await.unwrap(), "true");
+        assert_eq!(
+            eval(&mut cx, "Split('a,b,,c', ',')").await.unwrap(),
+            r#"["a", "b", "", "c"]"#
+        );
+        assert_eq!(eval(&mut cx, "Split('ab', '')").await.unwrap(), r#"["a", "b"]"#);
+        assert_eq!(
+            eval(&mut cx, "Join(['a', 1, TRUE], '-')").await.unwrap(),
+            "\"a-1-TRUE\""
+        );
+
+        assert!(&eval(&mut cx, "Lower(1)")
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("is not of type String"));
+        assert!(&eval(&mut cx, "Substring('a')")
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("'Substring' takes 2 to 3 arguments but 1 were provided"));
+    }
+
+    #[tokio::test]
+    async fn number_functions() {
+        let cx = ctx();
+        let mut cx = cx.child();
+
+        assert_eq!(eval(&mut cx, "Abs(3)").await.unwrap(), "3");
+        assert_eq!(eval(&mut cx, "Abs(1 - 3.5)").await.unwrap(), "2.5");
+        assert_eq!(eval(&mut cx, "Round(2.5)").await.unwrap(), "3.0");
+        assert_eq!(eval(&mut cx, "Floor(2.7)").await.unwrap(), "2.0");
+        assert_eq!(eval(&mut cx, "Ceil(2.1)").await.unwrap(), "3.0");
+        assert!(&eval(&mut cx, "Ceil('a')")
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("is not of type Number"));
+    }
+
+    #[tokio::test]
+    async fn collection_functions() {
+        let cx = ctx();
+        let mut cx = cx.child();
+
+        assert_eq!(eval(&mut cx, "Length('Grüße')").await.unwrap(), "5");
+        assert_eq!(eval(&mut cx, "Length([1, 2])").await.unwrap(), "2");
+        assert_eq!(eval(&mut cx, "Length({a: 1 b: 2 c: 3})").await.unwrap(), "3");
+        assert_eq!(eval(&mut cx, "Contains([1, 'x'], 'x')").await.unwrap(), "true");
+        assert_eq!(eval(&mut cx, "Contains([1, 'x'], 2)").await.unwrap(), "false");
+        assert_eq!(eval(&mut cx, "Contains({a: 1}, 'a')").await.unwrap(), "true");
+        assert_eq!(eval(&mut cx, "Contains({a: 1}, 'b')").await.unwrap(), "false");
+        assert_eq!(eval(&mut cx, "Keys({a: 1 b: 2})").await.unwrap(), r#"["a", "b"]"#);
+        assert_eq!(eval(&mut cx, "Values({a: 1 b: 2})").await.unwrap(), "[1, 2]");
+        assert!(&eval(&mut cx, "Length(1)")
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("requires a string, array, or object"));
+        assert!(&eval(&mut cx, "Keys([1])")
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("is not of type Object"));
+    }
+
+    #[tokio::test]
+    async fn time_functions() {
+        let cx = ctx();
+        let mut cx = cx.child();
+
+        assert_eq!(
+            eval(&mut cx, "ToIso(TIME(2021-08-13T07:45:03.418-06:00))")
+                .await
+                .unwrap(),
+            "\"2021-08-13T13:45:03.418000Z\""
+        );
+        assert_eq!(
+            eval(&mut cx, "ToIso(TruncateTime(TIME(2021-08-13T07:45:03.418Z), 'hour'))")
+                .await
+                .unwrap(),
+            "\"2021-08-13T07:00:00.000000Z\""
+        );
+        assert_eq!(
+            eval(&mut cx, "ToIso(TruncateTime(TIME(2021-08-13T07:45:03.418Z), 'month'))")
+                .await
+                .unwrap(),
+            "\"2021-08-01T00:00:00.000000Z\""
+        );
+        assert_eq!(
+            eval(
+                &mut cx,
+                "DurationBetween(TIME(2021-08-13T00:00:00Z), TIME(2021-08-13T00:01:30.500Z))"
+            )
+            .await
+            .unwrap(),
+            "90.5"
+        );
+        assert!(&eval(&mut cx, "TruncateTime(TIME(2021-08-13T00:00:00Z), 'week')")
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("unknown time unit 'week'"));
+        assert!(&eval(&mut cx, "ToIso(1)")
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("is not of type Timestamp"));
     }
 }
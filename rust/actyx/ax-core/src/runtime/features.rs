@@ -1,4 +1,4 @@
-use crate::runtime::{operation::Operation, query::Query};
+use crate::runtime::{functions, operation::Operation, query::Query};
 use ax_aql::{Arr, SimpleExpr, TagAtom, TagExpr, Traverse};
 use std::{collections::BTreeSet, str::FromStr};
 
@@ -68,11 +68,18 @@ features! {
     eventKeyRange: Released [],
     // unclear: metadata cloning for results
     multiEmission: Beta [Subscribe SubscribeMonotonic],
-    // unclear: group-by semantics
     aggregate: Beta [SubscribeMonotonic],
+    groupBy: Beta [SubscribeMonotonic],
+    window: Beta [SubscribeMonotonic],
+    // sorting needs to see all inputs before emitting anything
+    orderBy: Beta [Subscribe SubscribeMonotonic],
     // unclear: metadata for results, interaction with aggregate on subscribe endpoints
     subQuery: Beta [Subscribe SubscribeMonotonic],
     limit: Released [SubscribeMonotonic],
+    // unclear: memory use for unbounded keys on long-running subscriptions
+    distinct: Beta [],
+    // unclear: incremental updates when joined events arrive after the inputs
+    join: Beta [Subscribe SubscribeMonotonic],
     binding: Released [],
     // unclear: canonical string representation of all value kinds
     interpolation: Beta [],
@@ -80,6 +87,7 @@ features! {
     fromArray: Beta [Subscribe SubscribeMonotonic],
     // unclear: metadata for multiEmission results
     spread: Beta [],
+    builtinFunctions: Beta [],
 }
 
 #[derive(Debug, Clone, Copy, derive_more::Display)]
@@ -185,13 +193,40 @@ fn features_op(feat: &mut Features, op: &Operation) {
             feat.add(aggregate);
             features_simple(feat, a);
         }
+        Operation::AggregateWindow(a, _) => {
+            feat.add(aggregate);
+            feat.add(window);
+            features_simple(feat, a);
+        }
+        Operation::AggregateBy(a, k) => {
+            feat.add(aggregate);
+            feat.add(groupBy);
+            features_simple(feat, a);
+            features_simple(feat, k);
+        }
+        Operation::OrderBy(e, _) => {
+            feat.add(orderBy);
+            features_simple(feat, e);
+        }
         Operation::Limit(_) => {
             feat.add(limit);
         }
+        Operation::Distinct(k, _) => {
+            feat.add(distinct);
+            if let Some(k) = k {
+                features_simple(feat, k);
+            }
+        }
         Operation::Binding(_, e) => {
             feat.add(binding);
             features_simple(feat, e);
         }
+        Operation::Join(j) => {
+            feat.add(join);
+            features_tag(feat, &j.from);
+            features_simple(feat, &j.key);
+            features_simple(feat, &j.other_key);
+        }
     }
 }
 
@@ -252,6 +287,12 @@ fn features_simple(feat: &mut Features, expr: &SimpleExpr) {
             }
             Traverse::Descend
         }
+        SimpleExpr::FuncCall(f) => {
+            if let Some(f) = functions::lookup(&f.name).and_then(|f| f.feature) {
+                feat.add(f);
+            }
+            Traverse::Descend
+        }
         _ => Traverse::Descend,
     });
 }
@@ -337,6 +378,100 @@ mod tests {
         );
     }
 
+    #[test]
+    fn group_by() {
+        assert_eq!(
+            f("FROM 'x' AGGREGATE SUM(_) GROUP BY _.a").0,
+            btreeset!(aggregate, groupBy)
+        );
+        assert_eq!(
+            f("FROM 'x' AGGREGATE SUM(_) GROUP BY (FROM 'y' END)[0]").0,
+            btreeset!(aggregate, groupBy, subQuery)
+        );
+        assert_eq!(
+            q("FEATURES(aggregate) FROM 'x' AGGREGATE SUM(_) GROUP BY _.a"),
+            Err(Beta(s("groupBy")))
+        );
+        assert_eq!(
+            q("FEATURES(aggregate groupBy) FROM 'x' AGGREGATE SUM(_) GROUP BY _.a"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn windows() {
+        assert_eq!(
+            f("FROM 'x' AGGREGATE COUNT(_) WINDOW 1h EVERY 5m").0,
+            btreeset!(aggregate, window)
+        );
+        assert_eq!(
+            q("FEATURES(aggregate) FROM 'x' AGGREGATE COUNT(_) WINDOW 1h"),
+            Err(Beta(s("window")))
+        );
+        assert_eq!(
+            q("FEATURES(aggregate window) FROM 'x' AGGREGATE COUNT(_) WINDOW 1h"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn order_by() {
+        assert_eq!(f("FROM 'x' ORDER BY _.a DESC LIMIT 3").0, btreeset!(orderBy, limit));
+        assert_eq!(q("FROM 'x' ORDER BY _.a"), Err(Beta(s("orderBy"))));
+        assert_eq!(q("FEATURES(orderBy) FROM 'x' ORDER BY _.a"), Ok(()));
+
+        let mut f = Features::new();
+        f.add(Feature::orderBy);
+        assert_eq!(
+            f.validate(&[s("orderBy")], Endpoint::Subscribe),
+            Err(Unsupported {
+                features: s("orderBy"),
+                endpoint: s("Subscribe")
+            })
+        );
+    }
+
+    #[test]
+    fn dedup() {
+        assert_eq!(f("FROM 'x' DISTINCT").0, btreeset!(distinct));
+        assert_eq!(
+            f("FROM 'x' DISTINCT ON Lower(_.a) KEEP 10").0,
+            btreeset!(distinct, builtinFunctions)
+        );
+        assert_eq!(q("FROM 'x' DISTINCT"), Err(Beta(s("distinct"))));
+        assert_eq!(q("FEATURES(distinct) FROM 'x' DISTINCT ON _.a"), Ok(()));
+    }
+
+    #[test]
+    fn joins() {
+        assert_eq!(f("FROM 'x' JOIN 'y' AS y ON _.a = y.a").0, btreeset!(join));
+        assert_eq!(
+            f("FROM 'x' LEFT JOIN `y{1}` AS y ON _.a = y.a").0,
+            btreeset!(join, interpolation)
+        );
+        assert_eq!(q("FROM 'x' JOIN 'y' AS y ON _ = y"), Err(Beta(s("join"))));
+        assert_eq!(q("FEATURES(join) FROM 'x' JOIN 'y' AS y ON _ = y"), Ok(()));
+
+        let mut f = Features::new();
+        f.add(Feature::join);
+        assert_eq!(
+            f.validate(&[s("join")], Endpoint::SubscribeMonotonic),
+            Err(Unsupported {
+                features: s("join"),
+                endpoint: s("SubscribeMonotonic")
+            })
+        );
+    }
+
+    #[test]
+    fn builtin_functions() {
+        assert_eq!(f("FROM 'x' FILTER IsDefined(_.a)").0, btreeset!());
+        assert_eq!(f("FROM 'x' SELECT Lower(_.a)").0, btreeset!(builtinFunctions));
+        assert_eq!(f("FROM 'x' SELECT IsDefined(Length(_))").0, btreeset!(builtinFunctions));
+        assert_eq!(q("FROM 'x' SELECT Upper(_)"), Err(Beta(s("builtinFunctions"))));
+        assert_eq!(q("FEATURES(builtinFunctions) FROM 'x' SELECT Upper(_)"), Ok(()));
+    }
+
     #[test]
     fn lim() {
         assert_eq!(f("FROM 'x' LIMIT 1").0, btreeset!(limit));
@@ -0,0 +1,302 @@
+//! Registry of the builtin functions callable from AQL expressions, like `Lower(_.name)`.
+//!
+//! Each function declares its arity and the feature flag that needs to be enabled for using it;
+//! type checking of the arguments happens when the function is applied.
+use crate::runtime::{
+    error::RuntimeError,
+    eval::Context,
+    features::Feature,
+    value::{Value, ValueKind},
+};
+use anyhow::{anyhow, bail, ensure};
+use ax_aql::Num;
+use cbor_data::{
+    value::{self as cbor_value, Precision},
+    CborOwned, CborValue, Encoder, Writer,
+};
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use std::{borrow::Cow, cmp::Ordering};
+
+type StrictFn = fn(&Context<'_>, &[Value]) -> anyhow::Result<CborOwned>;
+
+pub enum Impl {
+    /// `IsDefined` needs to see evaluation errors of its argument, so it is handled by the evaluator
+    IsDefined,
+    /// function computed from its fully evaluated arguments
+    Strict(StrictFn),
+}
+
+pub struct Function {
+    pub name: &'static str,
+    pub min_args: usize,
+    pub max_args: usize,
+    pub feature: Option<Feature>,
+    pub imp: Impl,
+}
+
+impl Function {
+    pub fn check_arity(&self, args: usize) -> anyhow::Result<()> {
+        let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+        if self.min_args == self.max_args {
+            ensure!(
+                args == self.min_args,
+                "wrong number of arguments: '{}' takes {} {} but {} were provided",
+                self.name,
+                self.min_args,
+                plural(self.min_args),
+                args
+            );
+        } else {
+            ensure!(
+                (self.min_args..=self.max_args).contains(&args),
+                "wrong number of arguments: '{}' takes {} to {} arguments but {} were provided",
+                self.name,
+                self.min_args,
+                self.max_args,
+                args
+            );
+        }
+        Ok(())
+    }
+}
+
+macro_rules! functions {
+    ($($name:ident($min:literal $(..$max:literal)?) $($feature:ident)? => $imp:expr,)*) => {
+        static FUNCTIONS: &[Function] = &[$(Function {
+            name: stringify!($name),
+            min_args: $min,
+            max_args: functions!(@max $min $($max)?),
+            feature: functions!(@feature $($feature)?),
+            imp: $imp,
+        },)*];
+    };
+    (@max $min:literal) => { $min };
+    (@max $min:literal $max:literal) => { $max };
+    (@feature) => { None };
+    (@feature $feature:ident) => { Some(Feature::$feature) };
+}
+
+functions! {
+    IsDefined(1) => Impl::IsDefined,
+    // strings
+    Lower(1) builtinFunctions => Impl::Strict(lower),
+    Upper(1) builtinFunctions => Impl::Strict(upper),
+    Substring(2..3) builtinFunctions => Impl::Strict(substring),
+    StartsWith(2) builtinFunctions => Impl::Strict(starts_with),
+    Split(2) builtinFunctions => Impl::Strict(split),
+    Join(2) builtinFunctions => Impl::Strict(join),
+    // numbers
+    Abs(1) builtinFunctions => Impl::Strict(abs),
+    Round(1) builtinFunctions => Impl::Strict(round),
+    Floor(1) builtinFunctions => Impl::Strict(floor),
+    Ceil(1) builtinFunctions => Impl::Strict(ceil),
+    // arrays and objects (Contains also works on strings)
+    Length(1) builtinFunctions => Impl::Strict(length),
+    Contains(2) builtinFunctions => Impl::Strict(contains),
+    Keys(1) builtinFunctions => Impl::Strict(keys),
+    Values(1) builtinFunctions => Impl::Strict(values),
+    // timestamps
+    ToIso(1) builtinFunctions => Impl::Strict(to_iso),
+    TruncateTime(2) builtinFunctions => Impl::Strict(truncate_time),
+    DurationBetween(2) builtinFunctions => Impl::Strict(duration_between),
+}
+
+pub fn lookup(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|f| f.name == name)
+}
+
+fn type_error(v: &Value, expected: ValueKind) -> anyhow::Error {
+    RuntimeError::TypeError {
+        value: v.print(),
+        expected,
+    }
+    .into()
+}
+
+fn string(v: &Value) -> anyhow::Result<Cow<'_, str>> {
+    v.as_str().map_err(|_| type_error(v, ValueKind::String))
+}
+
+fn natural(v: &Value) -> anyhow::Result<usize> {
+    match v.as_number()? {
+        Num::Natural(n) => Ok(n.try_into()?),
+        Num::Decimal(_) => bail!("`{}` is not a natural number", v.print()),
+    }
+}
+
+fn timestamp(v: &Value) -> anyhow::Result<cbor_value::Timestamp> {
+    match v.value() {
+        CborValue::Timestamp(t) => Ok(t),
+        _ => Err(type_error(v, ValueKind::Timestamp)),
+    }
+}
+
+fn str_array<'a>(cx: &Context<'_>, items: impl IntoIterator<Item = &'a str>) -> CborOwned {
+    cx.mk_cbor(|b| {
+        b.encode_array(|b| {
+            for s in items {
+                b.encode_str(s);
+            }
+        })
+    })
+}
+
+fn lower(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    let s = string(&args[0])?.to_lowercase();
+    Ok(cx.mk_cbor(|b| b.encode_str(s)))
+}
+
+fn upper(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    let s = string(&args[0])?.to_uppercase();
+    Ok(cx.mk_cbor(|b| b.encode_str(s)))
+}
+
+/// `Substring(s, start, length?)` with positions counted in Unicode characters
+fn substring(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    let s = string(&args[0])?;
+    let start = natural(&args[1])?;
+    let len = args.get(2).map(natural).transpose()?.unwrap_or(usize::MAX);
+    let s = s.chars().skip(start).take(len).collect::<String>();
+    Ok(cx.mk_cbor(|b| b.encode_str(s)))
+}
+
+fn starts_with(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    let result = string(&args[0])?.starts_with(string(&args[1])?.as_ref());
+    Ok(cx.mk_cbor(|b| b.encode_bool(result)))
+}
+
+fn split(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    let s = string(&args[0])?;
+    let sep = string(&args[1])?;
+    if sep.is_empty() {
+        let chars = s.chars().map(String::from).collect::<Vec<_>>();
+        Ok(str_array(cx, chars.iter().map(|s| s.as_str())))
+    } else {
+        Ok(str_array(cx, s.split(sep.as_ref())))
+    }
+}
+
+/// `Join(array, separator)` renders the array elements like string interpolation does
+fn join(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    let items = args[0].as_array()?;
+    let sep = string(&args[1])?;
+    let s = items.iter().map(|v| v.print()).collect::<Vec<_>>().join(sep.as_ref());
+    Ok(cx.mk_cbor(|b| b.encode_str(s)))
+}
+
+fn map_decimal(cx: &Context<'_>, v: &Value, f: impl FnOnce(f64) -> f64) -> anyhow::Result<CborOwned> {
+    Ok(match v.as_number()? {
+        n @ Num::Natural(_) => cx.number(&n),
+        Num::Decimal(d) => cx.number(&Num::Decimal(f(d))),
+    })
+}
+
+fn abs(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    map_decimal(cx, &args[0], f64::abs)
+}
+
+fn round(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    map_decimal(cx, &args[0], f64::round)
+}
+
+fn floor(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    map_decimal(cx, &args[0], f64::floor)
+}
+
+fn ceil(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    map_decimal(cx, &args[0], f64::ceil)
+}
+
+fn length(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    let v = &args[0];
+    let len = match v.value() {
+        CborValue::Str(s) => s.chars().count(),
+        CborValue::Array(a) => a.len(),
+        CborValue::Dict(d) => d.len(),
+        _ => bail!("Length() requires a string, array, or object, got {}", v.kind()),
+    };
+    Ok(cx.number(&Num::Natural(len as u64)))
+}
+
+/// substring test for strings, element test for arrays, and key test for objects
+fn contains(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    let (v, needle) = (&args[0], &args[1]);
+    let result = match v.value() {
+        CborValue::Str(s) => s.contains(string(needle)?.as_ref()),
+        CborValue::Array(_) => v
+            .as_array()?
+            .iter()
+            .any(|x| x.partial_cmp(needle) == Some(Ordering::Equal)),
+        CborValue::Dict(d) => d.iter().any(|(k, _)| k.as_slice() == needle.as_slice()),
+        _ => bail!("Contains() requires a string, array, or object, got {}", v.kind()),
+    };
+    Ok(cx.mk_cbor(|b| b.encode_bool(result)))
+}
+
+fn keys(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    match args[0].value() {
+        CborValue::Dict(d) => Ok(cx.mk_cbor(|b| {
+            b.encode_array(|b| {
+                for (k, _) in d.iter() {
+                    b.write_trusting(k.as_slice());
+                }
+            })
+        })),
+        _ => Err(type_error(&args[0], ValueKind::Object)),
+    }
+}
+
+fn values(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    match args[0].value() {
+        CborValue::Dict(d) => Ok(cx.mk_cbor(|b| {
+            b.encode_array(|b| {
+                for (_, v) in d.iter() {
+                    b.write_trusting(v.as_slice());
+                }
+            })
+        })),
+        _ => Err(type_error(&args[0], ValueKind::Object)),
+    }
+}
+
+fn to_iso(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    timestamp(&args[0])?;
+    let s = args[0].print();
+    Ok(cx.mk_cbor(|b| b.encode_str(s)))
+}
+
+/// `TruncateTime(t, unit)` rounds the timestamp down to the start of the given unit in UTC
+fn truncate_time(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    let t = timestamp(&args[0])?;
+    let unit = string(&args[1])?;
+    let secs = t.unix_epoch();
+    let truncated = match unit.as_ref() {
+        "second" => secs,
+        "minute" => secs - secs.rem_euclid(60),
+        "hour" => secs - secs.rem_euclid(3600),
+        "day" => secs - secs.rem_euclid(86400),
+        "month" | "year" => {
+            let date = NaiveDateTime::from_timestamp_opt(secs, 0)
+                .ok_or_else(|| anyhow!("timestamp out of range"))?
+                .date();
+            let month = if unit == "year" { 1 } else { date.month() };
+            NaiveDate::from_ymd_opt(date.year(), month, 1)
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .ok_or_else(|| anyhow!("timestamp out of range"))?
+                .timestamp()
+        }
+        _ => bail!(
+            "unknown time unit '{}', expected one of second, minute, hour, day, month, year",
+            unit
+        ),
+    };
+    Ok(cx.mk_cbor(|b| b.encode_timestamp(cbor_value::Timestamp::new(truncated, 0, 0), Precision::Micros)))
+}
+
+/// `DurationBetween(from, to)` yields the number of seconds from `from` to `to`
+fn duration_between(cx: &Context<'_>, args: &[Value]) -> anyhow::Result<CborOwned> {
+    let from = timestamp(&args[0])?;
+    let to = timestamp(&args[1])?;
+    let secs = (to.unix_epoch() - from.unix_epoch()) as f64 + (to.nanos() as f64 - from.nanos() as f64) / 1e9;
+    Ok(cx.number(&Num::Decimal(secs)))
+}
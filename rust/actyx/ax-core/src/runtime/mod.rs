@@ -1,6 +1,7 @@
 pub mod error;
 pub mod eval;
 pub mod features;
+pub mod functions;
 pub mod number;
 pub mod operation;
 pub mod query;
@@ -1,14 +1,18 @@
+use super::sketch::Sketch;
 use crate::runtime::{
     error::{RuntimeError, RuntimeFailure},
     eval::Context,
-    value::Value,
+    value::{Value, ValueKind},
 };
 use anyhow::anyhow;
-use ax_aql::{AggrOp, Galactus, Num, SimpleExpr, Tactic, Var};
-use ax_types::service::{EventMeta, Order};
+use ax_aql::{AggrOp, Galactus, Num, SimpleExpr, Tactic, Var, Window};
+use ax_types::{
+    service::{EventMeta, Order},
+    Timestamp,
+};
 use cbor_data::Encoder;
 use futures::{future::BoxFuture, FutureExt};
-use std::{cmp::Ordering, marker::PhantomData, ops::AddAssign, sync::Arc};
+use std::{cmp::Ordering, collections::BTreeMap, marker::PhantomData, ops::AddAssign, sync::Arc};
 
 pub trait Aggregator {
     fn feed(&mut self, input: Value) -> anyhow::Result<()>;
@@ -258,12 +262,274 @@ impl Aggregator for Max {
     }
 }
 
+struct Count(EventMeta, u64);
+impl Aggregator for Count {
+    fn feed(&mut self, input: Value) -> anyhow::Result<()> {
+        if input.kind() == ValueKind::Null {
+            return Ok(());
+        }
+        if input.is_anti() {
+            self.1 = self
+                .1
+                .checked_sub(1)
+                .ok_or(RuntimeFailure::UnmatchedAntiInput(AggrOp::Count))?;
+        } else {
+            self.1 += 1;
+        }
+        self.0 += input.meta();
+        Ok(())
+    }
+
+    fn flush(&mut self, cx: &Context) -> anyhow::Result<Value> {
+        Ok(Value::new_meta(cx.number(&Num::Natural(self.1)), self.0.clone()))
+    }
+
+    fn has_value(&self) -> bool {
+        true
+    }
+}
+
+fn as_f64(input: &Value) -> anyhow::Result<f64> {
+    Ok(match input.as_number()? {
+        Num::Decimal(d) => d,
+        Num::Natural(n) => n as f64,
+    })
+}
+
+/// account for a non-numeric input to a numeric aggregator, which fails the result until retracted
+fn invalid_input(
+    op: AggrOp,
+    invalid: &mut u64,
+    error: &mut Option<anyhow::Error>,
+    anti: bool,
+    e: anyhow::Error,
+) -> anyhow::Result<()> {
+    if anti {
+        *invalid = invalid.checked_sub(1).ok_or(RuntimeFailure::UnmatchedAntiInput(op))?;
+        if *invalid == 0 {
+            *error = None;
+        }
+    } else {
+        *invalid += 1;
+        *error = Some(e);
+    }
+    Ok(())
+}
+
+/// running mean and variance (using Welford’s algorithm, which can also be run backwards)
+struct Moments {
+    op: AggrOp,
+    meta: EventMeta,
+    n: u64,
+    mean: f64,
+    m2: f64,
+    /// number of non-numeric inputs that have not been retracted, and the latest error
+    invalid: u64,
+    error: Option<anyhow::Error>,
+}
+impl Moments {
+    fn new(op: AggrOp) -> Self {
+        Self {
+            op,
+            meta: EventMeta::Synthetic,
+            n: 0,
+            mean: 0.0,
+            m2: 0.0,
+            invalid: 0,
+            error: None,
+        }
+    }
+}
+impl Aggregator for Moments {
+    fn feed(&mut self, input: Value) -> anyhow::Result<()> {
+        let x = match as_f64(&input) {
+            Ok(x) => x,
+            Err(e) => return invalid_input(self.op, &mut self.invalid, &mut self.error, input.is_anti(), e),
+        };
+        if input.is_anti() {
+            if self.n == 0 {
+                return Err(RuntimeFailure::UnmatchedAntiInput(self.op).into());
+            }
+            self.n -= 1;
+            if self.n == 0 {
+                self.mean = 0.0;
+                self.m2 = 0.0;
+            } else {
+                let mean = self.mean - (x - self.mean) / self.n as f64;
+                self.m2 = (self.m2 - (x - mean) * (x - self.mean)).max(0.0);
+                self.mean = mean;
+            }
+        } else {
+            self.n += 1;
+            let delta = x - self.mean;
+            self.mean += delta / self.n as f64;
+            self.m2 += delta * (x - self.mean);
+        }
+        self.meta += input.meta();
+        Ok(())
+    }
+
+    fn flush(&mut self, cx: &Context) -> anyhow::Result<Value> {
+        if let Some(e) = &self.error {
+            return Err(anyhow!("incompatible types in {}: {}", self.op.as_str(), e));
+        }
+        if self.n == 0 {
+            return Err(RuntimeError::NoValueYet.into());
+        }
+        let result = match self.op {
+            AggrOp::StdDev => (self.m2 / self.n as f64).sqrt(),
+            _ => self.mean,
+        };
+        Ok(Value::new_meta(cx.number(&Num::Decimal(result)), self.meta.clone()))
+    }
+
+    fn has_value(&self) -> bool {
+        true
+    }
+}
+
+struct Percentile {
+    op: AggrOp,
+    quantile: f64,
+    meta: EventMeta,
+    sketch: Sketch,
+    /// number of non-numeric inputs that have not been retracted, and the latest error
+    invalid: u64,
+    error: Option<anyhow::Error>,
+}
+impl Percentile {
+    fn new(op: AggrOp, quantile: f64) -> Self {
+        Self {
+            op,
+            quantile,
+            meta: EventMeta::Synthetic,
+            sketch: Sketch::default(),
+            invalid: 0,
+            error: None,
+        }
+    }
+}
+impl Aggregator for Percentile {
+    fn feed(&mut self, input: Value) -> anyhow::Result<()> {
+        let x = match as_f64(&input) {
+            Ok(x) => x,
+            Err(e) => return invalid_input(self.op, &mut self.invalid, &mut self.error, input.is_anti(), e),
+        };
+        if input.is_anti() {
+            if !self.sketch.remove(x) {
+                return Err(RuntimeFailure::UnmatchedAntiInput(self.op).into());
+            }
+        } else {
+            self.sketch.insert(x);
+        }
+        self.meta += input.meta();
+        Ok(())
+    }
+
+    fn flush(&mut self, cx: &Context) -> anyhow::Result<Value> {
+        if let Some(e) = &self.error {
+            return Err(anyhow!("incompatible types in {}: {}", self.op.as_str(), e));
+        }
+        let result = self.sketch.quantile(self.quantile).ok_or(RuntimeError::NoValueYet)?;
+        Ok(Value::new_meta(cx.number(&Num::Decimal(result)), self.meta.clone()))
+    }
+
+    fn has_value(&self) -> bool {
+        true
+    }
+}
+
 struct AggrState {
     key: Arc<(AggrOp, SimpleExpr)>,
     aggregator: Box<dyn Aggregator + Send + Sync + 'static>,
     variable: u32,
 }
 
+impl AggrState {
+    /// a copy of this state with a pristine aggregator, e.g. for starting a new group
+    fn fresh(&self) -> Self {
+        Self {
+            key: self.key.clone(),
+            aggregator: aggregator(self.key.0),
+            variable: self.variable,
+        }
+    }
+}
+
+fn aggregator(op: AggrOp) -> Box<dyn Aggregator + Send + Sync + 'static> {
+    match op {
+        AggrOp::Sum => Box::<Sum<AddOp>>::default(),
+        AggrOp::Prod => Box::<Sum<MulOp>>::default(),
+        AggrOp::Min => Box::new(Min(None)),
+        AggrOp::Max => Box::new(Max(None)),
+        AggrOp::First => Box::new(First(None)),
+        AggrOp::Last => Box::new(Last(None)),
+        AggrOp::Count => Box::new(Count(EventMeta::Synthetic, 0)),
+        AggrOp::Avg => Box::new(Moments::new(op)),
+        AggrOp::StdDev => Box::new(Moments::new(op)),
+        AggrOp::P50 => Box::new(Percentile::new(op, 0.5)),
+        AggrOp::P90 => Box::new(Percentile::new(op, 0.9)),
+        AggrOp::P95 => Box::new(Percentile::new(op, 0.95)),
+        AggrOp::P99 => Box::new(Percentile::new(op, 0.99)),
+    }
+}
+
+/// feed the current input `_` into all aggregators, returning the errors encountered
+async fn feed(state: &mut [AggrState], cx: &Context<'_>) -> Vec<anyhow::Result<Value>> {
+    /*
+     * Anti-flag propagation:
+     *
+     * If we get an anti-input, the specific aggregator will either ingest it or
+     * emit an error. Aggregators always build on a (positive) set of inputs, so
+     * they cannot contain an anti-value.
+     */
+    let anti = cx
+        .lookup_opt("_")
+        .map(|v| v.as_ref().map(|v| v.is_anti()).unwrap_or_default())
+        .unwrap_or_default();
+    let mut errors = vec![];
+    for aggr in state.iter_mut() {
+        match cx.eval(&aggr.key.1).await {
+            Ok(mut v) => {
+                if anti {
+                    v.anti();
+                }
+                if let Err(e) = aggr.aggregator.feed(v) {
+                    errors.push(Err(e))
+                }
+            }
+            Err(e) => errors.push(Err(e)),
+        }
+    }
+    errors
+}
+
+/// compute the AGGREGATE expression from the current aggregator values
+async fn compute(expr: &SimpleExpr, state: &mut [AggrState], cx: &Context<'_>) -> anyhow::Result<Value> {
+    let mut cx = cx.child();
+    for aggr in state.iter_mut() {
+        cx.bind_placeholder(format!("!{}", aggr.variable), aggr.aggregator.flush(&cx));
+    }
+    cx.eval(expr).await
+}
+
+/// emit the new result, retracting the previous one if it changed
+fn emit(previous: &mut Option<Value>, v: Value) -> Vec<anyhow::Result<Value>> {
+    if let Some(mut p) = previous.take() {
+        if p == v {
+            *previous = Some(p);
+            vec![]
+        } else {
+            *previous = Some(v.clone());
+            p.anti();
+            vec![Ok(p), Ok(v)]
+        }
+    } else {
+        *previous = Some(v.clone());
+        vec![Ok(v)]
+    }
+}
+
 struct Aggregate {
     expr: SimpleExpr,
     state: Vec<AggrState>,
@@ -271,75 +537,251 @@ struct Aggregate {
     previous: Option<Value>,
 }
 impl super::Processor for Aggregate {
+    fn apply<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
+        async move { feed(&mut self.state, cx).await }.boxed()
+    }
+
+    fn flush<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
+        async move {
+            match compute(&self.expr, &mut self.state, cx).await {
+                Ok(v) => emit(&mut self.previous, v),
+                e => vec![e],
+            }
+        }
+        .boxed()
+    }
+
+    fn preferred_order(&self) -> Option<Order> {
+        self.order
+    }
+
+    fn is_done(&self, order: Order) -> bool {
+        Some(order) == self.order && self.state.iter().all(|a| a.aggregator.has_value()) || self.state.is_empty()
+    }
+}
+
+struct Group {
+    state: Vec<AggrState>,
+    /// number of inputs that have not been retracted by anti-inputs
+    inputs: u64,
+    previous: Option<Value>,
+    /// whether inputs were fed since the last flush
+    dirty: bool,
+}
+
+impl Group {
+    fn new(template: &[AggrState]) -> Self {
+        Self {
+            state: template.iter().map(AggrState::fresh).collect(),
+            inputs: 0,
+            previous: None,
+            dirty: false,
+        }
+    }
+
+    /// feed the current input `_` into the group, an anti-input only counts if no aggregator rejected it
+    async fn feed(&mut self, anti: bool, cx: &Context<'_>) -> Vec<anyhow::Result<Value>> {
+        self.dirty = true;
+        let errors = feed(&mut self.state, cx).await;
+        if !anti {
+            self.inputs += 1;
+        } else if errors.is_empty() {
+            self.inputs = self.inputs.saturating_sub(1);
+        }
+        errors
+    }
+}
+
+struct AggregateBy {
+    expr: SimpleExpr,
+    key: SimpleExpr,
+    template: Vec<AggrState>,
+    /// groups keyed by the CBOR encoding of their GROUP BY value
+    groups: BTreeMap<Vec<u8>, Group>,
+}
+impl super::Processor for AggregateBy {
     fn apply<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
         async move {
             /*
              * Anti-flag propagation:
              *
-             * If we get an anti-input, the specific aggregator will either ingest it or
-             * emit an error. Aggregators always build on a (positive) set of inputs, so
-             * they cannot contain an anti-value.
+             * The anti-input is routed to the group it was originally counted in (the key
+             * is computed from the same value), where the aggregators deal with it as in
+             * the ungrouped case. When all inputs of a group have been retracted, the group
+             * is removed upon the next flush and its last result is retracted as well.
              */
             let anti = cx
                 .lookup_opt("_")
                 .map(|v| v.as_ref().map(|v| v.is_anti()).unwrap_or_default())
                 .unwrap_or_default();
-            let mut errors = vec![];
-            for aggr in self.state.iter_mut() {
-                match cx.eval(&aggr.key.1).await {
-                    Ok(mut v) => {
-                        if anti {
-                            v.anti();
-                        }
-                        if let Err(e) = aggr.aggregator.feed(v) {
-                            errors.push(Err(e))
-                        }
+            let key = match cx.eval(&self.key).await {
+                Ok(k) => k.as_slice().to_vec(),
+                Err(e) => return vec![Err(e)],
+            };
+            let template = &self.template;
+            let group = self.groups.entry(key).or_insert_with(|| Group::new(template));
+            group.feed(anti, cx).await
+        }
+        .boxed()
+    }
+
+    fn flush<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
+        async move {
+            let mut result = vec![];
+            let mut emptied = vec![];
+            // groups are emitted in the order of their keys
+            for (key, group) in self.groups.iter_mut().filter(|(_, g)| g.dirty) {
+                group.dirty = false;
+                if group.inputs == 0 {
+                    if let Some(mut p) = group.previous.take() {
+                        p.anti();
+                        result.push(Ok(p));
                     }
-                    Err(e) => errors.push(Err(e)),
+                    emptied.push(key.clone());
+                    continue;
+                }
+                match compute(&self.expr, &mut group.state, cx).await {
+                    Ok(v) => result.extend(emit(&mut group.previous, v)),
+                    e => result.push(e),
                 }
             }
-            errors
+            for key in emptied {
+                self.groups.remove(&key);
+            }
+            result
         }
         .boxed()
     }
+}
 
-    fn flush<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
+/// the start times of all windows containing the given time, in ascending order
+fn window_starts(window: &Window, time: u64) -> impl Iterator<Item = u64> {
+    let size = window.size.get();
+    let slide = window.slide.get();
+    let latest = time - time % slide;
+    // the earliest window must end after `time`
+    let earliest = time.saturating_add(1).saturating_sub(size);
+    let first = earliest + (slide - earliest % slide) % slide;
+    std::iter::successors(Some(first), move |start| start.checked_add(slide)).take_while(move |start| *start <= latest)
+}
+
+/// the event time of the current input `_`, i.e. `TIME(_)`
+fn event_time(cx: &Context<'_>) -> anyhow::Result<u64> {
+    match cx.lookup("_")?.meta() {
+        EventMeta::Event { meta, .. } => Ok(meta.timestamp.into()),
+        EventMeta::Range { from_time, .. } => Ok((*from_time).into()),
+        EventMeta::Synthetic => Err(anyhow!("WINDOW requires inputs with event time")),
+    }
+}
+
+/// report the window bounds as the time range of a result, available via `TIME(_)`
+fn window_meta(meta: &EventMeta, start: u64, end: u64) -> EventMeta {
+    let (from_key, to_key) = match meta {
+        EventMeta::Range { from_key, to_key, .. } => (*from_key, *to_key),
+        EventMeta::Event { key, .. } => (*key, *key),
+        EventMeta::Synthetic => return EventMeta::Synthetic,
+    };
+    EventMeta::Range {
+        from_key,
+        to_key,
+        from_time: Timestamp::new(start),
+        to_time: Timestamp::new(end),
+    }
+}
+
+struct AggregateWindow {
+    expr: SimpleExpr,
+    window: Window,
+    template: Vec<AggrState>,
+    /// windows keyed by their start time
+    windows: BTreeMap<u64, Group>,
+    /// latest event time seen so far, all windows ending at or before it are closed
+    ///
+    /// In a live subscription closed windows are dropped once emitted, later inputs for them are ignored.
+    watermark: u64,
+}
+impl super::Processor for AggregateWindow {
+    fn apply<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
         async move {
-            let mut cx = cx.child();
-            for aggr in self.state.iter_mut() {
-                cx.bind_placeholder(format!("!{}", aggr.variable), aggr.aggregator.flush(&cx));
-            }
-            let v = match cx.eval(&self.expr).await {
-                Ok(v) => v,
-                e => return vec![e],
+            /*
+             * Anti-flag propagation:
+             *
+             * Same as for GROUP BY, with each input being counted in all windows it falls into.
+             */
+            let anti = cx
+                .lookup_opt("_")
+                .map(|v| v.as_ref().map(|v| v.is_anti()).unwrap_or_default())
+                .unwrap_or_default();
+            let time = match event_time(cx) {
+                Ok(t) => t,
+                Err(e) => return vec![Err(e)],
             };
-            if let Some(mut p) = self.previous.take() {
-                if p == v {
-                    self.previous = Some(p);
-                    vec![]
-                } else {
-                    self.previous = Some(v.clone());
-                    p.anti();
-                    vec![Ok(p), Ok(v)]
+            if !anti {
+                self.watermark = self.watermark.max(time);
+            }
+            let live = cx.is_live();
+            let size = self.window.size.get();
+            let mut errors = vec![];
+            for start in window_starts(&self.window, time) {
+                if live && start.saturating_add(size) <= self.watermark && !self.windows.contains_key(&start) {
+                    // this window has already been emitted and dropped
+                    continue;
                 }
-            } else {
-                self.previous = Some(v.clone());
-                vec![Ok(v)]
+                let template = &self.template;
+                let group = self.windows.entry(start).or_insert_with(|| Group::new(template));
+                errors.extend(group.feed(anti, cx).await);
             }
+            errors
         }
         .boxed()
     }
 
-    fn preferred_order(&self) -> Option<Order> {
-        self.order
-    }
-
-    fn is_done(&self, order: Order) -> bool {
-        Some(order) == self.order && self.state.iter().all(|a| a.aggregator.has_value()) || self.state.is_empty()
+    fn flush<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
+        async move {
+            // a live subscription only emits closed windows, a query emits all of them at its end
+            let live = cx.is_live();
+            let size = self.window.size.get();
+            let mut result = vec![];
+            let mut emptied = vec![];
+            for (start, group) in self.windows.iter_mut().filter(|(_, g)| g.dirty) {
+                let end = start.saturating_add(size);
+                if live && end > self.watermark {
+                    break;
+                }
+                group.dirty = false;
+                if group.inputs == 0 {
+                    if let Some(mut p) = group.previous.take() {
+                        p.anti();
+                        result.push(Ok(p));
+                    }
+                    emptied.push(*start);
+                    continue;
+                }
+                match compute(&self.expr, &mut group.state, cx).await {
+                    Ok(v) => {
+                        let meta = window_meta(v.meta(), *start, end);
+                        result.extend(emit(&mut group.previous, Value::new_meta(v.cbor().to_owned(), meta)))
+                    }
+                    e => result.push(e),
+                }
+            }
+            for start in emptied {
+                self.windows.remove(&start);
+            }
+            if live {
+                // all closed windows have been emitted above
+                let open = self.watermark.saturating_add(1).saturating_sub(size);
+                self.windows = self.windows.split_off(&open);
+            }
+            result
+        }
+        .boxed()
     }
 }
 
-pub(super) fn aggregate(expr: &SimpleExpr) -> Box<dyn super::Processor> {
+/// replace the aggregation operators in `expr` by internal variables, returning the rewritten
+/// expression and the aggregator state that yields the values for these variables
+fn prepare(expr: &SimpleExpr) -> (SimpleExpr, Vec<AggrState>) {
     struct G<'a> {
         state: &'a mut Vec<AggrState>,
         counter: &'a mut u32,
@@ -351,20 +793,12 @@ pub(super) fn aggregate(expr: &SimpleExpr) -> Box<dyn super::Processor> {
                     let name = match self.state.binary_search_by_key(&a, |x| &x.key) {
                         Ok(found) => self.state[found].variable,
                         Err(idx) => {
-                            let aggregator: Box<dyn Aggregator + Send + Sync> = match a.0 {
-                                AggrOp::Sum => Box::<Sum<AddOp>>::default(),
-                                AggrOp::Prod => Box::<Sum<MulOp>>::default(),
-                                AggrOp::Min => Box::new(Min(None)),
-                                AggrOp::Max => Box::new(Max(None)),
-                                AggrOp::First => Box::new(First(None)),
-                                AggrOp::Last => Box::new(Last(None)),
-                            };
                             *self.counter += 1;
                             self.state.insert(
                                 idx,
                                 AggrState {
                                     key: a.clone(),
-                                    aggregator,
+                                    aggregator: aggregator(a.0),
                                     variable: *self.counter,
                                 },
                             );
@@ -380,7 +814,7 @@ pub(super) fn aggregate(expr: &SimpleExpr) -> Box<dyn super::Processor> {
                 // This is about delineated contexts: an AGGREGATE expression treats the AggrOps
                 // (like LAST()) specially, but the expression may also include sub-queries that
                 // run once the aggregation is finished. Inside those sub-queries there might be
-                // an AGGREGATE step, but it is not our place to touch that — it will be treated
+                // an AGGREGATE step, but it is not our place to touch that — it will be treated
                 // properly when its evaluation time comes.
                 SimpleExpr::SubQuery(_) => Tactic::KeepAsIs,
                 _ => Tactic::Scrutinise,
@@ -398,6 +832,12 @@ pub(super) fn aggregate(expr: &SimpleExpr) -> Box<dyn super::Processor> {
         })
         .0;
 
+    (expr, state)
+}
+
+pub(super) fn aggregate(expr: &SimpleExpr) -> Box<dyn super::Processor> {
+    let (expr, state) = prepare(expr);
+
     let order = {
         let mut first = false;
         let mut last = false;
@@ -408,6 +848,10 @@ pub(super) fn aggregate(expr: &SimpleExpr) -> Box<dyn super::Processor> {
                 AggrOp::Prod => other = true,
                 AggrOp::Min => other = true,
                 AggrOp::Max => other = true,
+                AggrOp::Count => other = true,
+                AggrOp::Avg => other = true,
+                AggrOp::StdDev => other = true,
+                AggrOp::P50 | AggrOp::P90 | AggrOp::P95 | AggrOp::P99 => other = true,
                 AggrOp::First => first = true,
                 AggrOp::Last => last = true,
             }
@@ -431,6 +875,27 @@ pub(super) fn aggregate(expr: &SimpleExpr) -> Box<dyn super::Processor> {
     })
 }
 
+pub(super) fn aggregate_by(expr: &SimpleExpr, key: &SimpleExpr) -> Box<dyn super::Processor> {
+    let (expr, template) = prepare(expr);
+    Box::new(AggregateBy {
+        expr,
+        key: key.clone(),
+        template,
+        groups: BTreeMap::new(),
+    })
+}
+
+pub(super) fn aggregate_window(expr: &SimpleExpr, window: Window) -> Box<dyn super::Processor> {
+    let (expr, template) = prepare(expr);
+    Box::new(AggregateWindow {
+        expr,
+        window,
+        template,
+        windows: BTreeMap::new(),
+        watermark: 0,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -449,6 +914,8 @@ mod tests {
         let q = Query::from(ax_aql::Query::parse(&s).unwrap(), app_id!("com.actyx.test")).0;
         match q.stages.into_iter().next().unwrap() {
             Operation::Aggregate(a) => aggregate(&a),
+            Operation::AggregateBy(a, k) => aggregate_by(&a, &k),
+            Operation::AggregateWindow(a, w) => aggregate_window(&a, w),
             _ => panic!(),
         }
     }
@@ -459,24 +926,34 @@ mod tests {
         Context::new(store())
     }
     async fn apply<'a, 'b: 'a>(a: &'a mut dyn Processor, cx: &'a mut Context<'b>, v: u64, t: u64) -> Vec<Value> {
-        cx.bind(
-            "_",
-            Value::new_meta(
-                cx.mk_cbor(|b| b.encode_u64(v)),
-                EventMeta::Event {
-                    key: EventKey {
-                        lamport: t.into(),
-                        stream: NodeId::default().stream(0.into()),
-                        offset: 0.into(),
-                    },
-                    meta: Metadata {
-                        timestamp: 0.into(),
-                        tags: tags!(),
-                        app_id: app_id!("x"),
-                    },
+        apply_anti(a, cx, v, t, false).await
+    }
+    async fn apply_anti<'a, 'b: 'a>(
+        a: &'a mut dyn Processor,
+        cx: &'a mut Context<'b>,
+        v: u64,
+        t: u64,
+        anti: bool,
+    ) -> Vec<Value> {
+        let mut value = Value::new_meta(
+            cx.mk_cbor(|b| b.encode_u64(v)),
+            EventMeta::Event {
+                key: EventKey {
+                    lamport: t.into(),
+                    stream: NodeId::default().stream(0.into()),
+                    offset: 0.into(),
                 },
-            ),
+                meta: Metadata {
+                    timestamp: t.into(),
+                    tags: tags!(),
+                    app_id: app_id!("x"),
+                },
+            },
         );
+        if anti {
+            value.anti();
+        }
+        cx.bind("_", value);
         a.apply(cx).await.into_iter().collect::<anyhow::Result<_>>().unwrap()
     }
     async fn flush<'a, 'b: 'a>(a: &'a mut dyn Processor, cx: &'a mut Context<'b>) -> String {
@@ -547,4 +1024,206 @@ mod tests {
         assert_eq!(apply(&mut *s, &mut cx, 3, 4).await, vec![]);
         assert_eq!(flush(&mut *s, &mut cx).await, "![2, 4, 1, 4],[2, 3, 1, 4]");
     }
+
+    #[tokio::test]
+    async fn group_by() {
+        let mut s = a("[LAST(_ % 2), SUM(_)] GROUP BY _ % 2");
+        let cx = ctx();
+        let mut cx = cx.child();
+
+        assert_eq!(apply(&mut *s, &mut cx, 1, 1).await, vec![]);
+        assert_eq!(apply(&mut *s, &mut cx, 2, 2).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "[0, 2],[1, 1]");
+        // only changed groups are emitted
+        assert_eq!(apply(&mut *s, &mut cx, 3, 3).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "![1, 1],[1, 4]");
+        assert_eq!(flush(&mut *s, &mut cx).await, "");
+
+        // retracting all inputs of a group retracts its result
+        let mut s = a("SUM(_) GROUP BY _ % 2");
+        assert_eq!(apply(&mut *s, &mut cx, 2, 1).await, vec![]);
+        assert_eq!(apply(&mut *s, &mut cx, 1, 2).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "2,1");
+        assert_eq!(apply_anti(&mut *s, &mut cx, 2, 1, true).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "!2");
+        assert_eq!(apply(&mut *s, &mut cx, 4, 3).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "4");
+
+        // an anti-input rejected by an aggregator does not retract the group’s input
+        let mut s = a("MAX(_) GROUP BY _ % 2");
+        assert_eq!(apply(&mut *s, &mut cx, 2, 1).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "2");
+        let mut v = Value::synthetic(cx.mk_cbor(|b| b.encode_u64(2)));
+        v.anti();
+        cx.bind("_", v);
+        assert_eq!(s.apply(&mut cx).await.len(), 1);
+        assert_eq!(flush(&mut *s, &mut cx).await, "");
+    }
+
+    #[tokio::test]
+    async fn statistics() {
+        let mut s = a("[COUNT(_), AVG(_), STDDEV(_)]");
+        let cx = ctx();
+        let mut cx = cx.child();
+
+        assert_eq!(apply(&mut *s, &mut cx, 1, 1).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "[1, 1.0, 0.0]");
+        assert_eq!(apply(&mut *s, &mut cx, 3, 2).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "![1, 1.0, 0.0],[2, 2.0, 1.0]");
+        assert_eq!(apply_anti(&mut *s, &mut cx, 1, 1, true).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "![2, 2.0, 1.0],[1, 3.0, 0.0]");
+
+        let mut count = Count(EventMeta::Synthetic, 0);
+        let mut v = Value::synthetic(cx.mk_cbor(|b| b.encode_u64(1)));
+        v.anti();
+        assert_eq!(
+            count.feed(v.clone()).unwrap_err().to_string(),
+            "anti-input in COUNT() does not match any previous input"
+        );
+        let mut avg = Moments::new(AggrOp::Avg);
+        assert!(avg.feed(v).is_err());
+
+        // the error from a non-numeric input is cleared by retracting it
+        let mut x = Value::synthetic(cx.mk_cbor(|b| b.encode_str("x")));
+        avg.feed(Value::synthetic(cx.mk_cbor(|b| b.encode_u64(4)))).unwrap();
+        avg.feed(x.clone()).unwrap();
+        assert!(avg.flush(&cx).is_err());
+        x.anti();
+        avg.feed(x.clone()).unwrap();
+        assert_eq!(avg.flush(&cx).unwrap().cbor().to_string(), "4.0");
+        assert!(avg.feed(x).is_err());
+    }
+
+    #[tokio::test]
+    async fn percentiles() {
+        let cx = ctx();
+        let cx = cx.child();
+        let value = |n: u64| Value::synthetic(cx.mk_cbor(|b| b.encode_u64(n)));
+        let result = |p: &mut Percentile| match p.flush(&cx).unwrap().as_number().unwrap() {
+            Num::Decimal(d) => d,
+            Num::Natural(n) => n as f64,
+        };
+
+        let mut p50 = Percentile::new(AggrOp::P50, 0.5);
+        let mut p95 = Percentile::new(AggrOp::P95, 0.95);
+        assert!(p50.flush(&cx).is_err());
+        for n in 1..=100 {
+            p50.feed(value(n)).unwrap();
+            p95.feed(value(n)).unwrap();
+        }
+        assert!((result(&mut p50) - 51.0).abs() < 0.6, "{}", result(&mut p50));
+        assert!((result(&mut p95) - 95.0).abs() < 1.0, "{}", result(&mut p95));
+
+        // retract the upper half
+        for n in 51..=100 {
+            let mut v = value(n);
+            v.anti();
+            p50.feed(v).unwrap();
+        }
+        assert!((result(&mut p50) - 26.0).abs() < 0.3, "{}", result(&mut p50));
+
+        let mut v = value(1000);
+        v.anti();
+        assert_eq!(
+            p50.feed(v).unwrap_err().to_string(),
+            "anti-input in P50() does not match any previous input"
+        );
+
+        let mut s = Value::synthetic(cx.mk_cbor(|b| b.encode_str("x")));
+        p50.feed(s.clone()).unwrap();
+        assert!(p50.flush(&cx).is_err());
+        s.anti();
+        p50.feed(s).unwrap();
+        assert!((result(&mut p50) - 26.0).abs() < 0.3, "{}", result(&mut p50));
+    }
+
+    #[tokio::test]
+    async fn window() {
+        let mut s = a("COUNT(_) WINDOW 1s");
+        let cx = ctx();
+        let mut cx = cx.child();
+
+        assert_eq!(apply(&mut *s, &mut cx, 1, 1).await, vec![]);
+        assert_eq!(apply(&mut *s, &mut cx, 1, 1_000_001).await, vec![]);
+        assert_eq!(apply(&mut *s, &mut cx, 1, 2).await, vec![]);
+        let result = s
+            .flush(&mut cx)
+            .await
+            .into_iter()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            result.iter().map(|v| v.cbor().to_string()).collect::<Vec<_>>(),
+            vec!["2", "1"]
+        );
+        // TIME(_) of a result yields the window bounds
+        match result[1].meta() {
+            EventMeta::Range { from_time, to_time, .. } => {
+                assert_eq!(*from_time, Timestamp::new(1_000_000));
+                assert_eq!(*to_time, Timestamp::new(2_000_000));
+            }
+            m => panic!("unexpected meta {:?}", m),
+        }
+
+        // the last windows end at the largest representable time
+        let mut s = a("COUNT(_) WINDOW 10ms EVERY 5ms");
+        assert_eq!(apply(&mut *s, &mut cx, 1, u64::MAX - 3).await, vec![]);
+        let result = s
+            .flush(&mut cx)
+            .await
+            .into_iter()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(result.len(), 2);
+        match result[1].meta() {
+            EventMeta::Range { from_time, to_time, .. } => {
+                assert_eq!(*from_time, Timestamp::new(u64::MAX - 3 - (u64::MAX - 3) % 5_000));
+                assert_eq!(*to_time, Timestamp::new(u64::MAX));
+            }
+            m => panic!("unexpected meta {:?}", m),
+        }
+    }
+
+    #[tokio::test]
+    async fn window_live() {
+        let mut s = a("COUNT(_) WINDOW 1s EVERY 500ms");
+        let cx = ctx().live();
+        let mut cx = cx.child();
+
+        assert_eq!(apply(&mut *s, &mut cx, 1, 100_000).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "");
+        // closes the first window
+        assert_eq!(apply(&mut *s, &mut cx, 1, 1_200_000).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "1");
+        // late input only counts in the windows that are still open
+        assert_eq!(apply(&mut *s, &mut cx, 1, 900_000).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "");
+        assert_eq!(apply(&mut *s, &mut cx, 1, 2_000_000).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "2,1");
+        // closed windows are final
+        assert_eq!(apply_anti(&mut *s, &mut cx, 1, 100_000, true).await, vec![]);
+        assert_eq!(apply_anti(&mut *s, &mut cx, 1, 900_000, true).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "");
+        // retraction within an open window
+        assert_eq!(apply(&mut *s, &mut cx, 1, 2_400_000).await, vec![]);
+        assert_eq!(apply_anti(&mut *s, &mut cx, 1, 2_000_000, true).await, vec![]);
+        assert_eq!(apply(&mut *s, &mut cx, 1, 3_000_000).await, vec![]);
+        assert_eq!(flush(&mut *s, &mut cx).await, "1,1");
+    }
+
+    #[test]
+    fn window_starts() {
+        let w = |size: u64, slide: u64| Window {
+            size: size.try_into().unwrap(),
+            slide: slide.try_into().unwrap(),
+        };
+        let starts = |win: Window, t: u64| super::window_starts(&win, t).collect::<Vec<_>>();
+        assert_eq!(starts(w(10, 10), 0), vec![0]);
+        assert_eq!(starts(w(10, 10), 19), vec![10]);
+        assert_eq!(starts(w(10, 5), 12), vec![5, 10]);
+        assert_eq!(starts(w(10, 5), 15), vec![10, 15]);
+        assert_eq!(starts(w(10, 3), 12), vec![3, 6, 9, 12]);
+        assert_eq!(starts(w(10, 3), 2), vec![0]);
+        assert_eq!(starts(w(10, 5), u64::MAX - 3), vec![u64::MAX - 10, u64::MAX - 5]);
+    }
 }
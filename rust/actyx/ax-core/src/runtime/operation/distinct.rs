@@ -0,0 +1,360 @@
+use super::Processor;
+use crate::runtime::{error::RuntimeFailure, eval::Context, value::Value};
+use ax_aql::SimpleExpr;
+use futures::{future::BoxFuture, FutureExt};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    num::NonZeroU64,
+};
+
+/// number of inputs remembered per key on live subscriptions, including the emitted one
+const REMEMBER_PER_KEY: usize = 16;
+
+/// The inputs remembered for one key, where the first one is the one that was emitted.
+struct Group {
+    /// position of this key in `Distinct::recent`
+    used: u64,
+    /// only filled on live subscriptions, where anti-inputs may retract earlier inputs
+    inputs: VecDeque<Value>,
+    /// number of further inputs that were not remembered since `inputs` was full
+    dropped: u64,
+}
+
+struct Distinct {
+    key: Option<SimpleExpr>,
+    /// only remember this many keys, forgetting the least recently seen ones
+    keep: Option<usize>,
+    seq: u64,
+    /// groups by the CBOR encoding of their key
+    groups: BTreeMap<Vec<u8>, Group>,
+    /// keys by the sequence number of their last use, for evicting the least recently seen
+    recent: BTreeMap<u64, Vec<u8>>,
+}
+
+impl Distinct {
+    /// mark the key as recently used, returning its group and whether it is new
+    fn touch(&mut self, key: Vec<u8>) -> (&mut Group, bool) {
+        let seq = self.seq;
+        self.seq += 1;
+        let mut new = false;
+        let group = self.groups.entry(key.clone()).or_insert_with(|| {
+            new = true;
+            Group {
+                used: seq,
+                inputs: VecDeque::new(),
+                dropped: 0,
+            }
+        });
+        self.recent.remove(&group.used);
+        group.used = seq;
+        self.recent.insert(seq, key);
+        (group, new)
+    }
+
+    fn evict(&mut self) {
+        let Some(keep) = self.keep else { return };
+        while self.groups.len() > keep {
+            let Some((_, key)) = self.recent.pop_first() else {
+                return;
+            };
+            self.groups.remove(&key);
+        }
+    }
+
+    fn forget(&mut self, key: &[u8]) {
+        if let Some(group) = self.groups.remove(key) {
+            self.recent.remove(&group.used);
+        }
+    }
+}
+
+impl Processor for Distinct {
+    fn apply<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
+        async move {
+            /*
+             * Anti-flag propagation:
+             *
+             * On live subscriptions all inputs are remembered per key. An anti-input removes
+             * its matching input; if that was the emitted one, it is retracted and the next
+             * remaining input for the same key (if any) is emitted in its place. Anti-inputs
+             * for keys that are not remembered (e.g. evicted due to KEEP) stop the query with
+             * an error, since we cannot know what was emitted for them.
+             *
+             * Only the first REMEMBER_PER_KEY inputs of a key are stored, later ones are only
+             * counted. An anti-input that matches none of the stored inputs retracts one of the
+             * counted ones, and the key is only forgotten once all its inputs have been retracted.
+             * Retracting the emitted input while only counted ones remain stops the query with an
+             * error, since there is no remembered input left to emit in its place.
+             */
+            let key = match &self.key {
+                Some(expr) => match cx.eval(expr).await {
+                    Ok(key) => Some(key.as_slice().to_vec()),
+                    Err(e) => return vec![Err(e)],
+                },
+                None => None,
+            };
+            let input = match cx.remove("_") {
+                Ok(input) => input,
+                Err(e) => return vec![Err(e)],
+            };
+            let key = key.unwrap_or_else(|| input.as_slice().to_vec());
+
+            if input.is_anti() {
+                let Some(group) = self.groups.get_mut(&key) else {
+                    return vec![Err(RuntimeFailure::AntiInputInDistinct.into())];
+                };
+                let pos = group
+                    .inputs
+                    .iter()
+                    .position(|v| v.meta() == input.meta() && v.as_slice() == input.as_slice());
+                let removed = match pos {
+                    Some(0) if group.inputs.len() == 1 && group.dropped > 0 => {
+                        return vec![Err(RuntimeFailure::AntiInputInDistinct.into())]
+                    }
+                    Some(pos) => group.inputs.remove(pos),
+                    None if group.dropped > 0 => {
+                        group.dropped -= 1;
+                        None
+                    }
+                    None => return vec![Err(RuntimeFailure::AntiInputInDistinct.into())],
+                };
+                let next = group.inputs.front().cloned();
+                if group.inputs.is_empty() && group.dropped == 0 {
+                    self.forget(&key);
+                }
+                return match (pos, removed) {
+                    (Some(0), Some(mut removed)) => {
+                        removed.anti();
+                        std::iter::once(removed).chain(next).map(Ok).collect()
+                    }
+                    _ => vec![],
+                };
+            }
+
+            let live = cx.is_live();
+            let (group, new) = self.touch(key);
+            if live {
+                if group.inputs.len() < REMEMBER_PER_KEY {
+                    group.inputs.push_back(input.clone());
+                } else {
+                    group.dropped += 1;
+                }
+            }
+            self.evict();
+            if new {
+                vec![Ok(input)]
+            } else {
+                vec![]
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Only let the first input per `key` (or per value, without key) pass; with `keep` at most that
+/// many keys are remembered, so that a key may pass again after having been evicted.
+pub(super) fn distinct(key: &Option<SimpleExpr>, keep: Option<NonZeroU64>) -> Box<dyn Processor> {
+    Box::new(Distinct {
+        key: key.clone(),
+        keep: keep.map(|k| usize::try_from(k.get()).unwrap_or(usize::MAX)),
+        seq: 0,
+        groups: BTreeMap::new(),
+        recent: BTreeMap::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{eval::RootContext, query::Query},
+        swarm::event_store_ref::EventStoreRef,
+    };
+    use ax_types::app_id;
+
+    fn store() -> EventStoreRef {
+        EventStoreRef::new(|_x| Err(crate::swarm::event_store_ref::Error::Aborted))
+    }
+    fn ctx() -> RootContext {
+        Context::new(store())
+    }
+
+    async fn run(q: &str, inputs: &[&str]) -> Vec<String> {
+        let q = Query::from(ax_aql::Query::parse(q).unwrap(), app_id!("com.actyx.test")).0;
+        let mut feeder = q.make_feeder();
+        let cx = ctx();
+        let cx = cx.child();
+        let mut results = vec![];
+        for input in inputs {
+            let v = cx.eval(&input.parse().unwrap()).await.unwrap();
+            results.extend(feeder.feed(Some(v), &cx).await);
+        }
+        results.extend(feeder.feed(None, &cx).await);
+        results
+            .into_iter()
+            .map(|v| v.map(|v| v.cbor().to_string()).unwrap_or_else(|e| e.to_string()))
+            .collect()
+    }
+
+    fn show(values: Vec<anyhow::Result<Value>>) -> Vec<String> {
+        values
+            .into_iter()
+            .map(|v| match v {
+                Ok(v) if v.is_anti() => format!("anti {}", v.cbor()),
+                Ok(v) => v.cbor().to_string(),
+                Err(e) => e.to_string(),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn values() {
+        assert_eq!(
+            run("FROM 'x' DISTINCT", &["1", "2", "1", "'a'", "2", "'a'", "3"]).await,
+            vec!["1", "2", "\"a\"", "3"]
+        );
+        assert_eq!(
+            run(
+                "FROM 'x' DISTINCT ON _.k SELECT _.n",
+                &["{k: 1 n: 1}", "{k: 2 n: 2}", "{k: 1 n: 3}", "{n: 4}", "{k: 3 n: 5}"]
+            )
+            .await,
+            vec!["1", "2", "property `k` not found in Object", "5"]
+        );
+    }
+
+    #[tokio::test]
+    async fn bounded() {
+        assert_eq!(
+            run("FROM 'x' DISTINCT KEEP 2", &["1", "2", "1", "3", "1", "2", "2"]).await,
+            vec!["1", "2", "3", "2"]
+        );
+
+        let mut p = Distinct {
+            key: None,
+            keep: Some(2),
+            seq: 0,
+            groups: BTreeMap::new(),
+            recent: BTreeMap::new(),
+        };
+        let cx = ctx().live();
+        let mut cx = cx.child();
+        for n in 0..10 {
+            let v = cx.eval(&format!("{}", n % 5).parse().unwrap()).await.unwrap();
+            cx.bind("_", v);
+            p.apply(&mut cx).await;
+            assert!(p.groups.len() <= 2);
+            assert!(p.recent.len() <= 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn anti() {
+        let mut p = distinct(&Some("_.k".parse().unwrap()), None);
+        let cx = ctx().live();
+        let mut cx = cx.child();
+        let v1 = cx.eval(&"{k: 1 n: 1}".parse().unwrap()).await.unwrap();
+        let v2 = cx.eval(&"{k: 1 n: 2}".parse().unwrap()).await.unwrap();
+        let v3 = cx.eval(&"{k: 2 n: 3}".parse().unwrap()).await.unwrap();
+        let anti = |v: &Value| {
+            let mut v = v.clone();
+            v.anti();
+            v
+        };
+
+        for v in [&v1, &v2, &v3] {
+            cx.bind("_", v.clone());
+            p.apply(&mut cx).await;
+        }
+
+        // retracting a suppressed input changes nothing
+        cx.bind("_", anti(&v2));
+        assert_eq!(show(p.apply(&mut cx).await), Vec::<String>::new());
+        cx.bind("_", v2.clone());
+        assert_eq!(show(p.apply(&mut cx).await), Vec::<String>::new());
+
+        // retracting the emitted input promotes the next one
+        cx.bind("_", anti(&v1));
+        assert_eq!(
+            show(p.apply(&mut cx).await),
+            vec!["anti {\"k\": 1, \"n\": 1}", "{\"k\": 1, \"n\": 2}"]
+        );
+        cx.bind("_", anti(&v2));
+        assert_eq!(show(p.apply(&mut cx).await), vec!["anti {\"k\": 1, \"n\": 2}"]);
+
+        // the key is free again
+        cx.bind("_", v1.clone());
+        assert_eq!(show(p.apply(&mut cx).await), vec!["{\"k\": 1, \"n\": 1}"]);
+
+        cx.bind("_", anti(&v2));
+        assert_eq!(
+            show(p.apply(&mut cx).await),
+            vec!["anti-input in DISTINCT does not match any remembered input"]
+        );
+    }
+
+    #[tokio::test]
+    async fn remember_per_key() {
+        let mut p = Distinct {
+            key: Some("_.k".parse().unwrap()),
+            keep: None,
+            seq: 0,
+            groups: BTreeMap::new(),
+            recent: BTreeMap::new(),
+        };
+        let cx = ctx().live();
+        let mut cx = cx.child();
+        let n = REMEMBER_PER_KEY + 5;
+        let mut inputs = vec![];
+        for n in 0..n {
+            let v = cx.eval(&format!("{{k: 1 n: {}}}", n).parse().unwrap()).await.unwrap();
+            cx.bind("_", v.clone());
+            p.apply(&mut cx).await;
+            inputs.push(v);
+        }
+        assert_eq!(p.groups.len(), 1);
+        let group = p.groups.values().next().unwrap();
+        assert_eq!(group.inputs.len(), REMEMBER_PER_KEY);
+        assert_eq!(group.dropped, 5);
+
+        // retracting inputs that were not remembered only counts them down
+        for v in inputs.iter().rev().take(5) {
+            let mut v = v.clone();
+            v.anti();
+            cx.bind("_", v);
+            assert_eq!(show(p.apply(&mut cx).await), Vec::<String>::new());
+        }
+        // retracting the remembered inputs frees the key
+        for v in inputs.iter().skip(1).take(REMEMBER_PER_KEY - 1) {
+            let mut v = v.clone();
+            v.anti();
+            cx.bind("_", v);
+            assert_eq!(show(p.apply(&mut cx).await), Vec::<String>::new());
+        }
+        let mut v = inputs[0].clone();
+        v.anti();
+        cx.bind("_", v);
+        assert_eq!(show(p.apply(&mut cx).await), vec!["anti {\"k\": 1, \"n\": 0}"]);
+        assert!(p.groups.is_empty());
+        assert!(p.recent.is_empty());
+
+        // retracting all remembered inputs while counted ones remain cannot be answered
+        for v in &inputs {
+            cx.bind("_", v.clone());
+            p.apply(&mut cx).await;
+        }
+        for v in inputs.iter().skip(1).take(REMEMBER_PER_KEY - 1) {
+            let mut v = v.clone();
+            v.anti();
+            cx.bind("_", v);
+            assert_eq!(show(p.apply(&mut cx).await), Vec::<String>::new());
+        }
+        let mut v = inputs[0].clone();
+        v.anti();
+        cx.bind("_", v);
+        assert_eq!(
+            show(p.apply(&mut cx).await),
+            vec!["anti-input in DISTINCT does not match any remembered input"]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use super::{Processor, StageState};
+use crate::{
+    ax_futures_util::ReceiverExt,
+    runtime::{eval::Context, value::Value},
+};
+use cbor_data::{Encoder, Writer};
+use futures::{future::BoxFuture, FutureExt, StreamExt};
+use std::collections::BTreeMap;
+
+/// joined events by the CBOR encoding of their key
+type Index = BTreeMap<Vec<u8>, Vec<Value>>;
+
+struct Join {
+    join: ax_aql::Join,
+    /// loaded when the first input arrives
+    index: Option<Index>,
+}
+
+/// Read all events selected by the join from the same offset range as the query, returning
+/// them indexed by their key along with the errors from computing keys.
+async fn load(join: &ax_aql::Join, cx: &Context<'_>) -> anyhow::Result<(Index, Vec<anyhow::Result<Value>>)> {
+    let tag_expr = cx.eval_from(&join.from).await?.into_owned();
+    let mut events = cx
+        .store()
+        .bounded_forward(
+            tag_expr,
+            cx.from_offsets_excluding().clone(),
+            cx.to_offsets_including().clone(),
+            false,
+        )
+        .await?
+        .stop_on_error();
+    let mut index = Index::new();
+    let mut errors = vec![];
+    while let Some(ev) = events.next().await {
+        let value = Value::from(ev?);
+        let mut cx = cx.child();
+        cx.bind(join.name.as_str(), value.clone());
+        match cx.eval(&join.other_key).await {
+            Ok(key) => index.entry(key.as_slice().to_vec()).or_default().push(value),
+            Err(e) => errors.push(Err(e)),
+        }
+    }
+    Ok((index, errors))
+}
+
+impl Processor for Join {
+    fn apply<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
+        async move {
+            /*
+             * Anti-flag propagation:
+             *
+             * The joined events are fixed when the first input arrives, so an anti-input
+             * retracts exactly the pairs that were emitted for the corresponding input.
+             */
+            let mut result = vec![];
+            if self.index.is_none() {
+                match load(&self.join, cx).await {
+                    Ok((index, errors)) => {
+                        self.index = Some(index);
+                        result = errors;
+                    }
+                    Err(e) => return vec![Err(e)],
+                }
+            }
+            let key = match cx.eval(&self.join.key).await {
+                Ok(key) => key,
+                Err(e) => {
+                    result.push(Err(e));
+                    return result;
+                }
+            };
+            let input = match cx.remove("_") {
+                Ok(input) => input,
+                Err(e) => {
+                    result.push(Err(e));
+                    return result;
+                }
+            };
+            let pair = |other: Option<&Value>| {
+                let cbor = cx.mk_cbor(|b| {
+                    b.encode_array(|b| {
+                        b.write_trusting(input.as_slice());
+                        match other {
+                            Some(other) => {
+                                b.write_trusting(other.as_slice());
+                            }
+                            None => {
+                                b.encode_null();
+                            }
+                        }
+                    })
+                });
+                let mut value = Value::new_meta(cbor, input.meta().clone());
+                if input.is_anti() {
+                    value.anti();
+                }
+                Ok(value)
+            };
+            match self.index.as_ref().and_then(|index| index.get(key.as_slice())) {
+                Some(others) => result.extend(others.iter().map(|other| pair(Some(other)))),
+                None if self.join.outer => result.push(pair(None)),
+                None => {}
+            }
+            result
+        }
+        .boxed()
+    }
+
+    fn save(&self) -> Option<StageState> {
+        // the index is loaded again from the same offset range when continuing
+        Some(StageState::Stateless)
+    }
+}
+
+pub(super) fn join(join: &ax_aql::Join) -> Box<dyn Processor> {
+    Box::new(Join {
+        join: join.clone(),
+        index: None,
+    })
+}
@@ -4,11 +4,16 @@ use crate::runtime::{
     query::Query,
     value::Value,
 };
-use ax_aql::{NonEmptyVec, SimpleExpr, SpreadExpr};
+use ax_aql::{NonEmptyVec, SimpleExpr, SortOrder, SpreadExpr, Window};
 use ax_types::service::Order;
 
 mod aggregate;
+mod distinct;
+mod join;
+mod sketch;
+mod sort;
 use futures::{future::BoxFuture, FutureExt};
+use serde::{Deserialize, Serialize};
 use std::{future::ready, num::NonZeroU64};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -16,8 +21,13 @@ pub enum Operation {
     Filter(SimpleExpr),
     Select(NonEmptyVec<SpreadExpr>),
     Aggregate(SimpleExpr),
+    AggregateBy(SimpleExpr, SimpleExpr),
+    AggregateWindow(SimpleExpr, Window),
+    OrderBy(SimpleExpr, SortOrder),
     Limit(NonZeroU64),
+    Distinct(Option<SimpleExpr>, Option<NonZeroU64>),
     Binding(String, SimpleExpr),
+    Join(ax_aql::Join),
 }
 
 #[allow(unused_variables)]
@@ -41,6 +51,28 @@ pub(crate) trait Processor: Send + 'static {
     fn is_done(&self, order: Order) -> bool {
         false
     }
+
+    /// capture the state needed to continue this stage in a later request, if possible
+    fn save(&self) -> Option<StageState> {
+        None
+    }
+
+    /// continue from the state captured by [`Processor::save`]
+    fn restore(&mut self, state: &StageState) -> anyhow::Result<()> {
+        match state {
+            StageState::Stateless => Ok(()),
+            _ => anyhow::bail!("cannot restore stage from {:?}", state),
+        }
+    }
+}
+
+/// State of one query stage between two pages of query results.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StageState {
+    /// the stage does not carry state from one input to the next
+    Stateless,
+    /// the number of values a LIMIT stage will still let through
+    Limit(u64),
 }
 
 impl Operation {
@@ -49,10 +81,31 @@ impl Operation {
             Operation::Filter(f) => Box::new(Filter(f.clone())),
             Operation::Select(s) => Box::new(Select(s.clone())),
             Operation::Aggregate(a) => aggregate::aggregate(a),
-            Operation::Limit(l) => Box::new(Limit((*l).into())),
+            Operation::AggregateBy(a, k) => aggregate::aggregate_by(a, k),
+            Operation::AggregateWindow(a, w) => aggregate::aggregate_window(a, *w),
+            Operation::OrderBy(e, o) => sort::order_by(e, *o, None),
+            Operation::Limit(l) => Box::new(Limit {
+                remaining: l.get(),
+                limit: l.get(),
+            }),
+            Operation::Distinct(k, n) => distinct::distinct(k, *n),
             Operation::Binding(n, e) => Box::new(Binding(n.clone(), e.clone())),
+            Operation::Join(j) => join::join(j),
         }
     }
+
+    /// Instantiate the processors for a sequence of stages, where an `ORDER BY` that is directly
+    /// followed by a `LIMIT` only needs to retain as many values as the limit lets through.
+    pub(super) fn make_processors(stages: &[Operation]) -> Vec<Box<dyn Processor>> {
+        stages
+            .iter()
+            .enumerate()
+            .map(|(idx, op)| match (op, stages.get(idx + 1)) {
+                (Operation::OrderBy(e, o), Some(Operation::Limit(l))) => sort::order_by(e, *o, Some(*l)),
+                _ => op.make_processor(),
+            })
+            .collect()
+    }
 }
 
 impl From<ax_aql::Operation> for Operation {
@@ -61,12 +114,40 @@ impl From<ax_aql::Operation> for Operation {
             ax_aql::Operation::Filter(f) => Self::Filter(f),
             ax_aql::Operation::Select(s) => Self::Select(s),
             ax_aql::Operation::Aggregate(a) => Self::Aggregate(a),
+            ax_aql::Operation::AggregateBy(a, k) => Self::AggregateBy(a, k),
+            ax_aql::Operation::AggregateWindow(a, w) => Self::AggregateWindow(a, w),
+            ax_aql::Operation::OrderBy(e, o) => Self::OrderBy(e, o),
             ax_aql::Operation::Limit(l) => Self::Limit(l),
+            ax_aql::Operation::Distinct(k, n) => Self::Distinct(k, n),
             ax_aql::Operation::Binding(n, e) => Self::Binding(n, e),
+            ax_aql::Operation::Join(j) => Self::Join(j),
+        }
+    }
+}
+
+impl From<Operation> for ax_aql::Operation {
+    fn from(op: Operation) -> Self {
+        match op {
+            Operation::Filter(f) => Self::Filter(f),
+            Operation::Select(s) => Self::Select(s),
+            Operation::Aggregate(a) => Self::Aggregate(a),
+            Operation::AggregateBy(a, k) => Self::AggregateBy(a, k),
+            Operation::AggregateWindow(a, w) => Self::AggregateWindow(a, w),
+            Operation::OrderBy(e, o) => Self::OrderBy(e, o),
+            Operation::Limit(l) => Self::Limit(l),
+            Operation::Distinct(k, n) => Self::Distinct(k, n),
+            Operation::Binding(n, e) => Self::Binding(n, e),
+            Operation::Join(j) => Self::Join(j),
         }
     }
 }
 
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        ax_aql::Operation::from(self.clone()).fmt(f)
+    }
+}
+
 struct Filter(SimpleExpr);
 impl Processor for Filter {
     fn apply<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
@@ -92,6 +173,10 @@ impl Processor for Filter {
         }
         .boxed()
     }
+
+    fn save(&self) -> Option<StageState> {
+        Some(StageState::Stateless)
+    }
 }
 
 struct Select(NonEmptyVec<SpreadExpr>);
@@ -143,9 +228,18 @@ impl Processor for Select {
         }
         .boxed()
     }
+
+    fn save(&self) -> Option<StageState> {
+        Some(StageState::Stateless)
+    }
 }
 
-struct Limit(u64);
+struct Limit {
+    /// number of values this stage will still let through
+    remaining: u64,
+    /// the configured limit, which bounds the states this stage may be restored from
+    limit: u64,
+}
 impl Processor for Limit {
     fn apply<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
         async move {
@@ -158,11 +252,11 @@ impl Processor for Limit {
              * corresponding output was emitted or suppressed earlier, so we stop the query
              * with an error.
              */
-            if self.0 > 0 {
+            if self.remaining > 0 {
                 let v = cx.remove("_");
                 match &v {
-                    Ok(v) if v.is_anti() => self.0 += 1,
-                    Ok(_) => self.0 -= 1,
+                    Ok(v) if v.is_anti() => self.remaining += 1,
+                    Ok(_) => self.remaining -= 1,
                     _ => {}
                 }
                 vec![v]
@@ -179,7 +273,22 @@ impl Processor for Limit {
     }
 
     fn is_done(&self, _order: Order) -> bool {
-        self.0 == 0
+        self.remaining == 0
+    }
+
+    fn save(&self) -> Option<StageState> {
+        Some(StageState::Limit(self.remaining))
+    }
+
+    fn restore(&mut self, state: &StageState) -> anyhow::Result<()> {
+        match state {
+            StageState::Limit(n) => {
+                anyhow::ensure!(*n <= self.limit, "cannot restore LIMIT {} from {:?}", self.limit, state);
+                self.remaining = *n;
+                Ok(())
+            }
+            _ => anyhow::bail!("cannot restore LIMIT from {:?}", state),
+        }
     }
 }
 
@@ -203,6 +312,10 @@ impl Processor for Binding {
         }
         .boxed()
     }
+
+    fn save(&self) -> Option<StageState> {
+        Some(StageState::Stateless)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+
+/// Relative accuracy of the quantiles computed by [`Sketch`]
+const ALPHA: f64 = 0.01;
+/// Magnitudes below this value are counted as zero
+const MIN_INDEXABLE: f64 = 1e-9;
+
+/// A quantile sketch with logarithmically sized buckets (as in DDSketch).
+///
+/// Every value is counted in the bucket covering its magnitude, so that the quantiles
+/// computed from this sketch are within [`ALPHA`] relative error of the exact result.
+/// Since buckets only hold counts, values can also be removed again (which is needed for
+/// processing anti-inputs) and two sketches could be merged by adding up their buckets.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sketch {
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+    zero: u64,
+    count: u64,
+}
+
+fn gamma() -> f64 {
+    (1.0 + ALPHA) / (1.0 - ALPHA)
+}
+
+fn index(magnitude: f64) -> i32 {
+    (magnitude.ln() / gamma().ln()).ceil() as i32
+}
+
+fn value(index: i32) -> f64 {
+    let gamma = gamma();
+    2.0 * gamma.powi(index) / (gamma + 1.0)
+}
+
+impl Sketch {
+    pub fn insert(&mut self, v: f64) {
+        if v > MIN_INDEXABLE {
+            *self.positive.entry(index(v)).or_default() += 1;
+        } else if v < -MIN_INDEXABLE {
+            *self.negative.entry(index(-v)).or_default() += 1;
+        } else {
+            self.zero += 1;
+        }
+        self.count += 1;
+    }
+
+    /// Remove a value that was previously inserted, returns `false` if it cannot have been.
+    pub fn remove(&mut self, v: f64) -> bool {
+        let removed = if v > MIN_INDEXABLE {
+            decrement(&mut self.positive, index(v))
+        } else if v < -MIN_INDEXABLE {
+            decrement(&mut self.negative, index(-v))
+        } else if self.zero > 0 {
+            self.zero -= 1;
+            true
+        } else {
+            false
+        };
+        if removed {
+            self.count -= 1;
+        }
+        removed
+    }
+
+    /// Compute the `q`-quantile (with `q` between 0 and 1), `None` if the sketch is empty.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (q.clamp(0.0, 1.0) * (self.count - 1) as f64).round() as u64;
+        let mut seen = 0;
+        // most negative values live in the largest indices of the negative buckets
+        for (idx, n) in self.negative.iter().rev() {
+            seen += n;
+            if seen > rank {
+                return Some(-value(*idx));
+            }
+        }
+        seen += self.zero;
+        if seen > rank {
+            return Some(0.0);
+        }
+        for (idx, n) in self.positive.iter() {
+            seen += n;
+            if seen > rank {
+                return Some(value(*idx));
+            }
+        }
+        // unreachable since the bucket counts add up to `count`
+        None
+    }
+}
+
+fn decrement(buckets: &mut BTreeMap<i32, u64>, idx: i32) -> bool {
+    match buckets.get_mut(&idx) {
+        Some(n) if *n > 1 => {
+            *n -= 1;
+            true
+        }
+        Some(_) => {
+            buckets.remove(&idx);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(actual: Option<f64>, expected: f64) {
+        let actual = actual.unwrap();
+        assert!(
+            (actual - expected).abs() <= expected.abs() * (ALPHA + 1e-9) + MIN_INDEXABLE,
+            "{} is not close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn quantiles() {
+        let mut s = Sketch::default();
+        assert_eq!(s.quantile(0.5), None);
+        for i in 1..=100 {
+            s.insert(i as f64);
+        }
+        close(s.quantile(0.0), 1.0);
+        close(s.quantile(0.5), 51.0);
+        close(s.quantile(0.95), 95.0);
+        close(s.quantile(1.0), 100.0);
+
+        let mut n = Sketch::default();
+        for i in 0..=100 {
+            n.insert(-i as f64);
+        }
+        close(n.quantile(0.0), -100.0);
+        close(n.quantile(0.5), -50.0);
+        close(n.quantile(1.0), 0.0);
+    }
+
+    #[test]
+    fn remove() {
+        let mut s = Sketch::default();
+        s.insert(3.0);
+        s.insert(-2.0);
+        s.insert(0.0);
+        assert!(s.remove(3.0));
+        assert!(!s.remove(3.0));
+        assert!(s.remove(0.0));
+        assert!(!s.remove(0.0));
+        close(s.quantile(0.5), -2.0);
+        close(s.quantile(1.0), -2.0);
+
+        assert!(s.remove(-2.0));
+        assert_eq!(s.quantile(0.5), None);
+        assert_eq!(s, Sketch::default());
+    }
+}
@@ -0,0 +1,246 @@
+use super::Processor;
+use crate::runtime::{
+    error::RuntimeFailure,
+    eval::Context,
+    value::{Value, ValueKind},
+};
+use ax_aql::{SimpleExpr, SortOrder};
+use cbor_data::CborValue;
+use futures::{future::BoxFuture, FutureExt};
+use std::{cmp::Ordering, collections::BinaryHeap, future::ready, num::NonZeroU64};
+
+/// An input value buffered together with its sort key.
+///
+/// Entries are ordered such that the entry to be emitted first is the smallest, which puts the
+/// entry to be dropped first on top of the (max-)heap when only the top k are retained.
+struct Entry {
+    key: Value,
+    seq: u64,
+    order: SortOrder,
+    value: Value,
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let key = compare(&self.key, &other.key);
+        let key = match self.order {
+            SortOrder::Asc => key,
+            SortOrder::Desc => key.reverse(),
+        };
+        // ties are broken by input order, which makes the sort stable
+        key.then(self.seq.cmp(&other.seq))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+fn rank(kind: ValueKind) -> u8 {
+    match kind {
+        ValueKind::Null => 0,
+        ValueKind::Bool => 1,
+        ValueKind::Number => 2,
+        ValueKind::Timestamp => 3,
+        ValueKind::String => 4,
+        ValueKind::Bytes => 5,
+        ValueKind::Array => 6,
+        ValueKind::Object => 7,
+        ValueKind::Other => 8,
+    }
+}
+
+/// Total order on sort keys: values of different kinds are ordered by kind (NULL first), values of
+/// the same kind by their natural order if they have one and by their CBOR encoding otherwise.
+fn compare(left: &Value, right: &Value) -> Ordering {
+    let (l, r) = (left.kind(), right.kind());
+    if l != r {
+        return rank(l).cmp(&rank(r));
+    }
+    let natural = match (left.value(), right.value()) {
+        (CborValue::Timestamp(l), CborValue::Timestamp(r)) => {
+            Some((l.unix_epoch(), l.nanos()).cmp(&(r.unix_epoch(), r.nanos())))
+        }
+        _ => left.partial_cmp(right),
+    };
+    natural.unwrap_or_else(|| left.as_slice().cmp(right.as_slice()))
+}
+
+struct OrderBy {
+    expr: SimpleExpr,
+    order: SortOrder,
+    /// only retain this many entries (set when directly followed by LIMIT)
+    limit: Option<usize>,
+    seq: u64,
+    entries: BinaryHeap<Entry>,
+}
+
+impl Processor for OrderBy {
+    fn apply<'a, 'b: 'a>(&'a mut self, cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
+        async move {
+            /*
+             * Anti-flag propagation:
+             *
+             * Since all outputs are emitted when flushing, retracting an input would require
+             * retracting the whole sorted sequence, so anti-inputs stop the query with an error.
+             */
+            let key = match cx.eval(&self.expr).await {
+                Ok(key) => key,
+                Err(e) => return vec![Err(e)],
+            };
+            let value = match cx.remove("_") {
+                Ok(value) => value,
+                Err(e) => return vec![Err(e)],
+            };
+            if value.is_anti() {
+                return vec![Err(RuntimeFailure::AntiInputInOrderBy.into())];
+            }
+            self.entries.push(Entry {
+                key,
+                seq: self.seq,
+                order: self.order,
+                value,
+            });
+            self.seq += 1;
+            if let Some(limit) = self.limit {
+                if self.entries.len() > limit {
+                    self.entries.pop();
+                }
+            }
+            vec![]
+        }
+        .boxed()
+    }
+
+    fn flush<'a, 'b: 'a>(&'a mut self, _cx: &'a mut Context<'b>) -> BoxFuture<'a, Vec<anyhow::Result<Value>>> {
+        let entries = std::mem::take(&mut self.entries);
+        let result = entries.into_sorted_vec().into_iter().map(|e| Ok(e.value)).collect();
+        ready(result).boxed()
+    }
+}
+
+/// Sort all inputs by `expr`; with a `limit` only the first `limit` outputs are retained while
+/// sorting, using memory proportional to the limit instead of the number of inputs.
+pub(super) fn order_by(expr: &SimpleExpr, order: SortOrder, limit: Option<NonZeroU64>) -> Box<dyn Processor> {
+    Box::new(OrderBy {
+        expr: expr.clone(),
+        order,
+        limit: limit.map(|l| usize::try_from(l.get()).unwrap_or(usize::MAX)),
+        seq: 0,
+        entries: BinaryHeap::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{eval::RootContext, query::Query},
+        swarm::event_store_ref::EventStoreRef,
+    };
+    use ax_types::app_id;
+    use cbor_data::Encoder;
+
+    fn store() -> EventStoreRef {
+        EventStoreRef::new(|_x| Err(crate::swarm::event_store_ref::Error::Aborted))
+    }
+    fn ctx() -> RootContext {
+        Context::new(store())
+    }
+
+    async fn run(q: &str, inputs: &[&str]) -> Vec<String> {
+        let q = Query::from(ax_aql::Query::parse(q).unwrap(), app_id!("com.actyx.test")).0;
+        let mut feeder = q.make_feeder();
+        let cx = ctx();
+        let cx = cx.child();
+        let mut results = vec![];
+        for input in inputs {
+            let v = cx.eval(&input.parse().unwrap()).await.unwrap();
+            results.extend(feeder.feed(Some(v), &cx).await);
+        }
+        results.extend(feeder.feed(None, &cx).await);
+        results
+            .into_iter()
+            .map(|v| v.map(|v| v.cbor().to_string()).unwrap_or_else(|e| e.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn sort() {
+        let inputs = ["{p: 2 n: 1}", "{p: 5 n: 2}", "{p: 1 n: 3}", "{p: 5 n: 4}", "{n: 5}"];
+        assert_eq!(
+            run("FROM 'x' ORDER BY _.p SELECT _.n", &inputs).await,
+            vec!["property `p` not found in Object", "3", "1", "2", "4"]
+        );
+        assert_eq!(
+            run("FROM 'x' ORDER BY _.p DESC SELECT _.n", &inputs[..4]).await,
+            vec!["2", "4", "1", "3"]
+        );
+        assert_eq!(
+            run("FROM 'x' ORDER BY _.p DESC LIMIT 3 SELECT _.n", &inputs[..4]).await,
+            vec!["2", "4", "1"]
+        );
+        assert_eq!(
+            run("FROM 'x' ORDER BY _.p ASC LIMIT 1 SELECT _.n", &inputs[..4]).await,
+            vec!["3"]
+        );
+    }
+
+    #[tokio::test]
+    async fn mixed_kinds() {
+        assert_eq!(
+            run(
+                "FROM 'x' ORDER BY _",
+                &["'b'", "2", "NULL", "TRUE", "'a'", "1.5", "[1]", "FALSE"]
+            )
+            .await,
+            vec!["null", "false", "true", "1.5", "2", "\"a\"", "\"b\"", "[1]"]
+        );
+    }
+
+    #[tokio::test]
+    async fn top_k() {
+        let expr = "_".parse::<SimpleExpr>().unwrap();
+        let mut p = OrderBy {
+            expr,
+            order: SortOrder::Desc,
+            limit: Some(2),
+            seq: 0,
+            entries: BinaryHeap::new(),
+        };
+        let cx = ctx();
+        let mut cx = cx.child();
+        for n in [3u64, 1, 4, 1, 5, 9, 2, 6] {
+            cx.bind("_", Value::synthetic(cx.mk_cbor(|b| b.encode_u64(n))));
+            assert!(p.apply(&mut cx).await.is_empty());
+            assert!(p.entries.len() <= 2);
+        }
+        let result = p
+            .flush(&mut cx)
+            .await
+            .into_iter()
+            .map(|v| v.unwrap().cbor().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(result, vec!["9", "6"]);
+
+        cx.bind("_", {
+            let mut v = Value::synthetic(cx.mk_cbor(|b| b.encode_u64(1)));
+            v.anti();
+            v
+        });
+        assert_eq!(
+            p.apply(&mut cx).await[0].as_ref().unwrap_err().to_string(),
+            "anti-input cannot be processed in ORDER BY"
+        );
+    }
+}
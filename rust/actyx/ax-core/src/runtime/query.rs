@@ -2,13 +2,14 @@ use crate::{
     ax_futures_util::ReceiverExt,
     runtime::{
         eval::Context,
-        operation::{Operation, Processor},
+        operation::{Operation, Processor, StageState},
         value::Value,
     },
 };
 use ax_aql::{Arr, Galactus, Tactic, TagAtom};
 use ax_types::{service::Order, AppId};
 use futures::{stream, StreamExt};
+use std::time::{Duration, Instant};
 
 pub struct Pragmas<'a>(Vec<(&'a str, &'a str)>);
 
@@ -131,29 +132,69 @@ impl Query {
     }
 
     pub fn make_feeder(&self) -> Feeder {
-        let processors = self.stages.iter().map(|op| op.make_processor()).collect();
-        Feeder::new(processors)
+        Feeder::new(Operation::make_processors(&self.stages))
     }
 
     pub fn feeder_from(stages: &[ax_aql::Operation]) -> Feeder {
-        let processors = stages
-            .iter()
-            .map(|s| Operation::from(s.clone()).make_processor())
-            .collect();
-        Feeder::new(processors)
+        let stages = stages.iter().cloned().map(Operation::from).collect::<Vec<_>>();
+        Feeder::new(Operation::make_processors(&stages))
     }
 }
 
+/// Values that went into and came out of one stage, and the time spent in it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StageStats {
+    pub inputs: u64,
+    pub outputs: u64,
+    pub elapsed: Duration,
+}
+
 pub struct Feeder {
     is_done: bool,
     processors: Vec<Box<dyn Processor>>,
+    /// only recorded when requested, one entry per processor
+    stats: Option<Vec<StageStats>>,
 }
 impl Feeder {
     fn new(processors: Vec<Box<dyn Processor>>) -> Self {
         Self {
             is_done: false,
             processors,
+            stats: None,
+        }
+    }
+
+    /// start recording [`StageStats`] for all further inputs
+    pub fn record_stats(&mut self) {
+        self.stats = Some(vec![StageStats::default(); self.processors.len()]);
+    }
+
+    pub fn stats(&self) -> Option<&[StageStats]> {
+        self.stats.as_deref()
+    }
+
+    /// Capture the state of all stages, or return the index of the first stage that cannot
+    /// be continued from a captured state.
+    pub fn checkpoint(&self) -> Result<Vec<StageState>, usize> {
+        self.processors
+            .iter()
+            .enumerate()
+            .map(|(idx, op)| op.save().ok_or(idx))
+            .collect()
+    }
+
+    /// Continue from the states returned by [`Feeder::checkpoint`] for the same stages.
+    pub fn restore(&mut self, states: &[StageState]) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            states.len() == self.processors.len(),
+            "expected state for {} stages, got {}",
+            self.processors.len(),
+            states.len()
+        );
+        for (op, state) in self.processors.iter_mut().zip(states) {
+            op.restore(state)?;
         }
+        Ok(())
     }
 
     pub fn preferred_order(&self) -> Option<Order> {
@@ -179,7 +220,7 @@ impl Feeder {
         let mut parent = cx;
         let mut input = vec![Ok(input).transpose()]; // inputs to be delivered to the current stage
 
-        for op in self.processors.iter_mut() {
+        for (idx, op) in self.processors.iter_mut().enumerate() {
             // create fresh child context, stored in the ctx slice
             let (curr_ctx, rest) = ctx.split_first_mut().unwrap();
             ctx = rest;
@@ -187,18 +228,32 @@ impl Feeder {
             let cx = curr_ctx.as_mut().unwrap();
             // then feed all inputs
             let mut output = vec![];
+            let mut stats = self.stats.as_mut().map(|s| (&mut s[idx], Instant::now()));
             for input in input {
+                let before = output.len();
                 match input {
                     Some(Ok(v)) => {
                         cx.bind("_", v);
                         output.extend(op.apply(cx).await.into_iter().map(Some));
+                        if let Some((stats, _)) = &mut stats {
+                            stats.inputs += 1;
+                        }
                     }
                     None => {
                         output.extend(op.flush(cx).await.into_iter().map(Some));
                         output.push(None);
                     }
-                    Some(Err(e)) => output.push(Some(Err(e))),
+                    Some(Err(e)) => {
+                        output.push(Some(Err(e)));
+                        continue;
+                    }
                 }
+                if let Some((stats, _)) = &mut stats {
+                    stats.outputs += output[before..].iter().filter(|o| o.is_some()).count() as u64;
+                }
+            }
+            if let Some((stats, start)) = stats {
+                stats.elapsed += start.elapsed();
             }
             if op.is_done(cx.order) {
                 self.is_done = true;
@@ -257,6 +312,52 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn stats() {
+        let cx = ctx(Order::Asc);
+        let cx = cx.child();
+        let mut feeder = feeder("FROM 'a' FILTER _ > 1 SELECT _, _ * 2 LIMIT 3");
+        assert_eq!(feeder.stats(), None);
+        feeder.record_stats();
+        for n in 1..=4 {
+            let v = cx.eval(&n.to_string().parse().unwrap()).await.unwrap();
+            feeder.feed(Some(v), &cx).await;
+        }
+        feeder.feed(None, &cx).await;
+        let counts = feeder
+            .stats()
+            .unwrap()
+            .iter()
+            .map(|s| (s.inputs, s.outputs))
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![(4, 3), (3, 6), (6, 3)]);
+    }
+
+    #[tokio::test]
+    async fn checkpoint() {
+        let cx = ctx(Order::Asc);
+        let cx = cx.child();
+        let mut first = feeder("FROM 'a' FILTER _ > 1 LIMIT 3");
+        for n in 1..=3 {
+            let v = cx.eval(&n.to_string().parse().unwrap()).await.unwrap();
+            first.feed(Some(v), &cx).await;
+        }
+        let states = first.checkpoint().unwrap();
+        assert_eq!(states, vec![StageState::Stateless, StageState::Limit(1)]);
+
+        let mut second = feeder("FROM 'a' FILTER _ > 1 LIMIT 3");
+        second.restore(&states).unwrap();
+        let v = cx.eval(&"4".parse().unwrap()).await.unwrap();
+        assert_eq!(second.feed(Some(v), &cx).await.len(), 1);
+        assert!(second.is_done());
+
+        assert_eq!(feeder("FROM 'a' FILTER _ > 1 AGGREGATE SUM(_)").checkpoint(), Err(1));
+        assert!(feeder("FROM 'a' LIMIT 3").restore(&states).is_err());
+        // states the stage cannot have produced are rejected
+        let forged = vec![StageState::Stateless, StageState::Limit(4)];
+        assert!(feeder("FROM 'a' FILTER _ > 1 LIMIT 3").restore(&forged).is_err());
+    }
+
     #[tokio::test]
     async fn select_multi() {
         assert_eq!(feed("FROM allEvents SELECT _, _ * 1.5", "42").await, vec!["42", "63.0"]);
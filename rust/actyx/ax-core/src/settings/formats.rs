@@ -1,6 +1,7 @@
+use crate::api::events::schemas::PayloadSchemas;
 use multiaddr::{Multiaddr, Protocol};
 use serde_json::Value;
-use std::str::FromStr;
+use std::{collections::BTreeMap, str::FromStr};
 use valico::json_schema::{
     errors,
     keywords::format::FormatBuilders,
@@ -54,6 +55,28 @@ impl Validator for MultiaddrValidator {
     }
 }
 
+/// Checks that an object maps valid tags to compilable JSON Schemas, see `api.events.schemas`.
+pub struct PayloadSchemasValidator;
+
+impl Validator for PayloadSchemasValidator {
+    fn validate(&self, val: &Value, path: &str, _scope: &scope::Scope) -> ValidationState {
+        let result = serde_json::from_value::<BTreeMap<String, Value>>(val.clone())
+            .map_err(|err| format!("The value must be an object. {}", err))
+            .and_then(|schemas| PayloadSchemas::new(&schemas, false).map_err(|err| format!("{:#}", err)));
+        match result {
+            Ok(_) => ValidationState::new(),
+            Err(err) => ValidationState {
+                errors: vec![Box::new(errors::Format {
+                    path: path.to_string(),
+                    detail: err,
+                })],
+                missing: vec![],
+                replacement: None,
+            },
+        }
+    }
+}
+
 pub fn extra_formats(formats: &mut FormatBuilders) {
     let multiaddr_builder = |with_peer_id: bool| {
         Box::new(move |_def: &Value, _ctx: &schema::WalkContext| {
@@ -62,4 +85,10 @@ pub fn extra_formats(formats: &mut FormatBuilders) {
     };
     formats.insert("multiaddr-with-peer-id".to_string(), multiaddr_builder(true));
     formats.insert("multiaddr-without-peer-id".to_string(), multiaddr_builder(false));
+    formats.insert(
+        "payload-schemas".to_string(),
+        Box::new(|_def: &Value, _ctx: &schema::WalkContext| {
+            Ok(Some(Box::new(PayloadSchemasValidator) as BoxedValidator))
+        }),
+    );
 }
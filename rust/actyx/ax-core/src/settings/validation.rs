@@ -108,6 +108,11 @@ impl Validator {
         }
     }
 
+    /// Validates a `json` value without filling in defaults.
+    pub fn validate(&self, value: &serde_json::Value) -> Result<()> {
+        Self::handle_result(self.get_schema().validate(value), || serde_json::Value::Null).map(|_| ())
+    }
+
     /// Validates a `json` value, given a `schema_json`. If individual fields are not set, but
     /// given a default in the schema, the default will be set. Note: If there are defaults given
     /// for multiple layers, the outer most one will be used.
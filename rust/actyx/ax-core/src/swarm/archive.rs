@@ -0,0 +1,426 @@
+//! Local cold storage for events removed by pruning, see the `eventRouting.archive` settings.
+//!
+//! Archive files use the format of `ax events dump`: a zstd compressed sequence of CBOR items,
+//! starting with a header and followed by one item per event. Each archival run appends a new
+//! zstd frame to the current file; once that file has grown beyond the configured size a new
+//! file is started. The `index.json` file lists the archive files together with the time range
+//! of the events they contain, so that `ax events restore --archive` can pick the files needed
+//! for restoring a time range.
+use crate::{
+    trees::axtrees::AxKey,
+    util::{formats::banyan_protocol::decode_dump_frame, version::NodeVersion},
+};
+use anyhow::Context;
+use ax_types::{app_id, Payload, StreamId, Timestamp};
+use cbor_data::{value::Precision, Cbor, CborBuilder, Encoder, Writer};
+use chrono::Local;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, BufReader, Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// Name of the index file within the archive directory.
+pub const INDEX_FILE: &str = "index.json";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveConfig {
+    /// directory the archive files and the index are written to
+    pub directory: PathBuf,
+    /// size in bytes after which a new archive file is started
+    pub max_file_size: u64,
+}
+
+impl ArchiveConfig {
+    pub fn new(directory: PathBuf, max_file_size: u64) -> Self {
+        Self {
+            directory,
+            max_file_size,
+        }
+    }
+}
+
+/// One archive file as listed in the index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveFile {
+    /// file name relative to the archive directory
+    pub name: String,
+    /// timestamp of the oldest event in the file
+    pub from: Timestamp,
+    /// timestamp of the newest event in the file
+    pub to: Timestamp,
+    pub events: u64,
+    /// compressed size in bytes
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveIndex {
+    pub files: Vec<ArchiveFile>,
+}
+
+impl ArchiveIndex {
+    /// Read the index of the given archive directory, which is empty if nothing has been archived yet.
+    pub fn load(directory: &Path) -> anyhow::Result<Self> {
+        let path = directory.join(INDEX_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let bytes = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+        serde_json::from_slice(&bytes).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    fn store(&self, directory: &Path) -> anyhow::Result<()> {
+        // write a new file and rename it, so that the index is never left half written
+        let tmp = directory.join(format!("{}.tmp", INDEX_FILE));
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?).with_context(|| format!("writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, directory.join(INDEX_FILE)).context("replacing archive index")?;
+        Ok(())
+    }
+
+    /// The archive files that may contain events with timestamps within `from..=to`.
+    pub fn select(&self, from: Timestamp, to: Timestamp) -> impl Iterator<Item = &ArchiveFile> {
+        self.files.iter().filter(move |file| file.to >= from && file.from <= to)
+    }
+}
+
+/// Writer for the archive files, shared by the pruning of all streams.
+pub struct Archive {
+    config: ArchiveConfig,
+    display_name: String,
+    topic: String,
+    lock: Mutex<()>,
+}
+
+impl Archive {
+    pub fn new(config: ArchiveConfig, display_name: String, topic: String) -> Self {
+        Self {
+            config,
+            display_name,
+            topic,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Append the given events of the stream to the current archive file and update the index.
+    ///
+    /// When this returns successfully, the events are stored on disk and may be removed from the stream.
+    pub fn write(&self, stream_id: StreamId, events: &[(u64, AxKey, Payload)]) -> anyhow::Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let _guard = self.lock.lock();
+        let directory = self.config.directory.as_path();
+        std::fs::create_dir_all(directory).with_context(|| format!("creating {}", directory.display()))?;
+        let mut index = ArchiveIndex::load(directory)?;
+
+        let mut encoder = zstd::Encoder::new(Vec::new(), 3)?;
+        let rotate = index
+            .files
+            .last()
+            .map_or(true, |file| file.size >= self.config.max_file_size);
+        if rotate {
+            let name = format!("pruned-{}.dump", u64::from(Timestamp::now()));
+            encoder.write_all(self.header(stream_id).as_slice())?;
+            index.files.push(ArchiveFile {
+                name,
+                from: Timestamp::from(u64::MAX),
+                to: Timestamp::from(0u64),
+                events: 0,
+                size: 0,
+            });
+        }
+        let file = index.files.last_mut().unwrap();
+
+        let mut scratch = Vec::new();
+        for (offset, key, payload) in events {
+            let cbor = CborBuilder::with_scratch_space(&mut scratch).encode_dict(|b| {
+                b.with_key("lamport", |b| b.encode_u64(key.lamport().into()));
+                b.with_key("stream", |b| {
+                    b.encode_array(|b| {
+                        b.write_bytes(stream_id.node_id().as_ref(), []);
+                        b.encode_u64(stream_id.stream_nr().into());
+                    })
+                });
+                b.with_key("offset", |b| b.encode_u64(*offset));
+                b.with_key("timestamp", |b| b.encode_u64(key.time().into()));
+                b.with_key("tags", |b| {
+                    b.encode_array(|b| {
+                        for tag in key.tags().public_tags() {
+                            b.encode_str(tag.as_ref());
+                        }
+                    })
+                });
+                // all published events carry an app ID, restoring requires one
+                let app_id = key.app_id().unwrap_or_else(|| app_id!("com.actyx"));
+                b.with_key("appId", |b| b.encode_str(app_id.as_str()));
+                b.with_key("payload", |b| b.write_trusting(payload.as_slice()));
+            });
+            encoder.write_all(cbor.as_slice())?;
+            file.from = file.from.min(key.time());
+            file.to = file.to.max(key.time());
+            file.events += 1;
+        }
+        let bytes = encoder.finish()?;
+
+        let path = directory.join(&file.name);
+        let mut out = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("opening {}", path.display()))?;
+        out.write_all(&bytes)
+            .and_then(|_| out.sync_all())
+            .with_context(|| format!("writing {}", path.display()))?;
+        file.size += bytes.len() as u64;
+
+        index.store(directory)?;
+        tracing::debug!(
+            "archived {} events of stream {} in {}",
+            events.len(),
+            stream_id,
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Header of a new archive file, as written by `ax events dump` and read by `decode_dump_header`.
+    fn header(&self, stream_id: StreamId) -> cbor_data::CborOwned {
+        let settings = serde_json::json!({ "swarm": { "topic": self.topic } });
+        CborBuilder::new().encode_dict(|b| {
+            b.with_key("nodeId", |b| b.write_bytes(stream_id.node_id().as_ref(), []));
+            b.with_key("displayName", |b| b.encode_str(&self.display_name));
+            b.with_key("timestamp", |b| {
+                b.encode_timestamp(Local::now().into(), Precision::Nanos)
+            });
+            b.with_key("actyxVersion", |b| b.encode_str(NodeVersion::get().to_string()));
+            b.with_key("settings", |b| b.encode_str(settings.to_string()));
+        })
+    }
+}
+
+/// Reader for the archived events with timestamps within `from..=to`, yielding them as one
+/// uncompressed dump: the header of an archive file followed by the selected events.
+///
+/// The archive files are decompressed and filtered while reading, so only one chunk of the
+/// current file is held in memory.
+pub struct ArchiveReader {
+    directory: PathBuf,
+    files: VecDeque<String>,
+    from: Timestamp,
+    to: Timestamp,
+    file: Option<zstd::Decoder<'static, BufReader<File>>>,
+    /// decompressed bytes of the current file, those before `input_pos` have been processed
+    input: Vec<u8>,
+    input_pos: usize,
+    /// whether the next item of the current file is its header
+    at_header: bool,
+    /// header to write before the first selected event
+    header: Option<Vec<u8>>,
+    /// selected bytes, those before `output_pos` have been read
+    output: Vec<u8>,
+    output_pos: usize,
+    events: u64,
+}
+
+impl ArchiveReader {
+    /// Open the archive in the given directory, failing if it holds no events within `from..=to`.
+    pub fn new(directory: &Path, from: Timestamp, to: Timestamp) -> anyhow::Result<Self> {
+        let index = ArchiveIndex::load(directory)?;
+        let mut reader = Self {
+            directory: directory.to_owned(),
+            files: index.select(from, to).map(|file| file.name.clone()).collect(),
+            from,
+            to,
+            file: None,
+            input: Vec::new(),
+            input_pos: 0,
+            at_header: false,
+            header: None,
+            output: Vec::new(),
+            output_pos: 0,
+            events: 0,
+        };
+        // look for the first event now, so that nothing is uploaded for an empty selection
+        anyhow::ensure!(reader.fill()?, "no archived events in the given time range");
+        Ok(reader)
+    }
+
+    /// Append the next selected event to the output, returning `false` at the end of the archive.
+    fn fill(&mut self) -> anyhow::Result<bool> {
+        while let Some(item) = self.next_item()? {
+            let bytes = &self.input[item];
+            if std::mem::take(&mut self.at_header) {
+                // all archive files are written by the same node, so one header suffices
+                if self.events == 0 && self.header.is_none() {
+                    self.header = Some(bytes.to_vec());
+                }
+                continue;
+            }
+            let (_, _, timestamp, _, _) =
+                decode_dump_frame(Cbor::unchecked(bytes)).context("malformed archived event")?;
+            if self.from <= timestamp && timestamp <= self.to {
+                if let Some(header) = self.header.take() {
+                    self.output.extend_from_slice(&header);
+                }
+                self.output.extend_from_slice(bytes);
+                self.events += 1;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The position of the next CBOR item of the archive files in `input`.
+    fn next_item(&mut self) -> anyhow::Result<Option<Range<usize>>> {
+        loop {
+            let decoder = match &mut self.file {
+                Some(decoder) => decoder,
+                None => {
+                    let name = match self.files.pop_front() {
+                        Some(name) => name,
+                        None => return Ok(None),
+                    };
+                    let path = self.directory.join(name);
+                    let file = File::open(&path).with_context(|| format!("opening {}", path.display()))?;
+                    self.at_header = true;
+                    self.file = Some(zstd::Decoder::new(file)?);
+                    continue;
+                }
+            };
+            match Cbor::checked_prefix(&self.input[self.input_pos..]) {
+                Ok((_, rest)) => {
+                    let start = self.input_pos;
+                    self.input_pos = self.input.len() - rest.len();
+                    return Ok(Some(start..self.input_pos));
+                }
+                Err(e) if matches!(e.kind(), cbor_data::ErrorKind::UnexpectedEof(_)) => {}
+                Err(e) => anyhow::bail!("malformed archive file: {}", e),
+            }
+            self.input.drain(..self.input_pos);
+            self.input_pos = 0;
+            let mut chunk = [0u8; 1 << 16];
+            let read = decoder.read(&mut chunk).context("decoding archive file")?;
+            if read == 0 {
+                anyhow::ensure!(self.input.is_empty(), "archive file ends within an event");
+                self.file = None;
+            } else {
+                self.input.extend_from_slice(&chunk[..read]);
+            }
+        }
+    }
+}
+
+impl Read for ArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.output_pos == self.output.len() {
+            self.output.clear();
+            self.output_pos = 0;
+            let filled = self
+                .fill()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:#}", e)))?;
+            if !filled {
+                return Ok(0);
+            }
+        }
+        let len = (self.output.len() - self.output_pos).min(buf.len());
+        buf[..len].copy_from_slice(&self.output[self.output_pos..self.output_pos + len]);
+        self.output_pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{stags, util::formats::banyan_protocol::decode_dump_header};
+    use ax_types::NodeId;
+
+    fn archive_file(name: &str, from: u64, to: u64) -> ArchiveFile {
+        ArchiveFile {
+            name: name.to_owned(),
+            from: from.into(),
+            to: to.into(),
+            events: 2,
+            size: 100,
+        }
+    }
+
+    #[test]
+    fn select() {
+        let index = ArchiveIndex {
+            files: vec![
+                archive_file("a", 10, 20),
+                archive_file("b", 30, 40),
+                archive_file("c", 50, 60),
+            ],
+        };
+        let select = |from: u64, to: u64| {
+            index
+                .select(from.into(), to.into())
+                .map(|file| file.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select(15, 35), ["a", "b"]);
+        assert_eq!(select(20, 30), ["a", "b"]);
+        assert_eq!(select(21, 29), Vec::<&str>::new());
+        assert_eq!(select(0, u64::MAX), ["a", "b", "c"]);
+        assert_eq!(select(0, 25), ["a"]);
+        assert_eq!(select(45, u64::MAX), ["c"]);
+    }
+
+    #[test]
+    fn read_time_range() {
+        let dir = tempfile::tempdir().unwrap();
+        // start a new file for every write
+        let archive = Archive::new(
+            ArchiveConfig::new(dir.path().to_owned(), 1),
+            "test".to_owned(),
+            "topic".to_owned(),
+        );
+        let stream_id = NodeId::new([1; 32]).stream(1.into());
+        let mut offset = 0;
+        for times in [[10u64, 20], [30, 40], [50, 60]] {
+            let events = times
+                .iter()
+                .map(|time| {
+                    offset += 1;
+                    let payload = Payload::from_json_str(&time.to_string()).unwrap();
+                    (offset, AxKey::new(stags!("test"), *time, *time), payload)
+                })
+                .collect::<Vec<_>>();
+            archive.write(stream_id, &events).unwrap();
+        }
+        assert_eq!(ArchiveIndex::load(dir.path()).unwrap().files.len(), 3);
+
+        let read = |from: u64, to: u64| -> anyhow::Result<Vec<u64>> {
+            let mut bytes = Vec::new();
+            ArchiveReader::new(dir.path(), from.into(), to.into())?.read_to_end(&mut bytes)?;
+            let (header, mut rest) = Cbor::checked_prefix(&bytes)?;
+            let (node_id, _, _) = decode_dump_header(header).unwrap();
+            assert_eq!(node_id, stream_id.node_id());
+            let mut times = Vec::new();
+            while !rest.is_empty() {
+                let (cbor, tail) = Cbor::checked_prefix(rest)?;
+                let (_, _, time, _, _) = decode_dump_frame(cbor).unwrap();
+                times.push(u64::from(time));
+                rest = tail;
+            }
+            Ok(times)
+        };
+        // overlapping two files
+        assert_eq!(read(15, 35).unwrap(), [20, 30]);
+        // open bounds
+        assert_eq!(read(0, u64::MAX).unwrap(), [10, 20, 30, 40, 50, 60]);
+        assert_eq!(read(0, 10).unwrap(), [10]);
+        assert_eq!(read(45, u64::MAX).unwrap(), [50, 60]);
+        // within a gap between files, and between the events of a file
+        read(41, 49).unwrap_err();
+        read(11, 19).unwrap_err();
+    }
+}
@@ -10,8 +10,8 @@ use crate::{
 };
 use ax_aql::TagExpr;
 use ax_types::{
-    AppId, Event, EventKey, LamportTimestamp, Metadata, NodeId, Offset, OffsetMap, OffsetOrMin, Payload, StreamId,
-    StreamNr, TagSet, Timestamp,
+    service::StreamPlan, AppId, Event, EventKey, LamportTimestamp, Metadata, NodeId, Offset, OffsetMap, OffsetOrMin,
+    Payload, StreamId, StreamNr, TagSet, Timestamp,
 };
 use banyan::FilteredChunk;
 use futures::{
@@ -25,10 +25,31 @@ pub enum Error {
     #[display(fmt = "Upper bounds must be within the current offsets’ present.")]
     InvalidUpperBounds,
     TagExprError(TagExprError),
+    #[display(fmt = "Precondition failed: there are events matching the tag expression beyond the given offsets.")]
+    #[from(ignore)]
+    PreconditionFailed,
+    #[display(fmt = "This node is a replica and does not publish events.")]
+    #[from(ignore)]
+    Replica,
+}
+
+/// Only persist events if no events matching `tag_expr` exist beyond `offsets`.
+#[derive(Clone, Debug)]
+pub struct Precondition {
+    pub tag_expr: TagExpr,
+    pub offsets: OffsetMap,
 }
 
 pub type PersistenceMeta = (LamportTimestamp, Offset, StreamNr, Timestamp);
 
+/// Outcome of [`EventStore::persist_checked`]
+#[derive(Clone, Debug)]
+pub struct Persisted {
+    pub meta: Vec<PersistenceMeta>,
+    /// whether the events had already been persisted by an earlier request with the same idempotency key
+    pub replayed: bool,
+}
+
 /// Wraps a [BanyanStore] and provides functionality for persisting events as well as receiving bounded and
 /// unbounded sets of events for queries across multiple streams with varying order guarantees.
 #[derive(Clone)]
@@ -130,9 +151,43 @@ impl EventStore {
         if events.is_empty() {
             return Ok(vec![]);
         }
+        if self.banyan_store.is_replica() {
+            return Err(Error::Replica.into());
+        }
         self.banyan_store.append(app_id, events).await
     }
 
+    /// Persist events unless the app already used the idempotency `key` recently, and only if the
+    /// `precondition` holds at the time of persisting.
+    pub async fn persist_checked(
+        &self,
+        app_id: AppId,
+        key: Option<&str>,
+        precondition: Option<Precondition>,
+        events: Vec<(TagSet, Payload)>,
+    ) -> anyhow::Result<Persisted> {
+        if events.is_empty() {
+            return Ok(Persisted {
+                meta: vec![],
+                replayed: false,
+            });
+        }
+        if self.banyan_store.is_replica() {
+            return Err(Error::Replica.into());
+        }
+        let check = async move {
+            if let Some(Precondition { tag_expr, offsets }) = precondition {
+                let present = self.current_offsets().present();
+                let mut events = self.bounded_forward(&tag_expr, offsets, present).await?;
+                if events.next().await.is_some() {
+                    return Err(Error::PreconditionFailed.into());
+                }
+            }
+            Ok(())
+        };
+        self.banyan_store.append_checked(app_id, key, events, check).await
+    }
+
     pub async fn bounded_forward(
         &self,
         tag_expr: &TagExpr,
@@ -178,6 +233,27 @@ impl EventStore {
         Ok(MergeOrdered::new_fixed(event_streams).map(|reverse| reverse.0).boxed())
     }
 
+    /// Describe which parts of which streams [`bounded_forward`](Self::bounded_forward) would read.
+    pub async fn explain(
+        &self,
+        tag_expr: &TagExpr,
+        from_offsets_excluding: OffsetMap,
+        to_offsets_including: OffsetMap,
+    ) -> Result<Vec<StreamPlan>, Error> {
+        Ok(self
+            .bounded_streams(tag_expr, from_offsets_excluding, to_offsets_including)
+            .await?
+            .into_iter()
+            .map(|selection| StreamPlan {
+                stream: selection.stream_id,
+                from_exclusive: selection.from_exclusive,
+                to_inclusive: selection.to_inclusive,
+                indexed: selection.tags_query.uses_tag_index(),
+                query: format!("{:?}", selection.tags_query),
+            })
+            .collect())
+    }
+
     pub fn unbounded_forward_per_stream(
         &self,
         tag_expr: &TagExpr,
@@ -262,6 +338,7 @@ mod tests {
     };
 
     use crate::ax_futures_util::stream::Drainer;
+    use acto::ActoRef;
     use ax_aql::{TagAtom, TagExpr};
     use ax_types::{app_id, service::Order, tag, tags, OffsetOrMin, StreamId, Tag};
     use futures::{future::try_join_all, Stream};
@@ -271,7 +348,7 @@ mod tests {
 
     use super::*;
     use crate::{
-        swarm::{selection::EventSelection, BanyanStore, EventRoute},
+        swarm::{selection::EventSelection, BanyanStore, EventRoute, SwarmConfig},
         trees::query::{LamportQuery, TimeQuery},
     };
     use chrono::{DateTime, SecondsFormat, Utc};
@@ -764,6 +841,29 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn replica() -> anyhow::Result<()> {
+        let banyan = BanyanStore::new(
+            SwarmConfig {
+                replica: true,
+                ..SwarmConfig::test("replica")
+            },
+            ActoRef::blackhole(),
+        )
+        .await?;
+        assert!(banyan.is_replica());
+        let store = EventStore::new(banyan);
+
+        let err = store
+            .persist(app_id(), vec![(tags!("a"), Payload::null())])
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Replica)));
+        // not even the internal streams are created
+        assert!(store.current_offsets().present().streams().next().is_none());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn should_stream_offsets() -> anyhow::Result<()> {
         fn test_offsets(store: &EventStore, stream: StreamId, offset: Offset) {
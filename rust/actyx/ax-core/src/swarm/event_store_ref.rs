@@ -1,12 +1,12 @@
 use crate::{
     swarm::{
-        event_store::{self, EventStore, PersistenceMeta},
+        event_store::{self, EventStore, Persisted, PersistenceMeta, Precondition},
         BanyanStore, SwarmOffsets,
     },
     trees::query::TagExprError,
 };
 use ax_aql::TagExpr;
-use ax_types::{AppId, Event, OffsetMap, Payload, TagSet};
+use ax_types::{service::StreamPlan, AppId, Event, OffsetMap, Payload, TagSet};
 use futures::{Future, Stream, StreamExt};
 use parking_lot::Mutex;
 use std::{
@@ -36,6 +36,10 @@ pub enum Error {
     InvalidUpperBounds,
     #[display(fmt = "AQL Error: {}", _0)]
     TagExprError(TagExprError),
+    #[display(fmt = "Precondition failed: there are events matching the tag expression beyond the given offsets.")]
+    PreconditionFailed,
+    #[display(fmt = "This node is a replica and does not publish events.")]
+    Replica,
 }
 
 impl From<super::event_store::Error> for Error {
@@ -43,6 +47,8 @@ impl From<super::event_store::Error> for Error {
         match x {
             event_store::Error::InvalidUpperBounds => Error::InvalidUpperBounds,
             event_store::Error::TagExprError(e) => Error::TagExprError(e),
+            event_store::Error::PreconditionFailed => Error::PreconditionFailed,
+            event_store::Error::Replica => Error::Replica,
         }
     }
 }
@@ -81,8 +87,11 @@ pub enum EventStoreRequest {
     #[display(fmt = "Persist({}, {})", app_id, "events.len()")]
     Persist {
         app_id: AppId,
+        /// idempotency key, see [`EventStoreRef::persist_checked`]
+        key: Option<String>,
+        precondition: Option<Precondition>,
         events: Vec<(TagSet, Payload)>,
-        reply: OneShot<Vec<PersistenceMeta>>,
+        reply: OneShot<Persisted>,
     },
     #[display(fmt = "Bounded({}, per_stream={})", tag_expr, per_stream)]
     BoundedForward {
@@ -99,6 +108,13 @@ pub enum EventStoreRequest {
         to_offsets_including: OffsetMap,
         reply: OneShot<StreamOf<Event<Payload>>>,
     },
+    #[display(fmt = "Explain({})", tag_expr)]
+    Explain {
+        tag_expr: TagExpr,
+        from_offsets_excluding: OffsetMap,
+        to_offsets_including: OffsetMap,
+        reply: OneShot<Vec<StreamPlan>>,
+    },
     #[display(fmt = "Unbounded({})", tag_expr)]
     UnboundedForward {
         tag_expr: TagExpr,
@@ -114,6 +130,26 @@ impl EventStoreRef {
         Self { tx: Arc::new(f) }
     }
 
+    /// A view of the store in which only events matching `scope` are visible, e.g. to enforce
+    /// the read scope of an app. This also applies to publication preconditions.
+    pub fn restricted(&self, scope: TagExpr) -> Self {
+        let tx = self.tx.clone();
+        Self::new(move |mut request| {
+            match &mut request {
+                BoundedForward { tag_expr, .. }
+                | BoundedBackward { tag_expr, .. }
+                | Explain { tag_expr, .. }
+                | UnboundedForward { tag_expr, .. }
+                | Persist {
+                    precondition: Some(Precondition { tag_expr, .. }),
+                    ..
+                } => *tag_expr = tag_expr.clone() & scope.clone(),
+                Offsets { .. } | Persist { precondition: None, .. } => {}
+            }
+            tx(request)
+        })
+    }
+
     pub async fn offsets(&self) -> Result<SwarmOffsets, Error> {
         let (reply, rx) = oneshot::channel();
         (self.tx)(Offsets { reply })?;
@@ -122,7 +158,35 @@ impl EventStoreRef {
 
     pub async fn persist(&self, app_id: AppId, events: Vec<(TagSet, Payload)>) -> Result<Vec<PersistenceMeta>, Error> {
         let (reply, rx) = oneshot::channel();
-        (self.tx)(Persist { app_id, events, reply })?;
+        (self.tx)(Persist {
+            app_id,
+            key: None,
+            precondition: None,
+            events,
+            reply,
+        })?;
+        rx.await.my_err()?.map(|persisted| persisted.meta)
+    }
+
+    /// Persist the events unless the same app already persisted events with the same `key`
+    /// recently, in which case the metadata of those earlier events is returned. With a
+    /// `precondition` that does not hold, nothing is persisted and
+    /// [`Error::PreconditionFailed`] is returned.
+    pub async fn persist_checked(
+        &self,
+        app_id: AppId,
+        key: Option<String>,
+        precondition: Option<Precondition>,
+        events: Vec<(TagSet, Payload)>,
+    ) -> Result<Persisted, Error> {
+        let (reply, rx) = oneshot::channel();
+        (self.tx)(Persist {
+            app_id,
+            key,
+            precondition,
+            events,
+            reply,
+        })?;
         rx.await.my_err()?
     }
 
@@ -160,6 +224,22 @@ impl EventStoreRef {
         rx.await.my_err()?
     }
 
+    pub async fn explain(
+        &self,
+        tag_expr: TagExpr,
+        from_offsets_excluding: OffsetMap,
+        to_offsets_including: OffsetMap,
+    ) -> Result<Vec<StreamPlan>, Error> {
+        let (reply, rx) = oneshot::channel();
+        (self.tx)(Explain {
+            tag_expr,
+            from_offsets_excluding,
+            to_offsets_including,
+            reply,
+        })?;
+        rx.await.my_err()?
+    }
+
     pub async fn unbounded_forward(
         &self,
         tag_expr: TagExpr,
@@ -225,13 +305,32 @@ impl EventStoreHandler {
             Offsets { reply } => {
                 let _ = reply.send(Ok(self.store.current_offsets()));
             }
-            Persist { app_id, events, reply } => {
+            Persist {
+                app_id,
+                key,
+                precondition,
+                events,
+                reply,
+            } => {
                 let store = self.store.clone();
                 self.state.persist.fetch_add(1, Ordering::Relaxed);
                 let state = self.state.clone();
                 runtime.spawn(async move {
                     let n = events.len();
-                    let _ = reply.send(store.persist(app_id, events).await.map_err(move |e| {
+                    let result = if key.is_some() || precondition.is_some() {
+                        store
+                            .persist_checked(app_id, key.as_deref(), precondition, events)
+                            .await
+                    } else {
+                        store
+                            .persist(app_id, events)
+                            .await
+                            .map(|meta| Persisted { meta, replayed: false })
+                    };
+                    let _ = reply.send(result.map_err(move |e| {
+                        if let Some(e) = e.downcast_ref::<event_store::Error>() {
+                            return e.clone().into();
+                        }
                         tracing::error!("failed to persist {} events: {:#}", n, e);
                         Error::Aborted
                     }));
@@ -273,6 +372,22 @@ impl EventStoreHandler {
                         .await
                 });
             }
+            Explain {
+                tag_expr,
+                from_offsets_excluding,
+                to_offsets_including,
+                reply,
+            } => {
+                let store = self.store.clone();
+                runtime.spawn(async move {
+                    let _ = reply.send(
+                        store
+                            .explain(&tag_expr, from_offsets_excluding, to_offsets_including)
+                            .await
+                            .map_err(Error::from),
+                    );
+                });
+            }
             UnboundedForward {
                 tag_expr,
                 from_offsets_excluding,
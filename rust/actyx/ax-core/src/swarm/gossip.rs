@@ -1,13 +1,14 @@
 use crate::{
     ax_futures_util::stream::ready_iter,
+    crypto::KeyPair,
     swarm::{
-        gossip_protocol::{GossipMessage, RootMap, RootUpdate},
+        gossip_protocol::{GossipMessage, RootMap, RootSignature, RootUpdate},
         BanyanStore, Ipfs, Link, RootPath, RootSource,
     },
 };
 use acto::ActoRef;
 use anyhow::Result;
-use ax_types::{LamportTimestamp, NodeId, Offset, StreamNr, Timestamp};
+use ax_types::{LamportTimestamp, NodeId, Offset, StreamId, StreamNr, Timestamp};
 use cbor_data::{
     codec::{CodecError, ReadCbor, WriteCbor},
     Cbor, CborBuilder,
@@ -37,6 +38,7 @@ struct PublishUpdate {
 }
 
 pub struct Gossip {
+    key_pair: KeyPair,
     tx: UnboundedSender<PublishUpdate>,
     publish_handle: tokio::task::JoinHandle<()>,
 }
@@ -44,12 +46,13 @@ pub struct Gossip {
 impl Gossip {
     pub fn new(
         mut ipfs: Ipfs,
-        node_id: NodeId,
+        key_pair: KeyPair,
         topic: String,
         enable_fast_path: bool,
         enable_slow_path: bool,
         swarm_observer: ActoRef<(PeerId, GossipMessage)>,
     ) -> Self {
+        let node_id = NodeId::from(key_pair);
         let (tx, mut rx) = unbounded::<PublishUpdate>();
         let publish_task = async move {
             let mut cbor_scratch = Vec::new();
@@ -66,6 +69,7 @@ impl Gossip {
                     let offset = update.offset;
                     let root = Cid::from(update.root);
                     let stream = node_id.stream(update.stream);
+                    let signature = Some(RootSignature::sign(&key_pair, stream, &root));
                     let mut size = 0;
                     let mut blocks = Vec::with_capacity(100);
                     for link in update.links {
@@ -90,6 +94,7 @@ impl Gossip {
                             lamport,
                             time,
                             offset: Some(offset),
+                            signature,
                         }),
                     ));
 
@@ -101,6 +106,7 @@ impl Gossip {
                             lamport,
                             time,
                             offset: Some(offset),
+                            signature,
                         };
                         let blob = GossipMessage::RootUpdate(root_update)
                             .write_cbor(CborBuilder::with_scratch_space(&mut cbor_scratch))
@@ -122,6 +128,7 @@ impl Gossip {
                             time,
                             blocks: Default::default(),
                             offset: Some(offset),
+                            signature,
                         };
                         let blob = GossipMessage::RootUpdate(root_update)
                             .write_cbor(CborBuilder::with_scratch_space(&mut cbor_scratch))
@@ -136,6 +143,7 @@ impl Gossip {
             tracing::error!("gossip loop stopped, live updates won’t work anymore");
         };
         Self {
+            key_pair,
             tx,
             publish_handle: tokio::spawn(publish_task),
         }
@@ -167,6 +175,8 @@ impl Gossip {
         swarm_observer: ActoRef<(PeerId, GossipMessage)>,
     ) -> impl Future<Output = ()> {
         let mut ipfs = store.ipfs().clone();
+        let key_pair = self.key_pair;
+        let node_id = NodeId::from(key_pair);
         async move {
             let mut cbor_scratch = Vec::new();
             loop {
@@ -180,10 +190,17 @@ impl Gossip {
 
                 let n_entries = root_map.len();
                 let mut offsets = Vec::with_capacity(n_entries);
+                let mut signatures = Vec::with_capacity(n_entries);
                 let entries = root_map
                     .into_iter()
-                    .map(|(stream, (root, offset, lamport))| {
+                    .map(|(stream, (root, offset, lamport, signature))| {
                         offsets.push((offset, lamport));
+                        // own roots are signed here, the owners’ signatures are forwarded for all others
+                        signatures.push(if stream.node_id() == node_id {
+                            Some(RootSignature::sign(&key_pair, stream, &root))
+                        } else {
+                            signature
+                        });
                         (stream, root)
                     })
                     .collect();
@@ -192,6 +209,7 @@ impl Gossip {
                 let msg = GossipMessage::RootMap(RootMap {
                     entries,
                     offsets,
+                    signatures,
                     lamport,
                     time,
                 });
@@ -211,10 +229,12 @@ impl Gossip {
     pub async fn ingest(
         store: BanyanStore,
         topic: String,
+        accept_unsigned_roots: bool,
         swarm_observer: ActoRef<(PeerId, GossipMessage)>,
     ) -> Result<impl Future<Output = ()>> {
         let mut ipfs = store.ipfs().clone();
         let mut subscription = ipfs.subscribe(topic.clone()).await?;
+        let mut signing_nodes = store.lock().index_store.get_signing_nodes()?;
         Ok(async move {
             while let Some(event) = subscription.next().await {
                 let (peer_id, message) = if let GossipEvent::Message(sender, message) = event {
@@ -237,6 +257,18 @@ impl Gossip {
                             root_update.lamport,
                             root_update.offset
                         );
+                        if !check_signature(
+                            root_update.stream,
+                            &root_update.root,
+                            root_update.signature.as_ref(),
+                            accept_unsigned_roots,
+                            &signing_nodes,
+                        ) {
+                            continue;
+                        }
+                        if root_update.signature.is_some() {
+                            remember_signing_node(&store, &mut signing_nodes, root_update.stream.node_id());
+                        }
                         let mut lock = store.lock();
                         tracing::trace!("got store lock");
                         lock.received_lamport(root_update.lamport)
@@ -260,7 +292,12 @@ impl Gossip {
                             }
                         }
                         match Link::try_from(root_update.root) {
-                            Ok(root) => store.update_root(root_update.stream, root, RootSource::new(peer_id, path)),
+                            Ok(root) => store.update_root(
+                                root_update.stream,
+                                root,
+                                RootSource::new(peer_id, path),
+                                root_update.signature,
+                            ),
                             Err(err) => tracing::error!("failed to parse link {}", err),
                         }
                     }
@@ -274,13 +311,29 @@ impl Gossip {
                             .received_lamport(root_map.lamport)
                             .expect("unable to update lamport");
                         for (idx, (stream, root)) in root_map.entries.into_iter().enumerate() {
+                            let signature = root_map.signatures.get(idx).copied().flatten();
+                            if !check_signature(
+                                stream,
+                                &root,
+                                signature.as_ref(),
+                                accept_unsigned_roots,
+                                &signing_nodes,
+                            ) {
+                                continue;
+                            }
+                            if signature.is_some() {
+                                remember_signing_node(&store, &mut signing_nodes, stream.node_id());
+                            }
                             if let Some((offset, _)) = root_map.offsets.get(idx) {
                                 store.update_highest_seen(stream, *offset);
                             }
                             match Link::try_from(root) {
-                                Ok(root) => {
-                                    store.update_root(stream, root, RootSource::new(peer_id, RootPath::RootMap))
-                                }
+                                Ok(root) => store.update_root(
+                                    stream,
+                                    root,
+                                    RootSource::new(peer_id, RootPath::RootMap),
+                                    signature,
+                                ),
                                 Err(err) => tracing::error!("failed to parse link {}", err),
                             }
                         }
@@ -292,8 +345,95 @@ impl Gossip {
     }
 }
 
+/// Check whether a root for `stream` may be accepted, i.e. it is signed by the node owning the
+/// stream, or unsigned and unsigned roots are accepted for compatibility with older nodes.
+///
+/// Unsigned roots are never accepted for streams of `signing_nodes`, which are known to sign
+/// their roots, so that a peer cannot inject roots on behalf of an upgraded node.
+fn check_signature(
+    stream: StreamId,
+    root: &Cid,
+    signature: Option<&RootSignature>,
+    accept_unsigned: bool,
+    signing_nodes: &BTreeSet<NodeId>,
+) -> bool {
+    match signature {
+        Some(signature) if signature.verify(stream, root) => true,
+        Some(_) => {
+            tracing::warn!(%stream, %root, "dropping root with invalid signature");
+            false
+        }
+        None if signing_nodes.contains(&stream.node_id()) => {
+            tracing::warn!(%stream, %root, "dropping unsigned root of a node that signs its roots");
+            false
+        }
+        None if accept_unsigned => true,
+        None => {
+            tracing::debug!(%stream, %root, "dropping unsigned root");
+            false
+        }
+    }
+}
+
+/// Remember that `node` signs its roots, persistently, so that its unsigned roots are dropped.
+fn remember_signing_node(store: &BanyanStore, signing_nodes: &mut BTreeSet<NodeId>, node: NodeId) {
+    if signing_nodes.insert(node) {
+        if let Err(err) = store.lock().index_store.add_signing_node(node) {
+            tracing::warn!(%node, "cannot persist that the node signs its roots: {}", err);
+        }
+    }
+}
+
 impl Drop for Gossip {
     fn drop(&mut self) {
         self.publish_handle.abort();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld::multihash::{Code, MultihashDigest};
+
+    #[test]
+    fn drop_unsigned_or_badly_signed_roots() {
+        let key_pair = KeyPair::generate();
+        let stream = NodeId::from(key_pair).stream(3.into());
+        let root = Cid::new_v1(0x00, Code::Sha2_256.digest(b"root"));
+        let signature = RootSignature::sign(&key_pair, stream, &root);
+
+        let other_root = Cid::new_v1(0x00, Code::Sha2_256.digest(b"other root"));
+        let other_stream = NodeId::from(key_pair).stream(4.into());
+        let forged = RootSignature::sign(&KeyPair::generate(), stream, &root);
+
+        let none = BTreeSet::new();
+        for accept_unsigned in [true, false] {
+            assert!(check_signature(stream, &root, Some(&signature), accept_unsigned, &none));
+            assert!(!check_signature(
+                stream,
+                &other_root,
+                Some(&signature),
+                accept_unsigned,
+                &none
+            ));
+            assert!(!check_signature(
+                other_stream,
+                &root,
+                Some(&signature),
+                accept_unsigned,
+                &none
+            ));
+            assert!(!check_signature(stream, &root, Some(&forged), accept_unsigned, &none));
+        }
+        assert!(check_signature(stream, &root, None, true, &none));
+        assert!(!check_signature(stream, &root, None, false, &none));
+
+        // once a node has been seen signing, its unsigned roots are dropped regardless of the policy
+        let signing = BTreeSet::from([NodeId::from(key_pair)]);
+        assert!(check_signature(stream, &root, Some(&signature), true, &signing));
+        assert!(!check_signature(stream, &root, None, true, &signing));
+        assert!(!check_signature(other_stream, &root, None, true, &signing));
+        let unrelated = NodeId::from(KeyPair::generate()).stream(3.into());
+        assert!(check_signature(unrelated, &root, None, true, &signing));
+    }
+}
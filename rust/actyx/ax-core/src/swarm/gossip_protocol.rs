@@ -1,7 +1,10 @@
 //! The [`GossipMessage`] protocol between AX nodes is encoded using [libipld].
 //!
 //! [libipld]: https://crates.io/crates/libipld
-use crate::swarm::Block;
+use crate::{
+    crypto::{KeyPair, PublicKey},
+    swarm::Block,
+};
 use ax_types::{LamportTimestamp, Offset, StreamId, Timestamp};
 use cbor_data::{
     codec::{CodecError, ReadCbor, WriteCbor},
@@ -67,7 +70,8 @@ impl ReadCbor for GossipMessage {
 /// version of Actyx v2 used a fixed size map, so this particular case needs to be special handled
 /// while decoding updates from older nodes.
 ///
-/// Up to including Actyx v2.3.1 the `offset` field was not present.
+/// Up to including Actyx v2.3.1 the `offset` field was not present, and up to including
+/// databank version 2.17 the `signature` field was not present.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct RootUpdate {
     pub stream: StreamId,
@@ -80,6 +84,9 @@ pub struct RootUpdate {
     /// Offset of the tree referenced by `root`
     /// Optional for backwards compatibility
     pub offset: Option<Offset>,
+    /// Signature of `root` by the node owning `stream`
+    /// Optional for backwards compatibility
+    pub signature: Option<RootSignature>,
 }
 
 impl RootUpdate {
@@ -109,6 +116,9 @@ impl WriteCbor for RootUpdate {
             w.with_key("lamport", |w| self.lamport.write_cbor(w));
             w.with_key("time", |w| self.time.write_cbor(w));
             w.with_key("offset", |w| self.offset.write_cbor(w));
+            if let Some(signature) = &self.signature {
+                w.with_key("signature", |w| signature.write_cbor(w));
+            }
             w.set_max_definite_size(None);
         })
     }
@@ -167,10 +177,66 @@ impl ReadCbor for RootUpdate {
             } else {
                 Default::default()
             },
+            signature: if let Some(signature) = d.get("signature") {
+                ReadCbor::read_cbor(signature.as_ref())?
+            } else {
+                Default::default()
+            },
         })
     }
 }
 
+/// Ed25519 signature of a stream root by the node owning the stream.
+///
+/// Only the stream ID and the root CID are signed: the root references the tree header, which
+/// in turn pins the lamport timestamp and all events of the stream. This allows the signature to
+/// be forwarded unchanged by other nodes, e.g. within a [`RootMap`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RootSignature([u8; 64]);
+
+impl RootSignature {
+    pub fn sign(key_pair: &KeyPair, stream: StreamId, root: &Cid) -> Self {
+        Self(key_pair.sign(&Self::message(stream, root)))
+    }
+
+    /// Check that this signature was made by the node owning `stream`.
+    pub fn verify(&self, stream: StreamId, root: &Cid) -> bool {
+        PublicKey::from(stream.node_id()).verify(&Self::message(stream, root), &self.0)
+    }
+
+    fn message(stream: StreamId, root: &Cid) -> Vec<u8> {
+        let mut message = b"ax-root:".to_vec();
+        message.extend_from_slice(stream.node_id().as_ref());
+        message.extend_from_slice(&u64::from(stream.stream_nr()).to_be_bytes());
+        message.extend_from_slice(&root.to_bytes());
+        message
+    }
+}
+
+impl WriteCbor for RootSignature {
+    fn write_cbor<W: cbor_data::Writer>(&self, w: W) -> W::Output {
+        w.encode_bytes(self.0)
+    }
+}
+
+impl ReadCbor for RootSignature {
+    fn fmt(f: &mut impl std::fmt::Write) -> std::fmt::Result {
+        write!(f, "RootSignature")
+    }
+
+    fn read_cbor_impl(cbor: &cbor_data::Cbor) -> cbor_data::codec::Result<Self>
+    where
+        Self: Sized,
+    {
+        let bytes = cbor
+            .decode()
+            .to_bytes()
+            .ok_or_else(|| CodecError::str("expected signature bytes"))?;
+        let bytes = bytes.as_ref().try_into().map_err(CodecError::custom)?;
+        Ok(Self(bytes))
+    }
+}
+
 struct AsNumberArray<'a>(Cow<'a, [u8]>);
 impl WriteCbor for AsNumberArray<'_> {
     fn write_cbor<W: cbor_data::Writer>(&self, w: W) -> W::Output {
@@ -242,13 +308,17 @@ impl ReadCbor for AsNumberArray<'static> {
 /// version of Actyx v2 used a fixed size map, so this particular case needs to be special handled
 /// while decoding updates from older nodes.
 ///
-/// Up to including Actyx v2.3.1 the `offsets` field was not present.
+/// Up to including Actyx v2.3.1 the `offsets` field was not present, and up to including
+/// databank version 2.17 the `signatures` field was not present.
 #[derive(Debug, Eq, PartialEq, Default, Clone)]
 pub struct RootMap {
     pub entries: BTreeMap<StreamId, Cid>,
     /// Offset and lamport timestamp of the trees referenced in the `entries` map.
     /// Could be empty (backwards compatibilty!)
     pub offsets: Vec<(Offset, LamportTimestamp)>,
+    /// Signatures of the roots in the `entries` map by their owning nodes, if known.
+    /// Could be empty (backwards compatibility!)
+    pub signatures: Vec<Option<RootSignature>>,
     /// Highest lamport timestamp known to the node at time of publishing the message
     pub lamport: LamportTimestamp,
     /// Message creation wallclock
@@ -262,6 +332,9 @@ impl WriteCbor for RootMap {
             w.with_key("entries", |w| self.entries.write_cbor(w));
             w.with_key("lamport", |w| self.lamport.write_cbor(w));
             w.with_key("offsets", |w| self.offsets.write_cbor(w));
+            if !self.signatures.is_empty() {
+                w.with_key("signatures", |w| self.signatures.write_cbor(w));
+            }
             w.with_key("time", |w| self.time.write_cbor(w));
             w.set_max_definite_size(None);
         })
@@ -293,6 +366,11 @@ impl ReadCbor for RootMap {
             } else {
                 Default::default()
             },
+            signatures: if let Some(signatures) = d.get("signatures") {
+                ReadCbor::read_cbor(signatures.as_ref())?
+            } else {
+                Default::default()
+            },
             lamport: ReadCbor::read_cbor(
                 d.get("lamport")
                     .ok_or_else(|| CodecError::str("missing field `lamport`"))?
@@ -333,13 +411,23 @@ mod tests {
         }
     }
 
+    impl Arbitrary for RootSignature {
+        fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+            let mut bytes = [0u8; 64];
+            bytes.iter_mut().for_each(|b| *b = u8::arbitrary(g));
+            Self(bytes)
+        }
+    }
+
     impl Arbitrary for RootMap {
         fn arbitrary(g: &mut quickcheck::Gen) -> Self {
             let mut offsets = vec![];
+            let mut signatures = vec![];
             let len = g.size();
             let entries = (0..len)
                 .map(|_| {
                     offsets.push((Arbitrary::arbitrary(g), Arbitrary::arbitrary(g)));
+                    signatures.push(Arbitrary::arbitrary(g));
                     let cid = Cid::new_v1(0x00, Code::Sha2_256.digest(&Vec::<u8>::arbitrary(g)[..]));
                     (Arbitrary::arbitrary(g), cid)
                 })
@@ -347,6 +435,7 @@ mod tests {
             Self {
                 entries,
                 offsets,
+                signatures,
                 lamport: Arbitrary::arbitrary(g),
                 time: Arbitrary::arbitrary(g),
             }
@@ -397,6 +486,7 @@ mod tests {
                 lamport: Arbitrary::arbitrary(g),
                 time: Arbitrary::arbitrary(g),
                 offset: Arbitrary::arbitrary(g),
+                signature: Arbitrary::arbitrary(g),
             }
         }
         fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
@@ -422,6 +512,22 @@ mod tests {
         decoded == message
     }
 
+    #[test]
+    fn sign_root() {
+        let key_pair = KeyPair::generate();
+        let stream = NodeId::from(key_pair).stream(3.into());
+        let root = Cid::new_v1(0x00, Code::Sha2_256.digest(b"root"));
+        let signature = RootSignature::sign(&key_pair, stream, &root);
+        assert!(signature.verify(stream, &root));
+
+        let other_root = Cid::new_v1(0x00, Code::Sha2_256.digest(b"other"));
+        assert!(!signature.verify(stream, &other_root));
+        assert!(!signature.verify(NodeId::from(key_pair).stream(4.into()), &root));
+        let forger = KeyPair::generate();
+        let forged = RootSignature::sign(&forger, stream, &root);
+        assert!(!forged.verify(stream, &root));
+    }
+
     #[test]
     fn test_decode_root_update_old() {
         #[rustfmt::skip]
@@ -470,6 +576,7 @@ mod tests {
             lamport: Default::default(),
             time: Default::default(),
             offset: None,
+            signature: None,
         };
         let root_update2 = RootUpdate::read_cbor(Cbor::checked(&cbor).unwrap()).unwrap();
         assert_eq!(root_update, root_update2);
@@ -530,6 +637,7 @@ mod tests {
             lamport: Default::default(),
             time: Default::default(),
             offset: None,
+            signature: None,
         });
         let msg = root_update.write_cbor(CborBuilder::default());
         assert_eq!(
@@ -24,7 +24,9 @@ pub fn metrics(store: BanyanStore, interval: Duration) -> Result<impl Future<Out
         let mut buffer = vec![];
         loop {
             tokio::time::sleep(interval).await;
-            let mf = registry.gather();
+            // the default registry holds the metrics of other components, e.g. rejected publications
+            let mut mf = registry.gather();
+            mf.extend(prometheus::gather());
             buffer.clear();
             if let Err(err) = encoder.encode(&mf, &mut buffer) {
                 tracing::warn!("error encoding metrics: {}", err);
@@ -10,6 +10,7 @@
 //! temporary struct that is created when acquiring mutable access to the state.
 //! inside this you have mutable access to the state - but if you lock again you will deadlock.
 
+pub mod archive;
 pub mod blob_store;
 mod discovery;
 pub mod event_store;
@@ -21,6 +22,7 @@ mod prune;
 pub mod selection;
 mod sqlite;
 mod sqlite_index_store;
+mod stream_keys;
 mod streams;
 pub mod transport;
 
@@ -28,9 +30,11 @@ pub mod transport;
 mod tests;
 
 pub use crate::swarm::{
-    gossip_protocol::{GossipMessage, RootMap, RootUpdate},
+    archive::ArchiveConfig,
+    gossip_protocol::{GossipMessage, RootMap, RootSignature, RootUpdate},
     sqlite::{StorageServiceStore, StorageServiceStoreWrite},
     sqlite_index_store::DbPath,
+    stream_keys::{StreamKeys, STREAM_KEY_LENGTH},
     streams::StreamAlias,
 };
 use crate::{
@@ -40,15 +44,17 @@ use crate::{
     },
     crypto::KeyPair,
     swarm::{
-        event_store::PersistenceMeta,
+        archive::Archive,
+        event_store::{self, Persisted, PersistenceMeta},
         gossip::Gossip,
         sqlite::{SqliteStore, SqliteStoreWrite},
+        sqlite_index_store::PublishKey,
         streams::{OwnStream, PublishedTree, ReplicatedStream},
     },
     trees::{
         axtrees::{AxKey, AxTrees, Sha256Digest},
         dnf::Dnf,
-        query::TagExprQuery,
+        query::{TagExprQuery, TimeQuery},
         tags::{ScopedTag, ScopedTagSet},
         AxTree, AxTreeHeader,
     },
@@ -66,13 +72,13 @@ use ax_types::{
 use banyan::{
     query::Query,
     store::{BranchCache, ReadOnlyStore},
-    FilteredChunk, Secrets,
+    FilteredChunk,
 };
 pub use banyan::{store::BlockWriter, Forest as BanyanForest, StreamBuilder, Transaction as BanyanTransaction};
 use fnv::FnvHashMap;
 use futures::{
     channel::mpsc,
-    future::{self, BoxFuture},
+    future::{self, BoxFuture, Future},
     stream, FutureExt, Stream, StreamExt, TryStreamExt,
 };
 use ipfs_embed::{
@@ -95,7 +101,7 @@ pub use prune::{RetainConfig, StreamAge, StreamSize};
 use serde::{Deserialize, Serialize};
 use sqlite_index_store::SqliteIndexStore;
 use std::{
-    collections::{BTreeMap, HashMap, VecDeque},
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
     convert::{TryFrom, TryInto},
     fmt::{Debug, Display},
     io::{BufRead, BufReader, Read},
@@ -162,18 +168,29 @@ fn internal_app_id() -> AppId {
 pub struct EphemeralEventsConfig {
     interval: Duration,
     streams: BTreeMap<String, RetainConfig>,
+    /// where to write events before they are pruned, configured from the `eventRouting.archive` settings
+    #[serde(skip)]
+    archive: Option<ArchiveConfig>,
 }
 
 impl EphemeralEventsConfig {
     pub fn new(interval: Duration, streams: BTreeMap<String, RetainConfig>) -> Self {
-        Self { interval, streams }
+        Self {
+            interval,
+            streams,
+            archive: None,
+        }
     }
     pub fn disable() -> Self {
         Self {
             streams: BTreeMap::default(),
             interval: Duration::from_secs(u64::MAX),
+            archive: None,
         }
     }
+    pub fn with_archive(self, archive: Option<ArchiveConfig>) -> Self {
+        Self { archive, ..self }
+    }
 }
 
 impl Default for EphemeralEventsConfig {
@@ -181,6 +198,7 @@ impl Default for EphemeralEventsConfig {
         Self {
             interval: Duration::from_secs(DEFAULT_PRUNING_INTERVAL),
             streams: BTreeMap::new(),
+            archive: None,
         }
     }
 }
@@ -197,6 +215,7 @@ impl From<BTreeMap<String, RetainConfig>> for EphemeralEventsConfig {
         Self {
             interval: Duration::from_secs(DEFAULT_PRUNING_INTERVAL),
             streams,
+            archive: None,
         }
     }
 }
@@ -232,6 +251,20 @@ pub struct SwarmConfig {
     pub bitswap_timeout: Duration,
     pub branch_cache_size: u64,
     pub event_routes: Vec<EventRoute>,
+    /// how long idempotency keys of published events are remembered
+    pub publish_key_window: Duration,
+    /// only replicate other nodes’ streams, never create or publish own streams
+    pub replica: bool,
+    /// accept stream roots from peers that do not sign them (databank version 2.17 and older),
+    /// except from nodes that have been seen signing their roots
+    pub accept_unsigned_roots: bool,
+    /// keys for encrypting own streams and decrypting replicated ones
+    pub stream_keys: StreamKeys,
+    /// names of the remote streams to replicate, all streams are replicated if `None`
+    ///
+    /// The default stream of each node is always replicated, it holds the mapping of stream
+    /// names to stream numbers.
+    pub replicated_streams: Option<BTreeSet<String>>,
 }
 impl SwarmConfig {
     pub fn basic() -> Self {
@@ -265,6 +298,11 @@ impl SwarmConfig {
             bitswap_timeout: Duration::from_secs(15),
             branch_cache_size: 67108864,
             event_routes: Default::default(),
+            publish_key_window: Duration::from_secs(60 * 60 * 24),
+            replica: false,
+            accept_unsigned_roots: true,
+            stream_keys: Default::default(),
+            replicated_streams: None,
         }
     }
 }
@@ -348,6 +386,11 @@ impl PartialEq for SwarmConfig {
             && self.bitswap_timeout == other.bitswap_timeout
             && self.branch_cache_size == other.branch_cache_size
             && self.event_routes == other.event_routes
+            && self.publish_key_window == other.publish_key_window
+            && self.replica == other.replica
+            && self.accept_unsigned_roots == other.accept_unsigned_roots
+            && self.stream_keys == other.stream_keys
+            && self.replicated_streams == other.replicated_streams
     }
 }
 
@@ -429,6 +472,18 @@ struct BanyanStoreData {
     lamport: Observer<LamportTimestamp>,
     /// Routing table
     routing_table: Lazy<RoutingTable, Box<dyn FnOnce() -> RoutingTable + Send>>,
+    /// how long idempotency keys of published events are remembered
+    publish_key_window: Duration,
+    /// whether this node only replicates other nodes’ streams, see [`SwarmConfig::replica`]
+    replica: bool,
+    /// keys for encrypted streams, see [`SwarmConfig::stream_keys`]
+    stream_keys: StreamKeys,
+    /// see [`SwarmConfig::replicated_streams`]
+    replicated_streams: Option<BTreeSet<String>>,
+    /// shared by plain appends and held exclusively by [`BanyanStore::append_checked`], so that
+    /// its checks cannot be invalidated by concurrent appends while plain appends still run
+    /// concurrently with each other
+    append_lock: tokio::sync::RwLock<()>,
 }
 
 /// Internal mutable state of the stream manager
@@ -447,6 +502,12 @@ struct BanyanStoreState {
     /// dispatcher to tell interested parties of newly discovered streams
     known_streams: Vec<mpsc::UnboundedSender<StreamId>>,
 
+    /// names of remote streams, learned from the mapping events in the owners’ default streams
+    remote_stream_names: BTreeMap<StreamId, String>,
+
+    /// remote streams that are not replicated, see [`SwarmConfig::replicated_streams`]
+    not_replicated: BTreeSet<StreamId>,
+
     /// tasks of the stream manager.
     tasks: Vec<(String, tokio::task::JoinHandle<()>)>,
 
@@ -507,10 +568,20 @@ impl<'a> BanyanStoreGuard<'a> {
         }
         tracing::debug!("creating new own stream {}", stream_nr);
         let stream_id = self.node_id().stream(stream_nr);
+        // the routing table is not yet available while creating the default stream during startup
+        let data = self.data.clone();
+        let stream_name = Lazy::get(&data.routing_table).and_then(|table| table.stream_name(stream_nr));
+        if let Some(name) = stream_name {
+            anyhow::ensure!(
+                !data.stream_keys.is_unavailable(name),
+                "cannot write to stream {}, it is encrypted and this node has no key for it",
+                name
+            );
+        }
         self.index_store
             .add_stream(stream_id)
             .context("unable to write stream id")?;
-        let (builder, latest) = if let Some(root) = self
+        let (builder, latest, key) = if let Some(root) = self
             .data
             .ipfs
             .resolve(StreamAlias::from(stream_id))
@@ -519,22 +590,34 @@ impl<'a> BanyanStoreGuard<'a> {
             let root = Link::try_from(root).context("wrong link format")?;
             let header = self.data.forest.store().get(&root).context("header not found")?;
             let header: AxTreeHeader = DagCborCodec.decode(&header).context("invalid header")?;
+            // existing streams keep their key, even if the configuration has changed
+            let secrets = match header.key {
+                Some(key) => self.data.stream_keys.get(Some(key), stream_id).with_context(|| {
+                    format!(
+                        "stream {} is encrypted with a key that is no longer configured",
+                        stream_nr
+                    )
+                })?,
+                None => self.banyan_config.secret.clone(),
+            };
             let builder = self
                 .data
                 .forest
-                .load_stream_builder(
-                    self.banyan_config.secret.clone(),
-                    self.banyan_config.tree.clone(),
-                    header.root,
-                )
+                .load_stream_builder(secrets, self.banyan_config.tree.clone(), header.root)
                 .with_context(|| format!("unable to load banyan tree for stream {}", stream_nr))?;
+            let key = header.key;
             let published = PublishedTree::new(root, header, builder.snapshot());
-            (builder, Some(published))
+            (builder, Some(published), key)
         } else {
-            let builder = StreamBuilder::new(self.banyan_config.tree.clone(), self.banyan_config.secret.clone());
-            (builder, None)
+            let encryption = stream_name.and_then(|name| data.stream_keys.for_name(name, stream_id));
+            let (key, secrets) = match encryption {
+                Some((key, secrets)) => (Some(key), secrets),
+                None => (None, self.banyan_config.secret.clone()),
+            };
+            let builder = StreamBuilder::new(self.banyan_config.tree.clone(), secrets);
+            (builder, None, key)
         };
-        let stream = Arc::new(OwnStream::new(stream_nr, builder, latest));
+        let stream = Arc::new(OwnStream::new(stream_nr, builder, latest, key));
         self.own_streams.insert(stream_nr, stream.clone());
         tracing::debug!("publish new stream_id {}", stream_id);
         self.publish_new_stream_id(stream_id);
@@ -555,12 +638,20 @@ impl<'a> BanyanStoreGuard<'a> {
             let root = Link::try_from(root).context("wrong link format")?;
             let header = self.data.forest.store().get(&root).context("header not found")?;
             let header: AxTreeHeader = DagCborCodec.decode(&header).context("invalid header")?;
-            let tree = self
-                .data
-                .forest
-                .load_tree(Secrets::default(), header.root)
-                .with_context(|| format!("unable to load banyan tree for stream {}", stream_id))?;
-            Some(PublishedTree::new(root, header, tree))
+            if let Some(secrets) = self.data.stream_keys.get(header.key, stream_id) {
+                let tree = self
+                    .data
+                    .forest
+                    .load_tree(secrets, header.root)
+                    .with_context(|| format!("unable to load banyan tree for stream {}", stream_id))?;
+                Some(PublishedTree::new(root, header, tree))
+            } else {
+                tracing::warn!(
+                    "stream {} is encrypted with a key that is no longer configured, not serving it",
+                    stream_id
+                );
+                None
+            }
         } else {
             None
         };
@@ -583,6 +674,29 @@ impl<'a> BanyanStoreGuard<'a> {
         stream_id.node_id() == self.node_id()
     }
 
+    /// Whether new roots of the given remote stream shall be replicated, see
+    /// [`SwarmConfig::replicated_streams`]. Streams that are not replicated are remembered.
+    fn should_replicate(&mut self, stream_id: StreamId) -> Replicate {
+        let selected = match &self.data.replicated_streams {
+            None => return Replicate::Yes,
+            Some(_) if stream_id.stream_nr() == DEFAULT_STREAM_NUMBER.into() => return Replicate::Yes,
+            Some(selected) => selected,
+        };
+        let result = match self.remote_stream_names.get(&stream_id) {
+            Some(name) if selected.contains(name) => Replicate::Yes,
+            Some(_) => Replicate::No,
+            None => Replicate::UnknownName,
+        };
+        if matches!(result, Replicate::Yes) {
+            self.not_replicated.remove(&stream_id);
+        } else if !self.not_replicated.insert(stream_id) && matches!(result, Replicate::UnknownName) {
+            // only try to learn the name when first seeing the stream, afterwards the name is
+            // learned when new mappings arrive in the owner’s default stream
+            return Replicate::No;
+        }
+        result
+    }
+
     fn has_stream(&self, stream_id: StreamId) -> bool {
         if self.is_local(stream_id) {
             self.own_streams.contains_key(&stream_id.stream_nr())
@@ -639,10 +753,14 @@ impl<'a> BanyanStoreGuard<'a> {
     }
 
     /// Get a complete root map from both own and replicated streams
-    pub fn root_map(&self) -> BTreeMap<StreamId, (Cid, Offset, LamportTimestamp)> {
+    ///
+    /// Signatures are only included for replicated streams, own roots need to be signed by the caller.
+    pub fn root_map(&self) -> BTreeMap<StreamId, (Cid, Offset, LamportTimestamp, Option<RootSignature>)> {
         let own = self.own_streams.iter().filter_map(|(stream_nr, inner)| {
             let stream_id = self.node_id().stream(*stream_nr);
-            inner.infos().map(|infos| (stream_id, infos))
+            inner
+                .infos()
+                .map(|(root, offset, lamport)| (stream_id, (root, offset, lamport, None)))
         });
 
         let other = self.remote_nodes.iter().flat_map(|(node_id, remote_node)| {
@@ -789,7 +907,8 @@ impl BanyanStore {
                         .with_max_failures(NonZeroU32::new(3).unwrap()),
                 ),
                 identify: Some(
-                    identify::Config::new("/actyx/2.0.0".to_string(), Ed25519(public)).with_agent_version(node_name),
+                    identify::Config::new("/actyx/2.0.0".to_string(), Ed25519(public))
+                        .with_agent_version(node_name.clone()),
                 ),
                 gossipsub: Some(
                     GossipsubConfigBuilder::default()
@@ -930,7 +1049,7 @@ impl BanyanStore {
         let forest = Forest::new(SqliteStore::wrap(ipfs.clone()), branch_cache.clone());
         let gossip = Gossip::new(
             ipfs.clone(),
-            node_id,
+            keypair,
             cfg.topic.clone(),
             cfg.enable_fast_path,
             cfg.enable_slow_path,
@@ -948,12 +1067,19 @@ impl BanyanStore {
                 lamport: index_store.observe_lamport(),
                 offsets: Default::default(),
                 routing_table: Lazy::new(Box::new(move || routing_table_reader.lock().take().unwrap())),
+                publish_key_window: cfg.publish_key_window,
+                replica: cfg.replica,
+                stream_keys: cfg.stream_keys,
+                replicated_streams: cfg.replicated_streams,
+                append_lock: Default::default(),
             }),
             state: Arc::new(ReentrantSafeMutex::new(BanyanStoreState {
                 index_store,
                 own_streams: Default::default(),
                 remote_nodes: Default::default(),
                 known_streams: Default::default(),
+                remote_stream_names: Default::default(),
+                not_replicated: Default::default(),
                 tasks: Default::default(),
                 banyan_config: cfg.banyan_config,
             })),
@@ -1080,11 +1206,16 @@ impl BanyanStore {
         );
         banyan.spawn_task(
             "gossip_ingest".to_owned(),
-            Gossip::ingest(banyan.clone(), cfg.topic.clone(), swarm_observer.clone())
-                .await?
-                .boxed(),
+            Gossip::ingest(
+                banyan.clone(),
+                cfg.topic.clone(),
+                cfg.accept_unsigned_roots,
+                swarm_observer.clone(),
+            )
+            .await?
+            .boxed(),
         );
-        if cfg.enable_root_map {
+        if cfg.enable_root_map && !cfg.replica {
             banyan.spawn_task(
                 "gossip_publish_root_map".to_owned(),
                 banyan
@@ -1094,10 +1225,12 @@ impl BanyanStore {
                     .boxed(),
             );
         }
-        banyan.spawn_task(
-            "compaction".to_owned(),
-            banyan.clone().compaction_loop(cfg.cadence_compact).boxed(),
-        );
+        if !cfg.replica {
+            banyan.spawn_task(
+                "compaction".to_owned(),
+                banyan.clone().compaction_loop(cfg.cadence_compact).boxed(),
+            );
+        }
         if cfg.enable_discovery {
             banyan.spawn_task(
                 "discovery_ingest".to_owned(),
@@ -1112,22 +1245,29 @@ impl BanyanStore {
                 banyan.clone(),
                 swarm_events,
                 external_addrs,
-                cfg.enable_discovery,
+                cfg.enable_discovery && !cfg.replica,
                 peers,
             )?
             .boxed(),
         );
-        if cfg.enable_metrics {
+        if cfg.enable_metrics && !cfg.replica {
             banyan.spawn_task(
                 "metrics".to_owned(),
                 metrics::metrics(banyan.clone(), cfg.metrics_interval)?.boxed(),
             );
         }
 
-        banyan.spawn_task(
-            "prune_events".to_owned(),
-            prune::prune(banyan.clone(), cfg.ephemeral_event_config).boxed(),
-        );
+        if !cfg.replica {
+            let archive = cfg
+                .ephemeral_event_config
+                .archive
+                .clone()
+                .map(|archive| Archive::new(archive, node_name, cfg.topic.clone()));
+            banyan.spawn_task(
+                "prune_events".to_owned(),
+                prune::prune(banyan.clone(), cfg.ephemeral_event_config, archive).boxed(),
+            );
+        }
 
         Ok(banyan)
     }
@@ -1136,6 +1276,11 @@ impl BanyanStore {
         self.data.topic.clone()
     }
 
+    /// Whether this node only replicates other nodes’ streams, see [`SwarmConfig::replica`].
+    pub fn is_replica(&self) -> bool {
+        self.data.replica
+    }
+
     /// Loads the default stream, reading all [RouteMappingEvents] from it and returning
     /// the respective route mapping.
     async fn get_published_mappings(&self, node_id: NodeId) -> Result<HashMap<String, StreamNr>> {
@@ -1349,8 +1494,16 @@ impl BanyanStore {
 
     /// Append events to a stream, publishing the new data.
     pub async fn append(&self, app_id: AppId, events: Vec<(TagSet, Event)>) -> Result<Vec<PersistenceMeta>> {
-        let timestamp = Timestamp::now();
+        let _guard = self.data.append_lock.read().await;
+        self.append_unlocked(app_id, Timestamp::now(), events).await
+    }
 
+    async fn append_unlocked(
+        &self,
+        app_id: AppId,
+        timestamp: Timestamp,
+        events: Vec<(TagSet, Event)>,
+    ) -> Result<Vec<PersistenceMeta>> {
         let mut metas = Vec::with_capacity(events.len());
         let mut grouped_events: Vec<(StreamNr, Vec<_>)> = vec![];
 
@@ -1386,7 +1539,112 @@ impl BanyanStore {
         Ok(metas)
     }
 
+    /// Append events like [`BanyanStore::append`] if `check` succeeds, without other events being
+    /// appended in between.
+    ///
+    /// With an idempotency `key` that `app_id` already used within the configured window, neither
+    /// `check` nor the append are performed and the metadata of the earlier events is returned.
+    /// If the earlier publish was interrupted, its record is completed with the events that were
+    /// stored, or removed if there are none, so that this publish proceeds.
+    pub async fn append_checked(
+        &self,
+        app_id: AppId,
+        key: Option<&str>,
+        events: Vec<(TagSet, Event)>,
+        check: impl Future<Output = Result<()>>,
+    ) -> Result<Persisted> {
+        let _guard = self.data.append_lock.write().await;
+        let now = Timestamp::now();
+        let expired = now - self.data.publish_key_window;
+        if let Some(key) = key {
+            match self.lock().index_store.get_publish_key(&app_id, key, expired)? {
+                Some(PublishKey::Published(response)) => {
+                    tracing::debug!("publish key {} of {} already used", key, app_id);
+                    let meta = serde_cbor::from_slice(&response).context("decoding publish key response")?;
+                    return Ok(Persisted { meta, replayed: true });
+                }
+                Some(PublishKey::Pending(published)) => {
+                    let meta = self.find_published(&app_id, published).await?;
+                    if meta.is_empty() {
+                        tracing::info!(
+                            "publish key {} of {} was interrupted before storing events",
+                            key,
+                            app_id
+                        );
+                        self.lock().index_store.remove_publish_key(&app_id, key)?;
+                    } else {
+                        tracing::info!("publish key {} of {} was interrupted after storing events", key, app_id);
+                        let response = serde_cbor::to_vec(&meta)?;
+                        self.lock()
+                            .index_store
+                            .put_publish_key(&app_id, key, published, Some(&response), expired)?;
+                        return Ok(Persisted { meta, replayed: true });
+                    }
+                }
+                None => {}
+            }
+        }
+        check.await?;
+        // the key is recorded before appending, so that a crash in between cannot lead to the
+        // events being published again by a retry; the events carry the recorded timestamp, so
+        // that a retry can find them
+        if let Some(key) = key {
+            self.lock()
+                .index_store
+                .put_publish_key(&app_id, key, now, None, expired)?;
+        }
+        let metas = match self.append_unlocked(app_id.clone(), now, events).await {
+            Ok(metas) => metas,
+            Err(e) => {
+                if let Some(key) = key {
+                    self.lock().index_store.remove_publish_key(&app_id, key)?;
+                }
+                return Err(e);
+            }
+        };
+        if let Some(key) = key {
+            let response = serde_cbor::to_vec(&metas)?;
+            self.lock()
+                .index_store
+                .put_publish_key(&app_id, key, now, Some(&response), expired)?;
+        }
+        Ok(Persisted {
+            meta: metas,
+            replayed: false,
+        })
+    }
+
+    /// The metadata of the events `app_id` published with the given timestamp on this node, in
+    /// the order in which they were published.
+    async fn find_published(&self, app_id: &AppId, timestamp: Timestamp) -> Result<Vec<PersistenceMeta>> {
+        let streams = self
+            .lock()
+            .own_streams
+            .iter()
+            .map(|(stream_nr, stream)| (*stream_nr, stream.clone()))
+            .collect::<Vec<_>>();
+        let query = TimeQuery::from(timestamp..timestamp + 1);
+        let mut metas = vec![];
+        for (stream_nr, stream) in streams {
+            let tree = stream.lock().await.snapshot();
+            for event in self.data.forest.iter_filtered(&tree, query.clone()) {
+                let (offset, key, _) = event?;
+                if key.app_id().as_ref() == Some(app_id) {
+                    metas.push((key.lamport(), Offset::try_from(offset)?, stream_nr, key.time()));
+                }
+            }
+        }
+        // lamports are reserved in publishing order across all streams
+        metas.sort_by_key(|meta| meta.0);
+        Ok(metas)
+    }
+
     async fn append_stream_mapping_event(&self, name: String, number: StreamNr) -> Result<()> {
+        if self.data.replica {
+            // the routing table is only needed for publishing, which replicas don’t do
+            tracing::debug!("replica not publishing mapping of stream {} to {}", name, number);
+            return Ok(());
+        }
         let event = EventRouteMappingEvent {
             stream_name: name,
             stream_nr: number,
@@ -1413,6 +1671,9 @@ impl BanyanStore {
         events: Vec<(TagSet, Event)>,
     ) -> Result<AppendMeta> {
         debug_assert!(!events.is_empty());
+        if self.data.replica {
+            anyhow::bail!("cannot publish on stream {}, this node is a replica", stream_nr);
+        }
         tracing::debug!("publishing {} events on stream {}", events.len(), stream_nr);
         let stream = self.get_or_create_own_stream(stream_nr)?;
         let mut guard = stream.lock().await;
@@ -1533,7 +1794,7 @@ impl BanyanStore {
 
         // grab the latest lamport
         let lamport = self.data.lamport.get();
-        let header = AxTreeHeader::new(curr.link().unwrap(), lamport);
+        let header = AxTreeHeader::new(curr.link().unwrap(), lamport).with_key(stream.key());
         let root = txn.writer_mut().put(DagCborCodec.encode(&header)?)?;
         let cid = Cid::from(root);
         // update the permanent alias. If this fails, we will revert the builder.
@@ -1555,15 +1816,59 @@ impl BanyanStore {
         Ok(res)
     }
 
-    fn update_root(&self, stream_id: StreamId, root: Link, source: RootSource) {
+    fn update_root(&self, stream_id: StreamId, root: Link, source: RootSource, signature: Option<RootSignature>) {
         if !self.is_local(stream_id) {
+            match self.lock().should_replicate(stream_id) {
+                Replicate::Yes => {}
+                Replicate::No => return,
+                Replicate::UnknownName => {
+                    tokio::spawn(self.clone().learn_stream_names(stream_id.node_id()));
+                    return;
+                }
+            }
             tracing::trace!("update_root {} {}", stream_id, root);
             self.get_or_create_replicated_stream(stream_id)
                 .unwrap()
-                .set_incoming(root, source);
+                .set_incoming(root, source, signature);
+        }
+    }
+
+    /// Read the stream names of the given remote node from its default stream, if it has
+    /// streams that are not replicated because their name is not yet known.
+    async fn learn_stream_names(self, node_id: NodeId) {
+        let unknown = {
+            let guard = self.lock();
+            guard
+                .not_replicated
+                .iter()
+                .any(|stream_id| stream_id.node_id() == node_id && !guard.remote_stream_names.contains_key(stream_id))
+        };
+        if !unknown {
+            return;
+        }
+        match self.get_published_mappings(node_id).await {
+            Ok(mappings) => {
+                let mut guard = self.lock();
+                for (name, stream_nr) in mappings {
+                    tracing::debug!("learned name {} of stream {}", name, node_id.stream(stream_nr));
+                    guard.remote_stream_names.insert(node_id.stream(stream_nr), name);
+                }
+            }
+            Err(err) => tracing::warn!("cannot read stream names of node {}: {}", node_id, err),
         }
     }
 
+    /// Remote streams that are not replicated, see [`SwarmConfig::replicated_streams`], with
+    /// their names if known.
+    pub fn not_replicated_streams(&self) -> Vec<(StreamId, Option<String>)> {
+        let guard = self.lock();
+        guard
+            .not_replicated
+            .iter()
+            .map(|stream_id| (*stream_id, guard.remote_stream_names.get(stream_id).cloned()))
+            .collect()
+    }
+
     async fn compaction_loop(self, interval: Duration) {
         loop {
             let stream_nrs = self.lock().local_stream_nrs();
@@ -1584,11 +1889,12 @@ impl BanyanStore {
     /// careful ingestion - basically just call sync_one on each new ingested root
     async fn careful_ingestion(self, stream_id: StreamId, state: Arc<ReplicatedStream>) {
         let state2 = state.clone();
+        let store = self.clone();
         state
             .incoming_root_stream()
-            .switch_map(move |(root, source)| {
+            .switch_map(move |(root, source, signature)| {
                 self.clone()
-                    .sync_one(stream_id, root, source)
+                    .sync_one(stream_id, root, source, signature)
                     .map(move |res| (res, root))
                     .into_stream()
             })
@@ -1610,6 +1916,12 @@ impl BanyanStore {
                     Ok(outcome) => {
                         tracing::trace!("sync completed {:?}", outcome);
                         state2.downgrade(root, false);
+                        if matches!(outcome, SyncOutcome::Success)
+                            && stream_id.stream_nr() == DEFAULT_STREAM_NUMBER.into()
+                        {
+                            // the default stream may contain new stream mappings
+                            tokio::spawn(store.clone().learn_stream_names(stream_id.node_id()));
+                        }
                     }
                 }
                 future::ready(())
@@ -1620,10 +1932,16 @@ impl BanyanStore {
     /// attempt to sync one stream to a new root.
     ///
     /// this future may be interrupted at any time when an even newer root comes along.
-    async fn sync_one(self, stream_id: StreamId, root: Link, source: RootSource) -> Result<SyncOutcome> {
-        if source.path == RootPath::SlowPath {
+    async fn sync_one(
+        self,
+        stream_id: StreamId,
+        root: Link,
+        source: RootSource,
+        signature: Option<RootSignature>,
+    ) -> Result<SyncOutcome> {
+        if source.path == RootPath::SlowPath && !self.data.replica {
             // it is not unlikely that this sync_one will be replaced by one from the FastPath,
-            // so don’t start bitswapping right away
+            // so don’t start bitswapping right away (unless replicating is all this node does)
             tokio::time::sleep(Duration::from_millis(100)).await;
         }
 
@@ -1671,15 +1989,20 @@ impl BanyanStore {
                         // this is not unexpected and should not be logged as an error
                         return Ok(SyncOutcome::OldHeader);
                     }
+                    if self.data.stream_keys.get(temp.key, stream_id).is_none() {
+                        // streams encrypted for other nodes are not replicated
+                        return Ok(SyncOutcome::UnknownKey);
+                    }
                     header = Some(temp);
                 }
             }
             if let Some(header) = header.as_ref() {
                 // try to load the tree. It should come immediately after the header
+                let secrets = self.data.stream_keys.get(header.key, stream_id).unwrap_or_default();
                 if let Ok(temp) = self
                     .data
                     .forest
-                    .load_tree(Secrets::default(), header.root)
+                    .load_tree(secrets, header.root)
                     .surface::<BlockNotFound>()?
                 {
                     // sanity check: we must never lose events.
@@ -1690,7 +2013,7 @@ impl BanyanStore {
         }
         let header = header.ok_or_else(|| anyhow::anyhow!("header was not loaded during sync"))?;
         let tree = tree.ok_or_else(|| anyhow::anyhow!("tree was not loaded during sync"))?;
-        let state = PublishedTree::new(root, header, tree.clone());
+        let state = PublishedTree::new(root, header, tree.clone()).with_signature(signature);
 
         // if we get here, we already know that the new tree is better than its predecessor
         tracing::trace!("completed sync of {}", root);
@@ -1790,9 +2113,17 @@ impl BanyanStore {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+enum Replicate {
+    Yes,
+    No,
+    UnknownName,
+}
+
 #[derive(Debug)]
 enum SyncOutcome {
     OldHeader,
+    UnknownKey,
     Success,
 }
 
@@ -2073,6 +2404,14 @@ impl RoutingTable {
         stream_nr
     }
 
+    /// The name of the stream mapped to the given number, if any.
+    fn stream_name(&self, stream_nr: StreamNr) -> Option<&str> {
+        self.stream_mapping
+            .iter()
+            .find(|(_, nr)| **nr == stream_nr)
+            .map(|(name, _)| name.as_str())
+    }
+
     /// "Routes" a provided [TagSet] to the corresponding [StreamNr].
     /// If it is not able to match the [TagSet], it will return `StreamNr::default()`.
     fn get_matching_stream_nr(&self, tag_set: &TagSet, app_id: &AppId) -> StreamNr {
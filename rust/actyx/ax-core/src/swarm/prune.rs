@@ -1,17 +1,21 @@
 use crate::{
-    swarm::{streams::OwnStreamGuard, BanyanStore, EphemeralEventsConfig, Link},
+    swarm::{archive::Archive, streams::OwnStreamGuard, BanyanStore, EphemeralEventsConfig, Link},
     trees::{
-        axtrees::AxTrees,
+        axtrees::{AxKey, AxTrees},
         query::{OffsetQuery, TimeQuery},
     },
 };
 use ax_types::{Payload, Timestamp};
-use banyan::{query::AndQuery, Tree};
+use banyan::{
+    index::Index,
+    query::{AllQuery, AndQuery, OffsetRangeQuery},
+    Tree,
+};
 use futures::future::{join_all, FutureExt};
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{de::Visitor, Deserialize, Serialize};
-use std::{future, str::FromStr, time::Duration};
+use std::{future, ops::Range, str::FromStr, time::Duration};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum StreamSize {
@@ -367,7 +371,7 @@ impl RetainConfig {
 }
 
 fn calculate_emit_from(store: &BanyanStore, tree: Tree<AxTrees, Payload>, size: u64) -> u64 {
-    let iter = store.data.forest.iter_index_reverse(&tree, banyan::query::AllQuery);
+    let iter = store.data.forest.iter_index_reverse(&tree, AllQuery);
     let mut bytes = 0u64;
     let mut current_offset = tree.count();
     for maybe_index in iter {
@@ -394,15 +398,65 @@ fn calculate_emit_from(store: &BanyanStore, tree: Tree<AxTrees, Payload>, size:
     0
 }
 
+/// The offset ranges of the events removed from `tree`, with adjacent ranges merged.
+fn purged_ranges(store: &BanyanStore, tree: &Tree<AxTrees, Payload>) -> anyhow::Result<Vec<Range<u64>>> {
+    let mut ranges: Vec<Range<u64>> = Vec::new();
+    let mut offset = 0;
+    for index in store.data.forest.iter_index(tree, AllQuery) {
+        let index = index?;
+        let purged = match &index {
+            Index::Leaf(leaf) => leaf.link.is_none(),
+            // the children of a branch that is not purged follow it
+            Index::Branch(branch) if branch.link.is_some() => continue,
+            Index::Branch(_) => true,
+        };
+        let end = offset + index.count();
+        if purged {
+            match ranges.last_mut() {
+                Some(last) if last.end == offset => last.end = end,
+                _ => ranges.push(offset..end),
+            }
+        }
+        offset = end;
+    }
+    Ok(ranges)
+}
+
+/// The events present in `before` but not in `after`, which resulted from pruning `before`.
+fn pruned_events(
+    store: &BanyanStore,
+    before: &Tree<AxTrees, Payload>,
+    after: &Tree<AxTrees, Payload>,
+) -> anyhow::Result<Vec<(u64, AxKey, Payload)>> {
+    // pruning replaces whole leaves and branches by placeholders without changing offsets, so only
+    // the leaves of `before` within the purged ranges of `after` are read; the ranges purged
+    // earlier have no events in `before` either
+    let mut pruned = Vec::new();
+    for range in purged_ranges(store, after)? {
+        for event in store.data.forest.iter_filtered(before, OffsetRangeQuery::from(range)) {
+            pruned.push(event?);
+        }
+    }
+    Ok(pruned)
+}
+
 // The timestamp parameter is used has an hack around having to use a fake system clock
 // to make testing this function deterministic
 fn prune_stream(
     store: &BanyanStore,
     mut stream: OwnStreamGuard<'_>,
     config: &RetainConfig,
+    archive: Option<&Archive>,
     now: Timestamp,
 ) -> anyhow::Result<Option<Link>> {
     let stream_nr = stream.stream_nr();
+    let stream_id = store.node_id().stream(stream_nr);
+    if archive.is_some() && stream.key().is_some() {
+        // the archive files are not encrypted, so they must not hold the events of encrypted streams,
+        // which therefore are kept instead of being removed without a copy
+        tracing::warn!("not pruning encrypted stream {} since archiving is enabled", stream_nr);
+        return Ok(stream.snapshot().link());
+    }
     store.transform_stream(&mut stream, |transaction, tree| {
         let _span = tracing::debug_span!("prune", stream_nr = u64::from(stream_nr)).entered();
         transaction.pack(tree)?;
@@ -424,7 +478,17 @@ fn prune_stream(
         );
 
         tracing::debug!("Pruning: events on {}; retain {:?}", stream_nr, query);
-        transaction.retain(tree, &query)
+        let before = tree.snapshot();
+        transaction.retain(tree, &query)?;
+
+        if let Some(archive) = archive {
+            let after = tree.snapshot();
+            if after.link() != before.link() {
+                // failing here reverts the pruning, so no event is lost without being archived
+                archive.write(stream_id, &pruned_events(store, &before, &after)?)?;
+            }
+        }
+        Ok(())
     })?;
     Ok(stream.snapshot().link())
 }
@@ -433,12 +497,15 @@ fn prune_stream(
 /// [`RetainConfig`] in [`EphemeralEventsConfig`] in parallel. After all streams
 /// have been cleaned, waits for the duration given in
 /// [`EphemeralEventsConfig::interval`].
+/// If an [`Archive`] is given, the removed events are written to it first and
+/// encrypted streams are not pruned.
 /// Note that any unsealed nodes remain untouched.
-pub(crate) async fn prune(store: BanyanStore, config: EphemeralEventsConfig) {
+pub(crate) async fn prune(store: BanyanStore, config: EphemeralEventsConfig, archive: Option<Archive>) {
     loop {
         tokio::time::sleep(config.interval).await;
         let tasks = config.streams.iter().map(|(stream_name, cfg)| {
             let store = store.clone();
+            let archive = archive.as_ref();
             tracing::debug!("Checking ephemeral event conditions for {}", stream_name);
 
             let stream_nr = store.data.routing_table.stream_mapping.get(stream_name).copied();
@@ -450,7 +517,7 @@ pub(crate) async fn prune(store: BanyanStore, config: EphemeralEventsConfig) {
             let fut = async move {
                 let stream = store.get_or_create_own_stream(stream_nr).unwrap();
                 let guard = stream.lock().await;
-                prune_stream(&store, guard, cfg, Timestamp::now())
+                prune_stream(&store, guard, cfg, archive, Timestamp::now())
             };
 
             fut.map(move |res| match res {
@@ -473,12 +540,17 @@ mod test {
     use super::*;
     use crate::{
         ax_futures_util::stream::AxStreamExt,
-        swarm::{BanyanConfig, EventRoute, SwarmConfig},
+        swarm::{
+            archive::{ArchiveConfig, ArchiveIndex},
+            BanyanConfig, EventRoute, StreamKeys, SwarmConfig,
+        },
         trees::query::TagExprQuery,
+        util::formats::banyan_protocol::{decode_dump_frame, decode_dump_header},
     };
     use acto::ActoRef;
     use ax_aql::TagExpr;
-    use ax_types::{app_id, tags, AppId, Payload, StreamNr};
+    use ax_types::{app_id, tags, AppId, NodeId, Payload, StreamNr};
+    use cbor_data::Cbor;
     use futures::{future, StreamExt, TryStreamExt};
     use itertools::Either;
     use parking_lot::Mutex;
@@ -490,6 +562,10 @@ mod test {
     }
 
     async fn create_store() -> anyhow::Result<BanyanStore> {
+        create_store_with_keys(StreamKeys::default()).await
+    }
+
+    async fn create_store_with_keys(stream_keys: StreamKeys) -> anyhow::Result<BanyanStore> {
         crate::util::setup_logger();
         let cfg: SwarmConfig = SwarmConfig {
             node_name: Some("ephemeral".to_owned()),
@@ -505,6 +581,7 @@ mod test {
                 TagExpr::from_str("'test'").unwrap(),
                 "test_stream".to_string(),
             )],
+            stream_keys,
             ..SwarmConfig::basic()
         };
         BanyanStore::new(cfg, ActoRef::blackhole()).await
@@ -530,7 +607,14 @@ mod test {
 
         let stream = store.get_or_create_own_stream(test_stream).unwrap();
         let guard = stream.lock().await;
-        super::prune_stream(&store, guard, &RetainConfig::events(events_to_retain), Timestamp::now()).unwrap();
+        super::prune_stream(
+            &store,
+            guard,
+            &RetainConfig::events(events_to_retain),
+            None,
+            Timestamp::now(),
+        )
+        .unwrap();
 
         let query = OffsetQuery::from(0..);
         let round_tripped = store
@@ -579,7 +663,7 @@ mod test {
 
         let stream = store.get_or_create_own_stream(test_stream).unwrap();
         let guard = stream.lock().await;
-        super::prune_stream(&store, guard, &RetainConfig::size(max_size), Timestamp::now()).unwrap();
+        super::prune_stream(&store, guard, &RetainConfig::size(max_size), None, Timestamp::now()).unwrap();
 
         let query = OffsetQuery::from(0..);
         let round_tripped = store
@@ -683,6 +767,7 @@ mod test {
             &store,
             guard,
             &RetainConfig::age_from_millis(dur.as_millis() as u64),
+            None,
             last,
         )
         .unwrap();
@@ -727,6 +812,117 @@ mod test {
         test_retain_age(200).await;
     }
 
+    fn read_archive_file(path: &std::path::Path) -> (NodeId, Vec<Payload>) {
+        let bytes = zstd::decode_all(std::fs::File::open(path).unwrap()).unwrap();
+        let (header, mut rest) = Cbor::checked_prefix(&bytes).unwrap();
+        let (node_id, _topic, _timestamp) = decode_dump_header(header).unwrap();
+        let mut payloads = Vec::new();
+        while !rest.is_empty() {
+            let (cbor, tail) = Cbor::checked_prefix(rest).unwrap();
+            let (orig_node, app_id, _timestamp, tags, payload) = decode_dump_frame(cbor).unwrap();
+            assert_eq!(orig_node, node_id);
+            assert_eq!(app_id, self::app_id());
+            assert_eq!(tags, tags!("test"));
+            payloads.push(payload);
+            rest = tail;
+        }
+        (node_id, payloads)
+    }
+
+    #[tokio::test]
+    async fn archive_pruned_events() {
+        let event_count = 1024;
+        let test_stream = StreamNr::from(1);
+        let store = publish_events(event_count).await.unwrap();
+        let stream_id = store.node_id().stream(test_stream);
+        let dir = tempfile::tempdir().unwrap();
+        // start a new file for every archival run
+        let archive = Archive::new(
+            ArchiveConfig::new(dir.path().to_owned(), 1),
+            "ephemeral".to_owned(),
+            store.get_topic(),
+        );
+
+        for events_to_retain in [512, 100] {
+            let stream = store.get_or_create_own_stream(test_stream).unwrap();
+            let guard = stream.lock().await;
+            super::prune_stream(
+                &store,
+                guard,
+                &RetainConfig::events(events_to_retain),
+                Some(&archive),
+                Timestamp::now(),
+            )
+            .unwrap();
+        }
+
+        let retained = store
+            .stream_filtered_chunked(stream_id, 0..=u64::MAX, OffsetQuery::from(0..))
+            .take_until_condition(|x| future::ready(x.as_ref().unwrap().range.end >= event_count))
+            .map_ok(|chunk| futures::stream::iter(chunk.data.into_iter().map(Ok)))
+            .try_flatten()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let index = ArchiveIndex::load(dir.path()).unwrap();
+        assert_eq!(index.files.len(), 2);
+        let mut archived = Vec::new();
+        for file in &index.files {
+            let (node_id, payloads) = read_archive_file(&dir.path().join(&file.name));
+            assert_eq!(node_id, store.node_id());
+            assert_eq!(file.events, payloads.len() as u64);
+            archived.extend(payloads);
+        }
+
+        // every event is either still in the stream or in the archive, in offset order
+        let expected = (0..event_count)
+            .map(|i| Payload::from_json_str(&i.to_string()).unwrap())
+            .collect::<Vec<_>>();
+        let all = archived
+            .into_iter()
+            .chain(retained.into_iter().map(|(_, _, payload)| payload))
+            .collect::<Vec<_>>();
+        assert_eq!(all, expected);
+    }
+
+    #[tokio::test]
+    async fn archive_skips_encrypted_streams() {
+        let test_stream = StreamNr::from(1);
+        let mut stream_keys = StreamKeys::default();
+        stream_keys
+            .insert("test_stream".to_owned(), &StreamKeys::generate())
+            .unwrap();
+        let store = create_store_with_keys(stream_keys).await.unwrap();
+        let events = (0..1024)
+            .map(|i| (tags!("test"), Payload::from_json_str(&i.to_string()).unwrap()))
+            .collect::<Vec<_>>();
+        store.append(app_id(), events).await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let archive = Archive::new(
+            ArchiveConfig::new(dir.path().to_owned(), 1),
+            "ephemeral".to_owned(),
+            store.get_topic(),
+        );
+
+        let stream = store.get_or_create_own_stream(test_stream).unwrap();
+        let guard = stream.lock().await;
+        assert!(guard.key().is_some());
+        let before = guard.snapshot().link();
+        let after = super::prune_stream(
+            &store,
+            guard,
+            &RetainConfig::events(100),
+            Some(&archive),
+            Timestamp::now(),
+        )
+        .unwrap();
+
+        // the events can be neither archived nor dropped, so the stream stays as it is
+        assert_eq!(after, before);
+        assert!(ArchiveIndex::load(dir.path()).unwrap().files.is_empty());
+    }
+
     async fn prune_replication_store(store_name: &str, enable_pruning: bool) -> BanyanStore {
         let banyan_config = BanyanConfig {
             tree: banyan::Config {
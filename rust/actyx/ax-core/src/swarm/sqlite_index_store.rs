@@ -1,8 +1,8 @@
 use crate::ax_futures_util::stream::variable::{Observer, Variable};
 use anyhow::{Context, Result};
-use ax_types::{LamportTimestamp, StreamId};
+use ax_types::{AppId, LamportTimestamp, NodeId, StreamId, Timestamp};
 use parking_lot::Mutex;
-use rusqlite::{backup, params, Connection, OpenFlags};
+use rusqlite::{backup, params, Connection, OpenFlags, OptionalExtension};
 use std::{collections::BTreeSet, convert::TryFrom, path::PathBuf, sync::Arc, time::Duration};
 use tracing::*;
 
@@ -12,6 +12,15 @@ pub enum DbPath {
     Memory,
 }
 
+/// What is recorded for a publish with an idempotency key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublishKey {
+    /// the publish was started at this time, but its completion has not been recorded
+    Pending(Timestamp),
+    /// the publish completed with this (CBOR encoded) response
+    Published(Vec<u8>),
+}
+
 pub struct SqliteIndexStore {
     conn: Arc<Mutex<Connection>>,
     /// local copy of the lamport timestamp for quick access
@@ -142,6 +151,73 @@ impl SqliteIndexStore {
         Ok(set)
     }
 
+    /// Look up what was recorded for a publish by `app_id` with the given idempotency key,
+    /// unless it was recorded before `since`.
+    pub fn get_publish_key(&self, app_id: &AppId, key: &str, since: Timestamp) -> Result<Option<PublishKey>> {
+        let row: Option<(i64, Option<Vec<u8>>)> = self
+            .conn
+            .lock()
+            .prepare_cached(
+                "SELECT published, response FROM publish_keys WHERE app_id = ? AND key = ? AND published >= ?",
+            )?
+            .query_row(params![app_id.as_str(), key, since.as_i64()], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?;
+        Ok(row.map(|(published, response)| match response {
+            Some(response) => PublishKey::Published(response),
+            None => PublishKey::Pending(Timestamp::new(published as u64)),
+        }))
+    }
+
+    /// Record a publish by `app_id` with the given idempotency key, which is pending without a
+    /// `response`, forgetting all keys recorded before `expired`.
+    pub fn put_publish_key(
+        &mut self,
+        app_id: &AppId,
+        key: &str,
+        published: Timestamp,
+        response: Option<&[u8]>,
+        expired: Timestamp,
+    ) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.prepare_cached("DELETE FROM publish_keys WHERE published < ?")?
+            .execute(params![expired.as_i64()])?;
+        conn.prepare_cached("INSERT OR REPLACE INTO publish_keys VALUES (?, ?, ?, ?)")?
+            .execute(params![app_id.as_str(), key, published.as_i64(), response])?;
+        Ok(())
+    }
+
+    /// Forget a pending publish whose events have not been stored.
+    pub fn remove_publish_key(&mut self, app_id: &AppId, key: &str) -> Result<()> {
+        self.conn
+            .lock()
+            .prepare_cached("DELETE FROM publish_keys WHERE app_id = ? AND key = ?")?
+            .execute(params![app_id.as_str(), key])?;
+        Ok(())
+    }
+
+    /// Remember that `node` signs the roots of its streams.
+    pub fn add_signing_node(&mut self, node: NodeId) -> Result<()> {
+        self.conn
+            .lock()
+            .prepare_cached("INSERT OR IGNORE INTO signing_nodes VALUES (?)")?
+            .execute(params![node.to_string()])?;
+        Ok(())
+    }
+
+    /// All nodes that have been seen signing the roots of their streams.
+    pub fn get_signing_nodes(&self) -> Result<BTreeSet<NodeId>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare_cached("SELECT node_id FROM signing_nodes")?;
+        let nodes = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut set = BTreeSet::new();
+        for node in nodes {
+            set.insert(node?.parse()?);
+        }
+        Ok(set)
+    }
+
     pub fn observe_lamport(&self) -> Observer<LamportTimestamp> {
         self.lamport.new_observer()
     }
@@ -175,6 +251,10 @@ pub fn initialize_db(conn: &Connection) -> Result<()> {
             (stream TEXT UNIQUE);\n\
         CREATE TABLE IF NOT EXISTS meta \
             (lamport INTEGER);\n\
+        CREATE TABLE IF NOT EXISTS publish_keys \
+            (app_id TEXT, key TEXT, published INTEGER, response BLOB, PRIMARY KEY (app_id, key));\n\
+        CREATE TABLE IF NOT EXISTS signing_nodes \
+            (node_id TEXT PRIMARY KEY);\n\
         COMMIT;",
     )
     .context("creating tables")?;
@@ -230,6 +310,60 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn publish_key_persistence() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db = dir.path().join("db").to_str().expect("illegal filename").to_owned();
+        let app_id = ax_types::app_id!("com.example.app");
+        let t = Timestamp::from(1_000_000);
+        let mut store = get_shared_memory_index_store(&db)?;
+        let published = |r: &[u8]| Some(PublishKey::Published(r.to_vec()));
+        store.put_publish_key(&app_id, "k1", t, Some(b"one"), t - 10)?;
+        store.put_publish_key(&app_id, "k2", t + 5, Some(b"two"), t - 10)?;
+        store.put_publish_key(&app_id, "k4", t + 5, None, t - 10)?;
+
+        let store = get_shared_memory_index_store(&db)?;
+        assert_eq!(store.get_publish_key(&app_id, "k1", t)?, published(b"one"));
+        assert_eq!(
+            store.get_publish_key(&app_id, "k4", t)?,
+            Some(PublishKey::Pending(t + 5))
+        );
+        assert_eq!(store.get_publish_key(&app_id, "k1", t + 1)?, None);
+        assert_eq!(
+            store.get_publish_key(&ax_types::app_id!("com.example.other"), "k1", t)?,
+            None
+        );
+
+        // recording a key forgets the expired ones
+        let mut store = store;
+        store.put_publish_key(&app_id, "k3", t + 10, Some(b"three"), t + 1)?;
+        assert_eq!(store.get_publish_key(&app_id, "k1", Timestamp::from(0))?, None);
+        assert_eq!(store.get_publish_key(&app_id, "k2", t)?, published(b"two"));
+
+        // a pending publish is completed or forgotten
+        store.put_publish_key(&app_id, "k4", t + 5, Some(b"four"), t + 1)?;
+        assert_eq!(store.get_publish_key(&app_id, "k4", t)?, published(b"four"));
+        store.put_publish_key(&app_id, "k5", t + 10, None, t + 1)?;
+        store.remove_publish_key(&app_id, "k5")?;
+        assert_eq!(store.get_publish_key(&app_id, "k5", t)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn signing_node_persistence() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db = dir.path().join("db").to_str().expect("illegal filename").to_owned();
+        let node = NodeId::from_bytes(&[1; 32])?;
+        let mut store = get_shared_memory_index_store(&db)?;
+        assert!(store.get_signing_nodes()?.is_empty());
+        store.add_signing_node(node)?;
+        store.add_signing_node(node)?;
+
+        let store = get_shared_memory_index_store(&db)?;
+        assert_eq!(store.get_signing_nodes()?, BTreeSet::from([node]));
+        Ok(())
+    }
+
     #[test]
     fn stream_id_persistence() {
         let mut s = empty_store();
@@ -0,0 +1,205 @@
+use crate::{
+    crypto::KeyStore,
+    trees::{KeyDerivation, TreeKey},
+};
+use ax_types::{NodeId, StreamId};
+use banyan::Secrets;
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryInto,
+};
+
+/// Length of the stream key material: the index key followed by the value key.
+pub const STREAM_KEY_LENGTH: usize = 64;
+
+/// Encryption keys for event streams, see the `eventRouting.streams` settings.
+///
+/// Own streams are encrypted with the key configured for their name. Each key is identified
+/// by an ID derived from it, which is stored in the tree header so that other nodes holding the
+/// same key can decrypt the stream without knowing its name.
+///
+/// The secrets of a tree are derived from the stream key and the [`StreamId`], so that the
+/// same-named streams of different nodes do not share their secrets; the derivation is recorded
+/// in the tree header next to the key ID.
+#[derive(Clone, Debug, Default)]
+pub struct StreamKeys {
+    names: BTreeMap<String, u64>,
+    keys: BTreeMap<u64, [u8; STREAM_KEY_LENGTH]>,
+    /// encrypted streams this node has no key for
+    unavailable: BTreeSet<String>,
+}
+
+impl StreamKeys {
+    /// Use `key` (as produced by [`StreamKeys::generate`]) to encrypt the stream with the given name.
+    pub fn insert(&mut self, stream_name: String, key: &[u8]) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            key.len() == STREAM_KEY_LENGTH,
+            "stream key must be {} bytes long, found {}",
+            STREAM_KEY_LENGTH,
+            key.len()
+        );
+        let mut hasher = Sha256::new();
+        hasher.update(b"ax-stream-key");
+        hasher.update(key);
+        let id = u64::from_be_bytes(hasher.finalize()[..8].try_into().unwrap());
+        self.names.insert(stream_name, id);
+        self.keys.insert(id, key.try_into().unwrap());
+        Ok(())
+    }
+
+    /// Mark the stream with the given name as encrypted with a key this node does not have.
+    ///
+    /// This node then refuses to create the stream, so that it doesn’t publish unencrypted events
+    /// into an encrypted stream.
+    pub fn insert_unavailable(&mut self, stream_name: String) {
+        self.unavailable.insert(stream_name);
+    }
+
+    pub fn is_unavailable(&self, stream_name: &str) -> bool {
+        self.unavailable.contains(stream_name)
+    }
+
+    /// Generate new random key material for a stream.
+    pub fn generate() -> [u8; STREAM_KEY_LENGTH] {
+        use rand::RngCore;
+        let mut key = [0u8; STREAM_KEY_LENGTH];
+        rand::rngs::OsRng.fill_bytes(&mut key);
+        key
+    }
+
+    /// Textual representation of key material, as handed out to operators.
+    pub fn encode(key: &[u8]) -> String {
+        base64::encode(key)
+    }
+
+    pub fn decode(key: &str) -> anyhow::Result<Vec<u8>> {
+        Ok(base64::decode(key)?)
+    }
+
+    /// Encrypt key material for the given node, yielding its entry in the `encryption` setting of a stream.
+    pub fn wrap(key: &[u8], node_id: NodeId) -> anyhow::Result<String> {
+        Ok(base64::encode(KeyStore::wrap_key(key, node_id.into())?))
+    }
+
+    /// Decrypt this node’s entry in the `encryption` setting of a stream.
+    pub fn unwrap(keystore: &KeyStore, wrapped: &str, node_id: NodeId) -> anyhow::Result<Vec<u8>> {
+        keystore.unwrap_key(&base64::decode(wrapped)?, node_id.into())
+    }
+
+    /// The tree key and secrets for the own stream with the given name, if it shall be encrypted.
+    pub fn for_name(&self, stream_name: &str, stream_id: StreamId) -> Option<(TreeKey, Secrets)> {
+        let key = TreeKey {
+            id: *self.names.get(stream_name)?,
+            derivation: KeyDerivation::HkdfStreamId,
+        };
+        Some((key, self.get(Some(key), stream_id)?))
+    }
+
+    /// The secrets for a tree of the given stream, unencrypted trees use the default secrets.
+    pub fn get(&self, key: Option<TreeKey>, stream_id: StreamId) -> Option<Secrets> {
+        let key = match key {
+            Some(key) => key,
+            None => return Some(Secrets::default()),
+        };
+        let material = self.keys.get(&key.id)?;
+        match key.derivation {
+            KeyDerivation::HkdfStreamId => {
+                let mut secrets = [0u8; STREAM_KEY_LENGTH];
+                Hkdf::<sha2_10::Sha256>::new(None, material)
+                    .expand_multi_info(
+                        &[
+                            b"ax-stream-secrets",
+                            stream_id.node_id().as_ref(),
+                            &u64::from(stream_id.stream_nr()).to_be_bytes(),
+                        ],
+                        &mut secrets,
+                    )
+                    .expect("valid HKDF-SHA256 output length");
+                let index_key: [u8; 32] = secrets[..32].try_into().unwrap();
+                let value_key: [u8; 32] = secrets[32..].try_into().unwrap();
+                Some(Secrets::new(index_key.into(), value_key.into()))
+            }
+        }
+    }
+}
+
+impl PartialEq for StreamKeys {
+    fn eq(&self, other: &Self) -> bool {
+        // the IDs are derived from the keys
+        self.names == other.names && self.unavailable == other.unavailable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trees::{
+        axtrees::{AxKey, AxTrees, Sha256Digest},
+        tags::ScopedTagSet,
+    };
+    use ax_types::Payload;
+    use banyan::{
+        store::{BranchCache, MemStore},
+        Config, Forest, StreamBuilder, Transaction,
+    };
+
+    #[test]
+    fn stream_keys() {
+        let key = StreamKeys::generate();
+        let mut keys = StreamKeys::default();
+        keys.insert("secret".to_owned(), &key[..]).unwrap();
+        keys.insert("short".to_owned(), &key[..32]).unwrap_err();
+
+        let stream_id = NodeId::new([1; 32]).stream(1.into());
+        let (key, _) = keys.for_name("secret", stream_id).unwrap();
+        assert!(keys.for_name("other", stream_id).is_none());
+        assert!(keys.get(Some(key), stream_id).is_some());
+        let unknown = TreeKey { id: key.id + 1, ..key };
+        assert!(keys.get(Some(unknown), stream_id).is_none());
+        assert!(keys.get(None, stream_id).is_some());
+
+        let mut store = KeyStore::default();
+        let node_id = NodeId::from(store.generate_key_pair().unwrap());
+        let wrapped = StreamKeys::wrap(&key, node_id).unwrap();
+        assert_eq!(StreamKeys::unwrap(&store, &wrapped, node_id).unwrap(), key);
+
+        let mut other = StreamKeys::default();
+        other.insert("secret".to_owned(), &key[..]).unwrap();
+        assert_eq!(keys, other);
+        other.insert_unavailable("other".to_owned());
+        assert!(other.is_unavailable("other"));
+        assert_ne!(keys, other);
+        other.insert("secret".to_owned(), &StreamKeys::generate()[..]).unwrap();
+        assert_ne!(keys, other);
+    }
+
+    #[test]
+    fn secrets_per_stream() -> anyhow::Result<()> {
+        let mut keys = StreamKeys::default();
+        keys.insert("secret".to_owned(), &StreamKeys::generate()[..])?;
+        let store = MemStore::new(usize::max_value(), Sha256Digest::new);
+        let mut txn = Transaction::<AxTrees, _, _>::new(Forest::new(store.clone(), BranchCache::new(1000)), store);
+        let event = (
+            AxKey::new(ScopedTagSet::empty(), 1u64, 2u64),
+            Payload::from_json_str(r#"{"secret":42}"#).unwrap(),
+        );
+
+        // the same event in the same-named streams of two nodes
+        let mut roots = Vec::new();
+        for node_id in [NodeId::new([1; 32]), NodeId::new([2; 32])] {
+            let stream_id = node_id.stream(1.into());
+            let (key, secrets) = keys.for_name("secret", stream_id).unwrap();
+            let mut builder = StreamBuilder::new(Config::debug(), secrets);
+            txn.extend(&mut builder, vec![event.clone()])?;
+
+            let secrets = keys.get(Some(key), stream_id).unwrap();
+            let tree = txn.load_tree(secrets, builder.link().unwrap())?;
+            assert_eq!(txn.collect(&tree)?, vec![Some(event.clone())]);
+            roots.push(builder.link().unwrap());
+        }
+        assert_ne!(roots[0], roots[1]);
+        Ok(())
+    }
+}
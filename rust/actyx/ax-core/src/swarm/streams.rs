@@ -1,7 +1,7 @@
 use crate::{
     ax_futures_util::stream::variable::Variable,
-    swarm::{AxStreamBuilder, Cid, Link, RootPath, RootSource, Tree},
-    trees::{axtrees::AxTrees, AxTree, AxTreeHeader},
+    swarm::{gossip_protocol::RootSignature, AxStreamBuilder, Cid, Link, RootPath, RootSource, Tree},
+    trees::{axtrees::AxTrees, AxTree, AxTreeHeader, TreeKey},
 };
 use ax_types::{LamportTimestamp, NodeId, Offset, Payload, StreamId, StreamNr};
 use banyan::StreamTransaction;
@@ -69,14 +69,22 @@ pub struct OwnStream {
     builder: tokio::sync::Mutex<AxStreamBuilder>,
     /// the latest published tree
     latest: Variable<Option<PublishedTree>>,
+    /// the key the stream is encrypted with, see [`StreamKeys`](crate::swarm::StreamKeys)
+    key: Option<TreeKey>,
 }
 
 impl OwnStream {
-    pub fn new(stream_nr: StreamNr, builder: AxStreamBuilder, latest: Option<PublishedTree>) -> Self {
+    pub fn new(
+        stream_nr: StreamNr,
+        builder: AxStreamBuilder,
+        latest: Option<PublishedTree>,
+        key: Option<TreeKey>,
+    ) -> Self {
         Self {
             stream_nr,
             builder: tokio::sync::Mutex::new(builder),
             latest: Variable::new(latest),
+            key,
         }
     }
 
@@ -116,6 +124,10 @@ impl<'a> OwnStreamGuard<'a> {
     pub fn stream_nr(&self) -> StreamNr {
         self.0.stream_nr
     }
+
+    pub fn key(&self) -> Option<TreeKey> {
+        self.0.key
+    }
 }
 
 impl<'a> Deref for OwnStreamGuard<'a> {
@@ -145,7 +157,7 @@ pub struct ReplicatedStream {
     // but have not yet validated it
     validated: Variable<Option<PublishedTree>>,
     // stream of incoming roots
-    incoming: Variable<Option<(Link, RootSource)>>,
+    incoming: Variable<Option<(Link, RootSource, Option<RootSignature>)>>,
 }
 
 /// Trees are published including a tree header.
@@ -157,11 +169,22 @@ pub struct PublishedTree {
     header: AxTreeHeader,
     /// the actual tree
     tree: AxTree,
+    /// signature of the root by the owning node, if known
+    signature: Option<RootSignature>,
 }
 
 impl PublishedTree {
     pub fn new(root: Link, header: AxTreeHeader, tree: AxTree) -> Self {
-        Self { root, header, tree }
+        Self {
+            root,
+            header,
+            tree,
+            signature: None,
+        }
+    }
+
+    pub fn with_signature(self, signature: Option<RootSignature>) -> Self {
+        Self { signature, ..self }
     }
 
     pub fn offset(&self) -> Offset {
@@ -195,10 +218,12 @@ impl ReplicatedStream {
         self.validated.get_cloned()
     }
 
-    // Infos about the latest validated tree
-    pub fn infos(&self) -> Option<(Cid, Offset, LamportTimestamp)> {
-        self.validated
-            .project(|x| x.as_ref().map(|x| (Cid::from(x.root), x.offset(), x.lamport())))
+    // Infos about the latest validated tree, including the owner’s signature if known
+    pub fn infos(&self) -> Option<(Cid, Offset, LamportTimestamp, Option<RootSignature>)> {
+        self.validated.project(|x| {
+            x.as_ref()
+                .map(|x| (Cid::from(x.root), x.offset(), x.lamport(), x.signature))
+        })
     }
 
     /// lamport of the header and count of the last validated tree.
@@ -216,11 +241,11 @@ impl ReplicatedStream {
     /// This will trigger validation if the `source` has sufficient priority compared to the current link.
     /// The recipient will have to call `downgrade()` after processing to ensure that later updates for
     /// new roots will be accepted.
-    pub fn set_incoming(&self, value: Link, source: RootSource) {
+    pub fn set_incoming(&self, value: Link, source: RootSource, signature: Option<RootSignature>) {
         self.incoming.transform_mut(|x| match x {
-            Some((l, s)) if *s > source || *s == source && *l == value => false,
+            Some((l, s, _)) if *s > source || *s == source && *l == value => false,
             _ => {
-                x.replace((value, source));
+                x.replace((value, source, signature));
                 true
             }
         });
@@ -230,7 +255,7 @@ impl ReplicatedStream {
     pub fn downgrade(&self, link: Link, error: bool) {
         self.incoming.transform_mut(|x| {
             match x {
-                Some((l, s)) if *l == link => {
+                Some((l, s, _)) if *l == link => {
                     if error {
                         x.take();
                     } else {
@@ -249,7 +274,7 @@ impl ReplicatedStream {
             .boxed()
     }
 
-    pub fn incoming_root_stream(&self) -> impl Stream<Item = (Link, RootSource)> {
+    pub fn incoming_root_stream(&self) -> impl Stream<Item = (Link, RootSource, Option<RootSignature>)> {
         self.incoming.new_observer().filter_map(future::ready)
     }
 }
@@ -2,7 +2,7 @@ use crate::{
     ax_futures_util::stream::{interval, AxStreamExt, Drainer},
     crypto::{KeyPair, KeyStore, PublicKey},
     swarm::{
-        AxTreeExt, BanyanStore, EphemeralEventsConfig, EventRoute, EventRouteMappingEvent, SwarmConfig,
+        AxTreeExt, BanyanStore, EphemeralEventsConfig, EventRoute, EventRouteMappingEvent, Replicate, SwarmConfig,
         DEFAULT_STREAM_NAME, DISCOVERY_STREAM_NAME, FILES_STREAM_NAME, MAX_TREE_LEVEL, METRICS_STREAM_NAME,
     },
     trees::query::TagExprQuery,
@@ -10,11 +10,11 @@ use crate::{
 use acto::ActoRef;
 use anyhow::Result;
 use ax_aql::TagExpr;
-use ax_types::{app_id, tags, AppId, Offset, OffsetMap, Payload, StreamNr, Tag, TagSet};
+use ax_types::{app_id, tags, AppId, NodeId, Offset, OffsetMap, Payload, StreamNr, Tag, TagSet, Timestamp};
 use banyan::query::AllQuery;
 use futures::{pin_mut, prelude::*, StreamExt};
 use libipld::Cid;
-use maplit::btreemap;
+use maplit::{btreemap, btreeset};
 use std::{
     collections::BTreeMap,
     convert::TryFrom,
@@ -305,6 +305,40 @@ async fn test_add_cat() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn should_resolve_interrupted_publish() -> Result<()> {
+    let store = BanyanStore::test("interrupted").await?;
+    let events = || vec![(tags!("a"), Payload::null()), (tags!("b"), Payload::null())];
+    let ok = || future::ready(Ok(()));
+    let now = Timestamp::now();
+    let expired = now - Duration::from_secs(60);
+
+    // interrupted after storing the events: the record is completed with them
+    store
+        .lock()
+        .index_store
+        .put_publish_key(&app_id(), "stored", now, None, expired)?;
+    let stored = store.append_unlocked(app_id(), now, events()).await?;
+    for _ in 0..2 {
+        let persisted = store.append_checked(app_id(), Some("stored"), events(), ok()).await?;
+        assert!(persisted.replayed);
+        assert_eq!(persisted.meta, stored);
+    }
+
+    // interrupted before storing the events: they are published now
+    let later = now + 1_000u64;
+    store
+        .lock()
+        .index_store
+        .put_publish_key(&app_id(), "lost", later, None, expired)?;
+    store.append_unlocked(app_id!("other"), later, events()).await?;
+    let persisted = store.append_checked(app_id(), Some("lost"), events(), ok()).await?;
+    assert!(!persisted.replayed);
+    assert_eq!(persisted.meta.len(), 2);
+    assert!(persisted.meta.iter().all(|meta| meta.1 > stored[1].1));
+    Ok(())
+}
+
 #[test]
 fn test_add_zero_bytes() -> Result<()> {
     let rt = Runtime::new()?;
@@ -467,6 +501,33 @@ async fn existing_swarm_config() {
     }
 }
 
+#[tokio::test]
+async fn should_replicate_selected_streams() {
+    let mut config = SwarmConfig::test("selective_replication");
+    config.replicated_streams = Some(btreeset! {"logs".to_owned()});
+    let store = BanyanStore::new(config, ActoRef::blackhole()).await.unwrap();
+    let other = NodeId::from(KeyPair::generate());
+    let default = other.stream(0.into());
+    let logs = other.stream(1.into());
+    let metrics = other.stream(2.into());
+
+    let mut guard = store.lock();
+    assert!(matches!(guard.should_replicate(default), Replicate::Yes));
+    assert!(matches!(guard.should_replicate(logs), Replicate::UnknownName));
+    // the name is only looked up once
+    assert!(matches!(guard.should_replicate(logs), Replicate::No));
+    guard.remote_stream_names.insert(logs, "logs".to_owned());
+    guard.remote_stream_names.insert(metrics, "metrics".to_owned());
+    assert!(matches!(guard.should_replicate(metrics), Replicate::No));
+    assert!(matches!(guard.should_replicate(logs), Replicate::Yes));
+    drop(guard);
+
+    assert_eq!(
+        store.not_replicated_streams(),
+        vec![(metrics, Some("metrics".to_owned()))]
+    );
+}
+
 fn copy_dir_recursive(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
     fs::create_dir_all(&dst)?;
     for entry in fs::read_dir(src)? {
@@ -30,6 +30,17 @@ impl Dnf {
         }
         Dnf(ret)
     }
+
+    /// the conjunctions of this disjunction, each rendered as a tag expression
+    pub fn terms(&self) -> impl Iterator<Item = ax_aql::TagExpr> + '_ {
+        self.0.iter().filter_map(|atoms| {
+            atoms
+                .iter()
+                .map(|atom| ax_aql::TagExpr::Atom(atom.clone()))
+                .reduce(|l, r| l & r)
+        })
+    }
+
     fn insert_unless_redundant(aa: &mut BTreeSet<BTreeSet<ax_aql::TagAtom>>, b: BTreeSet<ax_aql::TagAtom>) {
         let mut to_remove = vec![];
         for a in aa.iter() {
@@ -139,4 +150,16 @@ mod tests {
         let c = l("c");
         assert_dnf((a.clone() & b).or(a.clone() & c).or(a), &[&["a"]]);
     }
+
+    #[test]
+    fn test_dnf_terms() {
+        let a = l("a");
+        let b = l("b");
+        let c = l("c");
+        let terms = Dnf::from(&(c & (a | b)))
+            .terms()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(terms, vec!["('a' & 'c')", "('b' & 'c')"]);
+    }
 }
@@ -9,22 +9,62 @@ use std::io;
 
 /// AX tree header.
 ///
-/// This is v0, which just contains a lamport timestamp. Later there will also be a signature.
+/// v0 just contains a lamport timestamp, v1 additionally identifies the key the tree is
+/// encrypted with. Unencrypted trees are still written as v0 so that older nodes can read them.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Header {
     pub root: Sha256Digest,
     pub lamport: LamportTimestamp,
+    pub key: Option<TreeKey>,
+}
+
+/// The key a tree is encrypted with, see [`StreamKeys`](crate::swarm::StreamKeys).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeKey {
+    /// ID of the configured stream key
+    pub id: u64,
+    /// how the secrets of the tree are derived from the stream key
+    pub derivation: KeyDerivation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyDerivation {
+    /// HKDF-SHA256 with the stream key as input key material and the stream ID as info
+    HkdfStreamId,
+}
+
+impl KeyDerivation {
+    fn code(self) -> u64 {
+        match self {
+            Self::HkdfStreamId => 0,
+        }
+    }
+
+    fn from_code(code: u64) -> anyhow::Result<Self> {
+        match code {
+            0 => Ok(Self::HkdfStreamId),
+            _ => anyhow::bail!("unknown key derivation {}", code),
+        }
+    }
 }
 
 impl Header {
     pub fn new(root: Sha256Digest, lamport: LamportTimestamp) -> Self {
-        Self { root, lamport }
+        Self {
+            root,
+            lamport,
+            key: None,
+        }
+    }
+
+    pub fn with_key(self, key: Option<TreeKey>) -> Self {
+        Self { key, ..self }
     }
 }
 
 impl Decode<DagCborCodec> for Header {
     fn decode<R: std::io::Read + std::io::Seek>(c: DagCborCodec, r: &mut R) -> anyhow::Result<Self> {
-        HeaderIo::decode(c, r).map(Into::into)
+        HeaderIo::decode(c, r)?.try_into()
     }
 }
 
@@ -36,15 +76,24 @@ impl Encode<DagCborCodec> for Header {
 
 impl From<&Header> for HeaderIo {
     fn from(value: &Header) -> Self {
-        HeaderIo::V0(value.root, value.lamport)
+        match value.key {
+            None => HeaderIo::V0(value.root, value.lamport),
+            Some(key) => HeaderIo::V1(value.root, value.lamport, key.id, key.derivation.code()),
+        }
     }
 }
 
-impl From<HeaderIo> for Header {
-    fn from(value: HeaderIo) -> Self {
-        match value {
-            HeaderIo::V0(root, lamport) => Self { root, lamport },
-        }
+impl TryFrom<HeaderIo> for Header {
+    type Error = anyhow::Error;
+
+    fn try_from(value: HeaderIo) -> anyhow::Result<Self> {
+        Ok(match value {
+            HeaderIo::V0(root, lamport) => Self::new(root, lamport),
+            HeaderIo::V1(root, lamport, id, derivation) => Self::new(root, lamport).with_key(Some(TreeKey {
+                id,
+                derivation: KeyDerivation::from_code(derivation)?,
+            })),
+        })
     }
 }
 
@@ -52,6 +101,7 @@ impl From<HeaderIo> for Header {
 #[ipld(repr = "int-tuple")]
 enum HeaderIo {
     V0(Sha256Digest, LamportTimestamp),
+    V1(Sha256Digest, LamportTimestamp, u64, u64),
 }
 
 #[cfg(test)]
@@ -78,4 +128,31 @@ mod tests {
         };
         assert_roundtrip(DagCborCodec, &header, &expected);
     }
+
+    #[test]
+    fn header_roundtrip_encrypted() {
+        let root = Sha256Digest::new(b"thisisatest");
+        let cid = Cid::from(root);
+        let lamport = 1234.into();
+        let header = Header::new(root, lamport).with_key(Some(TreeKey {
+            id: 42,
+            derivation: KeyDerivation::HkdfStreamId,
+        }));
+        let expected = ipld! {
+            [1,
+                // version 1
+                [
+                    // root
+                    cid,
+                    // lamport
+                    1234,
+                    // key id
+                    42,
+                    // key derivation
+                    0,
+                ]
+            ]
+        };
+        assert_roundtrip(DagCborCodec, &header, &expected);
+    }
 }
@@ -11,7 +11,7 @@ pub mod tags;
 #[cfg(test)]
 mod tests;
 
-pub use self::header::Header as AxTreeHeader;
+pub use self::header::{Header as AxTreeHeader, KeyDerivation, TreeKey};
 
 type TagIndex = cbor_tag_index::TagIndex<ScopedTag>;
 
@@ -357,6 +357,11 @@ impl TagExprQuery {
     pub fn is_empty(&self) -> bool {
         self.tags.is_empty() || self.lamport.is_empty() || self.time.is_empty()
     }
+
+    /// Whether the tag index can be used to skip events, i.e. not all tags match.
+    pub fn uses_tag_index(&self) -> bool {
+        !self.tags.is_all()
+    }
 }
 
 fn get_lamport_query(tag_set: &BTreeSet<TagAtom>, q: &mut Option<LamportQueryBuilder>) -> Result<(), TagExprError> {
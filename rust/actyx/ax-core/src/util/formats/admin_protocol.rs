@@ -1,6 +1,6 @@
 use super::ActyxOSResult;
 use crate::util::version::NodeVersion;
-use ax_types::NodeId;
+use ax_types::{AppId, NodeId};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -34,7 +34,7 @@ impl crate::libp2p_streaming_response::Codec for AdminProtocol {
     }
 
     fn info_v2() -> &'static [&'static str] {
-        &["/actyx/admin/1.2", "/actyx/admin/1.1"]
+        &["/actyx/admin/1.3", "/actyx/admin/1.2", "/actyx/admin/1.1"]
     }
 }
 
@@ -65,6 +65,10 @@ pub enum AdminRequest {
     TopicDelete {
         name: String,
     },
+    /// Revoke auth tokens handed out by the node
+    TokensRevoke {
+        target: TokensRevokeTarget,
+    },
     // Without this, the request isn't processed and the client times out
     #[serde(other)]
     FutureCompat,
@@ -81,6 +85,7 @@ pub enum AdminResponse {
     SettingsUnsetResponse,
     TopicLsResponse(TopicLsResponse),
     TopicDeleteResponse(TopicDeleteResponse),
+    TokensRevokeResponse(TokensRevokeResponse),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -106,6 +111,20 @@ pub struct NodesInspectResponse {
     pub admin_addrs: Vec<String>,
     pub connections: Vec<Connection>,
     pub known_peers: Vec<Peer>,
+    /// whether the node only replicates other nodes’ streams, see the `swarm.replica` setting
+    #[serde(default)]
+    pub replica: bool,
+    /// remote streams that are not replicated, see the `swarm.replicatedStreams` setting
+    #[serde(default)]
+    pub not_replicated_streams: Vec<NotReplicatedStream>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotReplicatedStream {
+    pub stream_id: String,
+    /// unknown until the stream mappings of the owning node have been replicated
+    pub name: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -195,3 +214,20 @@ pub struct TopicDeleteResponse {
     /// True if any file was deleted.
     pub deleted: bool,
 }
+
+/// Which tokens to revoke.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TokensRevokeTarget {
+    /// A single token, as handed out by the auth API.
+    Token(String),
+    /// All tokens handed out to the app so far.
+    App(AppId),
+}
+
+/// Response to the revocation of tokens in a node.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TokensRevokeResponse {
+    pub node_id: NodeId,
+}
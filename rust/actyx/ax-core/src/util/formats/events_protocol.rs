@@ -1,8 +1,8 @@
 use crate::libp2p_streaming_response::Codec;
 use ax_types::{
     service::{
-        Diagnostic, EventResponse, OffsetsResponse, PublishRequest, PublishResponse, QueryRequest,
-        SubscribeMonotonicRequest, SubscribeRequest,
+        CursorResponse, Diagnostic, EventResponse, OffsetsResponse, PublishRequest, PublishResponse, QueryBatchRequest,
+        QueryBatchResponse, QueryPlan, QueryRequest, SubscribeMonotonicRequest, SubscribeRequest,
     },
     OffsetMap, Payload,
 };
@@ -29,6 +29,7 @@ impl Codec for EventsProtocol {
 pub enum EventsRequest {
     Offsets,
     Query(QueryRequest),
+    QueryBatch(QueryBatchRequest),
     Subscribe(SubscribeRequest),
     SubscribeMonotonic(SubscribeMonotonicRequest),
     Publish(PublishRequest),
@@ -48,6 +49,9 @@ pub enum EventsResponse {
     },
     Publish(PublishResponse),
     Diagnostic(Diagnostic),
+    Plan(QueryPlan),
+    Cursor(CursorResponse),
+    QueryBatch(QueryBatchResponse),
     #[serde(other)]
     FutureCompat,
 }
@@ -56,7 +60,9 @@ pub enum EventsResponse {
 mod tests {
     use super::*;
     use ax_types::{
-        app_id, service::Severity, tags, Event, EventKey, LamportTimestamp, Metadata, NodeId, Offset, Timestamp,
+        app_id,
+        service::{BatchQuery, QueryResponse, Severity},
+        tags, Event, EventKey, LamportTimestamp, Metadata, NodeId, Offset, Timestamp,
     };
     use std::collections::BTreeMap;
 
@@ -76,13 +82,17 @@ mod tests {
                 lower_bound: None,
                 upper_bound: None,
                 query: "FROM allEvents".parse().unwrap(),
-                order: ax_types::service::Order::Asc
+                order: ax_types::service::Order::Asc,
+                explain: false,
+                page_size: None,
+                cursor: None
             })),
             r#"{"type":"query","query":"FROM allEvents","lowerBound":null,"upperBound":null,"order":"asc"}"#
         );
         assert_eq!(
             req(EventsRequest::Subscribe(SubscribeRequest {
                 lower_bound: None,
+                invalid_payloads: None,
                 query: "FROM allEvents".parse().unwrap(),
             })),
             r#"{"type":"subscribe","query":"FROM allEvents","lowerBound":null}"#
@@ -95,6 +105,18 @@ mod tests {
             })),
             r#"{"type":"subscribeMonotonic","query":"FROM allEvents","session":"","lowerBound":{}}"#
         );
+        assert_eq!(
+            req(EventsRequest::QueryBatch(QueryBatchRequest {
+                lower_bound: None,
+                upper_bound: None,
+                order: ax_types::service::Order::Asc,
+                queries: vec![BatchQuery {
+                    id: "q1".to_owned(),
+                    query: "FROM allEvents".to_owned(),
+                }],
+            })),
+            r#"{"type":"queryBatch","lowerBound":null,"upperBound":null,"order":"asc","queries":[{"id":"q1","query":"FROM allEvents"}]}"#
+        );
     }
 
     fn ev(n: u32) -> EventResponse<Payload> {
@@ -154,6 +176,13 @@ mod tests {
             res(EventsResponse::Publish(PublishResponse { data: vec![] })),
             r#"{"type":"publish","data":[]}"#
         );
+        assert_eq!(
+            res(EventsResponse::QueryBatch(QueryBatchResponse {
+                id: "q1".to_owned(),
+                response: QueryResponse::Diagnostic(Diagnostic::warn("buh".to_owned())),
+            })),
+            r#"{"type":"queryBatch","id":"q1","response":{"type":"diagnostic","severity":"warning","message":"buh"}}"#
+        );
     }
 
     #[test]
@@ -26,6 +26,9 @@ fn node_schema_in_sync() {
             gossip_interval: 10,
             detection_cycles_low_latency: 2.0,
             detection_cycles_high_latency: 5.0,
+            replica: false,
+            accept_unsigned_roots: true,
+            replicated_streams: btreeset![],
         },
         admin: Admin {
             display_name: "some name".into(),
@@ -37,6 +40,13 @@ fn node_schema_in_sync() {
             events: Events {
                 internal: None,
                 read_only: true,
+                publish_key_window: 86400,
+                schemas: Default::default(),
+                reject_invalid_payloads: false,
+                max_payload_size: 0,
+                max_events_per_request: 0,
+                publish_rate: 0,
+                publish_burst: 1000,
             },
         },
         event_routing: Default::default(),
@@ -53,3 +63,50 @@ fn node_schema_in_sync() {
     repo.update_settings(&scope, serde_json::to_value(&sample_settings).unwrap(), false)
         .unwrap();
 }
+
+#[test]
+fn invalid_payload_schemas() {
+    use serde_json::json;
+    let current_schema: serde_json::Value = serde_json::from_slice(include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/resources/json-schema/node-settings.schema.json"
+    )))
+    .unwrap();
+
+    let repo = Repository::new_in_memory();
+    let scope: Scope = "com.actyx".parse().unwrap();
+    repo.set_schema(&scope, current_schema).unwrap();
+    let schemas: Scope = "com.actyx/api/events/schemas".parse().unwrap();
+
+    repo.update_settings(&schemas, json!({ "temperature": { "type": "number" } }), false)
+        .unwrap();
+    let err = repo
+        .update_settings(&schemas, json!({ "": { "type": "number" } }), false)
+        .unwrap_err();
+    assert!(err.to_string().contains("invalid tag"), "{}", err);
+    let err = repo
+        .update_settings(&schemas, json!({ "temperature": { "type": 42 } }), false)
+        .unwrap_err();
+    assert!(err.to_string().contains("invalid payload schema"), "{}", err);
+}
+
+#[test]
+fn publish_burst() {
+    use serde_json::json;
+    let current_schema: serde_json::Value = serde_json::from_slice(include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/resources/json-schema/node-settings.schema.json"
+    )))
+    .unwrap();
+
+    let repo = Repository::new_in_memory();
+    let scope: Scope = "com.actyx".parse().unwrap();
+    repo.set_schema(&scope, current_schema).unwrap();
+    let events: Scope = "com.actyx/api/events".parse().unwrap();
+
+    repo.update_settings(&events, json!({ "publishRate": 10, "publishBurst": 20 }), false)
+        .unwrap();
+    // a burst of zero would reject every publication
+    repo.update_settings(&events, json!({ "publishRate": 10, "publishBurst": 0 }), false)
+        .unwrap_err();
+}
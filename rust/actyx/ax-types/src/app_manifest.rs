@@ -1,4 +1,4 @@
-use crate::{app_id, AppId};
+use crate::{app_id, AppId, TagSet};
 use core::convert::TryFrom;
 use serde::{Deserialize, Serialize};
 
@@ -10,6 +10,65 @@ struct AppManifestIo {
     pub version: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub signature: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<AppScopes>,
+}
+
+/// Restrictions an app declares for itself in its manifest.
+///
+/// The node copies them into the bearer token handed out to the app and enforces them on every
+/// API call. The default (also used when a trial manifest has no scopes) grants full access, a
+/// signed manifest without scopes gets [`AppScopes::least_privilege`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub struct AppScopes {
+    /// AQL tag expression that all events read by the app must match, e.g. `'machine' & 'machine:42'`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read: Option<String>,
+    /// tags the app may put on published events; events with other tags are rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write: Option<TagSet>,
+    /// access to the files API
+    #[serde(default = "granted")]
+    pub files: bool,
+    /// access to the blob API
+    #[serde(default = "granted")]
+    pub blob: bool,
+}
+
+fn granted() -> bool {
+    true
+}
+
+impl Default for AppScopes {
+    fn default() -> Self {
+        Self {
+            read: None,
+            write: None,
+            files: true,
+            blob: true,
+        }
+    }
+}
+
+impl AppScopes {
+    /// Scopes that only allow the app to read its own events and to publish untagged events.
+    pub fn least_privilege(app_id: &AppId) -> Self {
+        Self {
+            read: Some(format!("appId({})", app_id)),
+            write: Some(TagSet::empty()),
+            files: false,
+            blob: false,
+        }
+    }
+
+    /// Whether these scopes allow publishing an event with the given tags.
+    pub fn may_write(&self, tags: &TagSet) -> bool {
+        match &self.write {
+            Some(allowed) => tags.is_subset(allowed),
+            None => true,
+        }
+    }
 }
 
 impl From<AppManifest> for AppManifestIo {
@@ -43,6 +102,7 @@ impl AppManifest {
             display_name,
             version,
             signature: Some(signature),
+            scopes: None,
         })
     }
 
@@ -55,6 +115,7 @@ impl AppManifest {
             display_name,
             version,
             signature: None,
+            scopes: None,
         }))
     }
 
@@ -77,6 +138,29 @@ impl AppManifest {
     pub fn is_signed(&self) -> bool {
         self.0.signature.is_some()
     }
+
+    /// The scopes the app requests.
+    ///
+    /// Without declared scopes a trial app gets full access while a signed app only gets
+    /// [`AppScopes::least_privilege`], its developer has to sign the scopes it needs.
+    pub fn scopes(&self) -> AppScopes {
+        match &self.0.scopes {
+            Some(scopes) => scopes.clone(),
+            None if self.is_signed() => AppScopes::least_privilege(&self.0.app_id),
+            None => AppScopes::default(),
+        }
+    }
+
+    /// The scopes as declared in the manifest.
+    pub fn declared_scopes(&self) -> Option<&AppScopes> {
+        self.0.scopes.as_ref()
+    }
+
+    /// Restrict the app to the given scopes.
+    pub fn with_scopes(mut self, scopes: AppScopes) -> Self {
+        self.0.scopes = Some(scopes);
+        self
+    }
 }
 
 impl Default for AppManifest {
@@ -124,6 +208,7 @@ mod tests {
             display_name: "display name".into(),
             version: "v0.0.1".into(),
             signature: None,
+            scopes: None,
         })
         .unwrap_err();
         assert_eq!(
@@ -166,6 +251,41 @@ mod tests {
         assert_eq!(serialized, json);
     }
 
+    #[test]
+    fn scopes() {
+        let manifest = serde_json::from_value::<AppManifest>(serde_json::json!({
+            "appId": "com.example.test-app",
+            "displayName": "display name",
+            "version": "v0.0.1",
+            "scopes": { "write": ["a", "b"], "files": false }
+        }))
+        .unwrap();
+        let scopes = manifest.scopes();
+        assert_eq!(scopes.read, None);
+        assert!(!scopes.files);
+        assert!(scopes.blob);
+        assert!(scopes.may_write(&crate::tags!("a")));
+        assert!(!scopes.may_write(&crate::tags!("a", "c")));
+
+        let manifest = AppManifest::default();
+        assert_eq!(manifest.scopes(), AppScopes::default());
+        assert!(manifest.scopes().may_write(&crate::tags!("c")));
+
+        let manifest = AppManifest::signed(
+            app_id!("com.not-example.x"),
+            "display_name".into(),
+            "0.1.0".into(),
+            "signature".into(),
+        );
+        let scopes = manifest.scopes();
+        assert_eq!(scopes, AppScopes::least_privilege(&app_id!("com.not-example.x")));
+        assert_eq!(scopes.read.as_deref(), Some("appId(com.not-example.x)"));
+        assert!(!scopes.files);
+        assert!(!scopes.blob);
+        assert!(scopes.may_write(&crate::tags!()));
+        assert!(!scopes.may_write(&crate::tags!("a")));
+    }
+
     #[test]
     fn deserialize_and_eq() {
         let from_json = serde_json::from_value::<AppManifest>(serde_json::json!({
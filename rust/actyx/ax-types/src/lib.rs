@@ -19,7 +19,7 @@ mod tags;
 mod timestamp;
 pub mod types;
 
-pub use app_manifest::AppManifest;
+pub use app_manifest::{AppManifest, AppScopes};
 pub use event::{Event, EventKey, Metadata, Opaque, Payload};
 pub use offset::{Offset, OffsetError, OffsetMap, OffsetOrMin};
 pub use scalars::{AppId, NodeId, StreamId, StreamNr};
@@ -7,7 +7,7 @@ use crate::{
     event::{Event, EventKey, Metadata},
     scalars::StreamId,
     tags::TagSet,
-    LamportTimestamp, Offset, OffsetMap, Payload, Timestamp,
+    LamportTimestamp, Offset, OffsetMap, OffsetOrMin, Payload, Timestamp,
 };
 use lazy_static::lazy_static;
 
@@ -52,6 +52,55 @@ pub struct QueryRequest {
     pub upper_bound: Option<OffsetMap>,
     /// Order in which events should be received.
     pub order: Order,
+    /// Also report how the query was executed, as a [`QueryResponse::Plan`] before the final
+    /// offsets.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub explain: bool,
+    /// Maximum number of results to return, followed by a [`QueryResponse::Cursor`] if the
+    /// query has not been completed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<NonZeroU64>,
+    /// Continue a previous query where its last page ended. The query must be the same as in
+    /// the previous request, while bounds and order are taken from the cursor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// Several queries over the same bounded set of events, evaluated together.
+///
+/// Queries reading from the same tag expression in the same order share a single pass
+/// over the events in the store.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryBatchRequest {
+    /// Optional lower bound offset per stream, shared by all queries.
+    pub lower_bound: Option<OffsetMap>,
+    /// Upper bound offset per stream, shared by all queries.
+    pub upper_bound: Option<OffsetMap>,
+    /// Order in which events should be received, unless specified by a query.
+    pub order: Order,
+    pub queries: Vec<BatchQuery>,
+}
+
+/// One query within a [`QueryBatchRequest`]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchQuery {
+    /// Identifies the results of this query in the [`QueryBatchResponse`]s
+    pub id: String,
+    pub query: String,
+}
+
+/// A response to one query of a [`QueryBatchRequest`].
+///
+/// Responses to different queries may be interleaved, the responses to each query arrive in
+/// the same order as for a single query request.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryBatchResponse {
+    /// The [`BatchQuery::id`] of the query
+    pub id: String,
+    pub response: QueryResponse,
 }
 
 /// Subscription to an unbounded set of events across multiple streams.
@@ -62,6 +111,21 @@ pub struct SubscribeRequest {
     pub query: String,
     /// Optional lower bound offset per stream.
     pub lower_bound: Option<OffsetMap>,
+    /// What to do with events whose payload does not match the schema registered for one of their tags.
+    ///
+    /// If not set, such events are delivered like any other.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invalid_payloads: Option<InvalidPayloads>,
+}
+
+/// Treatment of events whose payload does not conform to the schemas registered for their tags
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum InvalidPayloads {
+    /// Leave them out silently
+    Skip,
+    /// Replace each of them with a warning [`Diagnostic`]
+    Flag,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Ord, PartialOrd, Eq, PartialEq)]
@@ -353,6 +417,24 @@ impl From<(TagSet, Payload)> for PublishEvent {
 pub struct PublishRequest {
     /// Events to be published
     pub data: Vec<PublishEvent>,
+    /// Client-chosen key identifying this publication: if the same app published with the same
+    /// key recently, nothing is published and the original [`PublishResponse`] is returned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Only publish if the precondition holds, otherwise fail without publishing anything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub precondition: Option<PublishPrecondition>,
+}
+
+/// Condition for publishing events: no event matching `tag_expr` has been stored beyond
+/// `offsets` on the node, i.e. nothing new has arrived since the caller looked.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishPrecondition {
+    /// AQL tag expression, e.g. `'workflow' & 'workflow:42'`
+    pub tag_expr: String,
+    /// Offsets up to which the matching events are known to the caller
+    pub offsets: OffsetMap,
 }
 
 /// Result of an event publication
@@ -470,10 +552,90 @@ pub enum QueryResponse {
     Offsets(OffsetMapResponse),
     #[serde(rename_all = "camelCase")]
     Diagnostic(Diagnostic),
+    #[serde(rename_all = "camelCase")]
+    Plan(QueryPlan),
+    #[serde(rename_all = "camelCase")]
+    Cursor(CursorResponse),
     #[serde(other)]
     FutureCompat,
 }
 
+/// Position after the last result of a page, see [`QueryRequest::page_size`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CursorResponse {
+    /// Opaque value to be passed as [`QueryRequest::cursor`] to get the next page
+    pub cursor: String,
+}
+
+/// How a query was executed, see [`QueryRequest::explain`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryPlan {
+    /// Tag expression after resolving interpolations and `appId(me)`, absent for queries
+    /// from an array
+    pub tag_expr: Option<String>,
+    /// Disjunctive normal form of the tag expression, one conjunction per entry
+    pub dnf: Vec<String>,
+    /// Event streams that were read
+    pub streams: Vec<StreamPlan>,
+    /// Processing stages in query order
+    pub stages: Vec<StagePlan>,
+}
+
+impl Display for QueryPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.tag_expr {
+            Some(tag_expr) => writeln!(f, "FROM {}", tag_expr)?,
+            None => writeln!(f, "FROM array")?,
+        }
+        for term in &self.dnf {
+            writeln!(f, "  term {}", term)?;
+        }
+        for stream in &self.streams {
+            writeln!(
+                f,
+                "stream {} offsets ({}, {}] {}",
+                stream.stream,
+                stream.from_exclusive,
+                stream.to_inclusive,
+                if stream.indexed { "indexed" } else { "full scan" }
+            )?;
+        }
+        for stage in &self.stages {
+            writeln!(
+                f,
+                "stage {}: {} in, {} out, {}µs",
+                stage.stage, stage.inputs, stage.outputs, stage.micros
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The part of one event stream that was read for a query.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamPlan {
+    pub stream: StreamId,
+    pub from_exclusive: OffsetOrMin,
+    pub to_inclusive: OffsetOrMin,
+    /// Whether the tag index is used to skip events; otherwise the whole offset range is scanned
+    pub indexed: bool,
+    /// The query evaluated against the stream’s tree
+    pub query: String,
+}
+
+/// Number of values that went into and came out of a processing stage, and the time spent in it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StagePlan {
+    pub stage: String,
+    pub inputs: u64,
+    pub outputs: u64,
+    pub micros: u64,
+}
+
 /// The response to a subscribe request.
 ///
 /// This will currently only be elements of type `Event` but will eventually contain
@@ -1,10 +1,12 @@
 mod license;
+mod revoke;
 mod sign;
 
 use crate::cmd::AxCliCommand;
 use futures::Future;
 
 use license::LicenseOpts;
+use revoke::RevokeOpts;
 use sign::SignOpts;
 
 #[derive(clap::Subcommand, Clone, Debug)]
@@ -14,11 +16,14 @@ pub enum AppsOpts {
     License(LicenseOpts),
     /// Sign application manifest
     Sign(SignOpts),
+    /// Revoke auth tokens of apps on a node
+    Revoke(RevokeOpts),
 }
 
 pub(crate) fn run(opts: AppsOpts, json: bool) -> Box<dyn Future<Output = ()> + Unpin> {
     match opts {
         AppsOpts::Sign(opt) => sign::AppsSign::output(opt, json),
         AppsOpts::License(opt) => license::AppsLicense::output(opt, json),
+        AppsOpts::Revoke(opt) => revoke::AppsRevoke::output(opt, json),
     }
 }
@@ -0,0 +1,70 @@
+use crate::cmd::{AxCliCommand, ConsoleOpt};
+use ax_core::{
+    node_connection::{request_single, Task},
+    util::formats::{ActyxOSError, ActyxOSResult, AdminRequest, AdminResponse, TokensRevokeTarget},
+};
+use ax_sdk::types::AppId;
+use futures::{stream, Stream};
+use serde::{Deserialize, Serialize};
+
+#[derive(clap::Parser, Clone, Debug)]
+/// revoke auth tokens handed out to apps by a node
+pub struct RevokeOpts {
+    #[command(flatten)]
+    console_opt: ConsoleOpt,
+    /// Revoke a single token
+    #[arg(long, required_unless_present = "app_id", conflicts_with = "app_id")]
+    token: Option<String>,
+    /// Revoke all tokens handed out to this app so far
+    #[arg(long)]
+    app_id: Option<AppId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Output {
+    node_id: String,
+    target: TokensRevokeTarget,
+}
+
+async fn run(opts: RevokeOpts) -> ActyxOSResult<Output> {
+    let target = match (opts.token, opts.app_id) {
+        (Some(token), _) => TokensRevokeTarget::Token(token),
+        (None, Some(app_id)) => TokensRevokeTarget::App(app_id),
+        (None, None) => return Err(ActyxOSError::internal("either a token or an app ID is required")),
+    };
+    let (mut conn, peer) = opts.console_opt.connect().await?;
+    let request = AdminRequest::TokensRevoke { target: target.clone() };
+    request_single(
+        &mut conn,
+        move |tx| Task::Admin(peer, request, tx),
+        move |m| match m {
+            AdminResponse::TokensRevokeResponse(response) => Ok(Output {
+                node_id: response.node_id.to_string(),
+                target: target.clone(),
+            }),
+            r => Err(ActyxOSError::internal(format!("Unexpected reply: {:?}", r))),
+        },
+    )
+    .await
+}
+
+pub struct AppsRevoke();
+
+impl AxCliCommand for AppsRevoke {
+    type Opt = RevokeOpts;
+    type Output = Output;
+    fn run(opts: RevokeOpts) -> Box<dyn Stream<Item = ActyxOSResult<Self::Output>> + Unpin> {
+        let r = Box::pin(run(opts));
+        Box::new(stream::once(r))
+    }
+
+    fn pretty(result: Self::Output) -> String {
+        match result.target {
+            TokensRevokeTarget::Token(_) => format!("Revoked the token on node {}", result.node_id),
+            TokensRevokeTarget::App(app_id) => {
+                format!("Revoked all tokens of app {} on node {}", app_id, result.node_id)
+            }
+        }
+    }
+}
@@ -230,6 +230,9 @@ impl AxCliCommand for EventsDump {
                     upper_bound: None,
                     query: opts.query,
                     order: Order::Asc,
+                    explain: false,
+                    page_size: None,
+                    cursor: None,
                 }),
                 tx,
             ))
@@ -71,6 +71,8 @@ impl AxCliCommand for EventsPublish {
                     peer,
                     EventsRequest::Publish(PublishRequest {
                         data: vec![PublishEvent { tags, payload }],
+                        idempotency_key: None,
+                        precondition: None,
                     }),
                     tx,
                 ))
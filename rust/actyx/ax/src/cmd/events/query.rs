@@ -18,6 +18,9 @@ pub struct QueryOpts {
     console_opt: ConsoleOpt,
     /// event API query (read from file if the argument starts with @)
     query: String,
+    /// after the results, show how the query was executed
+    #[arg(long)]
+    explain: bool,
 }
 
 pub struct EventsQuery;
@@ -49,6 +52,9 @@ impl AxCliCommand for EventsQuery {
                     upper_bound: None,
                     query,
                     order: Order::Asc,
+                    explain: opts.explain,
+                    page_size: None,
+                    cursor: None,
                 }),
             )
             .await?;
@@ -67,6 +73,7 @@ impl AxCliCommand for EventsQuery {
             EventDiagnostic::Event(e) => Value::from(e).to_string(),
             EventDiagnostic::AntiEvent(e) => format!("- {}", Value::from(e)),
             EventDiagnostic::Diagnostic(d) => format!("{:?}: {}", d.severity, d.message),
+            EventDiagnostic::Plan(p) => p.to_string().trim_end().to_owned(),
         }
     }
 }
@@ -4,6 +4,7 @@ use ax_core::{
     crypto::KeyPair,
     node_connection::request_banyan,
     private_key::{load_dev_cert, AxPrivateKey},
+    swarm::archive::{ArchiveIndex, ArchiveReader},
     util::{
         formats::{
             banyan_protocol::{decode_dump_header, BanyanRequest, BanyanResponse},
@@ -12,18 +13,20 @@ use ax_core::{
         gen_stream::GenStream,
     },
 };
+use ax_sdk::types::Timestamp;
 use cbor_data::{Cbor, CborBuilder, Encoder};
+use chrono::{DateTime, Utc};
 use futures::Stream;
 use std::{
     fs::File,
     io::{ErrorKind, Read, Write},
     net::TcpStream,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 use tungstenite::{connect, stream::MaybeTlsStream, Message, WebSocket};
 
 #[derive(clap::Parser, Clone, Debug)]
-/// restore events from an event dump to a temporary topic
+/// restore events from an event dump or archive to a temporary topic
 pub struct RestoreOpts {
     /// file to read the dump from
     #[arg(long, short = 'I', value_name = "FILE")]
@@ -43,6 +46,15 @@ pub struct RestoreOpts {
     /// defaults to wss://cloudmirror.actyx.net/forward
     #[arg(long, value_name = "URL")]
     url: Option<String>,
+    /// restore events from the archive directory of pruned events
+    #[arg(long, value_name = "DIR")]
+    archive: Option<PathBuf>,
+    /// only restore archived events from this time on, in ISO 8601 (i.e. 2014-11-28T12:00:09Z)
+    #[arg(long, requires = "archive")]
+    from: Option<DateTime<Utc>>,
+    /// only restore archived events up to this time, in ISO 8601 (i.e. 2014-11-28T12:00:09Z)
+    #[arg(long, requires = "archive")]
+    to: Option<DateTime<Utc>>,
 }
 pub const URL: &str = "wss://cloudmirror.actyx.net/forward";
 
@@ -83,16 +95,21 @@ impl AxCliCommand for EventsRestore {
 
     fn run(opts: Self::Opt) -> Box<dyn Stream<Item = ActyxOSResult<Self::Output>> + Unpin> {
         Box::new(GenStream::new(move |_co| async move {
-            if opts.cloud.is_some() && opts.input.is_some() {
+            let sources = [opts.cloud.is_some(), opts.input.is_some(), opts.archive.is_some()];
+            if sources.iter().filter(|source| **source).count() > 1 {
                 return Err(ActyxOSError::new(
                     ActyxOSCode::ERR_UNSUPPORTED,
-                    "cannot restore from cloud and file at the same time",
+                    "cannot restore from more than one of cloud, file and archive at the same time",
                 ));
             }
 
             let mut diag = Diag::new(opts.quiet);
 
-            let mut input: Box<dyn Read> = if let Some(ref input) = opts.input {
+            let mut input: Box<dyn Read> = if let Some(ref archive) = opts.archive {
+                let from = opts.from.map_or(Timestamp::from(0u64), Timestamp::from);
+                let to = opts.to.map_or(Timestamp::from(u64::MAX), Timestamp::from);
+                Box::new(read_archive(archive, from, to, &mut diag)?)
+            } else if let Some(ref input) = opts.input {
                 Box::new(File::open(input.as_path()).io("opening input dump")?)
             } else if let Some(ref cloud) = opts.cloud {
                 let file = File::create(cloud.as_path()).io("opening cloud dump")?;
@@ -199,6 +216,18 @@ impl AxCliCommand for EventsRestore {
     }
 }
 
+/// Stream the archived events with timestamps within `from..=to` as a single compressed dump.
+fn read_archive(directory: &Path, from: Timestamp, to: Timestamp, diag: &mut Diag) -> ActyxOSResult<impl Read> {
+    let index =
+        ArchiveIndex::load(directory).ax_err_ctx(ActyxOSCode::ERR_INVALID_INPUT, "cannot read archive index")?;
+    for file in index.select(from, to) {
+        diag.log(format!("reading archive file {} ({} events)", file.name, file.events))?;
+    }
+    let events = ArchiveReader::new(directory, from, to)
+        .map_err(|e| ActyxOSError::new(ActyxOSCode::ERR_INVALID_INPUT, format!("{:#}", e)))?;
+    zstd::stream::read::Encoder::new(events, 3).io("initialising zstd")
+}
+
 struct WsRead {
     file: File,
     sock: WebSocket<MaybeTlsStream<TcpStream>>,
@@ -46,6 +46,7 @@ impl AxCliCommand for EventsSubscribe {
                 peer,
                 EventsRequest::Subscribe(SubscribeRequest {
                     lower_bound: None,
+                    invalid_payloads: None,
                     query,
                 }),
             )
@@ -65,6 +66,7 @@ impl AxCliCommand for EventsSubscribe {
             EventDiagnostic::Event(e) => Value::from(e).to_string(),
             EventDiagnostic::AntiEvent(e) => format!("- {}", Value::from(e)),
             EventDiagnostic::Diagnostic(d) => format!("{:?}: {}", d.severity, d.message),
+            EventDiagnostic::Plan(p) => p.to_string().trim_end().to_owned(),
         }
     }
 }
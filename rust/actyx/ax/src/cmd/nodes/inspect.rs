@@ -73,6 +73,9 @@ impl AxCliCommand for NodesInspect {
         if let Some(node_version) = node_version {
             writeln!(&mut s, "Node version: {}", node_version).unwrap()
         }
+        if result.replica {
+            writeln!(&mut s, "Mode: replica").unwrap()
+        }
 
         writeln!(&mut s, "SwarmAddrs:").unwrap();
         for addr in &result.swarm_addrs {
@@ -197,6 +200,16 @@ impl AxCliCommand for NodesInspect {
             writeln!(&mut s, "{}", ping).unwrap();
         }
 
+        if !result.not_replicated_streams.is_empty() {
+            writeln!(&mut s, "Streams not replicated:").unwrap();
+            let mut table = Table::new();
+            table.load_preset(UTF8_FULL_CONDENSED).set_header(["STREAM", "NAME"]);
+            for stream in &result.not_replicated_streams {
+                table.add_row([&*stream.stream_id, stream.name.as_deref().unwrap_or("unknown")]);
+            }
+            writeln!(&mut s, "{}", table).unwrap();
+        }
+
         s
     }
 }
@@ -1,6 +1,10 @@
 pub mod keygen;
+pub mod stream_key;
 
-use crate::cmd::{swarms::keygen::KeygenOpts, AxCliCommand};
+use crate::cmd::{
+    swarms::{keygen::KeygenOpts, stream_key::StreamKeyOpts},
+    AxCliCommand,
+};
 use futures::Future;
 
 #[derive(clap::Subcommand, Clone, Debug)]
@@ -8,10 +12,13 @@ use futures::Future;
 pub enum SwarmsOpts {
     /// Generate a new swarm key.
     Keygen(KeygenOpts),
+    /// Generate an encryption key for an event stream and wrap it for the given nodes.
+    StreamKey(StreamKeyOpts),
 }
 
 pub fn run(opts: SwarmsOpts, json: bool) -> Box<dyn Future<Output = ()> + Unpin> {
     match opts {
         SwarmsOpts::Keygen(opt) => keygen::SwarmsKeygen::output(opt, json),
+        SwarmsOpts::StreamKey(opt) => stream_key::SwarmsStreamKey::output(opt, json),
     }
 }
@@ -0,0 +1,65 @@
+use crate::cmd::AxCliCommand;
+use ax_core::{
+    swarm::{StreamKeys, STREAM_KEY_LENGTH},
+    util::formats::{ActyxOSCode, ActyxOSResult, ActyxOSResultExt},
+};
+use ax_sdk::types::NodeId;
+use futures::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Output {
+    stream_key: String,
+    encryption: BTreeMap<String, String>,
+}
+pub struct SwarmsStreamKey();
+impl AxCliCommand for SwarmsStreamKey {
+    type Opt = StreamKeyOpts;
+    type Output = Output;
+    fn run(opts: Self::Opt) -> Box<dyn Stream<Item = ActyxOSResult<Self::Output>> + Unpin> {
+        Box::new(stream::once(futures::future::ready(run(opts))))
+    }
+    fn pretty(result: Self::Output) -> String {
+        format!(
+            "Stream key (keep it secret, it is needed for adding nodes later): {}\n\
+            Value for the `encryption` property of the stream in the `eventRouting.streams` settings:\n{}",
+            result.stream_key,
+            serde_json::to_string_pretty(&result.encryption).unwrap()
+        )
+    }
+}
+
+#[derive(clap::Parser, Clone, Debug)]
+/// generate an encryption key for an event stream
+pub struct StreamKeyOpts {
+    /// IDs of the nodes that shall be able to read the stream.
+    #[arg(required = true)]
+    node_ids: Vec<NodeId>,
+    /// Use this previously generated stream key instead of generating a new one.
+    #[arg(short, long)]
+    key: Option<String>,
+}
+
+pub fn run(opts: StreamKeyOpts) -> ActyxOSResult<Output> {
+    let key = match opts.key {
+        Some(key) => {
+            let key = StreamKeys::decode(&key).ax_invalid_input()?;
+            if key.len() != STREAM_KEY_LENGTH {
+                return Err(ActyxOSCode::ERR_INVALID_INPUT.with_message("this is not a stream key"));
+            }
+            key
+        }
+        None => StreamKeys::generate().to_vec(),
+    };
+    let encryption = opts
+        .node_ids
+        .into_iter()
+        .map(|node_id| Ok((node_id.to_string(), StreamKeys::wrap(&key, node_id).ax_invalid_input()?)))
+        .collect::<ActyxOSResult<_>>()?;
+    Ok(Output {
+        stream_key: StreamKeys::encode(&key),
+        encryption,
+    })
+}
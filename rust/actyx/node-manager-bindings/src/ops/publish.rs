@@ -21,7 +21,16 @@ struct Args {
 }
 
 async fn publish(mut tx: Sender<Task>, peer: PeerId, data: Vec<PublishEvent>) -> ActyxOSResult<PublishResponse> {
-    let r = publish_impl(&mut tx, peer, EventsRequest::Publish(PublishRequest { data })).await;
+    let r = publish_impl(
+        &mut tx,
+        peer,
+        EventsRequest::Publish(PublishRequest {
+            data,
+            idempotency_key: None,
+            precondition: None,
+        }),
+    )
+    .await;
 
     match r {
         Err(err) => ax_err(
@@ -35,6 +35,9 @@ async fn do_query(mut tx: Sender<Task>, peer: PeerId, query: String) -> ActyxOSR
             upper_bound: None,
             query,
             order: Order::Asc,
+            explain: false,
+            page_size: None,
+            cursor: None,
         }),
     )
     .await;
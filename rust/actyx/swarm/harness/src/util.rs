@@ -26,6 +26,8 @@ pub fn to_publish(events: Vec<(TagSet, Payload)>) -> PublishRequest {
             .into_iter()
             .map(|(tags, payload)| PublishEvent { tags, payload })
             .collect(),
+        idempotency_key: None,
+        precondition: None,
     }
 }
 
@@ -0,0 +1,130 @@
+/*
+ * Copyright 2021 Actyx AG
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::value::{RawValue, Value};
+use warp::filters::ws::Message;
+
+/// Fast compression, the streams are mostly sent to constrained consumers in real time.
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum Incoming<'a> {
+    Request(#[serde(borrow)] RequestBody<'a>),
+    #[serde(rename_all = "camelCase")]
+    Cancel {
+        request_id: ReqId,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestBody<'a> {
+    pub service_id: &'a str,
+    pub request_id: ReqId,
+    pub payload: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum Outgoing {
+    #[serde(rename_all = "camelCase")]
+    Next { request_id: ReqId, payload: Vec<Payload> },
+    #[serde(rename_all = "camelCase")]
+    Complete { request_id: ReqId },
+    #[serde(rename_all = "camelCase")]
+    Error { request_id: ReqId, kind: ErrorKind },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    UnknownEndpoint {
+        endpoint: String,
+        valid_endpoints: Vec<String>,
+    },
+    InternalError,
+    BadRequest {
+        message: String,
+    },
+    ServiceError {
+        value: Value,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, PartialEq, Eq)]
+pub struct ReqId(pub u64);
+
+/// Encoding of the messages sent to the client.
+///
+/// Requests are accepted as JSON text messages or as CBOR binary messages regardless of the codec.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Codec {
+    /// Each envelope is sent as one text message holding JSON.
+    #[default]
+    Json,
+    /// Each envelope is sent as one binary message holding a CBOR data item, which is compressed
+    /// into one zstd frame if `zstd` is set.
+    Cbor { zstd: bool },
+}
+
+impl Codec {
+    pub(crate) fn payload<T: Serialize>(self, value: &T) -> Result<Payload, String> {
+        match self {
+            Codec::Json => serde_json::value::to_raw_value(value)
+                .map(Payload::Json)
+                .map_err(|e| e.to_string()),
+            Codec::Cbor { .. } => serde_cbor::value::to_value(value)
+                .map(Payload::Cbor)
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    pub(crate) fn message(self, envelope: &Outgoing) -> Message {
+        match self {
+            Codec::Json => Message::text(serde_json::to_string(envelope).expect("Could not serialize envelope")),
+            Codec::Cbor { zstd } => {
+                let bytes = serde_cbor::to_vec(envelope).expect("Could not serialize envelope");
+                if zstd {
+                    Message::binary(
+                        zstd::encode_all(bytes.as_slice(), ZSTD_LEVEL).expect("Could not compress envelope"),
+                    )
+                } else {
+                    Message::binary(bytes)
+                }
+            }
+        }
+    }
+}
+
+/// A response encoded by the [`Codec`] of the connection.
+#[derive(Debug, Clone)]
+pub enum Payload {
+    Json(Box<RawValue>),
+    Cbor(serde_cbor::Value),
+}
+
+impl Serialize for Payload {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Payload::Json(value) => value.serialize(serializer),
+            Payload::Cbor(value) => value.serialize(serializer),
+        }
+    }
+}
@@ -2,30 +2,31 @@ use crate::files::FilesGetResponse;
 use anyhow::Result;
 use ax_types::{
     service::{
-        AuthenticationResponse, OffsetsResponse, Order, PublishEvent, PublishRequest, PublishResponse, QueryRequest,
-        QueryResponse, SessionId, SubscribeMonotonicRequest, SubscribeMonotonicResponse, SubscribeRequest,
-        SubscribeResponse,
+        AuthenticationResponse, InvalidPayloads, OffsetsResponse, Order, PublishEvent, PublishPrecondition,
+        PublishRequest, PublishResponse, QueryRequest, QueryResponse, SessionId, SubscribeMonotonicRequest,
+        SubscribeMonotonicResponse, SubscribeRequest, SubscribeResponse,
     },
     AppManifest, NodeId, OffsetMap, Payload, TagSet,
 };
 use bytes::Bytes;
 use futures::{
     future::{self, BoxFuture, FusedFuture},
-    stream::{iter, BoxStream, Stream, StreamExt},
+    stream::{iter, once, BoxStream, Stream, StreamExt},
     FutureExt,
 };
 use libipld::Cid;
 use rand::Rng;
 use reqwest::{
-    header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    header::{ACCEPT, CONTENT_DISPOSITION, CONTENT_TYPE},
     multipart::Form,
     Client, RequestBuilder, Response, StatusCode,
 };
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
     fmt::Debug,
     future::Future,
     mem::replace,
+    num::NonZeroU64,
     pin::Pin,
     str::FromStr,
     sync::{Arc, RwLock},
@@ -448,6 +449,79 @@ pub(crate) fn to_lines(stream: impl Stream<Item = Result<Bytes, reqwest::Error>>
         .flatten()
 }
 
+/// Response encodings for event streams, in order of preference; CBOR is more compact and cheaper to decode.
+const ACCEPT_STREAM: &str = "application/cbor, application/x-ndjson";
+
+/// Decode a stream of responses, sent as CBOR sequence or NDJSON depending on the node version.
+fn response_stream<T: DeserializeOwned + Send + 'static>(response: Response) -> BoxStream<'static, T> {
+    let is_cbor = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .map_or(false, |content_type| content_type == "application/cbor");
+    if is_cbor {
+        from_cbor_seq(response.bytes_stream())
+            // FIXME this swallows deserialization errors, silently dropping event envelopes
+            .filter_map(|res| {
+                future::ready(
+                    res.map_err(|e| tracing::warn!("dropping undecodable CBOR response: {}", e))
+                        .ok(),
+                )
+            })
+            .boxed()
+    } else {
+        to_lines(response.bytes_stream())
+            .map(|bytes| serde_json::from_slice::<T>(&bytes))
+            // FIXME this swallows deserialization errors, silently dropping event envelopes
+            .filter_map(|res| future::ready(res.ok()))
+            .boxed()
+    }
+}
+
+/// Split a CBOR sequence into its data items, skipping `null` keep-alive messages.
+///
+/// Malformed or truncated data end the stream with an error, since the following items cannot be located.
+fn from_cbor_seq<T: DeserializeOwned>(
+    stream: impl Stream<Item = Result<Bytes, reqwest::Error>>,
+) -> impl Stream<Item = serde_cbor::Result<T>> {
+    let mut buf = Vec::<u8>::new();
+    let mut failed = false;
+    // `None` marks the end of the response, after which no incomplete item may remain
+    let to_items = move |bytes: Option<Bytes>| {
+        let mut items = vec![];
+        if failed {
+            return iter(items);
+        }
+        let end = bytes.is_none();
+        buf.extend_from_slice(bytes.as_deref().unwrap_or_default());
+        let mut consumed = 0;
+        while consumed < buf.len() {
+            let mut de = serde_cbor::Deserializer::from_slice(&buf[consumed..]);
+            // decode the structure first, so that an item of unexpected shape doesn't lose the position
+            match serde_cbor::Value::deserialize(&mut de) {
+                Ok(serde_cbor::Value::Null) => consumed += de.byte_offset(),
+                Ok(value) => {
+                    consumed += de.byte_offset();
+                    items.push(serde_cbor::value::from_value(value));
+                }
+                Err(e) if e.is_eof() && !end => break,
+                Err(e) => {
+                    failed = true;
+                    items.push(Err(e));
+                    break;
+                }
+            }
+        }
+        buf.drain(..consumed);
+        iter(items)
+    };
+    stream
+        .take_while(|res| future::ready(res.is_ok()))
+        .map(|res| res.ok())
+        .chain(once(future::ready(None)))
+        .map(to_items)
+        .flatten()
+}
+
 /// Request builder for event publishing.
 ///
 /// Warning: [`Publish`] implements the [`Future`] trait and as such it can be polled.
@@ -462,7 +536,11 @@ impl<'a> Publish<'a> {
     fn new(client: &'a Ax) -> Self {
         Self::Initial {
             client,
-            request: PublishRequest { data: vec![] },
+            request: PublishRequest {
+                data: vec![],
+                idempotency_key: None,
+                precondition: None,
+            },
         }
     }
 
@@ -546,6 +624,45 @@ impl<'a> Publish<'a> {
         }
         panic!("Calling Publish::events after polling.");
     }
+
+    /// Set an idempotency key for this publication.
+    ///
+    /// If the same app already published with this key recently (see the node setting
+    /// `api.events.publishKeyWindow`), no events are published and the response of the
+    /// earlier publication is returned. This makes it safe to retry a publication whose
+    /// response was lost.
+    ///
+    /// # Panics
+    ///
+    /// Calling this function after polling [`Publish`] will result in a panic.
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        if let Self::Initial { ref mut request, .. } = self {
+            request.idempotency_key = Some(key.into());
+            return self;
+        }
+        panic!("Calling Publish::with_idempotency_key after polling.");
+    }
+
+    /// Only publish if no events matching the tag expression exist beyond the given offsets.
+    ///
+    /// The check happens atomically with the publication on the node, so this can be used for
+    /// optimistic concurrency control: take the offsets from a query, decide what to publish,
+    /// and fail with a conflict (HTTP 409) if the node stored new matching events in between.
+    /// Events from other nodes that have not yet been replicated cannot be taken into account.
+    ///
+    /// # Panics
+    ///
+    /// Calling this function after polling [`Publish`] will result in a panic.
+    pub fn with_precondition(mut self, tag_expr: impl Into<String>, offsets: OffsetMap) -> Self {
+        if let Self::Initial { ref mut request, .. } = self {
+            request.precondition = Some(PublishPrecondition {
+                tag_expr: tag_expr.into(),
+                offsets,
+            });
+            return self;
+        }
+        panic!("Calling Publish::with_precondition after polling.");
+    }
 }
 
 impl<'a> Future for Publish<'a> {
@@ -602,6 +719,9 @@ impl<'a> Query<'a> {
                 lower_bound: Some(OffsetMap::empty()),
                 upper_bound: None,
                 order: Order::Asc,
+                explain: false,
+                page_size: None,
+                cursor: None,
             },
         }
     }
@@ -887,6 +1007,105 @@ impl<'a> Query<'a> {
         }
         panic!("Calling Query::with_order after polling.")
     }
+
+    /// Request a report on how the query was executed.
+    ///
+    /// After all results, the response contains a [`QueryResponse::Plan`] listing the
+    /// resolved tag expression, the event streams and offset ranges that were read (and whether
+    /// the tag index could be used for them), as well as the number of values and time spent in
+    /// each processing stage.
+    ///
+    /// # Panics
+    ///
+    /// Calling this function after polling [`Query`] will result in a panic.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use ax_sdk::{Ax, AxOpts, types::service::QueryResponse};
+    /// use futures::stream::StreamExt;
+    /// async fn explain_example() {
+    ///     let service = Ax::new(AxOpts::default()).await.unwrap();
+    ///     let mut response = service.query("FROM 'temperature' FILTER _.value > 20")
+    ///         .with_explain()
+    ///         .await
+    ///         .unwrap();
+    ///     while let Some(result) = response.next().await {
+    ///         if let QueryResponse::Plan(plan) = result {
+    ///             println!("{:#?}", plan);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn with_explain(mut self) -> Self {
+        if let Self::Initial { ref mut request, .. } = self {
+            request.explain = true;
+            return self;
+        }
+        panic!("Calling Query::with_explain after polling.")
+    }
+
+    /// Return at most `page_size` results.
+    ///
+    /// If the query has not been completed, the last response is a [`QueryResponse::Cursor`]
+    /// that can be passed to [`Query::with_cursor`] to get the next page.
+    ///
+    /// # Panics
+    ///
+    /// Calling this function after polling [`Query`] will result in a panic.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use ax_sdk::{Ax, AxOpts, types::service::QueryResponse};
+    /// use futures::stream::StreamExt;
+    /// use std::num::NonZeroU64;
+    /// async fn pagination_example() {
+    ///     let service = Ax::new(AxOpts::default()).await.unwrap();
+    ///     let page_size = NonZeroU64::new(100).unwrap();
+    ///     let mut cursor = None;
+    ///     loop {
+    ///         let mut query = service.query("FROM 'temperature'").with_page_size(page_size);
+    ///         if let Some(cursor) = cursor.take() {
+    ///             query = query.with_cursor(cursor);
+    ///         }
+    ///         let mut response = query.await.unwrap();
+    ///         while let Some(result) = response.next().await {
+    ///             match result {
+    ///                 QueryResponse::Event(event) => println!("{:?}", event),
+    ///                 QueryResponse::Cursor(c) => cursor = Some(c.cursor),
+    ///                 _ => {}
+    ///             }
+    ///         }
+    ///         if cursor.is_none() {
+    ///             break;
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn with_page_size(mut self, page_size: NonZeroU64) -> Self {
+        if let Self::Initial { ref mut request, .. } = self {
+            request.page_size = Some(page_size);
+            return self;
+        }
+        panic!("Calling Query::with_page_size after polling.")
+    }
+
+    /// Continue a previous query where the page with the given cursor ended.
+    ///
+    /// The query needs to be the same as in the previous request; bounds and order are
+    /// taken from the cursor. See [`Query::with_page_size`] for an example.
+    ///
+    /// # Panics
+    ///
+    /// Calling this function after polling [`Query`] will result in a panic.
+    pub fn with_cursor(mut self, cursor: String) -> Self {
+        if let Self::Initial { ref mut request, .. } = self {
+            request.cursor = Some(cursor);
+            return self;
+        }
+        panic!("Calling Query::with_cursor after polling.")
+    }
 }
 
 impl<'a> Future for Query<'a> {
@@ -899,12 +1118,10 @@ impl<'a> Future for Query<'a> {
                 Query::Initial { client, request } => {
                     let query_response = async move {
                         let query_url = client.events_url("query");
-                        let response = client.do_request(|c| c.post(query_url).json(&request)).await?;
-                        let response_stream = to_lines(response.bytes_stream())
-                            .map(|bytes| serde_json::from_slice::<QueryResponse>(&bytes))
-                            // FIXME this swallows deserialization errors, silently dropping event envelopes
-                            .filter_map(|res| future::ready(res.ok()))
-                            .boxed();
+                        let response = client
+                            .do_request(|c| c.post(query_url).header(ACCEPT, ACCEPT_STREAM).json(&request))
+                            .await?;
+                        let response_stream = response_stream::<QueryResponse>(response);
                         Ok(response_stream)
                     };
                     Query::Pending(query_response.boxed())
@@ -945,6 +1162,7 @@ impl<'a> Subscribe<'a> {
             request: SubscribeRequest {
                 query: query.into(),
                 lower_bound: Some(OffsetMap::empty()),
+                invalid_payloads: None,
             },
         }
     }
@@ -1045,6 +1263,20 @@ impl<'a> Subscribe<'a> {
         }
         panic!("Calling Subscribe::with_lower_bound after polling.")
     }
+
+    /// Skip or flag events whose payload does not conform to the JSON Schema the node has
+    /// registered for one of their tags, instead of delivering them.
+    ///
+    /// # Panics
+    ///
+    /// Calling this function after polling [`Subscribe`] will result in a panic.
+    pub fn with_invalid_payloads(mut self, invalid_payloads: InvalidPayloads) -> Self {
+        if let Self::Initial { ref mut request, .. } = self {
+            request.invalid_payloads = Some(invalid_payloads);
+            return self;
+        }
+        panic!("Calling Subscribe::with_invalid_payloads after polling.")
+    }
 }
 
 impl<'a> Future for Subscribe<'a> {
@@ -1057,12 +1289,10 @@ impl<'a> Future for Subscribe<'a> {
                 Self::Initial { client, request } => {
                     let query_response = async move {
                         let query_url = client.events_url("subscribe");
-                        let response = client.do_request(|c| c.post(query_url).json(&request)).await?;
-                        let response_stream = to_lines(response.bytes_stream())
-                            .map(|bytes| serde_json::from_slice::<SubscribeResponse>(&bytes))
-                            // FIXME this swallows deserialization errors, silently dropping event envelopes
-                            .filter_map(|res| future::ready(res.ok()))
-                            .boxed();
+                        let response = client
+                            .do_request(|c| c.post(query_url).header(ACCEPT, ACCEPT_STREAM).json(&request))
+                            .await?;
+                        let response_stream = response_stream::<SubscribeResponse>(response);
                         Ok(response_stream)
                     };
                     Self::Pending(query_response.boxed())
@@ -1236,12 +1466,10 @@ impl<'a> Future for SubscribeMonotonic<'a> {
                 Self::Initial { client, request } => {
                     let query_response = async move {
                         let query_url = client.events_url("subscribe_monotonic");
-                        let response = client.do_request(|c| c.post(query_url).json(&request)).await?;
-                        let response_stream = to_lines(response.bytes_stream())
-                            .map(|bytes| serde_json::from_slice::<SubscribeMonotonicResponse>(&bytes))
-                            // FIXME this swallows deserialization errors, silently dropping event envelopes
-                            .filter_map(|res| future::ready(res.ok()))
-                            .boxed();
+                        let response = client
+                            .do_request(|c| c.post(query_url).header(ACCEPT, ACCEPT_STREAM).json(&request))
+                            .await?;
+                        let response_stream = response_stream::<SubscribeMonotonicResponse>(response);
                         Ok(response_stream)
                     };
                     Self::Pending(query_response.boxed())
@@ -1499,4 +1727,108 @@ mod tests {
             assert_eq!(request.lower_bound, OffsetMap::empty());
         }
     }
+
+    #[tokio::test]
+    async fn test_from_cbor_seq() {
+        use futures::StreamExt;
+
+        let mut bytes = serde_cbor::to_vec(&"first").unwrap();
+        bytes.push(0xf6);
+        bytes.extend(serde_cbor::to_vec(&42).unwrap());
+        bytes.extend(serde_cbor::to_vec(&"second").unwrap());
+        let chunks = bytes
+            .chunks(3)
+            .map(|chunk| Ok(bytes::Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+
+        let items = super::from_cbor_seq::<String>(futures::stream::iter(chunks))
+            .map(|res| res.ok())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(items, vec![Some("first".to_owned()), None, Some("second".to_owned())]);
+    }
+
+    #[tokio::test]
+    async fn test_from_cbor_seq_malformed() {
+        use futures::StreamExt;
+
+        async fn decode(bytes: Vec<u8>) -> Vec<Option<String>> {
+            let chunks = vec![Ok(bytes::Bytes::from(bytes))];
+            super::from_cbor_seq::<String>(futures::stream::iter(chunks))
+                .map(|res| res.ok())
+                .collect()
+                .await
+        }
+        let first = serde_cbor::to_vec(&"first").unwrap();
+        let second = serde_cbor::to_vec(&"second").unwrap();
+
+        // a stray break code, nothing after it can be decoded
+        let bytes = [first.as_slice(), &[0xff], &second].concat();
+        assert_eq!(decode(bytes).await, vec![Some("first".to_owned()), None]);
+
+        // the response ends in the middle of an item
+        let bytes = [first.as_slice(), &second[..2]].concat();
+        assert_eq!(decode(bytes).await, vec![Some("first".to_owned()), None]);
+    }
+
+    #[tokio::test]
+    async fn test_cbor_responses() {
+        use ax_types::{
+            app_id,
+            service::{Diagnostic, EventResponse, OffsetMapResponse, QueryResponse, SubscribeResponse},
+            Event, EventKey, Metadata, Timestamp,
+        };
+        use futures::StreamExt;
+        use serde::{de::DeserializeOwned, Serialize};
+
+        // encoded like the node does, see `KeepAlive::cbor`
+        async fn roundtrip<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(responses: Vec<T>) {
+            let mut bytes = vec![];
+            for response in &responses {
+                serde_cbor::to_writer(&mut bytes, response).unwrap();
+                bytes.push(0xf6);
+            }
+            let chunks = bytes
+                .chunks(7)
+                .map(|chunk| Ok(bytes::Bytes::copy_from_slice(chunk)))
+                .collect::<Vec<_>>();
+            let decoded = super::from_cbor_seq::<T>(futures::stream::iter(chunks))
+                .map(|res| res.unwrap())
+                .collect::<Vec<_>>()
+                .await;
+            assert_eq!(decoded, responses);
+        }
+
+        let stream = NodeId::new([1; 32]).stream(2.into());
+        let event = EventResponse::from(Event {
+            key: EventKey {
+                lamport: 3.into(),
+                stream,
+                offset: 5.into(),
+            },
+            meta: Metadata {
+                timestamp: Timestamp::new(1_000_000),
+                tags: tags!("a", "b"),
+                app_id: app_id!("com.example"),
+            },
+            payload: Payload::compact(&serde_json::json!({ "value": [1, "two", null] })).unwrap(),
+        });
+        let mut offsets = OffsetMap::empty();
+        offsets.update(stream, 5.into());
+
+        roundtrip(vec![
+            QueryResponse::Event(event.clone()),
+            QueryResponse::Diagnostic(Diagnostic::warn("careful".to_owned())),
+            QueryResponse::Offsets(OffsetMapResponse {
+                offsets: offsets.clone(),
+            }),
+        ])
+        .await;
+        roundtrip(vec![
+            SubscribeResponse::Event(event.clone()),
+            SubscribeResponse::AntiEvent(event),
+            SubscribeResponse::Offsets(OffsetMapResponse { offsets }),
+        ])
+        .await;
+    }
 }
@@ -147,6 +147,7 @@ module.exports = {
       items: [
         'reference/cli/cli-overview',
         'reference/cli/apps/sign',
+        'reference/cli/apps/revoke',
         'reference/cli/events/dump',
         'reference/cli/events/offsets',
         'reference/cli/events/publish',
@@ -159,6 +160,7 @@ module.exports = {
         'reference/cli/settings/set',
         'reference/cli/settings/unset',
         'reference/cli/swarms/keygen',
+        'reference/cli/swarms/stream-key',
         'reference/cli/users/keygen',
         'reference/cli/users/add-key',
         'reference/cli/topics/delete',